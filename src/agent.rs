@@ -0,0 +1,517 @@
+//! Comparing folders on machines that share no filesystem: `diff-folders
+//! serve <dir>` answers listings, hashes and reads of the files under `dir`
+//! over TCP, and `host:port:/path` names such a folder as one side of a
+//! comparison. Both ends share a token (`--token` or `DIFF_FOLDERS_TOKEN`),
+//! sent first on every connection.
+//!
+//! Every message is a frame: its length as a big endian `u64`, then that many
+//! bytes. A request is `<verb>\0<tree>\0<rel>`, where `verb` is `list`,
+//! `stat`, `read`, `head` or `hash`, `tree` the folder named by the client and `rel` a
+//! path below it. A response starts with a status byte, 0 for the answer
+//! that follows and 1 for an error, given as an error kind byte and a message.
+//! Entries are listed as `<kind> <size> <mtime> <rel>\0` records, `kind`
+//! being `d`, `f` or the name of a special file.
+//!
+//! `head` requests carry the most bytes to read, in decimal, after one more
+//! `\0`, and are answered with the start of the file.
+//!
+//! `delta` requests carry a [`delta::Signature`] of the client's own copy
+//! after one more `\0`, and are answered with the SHA-256 of the file in hex,
+//! then the encoded [`delta::Op`]s that rebuild it from that copy.
+
+use crate::delta;
+use crate::filter::WalkFilter;
+use crate::manifest;
+use crate::source::{list_dir, not_found, Source};
+use crate::status::{Entry, Special};
+use crate::text;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Where `serve` listens unless told otherwise; other machines can only reach
+/// it when `--listen` names an address of theirs, e.g. `0.0.0.0:7873`.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7873";
+
+/// Sent before the token, so a client and a server that speak different
+/// versions of the protocol tell it apart from a wrong token.
+const GREETING: &str = "diff-folders 1";
+
+/// Requests are paths and at most one signature, so anything longer is a
+/// client up to no good.
+const MAX_REQUEST: u64 = 16 * 1024 * 1024;
+
+/// Clients answered at once; more are turned away until one hangs up.
+const MAX_CONNECTIONS: usize = 64;
+
+/// How long a client has to greet before it is dropped, so idle connections
+/// do not hold on to one of [`MAX_CONNECTIONS`].
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The token `host:port:/path` sides are opened with, see [`set_token`].
+static TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// Uses `token` for the agents opened from now on.
+pub fn set_token(token: &str) {
+    *TOKEN.lock().unwrap() = Some(token.to_string());
+}
+
+/// Splits `host:port:/path` into the address and the path, `None` for
+/// anything else. The port must be a number, so `C:\dir` is not taken for
+/// one.
+pub fn parse_spec(spec: &str) -> Option<(&str, &str)> {
+    let (host, rest) = spec.split_once(':')?;
+    let (port, path) = rest.split_once(':')?;
+    if host.is_empty() || host.contains('/') || path.is_empty() || port.parse::<u16>().is_err() {
+        return None;
+    }
+    Some((&spec[..host.len() + 1 + port.len()], path))
+}
+
+/// Answers every client of `listener` from the files under `root`, one
+/// thread per connection and at most [`MAX_CONNECTIONS`] at a time, until the
+/// process is stopped.
+pub fn serve(listener: TcpListener, root: &Path, token: &str) -> io::Result<()> {
+    let root = root.canonicalize()?;
+    let open = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("accept a connection failed: {}", e);
+                continue;
+            }
+        };
+        if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            open.fetch_sub(1, Ordering::SeqCst);
+            let e = io::Error::other("too many connections");
+            let _ = write_frame(&mut stream, &error_payload(&e));
+            continue;
+        }
+        let (root, token) = (root.clone(), token.to_string());
+        let open = scopeguard::guard(open.clone(), |open| {
+            open.fetch_sub(1, Ordering::SeqCst);
+        });
+        thread::spawn(move || {
+            let _open = open;
+            let peer = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            if let Err(e) = answer(stream, &root, &token) {
+                log::warn!("{}: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Checks the client's token, then answers its requests until it hangs up.
+fn answer(stream: TcpStream, root: &Path, token: &str) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    // nothing longer than the greeting is read before the token checks out
+    let expected = format!("{}\0{}", GREETING, token);
+    reader.get_ref().set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let hello = match read_frame(&mut reader, expected.len() as u64) {
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => Vec::new(),
+        hello => hello?,
+    };
+    reader.get_ref().set_read_timeout(None)?;
+    if !same_secret(&hello, expected.as_bytes()) {
+        let e = io::Error::new(io::ErrorKind::PermissionDenied, "wrong token or version");
+        write_frame(&mut writer, &error_payload(&e))?;
+        writer.flush()?;
+        return Err(e);
+    }
+    write_frame(&mut writer, &[0])?;
+    writer.flush()?;
+    loop {
+        let request = match read_frame(&mut reader, MAX_REQUEST) {
+            Ok(request) => request,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let payload = match handle(root, &request) {
+            Ok(mut answer) => {
+                answer.insert(0, 0);
+                answer
+            }
+            Err(e) => error_payload(&e),
+        };
+        write_frame(&mut writer, &payload)?;
+        writer.flush()?;
+    }
+}
+
+fn handle(root: &Path, request: &[u8]) -> io::Result<Vec<u8>> {
+    let mut parts = request.splitn(4, |b| *b == 0);
+    let (verb, tree, rel) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(verb), Some(tree), Some(rel)) => (
+            String::from_utf8_lossy(verb),
+            String::from_utf8_lossy(tree),
+            String::from_utf8_lossy(rel),
+        ),
+        _ => return Err(invalid("malformed request")),
+    };
+    let body = parts.next().unwrap_or_default();
+    let tree = resolve_tree(root, &tree)?;
+    let rel = Path::new(rel.as_ref());
+    if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(invalid(&format!(
+            "{} leaves the served folder",
+            rel.display()
+        )));
+    }
+    let path = tree.join(rel);
+    match verb.as_ref() {
+        "list" => {
+            // everything is listed, the client applies its own filter
+            let filter = WalkFilter::from_rules(&[])
+                .with_hidden(true)
+                .with_vcs_exclude(false);
+            let mut out = Vec::new();
+            for (rel, entry) in list_dir(&path, &filter)? {
+                if !rel.as_os_str().is_empty() {
+                    out.extend(record(&rel, &entry));
+                }
+            }
+            Ok(out)
+        }
+        "stat" => {
+            let meta = path.symlink_metadata()?;
+            let mut entry = Entry::new(path, meta.is_dir(), meta.len());
+            entry.mtime = crate::status::mtime_nanos(&meta);
+            entry.special = Special::of(meta.file_type());
+            Ok(record(rel, &entry))
+        }
+        "read" => fs::read(resolve_file(root, &path)?),
+        "head" => {
+            let len: u64 = String::from_utf8_lossy(body)
+                .parse()
+                .map_err(|_| invalid("malformed length"))?;
+            let mut bytes = Vec::new();
+            fs::File::open(resolve_file(root, &path)?)?
+                .take(len)
+                .read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+        "hash" => Ok(manifest::hash_file(&resolve_file(root, &path)?)?.into_bytes()),
+        "delta" => {
+            let signature = delta::Signature::from_bytes(body)?;
+            let data = fs::read(resolve_file(root, &path)?)?;
+            let mut out = manifest::hash_bytes(&data).into_bytes();
+            out.extend(delta::encode(&delta::delta(&signature, &data)));
+            Ok(out)
+        }
+        _ => Err(invalid(&format!("unknown request {}", verb))),
+    }
+}
+
+/// The folder a client asked for, which must be `root` or lie below it;
+/// relative paths are taken from `root`.
+fn resolve_tree(root: &Path, tree: &str) -> io::Result<PathBuf> {
+    let tree = root.join(tree).canonicalize()?;
+    if !tree.starts_with(root) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is outside the served folder", tree.display()),
+        ));
+    }
+    Ok(tree)
+}
+
+/// The file at `path` with its links followed, which must still be below
+/// `root` and must not be a special file.
+fn resolve_file(root: &Path, path: &Path) -> io::Result<PathBuf> {
+    let path = path.canonicalize()?;
+    if !path.starts_with(root) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} links outside the served folder", path.display()),
+        ));
+    }
+    crate::status::ensure_not_special(&path)?;
+    Ok(path)
+}
+
+/// `<kind> <size> <mtime> <rel>\0`, see the module docs.
+fn record(rel: &Path, entry: &Entry) -> Vec<u8> {
+    let kind = match (entry.special, entry.is_dir()) {
+        (Some(special), _) => special.name(),
+        (None, true) => "d",
+        (None, false) => "f",
+    };
+    let mtime = entry.mtime.map_or("-".to_string(), |m| m.to_string());
+    format!(
+        "{} {} {} {}\0",
+        kind,
+        entry.size,
+        mtime,
+        text::slash_path(rel)
+    )
+    .into_bytes()
+}
+
+/// Compares the whole of both, so how long it takes tells nothing about how
+/// much of a token was right.
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn error_payload(e: &io::Error) -> Vec<u8> {
+    let kind = match e.kind() {
+        io::ErrorKind::NotFound => 1,
+        io::ErrorKind::PermissionDenied => 2,
+        io::ErrorKind::InvalidInput => 3,
+        _ => 0,
+    };
+    let mut payload = vec![1, kind];
+    payload.extend(e.to_string().into_bytes());
+    payload
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+fn write_frame(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&(payload.len() as u64).to_be_bytes())?;
+    writer.write_all(payload)
+}
+
+fn read_frame(reader: &mut impl Read, max: u64) -> io::Result<Vec<u8>> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_be_bytes(len);
+    if len > max {
+        return Err(invalid(&format!("a message of {} bytes is too long", len)));
+    }
+    let mut payload = Vec::new();
+    reader.take(len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed mid-message",
+        ));
+    }
+    Ok(payload)
+}
+
+/// A folder served by `diff-folders serve` on another machine.
+pub struct Agent {
+    // host:port
+    addr: String,
+    // the folder on the server, as the user named it
+    tree: String,
+    token: String,
+    label: PathBuf,
+    // kept open between requests, opened again after an error
+    conn: Mutex<Option<(BufReader<TcpStream>, BufWriter<TcpStream>)>>,
+}
+
+impl Agent {
+    /// Connects to the server named by `spec`, `host:port:/path`, and checks
+    /// that it takes `token` and serves the folder.
+    pub fn open(spec: &str, token: &str) -> io::Result<Self> {
+        let (addr, tree) =
+            parse_spec(spec).ok_or_else(|| invalid(&format!("{} is not host:port:/path", spec)))?;
+        let agent = Agent {
+            addr: addr.to_string(),
+            tree: tree.to_string(),
+            token: token.to_string(),
+            label: PathBuf::from(spec),
+            conn: Mutex::new(None),
+        };
+        agent.request("stat", Path::new(""), &[])?;
+        Ok(agent)
+    }
+
+    /// Opens `spec` with the token given by [`set_token`].
+    pub fn open_with_global_token(spec: &str) -> io::Result<Self> {
+        let token = TOKEN.lock().unwrap().clone().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} needs --token or DIFF_FOLDERS_TOKEN", spec),
+            )
+        })?;
+        Agent::open(spec, &token)
+    }
+
+    fn connect(&self) -> io::Result<(BufReader<TcpStream>, BufWriter<TcpStream>)> {
+        let stream = TcpStream::connect(&self.addr)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        write_frame(
+            &mut writer,
+            format!("{}\0{}", GREETING, self.token).as_bytes(),
+        )?;
+        writer.flush()?;
+        response(&read_frame(&mut reader, u64::MAX)?)?;
+        Ok((reader, writer))
+    }
+
+    /// Sends `verb` for `rel`, with `body` after it when there is one.
+    fn request(&self, verb: &str, rel: &Path, body: &[u8]) -> io::Result<Vec<u8>> {
+        let mut conn = self.conn.lock().unwrap();
+        let reused = conn.is_some();
+        let (reader, writer) = match conn.as_mut() {
+            Some(conn) => conn,
+            None => conn.insert(self.connect()?),
+        };
+        let mut request =
+            format!("{}\0{}\0{}", verb, self.tree, text::slash_path(rel)).into_bytes();
+        if !body.is_empty() {
+            request.push(0);
+            request.extend(body);
+        }
+        let sent = write_frame(writer, &request).and_then(|_| writer.flush());
+        let payload = match sent.and_then(|_| read_frame(reader, u64::MAX)) {
+            Ok(payload) => payload,
+            Err(e) => {
+                // a broken connection is not reused; one the server closed
+                // while idle is worth a second try on a fresh one
+                *conn = None;
+                drop(conn);
+                return if reused {
+                    self.request(verb, rel, body)
+                } else {
+                    Err(e)
+                };
+            }
+        };
+        response(&payload).map(<[u8]>::to_vec)
+    }
+
+    /// Parses the records of a `list` or `stat` answer, whose paths are
+    /// relative to the folder `base` of the tree.
+    fn parse_records(&self, base: &Path, out: &[u8]) -> Vec<(PathBuf, Entry)> {
+        let mut entries = Vec::new();
+        for record in out.split(|b| *b == 0).filter(|r| !r.is_empty()) {
+            let record = String::from_utf8_lossy(record);
+            let mut parts = record.splitn(4, ' ');
+            let (kind, size, mtime, rel) =
+                match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                    (Some(kind), Some(size), Some(mtime), Some(rel)) => (kind, size, mtime, rel),
+                    _ => continue,
+                };
+            let rel = base.join(rel);
+            let mut entry = Entry::new(
+                self.label.join(&rel),
+                kind == "d",
+                size.parse().unwrap_or(0),
+            );
+            entry.mtime = mtime.parse().ok();
+            entry.special = kind.parse().ok();
+            entries.push((rel, entry));
+        }
+        entries
+    }
+}
+
+/// The answer in a response, or the error the server sent instead.
+fn response(payload: &[u8]) -> io::Result<&[u8]> {
+    match payload {
+        [0, answer @ ..] => Ok(answer),
+        [1, kind, message @ ..] => {
+            let kind = match kind {
+                1 => io::ErrorKind::NotFound,
+                2 => io::ErrorKind::PermissionDenied,
+                3 => io::ErrorKind::InvalidInput,
+                _ => io::ErrorKind::Other,
+            };
+            Err(io::Error::new(kind, String::from_utf8_lossy(message)))
+        }
+        _ => Err(invalid("malformed response")),
+    }
+}
+
+impl Source for Agent {
+    fn root(&self) -> &Path {
+        &self.label
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        self.list_under(Path::new(""), filter)
+    }
+
+    /// Only the folder `rel` is walked on the server.
+    fn list_under(&self, rel: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let out = self.request("list", rel, &[])?;
+        let mut files = HashMap::new();
+        for (rel, entry) in self.parse_records(rel, &out) {
+            // the server walks everything, an ignored folder hides what is in it
+            let ignored = rel
+                .ancestors()
+                .take_while(|a| !a.as_os_str().is_empty())
+                .any(|a| filter.is_ignored(a, a != rel || entry.is_dir()));
+            if !ignored {
+                files.insert(rel, entry);
+            }
+            if filter.is_full(files.len()) {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        self.request("read", rel, &[])
+    }
+
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        self.request("head", rel, len.to_string().as_bytes())
+    }
+
+    /// Only the blocks that differ from `basis` come over the network.
+    fn read_like(&self, rel: &Path, basis: &[u8]) -> io::Result<Vec<u8>> {
+        if basis.len() < delta::MIN_BASIS {
+            return self.read(rel);
+        }
+        let signature = delta::Signature::of(basis);
+        let out = self.request("delta", rel, &signature.to_bytes())?;
+        if out.len() < 64 {
+            return Err(invalid("malformed delta"));
+        }
+        let (sum, ops) = out.split_at(64);
+        let data = delta::apply(basis, signature.block_size, &delta::decode(ops)?)?;
+        if manifest::hash_bytes(&data).as_bytes() != sum {
+            // two different blocks with the same sums, rare but possible
+            log::warn!(
+                "{}: rebuilt from blocks with the wrong hash, reading it whole",
+                self.label.join(rel).display()
+            );
+            return self.read(rel);
+        }
+        Ok(data)
+    }
+
+    fn reads_deltas(&self) -> bool {
+        true
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        let out = self.request("stat", rel, &[])?;
+        self.parse_records(Path::new(""), &out)
+            .into_iter()
+            .next()
+            .map(|(_, e)| e)
+            .ok_or_else(|| not_found(&self.label, rel))
+    }
+
+    /// Hashed on the server, so only the digest crosses the network.
+    fn hash(&self, rel: &Path) -> io::Result<String> {
+        let out = self.request("hash", rel, &[])?;
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+}
@@ -1,56 +1,265 @@
+use crate::cli::Options;
+use crate::config;
+use crate::engine::{Engine, Progress};
+use crate::external;
+use crate::format;
+use crate::icons::IconSet;
+use crate::interrupt;
+use crate::journal::{Journal, Kind, Record};
+use crate::launch::Launch;
+use crate::mime::TypeFilter;
+use crate::model::Comparison;
+use crate::ops::{self, ApplyDirection};
+use crate::patch::{self, Hunk};
+use crate::session::{Review, Session};
+use crate::source::{self, Source};
 use crate::status::{FolderStatefulList, StatefulList};
-use crossterm::event::KeyCode;
-use file_diff::diff;
-use similar::{ChangeTag, TextDiff};
-use std::collections::HashMap;
+use crate::text::{self, PathStyle, RenderOptions};
+use crate::ui::{
+    clip_line, dim_hunks, mark_hunks, state_color, wrap_line, Modal, Monochrome, Outcome,
+};
+use crate::view::{self, Content, Diff};
+use crossterm::event::{self, Event, KeyCode, MouseButton, MouseEvent, MouseEventKind};
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
-use std::fs::File;
-use std::io::{self, BufRead, Read};
-use tui::layout::{Constraint, Direction, Layout};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+use tui::layout::{Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, Gauge, List, ListItem, Paragraph};
+use tui::widgets::{Block, Borders, List, ListItem, Paragraph};
 use tui::Terminal;
 use tui::{backend::Backend, Frame};
-use walkdir::DirEntry;
 
 enum WindowType {
     Left,
     Right,
 }
-pub struct App {
+
+/// What the open modal was opened for, done once it is confirmed or submitted.
+enum Pending {
+    Apply,
+    ApplyHunks,
+    Undo,
+    Search,
+    // the note of the entry at this relative path
+    Note(PathBuf),
+    // the walk hit `--max-files`
+    Limit,
+    Exclude,
+}
+
+/// The diff window contents of one entry, computed once when it is selected
+/// rather than on every draw.
+struct CachedView {
+    path: PathBuf,
+    show_credits: bool,
+    content: Content,
+    title: String,
+    // both files with their sizes and mtimes, for the status bar
+    sides: String,
+}
+
+pub struct App<S: Source = Box<dyn Source>> {
+    engine: Engine<S>,
+    // labels of the two trees
     new_dir: String,
     old_dir: String,
+    apply_to: ApplyDirection,
+    // files overwritten or removed by `A` are saved here first
+    backup_dir: PathBuf,
+    // removed files skip the system trash
+    permanent: bool,
+    applied: usize,
+    // operations of this session, undone with `u`
+    journal: Journal,
     tab: WindowType,
+    // everything the scan found; `items` holds the part `state_filter` and
+    // `type_filter` let through
+    comparison: Comparison,
+    // totals and top lists shown instead of the list until Tab or a selection
+    show_dashboard: bool,
+    // one of several pairs open in tabs, where Tab switches the pair and
+    // `:dashboard` toggles the dashboard instead
+    is_tabbed: bool,
     items: StatefulList<FolderStatefulList>,
+    // files inside the new and deleted folders opened with Enter, listed
+    // below the folder, by its path
+    expanded: HashMap<PathBuf, Vec<FolderStatefulList>>,
+    // results and opened files whose type is still to be detected, which
+    // happens behind the progress bar before the next draw
+    untyped: Vec<FolderStatefulList>,
+    state_filter: Option<crate::status::StatusItemType>,
+    type_filter: Option<TypeFilter>,
+    // bookmarks, notes and reviews, saved to the session file on every change
+    session: Session,
+    // entries with a review state are left out of `items`
+    hide_reviewed: bool,
+    // popup that takes the keys until it is closed
+    modal: Option<(Modal, Pending)>,
+    // the `:` command line while it is being typed
+    command: Option<String>,
+    // outcome of the last command, shown until the next key
+    command_message: Option<String>,
+    // what the last key did, like a copy, shown in the status bar until the
+    // next key
+    notice: Option<String>,
+    // `--difftool`
+    difftool: Option<String>,
+    // a program to run on the plain terminal, picked up by the event loop
+    launch: Option<Launch>,
 
-    // window status
-    scroll: u16,
+    // window status, `scroll` is the first diff line shown
+    scroll: usize,
+    h_scroll: u16,
+    is_wrapped: bool,
+    render: RenderOptions,
+    path_style: PathStyle,
+    // the style saved in the layout, which `P` changes but `--path-style`
+    // does not, since that only holds for the run
+    saved_path_style: Option<PathStyle>,
+    icons: IconSet,
+    // attributes and symbols instead of colors, `--no-color`
+    monochrome: bool,
     len_contents: usize,
+    // lines that fit in the diff window, from the last draw
+    view_height: usize,
+    // columns inside the diff window, from the last draw
+    view_width: usize,
     cur_file_path: Option<FolderStatefulList>,
+    view: Option<CachedView>,
+    // hunks of the displayed file, picked with space and applied with `a`
+    hunks: Vec<Hunk>,
+    hunk_selected: Vec<bool>,
+    hunk_cursor: usize,
+    // changes made only of `--ignore-matching-lines` lines, drawn dimmed
+    ignored_hunks: Vec<Hunk>,
+    // log viewer pane below the two windows
+    show_log: bool,
+    // `log::lines_written` when the viewer was last drawn
+    log_seen: usize,
+    // where the minimap was last drawn, for mouse clicks
+    minimap_area: Option<Rect>,
 
-    page_size: u16,
-    is_home: bool,
+    page_size: usize,
+    // percentage of the width given to the focused pane
+    split: u16,
+    // diff pane takes the whole screen
+    is_zoomed: bool,
+    // modified files show old and new side by side instead of a unified diff
+    is_columns: bool,
+    // the authors' letter replaces the diff, toggled with `?`
+    show_credits: bool,
     is_loaded: bool,
 }
 
 impl App {
-    pub fn new(old_dir: String, new_dir: String) -> Self {
+    /// Opens both sides named on the command line, see [`source::open`].
+    /// Without `--session` the review is kept for this pair of folders under
+    /// the cache directory.
+    pub fn new(mut options: Options) -> io::Result<Self> {
+        let old = source::open(&options.old_dir)?;
+        let new = source::open(&options.new_dir)?;
+        if !options.session.has_file() {
+            match Session::open_for_pair(&options.old_dir, &options.new_dir) {
+                Ok(mut session) => {
+                    session.merge(std::mem::take(&mut options.session));
+                    options.session = session;
+                }
+                Err(e) => log::warn!("read the review session failed: {}", e),
+            }
+        }
+        Ok(Self::with_sources(old, new, options))
+    }
+}
+
+impl<S: Source> App<S> {
+    pub fn with_sources(old: S, new: S, options: Options) -> Self {
         Self {
-            new_dir,
-            old_dir,
+            new_dir: new.root().to_string_lossy().into_owned(),
+            old_dir: old.root().to_string_lossy().into_owned(),
+            engine: Engine::with_sources(old, new, &options),
+            apply_to: options.apply_to,
+            backup_dir: crate::log::cache_dir()
+                .join("backup")
+                .join(format!("{}", unix_now())),
+            permanent: options.permanent,
+            applied: 0,
+            journal: Journal::default(),
             tab: WindowType::Left,
             scroll: 0,
+            h_scroll: 0,
+            is_wrapped: options.layout.wrap.unwrap_or(true),
+            render: RenderOptions {
+                tab_width: options.tab_width,
+                show_whitespace: options.layout.whitespace.unwrap_or(false),
+            },
+            path_style: options.path_style,
+            saved_path_style: options.layout.path_style,
+            // without colors the list needs the status symbols
+            icons: match (options.no_color, options.icons) {
+                (true, IconSet::Plain) => IconSet::Symbols,
+                (_, icons) => icons,
+            },
+            monochrome: options.no_color,
             len_contents: 0,
+            view_height: 0,
+            view_width: 0,
             cur_file_path: None,
-            is_home: false,
+            view: None,
+            hunks: Vec::new(),
+            hunk_selected: Vec::new(),
+            hunk_cursor: 0,
+            ignored_hunks: Vec::new(),
+            show_log: options.layout.log.unwrap_or(false),
+            log_seen: 0,
+            minimap_area: None,
+            show_credits: false,
             is_loaded: false,
             page_size: 0,
+            split: options
+                .layout
+                .split
+                .map_or(DEFAULT_SPLIT, |s| s.clamp(MIN_SPLIT, MAX_SPLIT)),
+            is_zoomed: false,
+            is_columns: options.layout.columns.unwrap_or(false),
+            comparison: Comparison::default(),
+            show_dashboard: true,
+            is_tabbed: false,
             items: StatefulList::with_items(Vec::new()),
+            expanded: HashMap::new(),
+            untyped: Vec::new(),
+            state_filter: None,
+            type_filter: options.type_filter,
+            session: options.session,
+            hide_reviewed: false,
+            modal: None,
+            command: None,
+            command_message: None,
+            notice: None,
+            difftool: options.difftool,
+            launch: None,
         }
     }
 
     pub fn event(&mut self, key_code: KeyCode) {
+        self.command_message = None;
+        self.notice = None;
+        if let Some((modal, _)) = &mut self.modal {
+            let outcome = modal.key(key_code);
+            if outcome != Outcome::Open {
+                if let Some((_, pending)) = self.modal.take() {
+                    self.resolve(pending, outcome);
+                }
+            }
+            return;
+        }
+        if self.command.is_some() {
+            return self.command_key(key_code);
+        }
         match key_code {
             KeyCode::Left => {
                 self.left();
@@ -66,14 +275,371 @@ impl App {
             }
             KeyCode::PageUp => self.page_up(),
             KeyCode::PageDown => self.page_down(),
-            KeyCode::Enter => self.enter(),
+            KeyCode::Enter => self.expand_or_enter(),
             KeyCode::Home => self.home(),
+            KeyCode::End => self.end(),
+            KeyCode::Char('?') => self.credits(),
+            KeyCode::Char('+') => self.resize(SPLIT_STEP as i16),
+            KeyCode::Char('-') => self.resize(-(SPLIT_STEP as i16)),
+            KeyCode::Char('z') => self.is_zoomed = !self.is_zoomed,
+            KeyCode::Char('c') => self.is_columns = !self.is_columns,
+            KeyCode::Char('y') => self.copy_path(),
+            KeyCode::Char('Y') => self.copy_diff(),
+            KeyCode::Char('A') => self.confirm_apply(),
+            KeyCode::Char('n') => self.move_hunk(1),
+            KeyCode::Char('p') => self.move_hunk(-1),
+            KeyCode::Char(' ') => self.toggle_hunk(),
+            KeyCode::Char('a') => self.confirm_apply_hunks(),
+            KeyCode::Char('u') => self.confirm_undo(),
+            KeyCode::Char('m') => self.toggle_bookmark(),
+            KeyCode::Char('\'') => self.next_bookmark(),
+            KeyCode::Char('N') => self.edit_note(),
+            KeyCode::Char('r') => self.cycle_review(),
+            KeyCode::Char('R') => {
+                self.hide_reviewed = !self.hide_reviewed;
+                self.refilter();
+            }
+            KeyCode::Char('/') => self.modal = Some((Modal::input("Go to file"), Pending::Search)),
+            KeyCode::Char('H') => {
+                let show_hidden = !self.engine.show_hidden();
+                self.engine.set_show_hidden(show_hidden);
+                self.reload();
+            }
+            KeyCode::Char('L') => self.show_log = !self.show_log,
+            KeyCode::Char('D') => self.open_difftool(),
+            KeyCode::Char('e') => self.open_editor(),
+            KeyCode::Char('P') => {
+                self.path_style = self.path_style.next();
+                self.saved_path_style = Some(self.path_style);
+            }
+            KeyCode::Char(':') => self.command = Some(String::new()),
+            KeyCode::Tab if !self.is_tabbed => self.show_dashboard = !self.show_dashboard,
+            KeyCode::Char('W') => {
+                self.render.show_whitespace = !self.render.show_whitespace;
+            }
+            KeyCode::Char('w') => {
+                self.is_wrapped = !self.is_wrapped;
+                self.h_scroll = 0;
+            }
             _ => {}
         }
     }
 
+    /// Whether keys go to the `:` command line or a modal, so the caller must
+    /// not treat `q` or Esc as quitting.
+    pub fn is_typing(&self) -> bool {
+        self.command.is_some() || self.modal.is_some()
+    }
+
+    /// Carries out what a closed modal was opened for.
+    fn resolve(&mut self, pending: Pending, outcome: Outcome) {
+        match (pending, outcome) {
+            (Pending::Apply, Outcome::Confirmed) => self.apply(),
+            (Pending::ApplyHunks, Outcome::Confirmed) => self.apply_hunks(),
+            (Pending::Undo, Outcome::Confirmed) => self.undo(),
+            (Pending::Note(rel), Outcome::Submitted(note)) => {
+                self.session.set_note(rel, &note);
+                self.save_session();
+            }
+            (Pending::Search, Outcome::Submitted(path)) => {
+                if let Err(e) = self.goto_file(path.trim()) {
+                    self.command_message = Some(e);
+                }
+            }
+            (Pending::Limit, Outcome::Chosen('c')) => {
+                self.engine.set_max_files(None);
+                self.reload();
+            }
+            (Pending::Limit, Outcome::Chosen('f')) => {
+                let prompt = Modal::input("Leave out (gitignore pattern)");
+                self.modal = Some((prompt, Pending::Exclude));
+            }
+            (Pending::Exclude, Outcome::Submitted(pattern)) if !pattern.trim().is_empty() => {
+                self.engine.add_exclude(pattern.trim().to_string());
+                self.reload();
+            }
+            _ => {}
+        }
+    }
+
+    fn command_key(&mut self, key_code: KeyCode) {
+        let line = match &mut self.command {
+            Some(line) => line,
+            None => return,
+        };
+        match key_code {
+            KeyCode::Char(c) => line.push(c),
+            KeyCode::Backspace if line.is_empty() => self.command = None,
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            KeyCode::Esc => self.command = None,
+            KeyCode::Enter => {
+                let line = self.command.take().unwrap_or_default();
+                if let Err(e) = self.run_command(&line) {
+                    self.command_message = Some(e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs a command typed after `:`:
+    /// - `42` scrolls the diff window to line 42
+    /// - `file src/app.rs` selects that path in the list
+    /// - `filter modified|new|deleted|all` lists only entries in that state
+    /// - `type text|binary|image|audio|video|font|archive|all` lists only files
+    ///   of that kind
+    /// - `dashboard` shows or hides the dashboard, like Tab with a single pair
+    fn run_command(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if let Ok(n) = line.parse::<usize>() {
+            return self.goto_line(n);
+        }
+        let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match name {
+            "" => Ok(()),
+            "file" | "f" => self.goto_file(arg),
+            "journal" => {
+                if arg.is_empty() {
+                    return Err("journal: expected a file".to_string());
+                }
+                self.journal
+                    .export(Path::new(arg))
+                    .map_err(|e| format!("journal: {}", e))?;
+                log::info!("session journal written to {}", arg);
+                Ok(())
+            }
+            "bookmarks" => {
+                if arg.is_empty() {
+                    return Err("bookmarks: expected a file".to_string());
+                }
+                self.session
+                    .bookmarks
+                    .export(Path::new(arg))
+                    .map_err(|e| format!("bookmarks: {}", e))?;
+                self.notice = Some(format!(
+                    "{} bookmarks written to {}",
+                    self.session.bookmarks.len(),
+                    arg
+                ));
+                Ok(())
+            }
+            "export" => {
+                if arg.is_empty() {
+                    return Err("export: expected a file".to_string());
+                }
+                let entries: Vec<_> = self
+                    .comparison
+                    .results
+                    .iter()
+                    .map(|item| (self.engine.rel_path(item), item))
+                    .collect();
+                self.session
+                    .export(Path::new(arg), &self.old_dir, &self.new_dir, &entries)
+                    .map_err(|e| format!("export: {}", e))?;
+                self.notice = Some(format!("{} entries written to {}", entries.len(), arg));
+                Ok(())
+            }
+            "filter" => {
+                self.state_filter = match arg {
+                    "modified" | "m" => Some(crate::status::StatusItemType::Modified),
+                    "new" | "n" => Some(crate::status::StatusItemType::New),
+                    "deleted" | "d" => Some(crate::status::StatusItemType::Deleted),
+                    "all" | "" => None,
+                    _ => {
+                        return Err(format!(
+                            "filter: expected modified, new, deleted or all, not {}",
+                            arg
+                        ))
+                    }
+                };
+                self.refilter();
+                Ok(())
+            }
+            "type" => {
+                self.type_filter = match arg {
+                    "all" | "" => None,
+                    kind => Some(kind.parse().map_err(|e| format!("type: {}", e))?),
+                };
+                self.refilter();
+                Ok(())
+            }
+            "dashboard" => {
+                self.show_dashboard = !self.show_dashboard;
+                Ok(())
+            }
+            _ => Err(format!("unknown command: {}", name)),
+        }
+    }
+
+    /// Scrolls the diff window so 1-based `line` is at the top.
+    fn goto_line(&mut self, line: usize) -> Result<(), String> {
+        if self.cur_file_path.is_none() || self.show_credits {
+            return Err("no file is shown".to_string());
+        }
+        self.scroll_to(line.saturating_sub(1));
+        self.tab = WindowType::Right;
+        self.show_dashboard = false;
+        Ok(())
+    }
+
+    /// Selects the entry whose path relative to its tree is `rel`, or failing
+    /// that the first one ending with it.
+    fn goto_file(&mut self, rel: &str) -> Result<(), String> {
+        if rel.is_empty() {
+            return Err("file: expected a path".to_string());
+        }
+        let rel = rel.trim_start_matches("./");
+        let paths: Vec<String> = self
+            .items
+            .items
+            .iter()
+            .map(|i| {
+                let path = i.entry.path();
+                text::slash_path(path.strip_prefix(self.root_of(i)).unwrap_or(path))
+            })
+            .collect();
+        let found = paths
+            .iter()
+            .position(|p| p == rel)
+            .or_else(|| paths.iter().position(|p| p.ends_with(&format!("/{}", rel))));
+        match found {
+            Some(i) => {
+                self.items.state.select(Some(i));
+                self.tab = WindowType::Left;
+                self.enter();
+                Ok(())
+            }
+            None => Err(format!("no such file in the list: {}", rel)),
+        }
+    }
+
+    /// Rebuilds the list from `results` with `state_filter` and `type_filter`
+    /// applied, keeping the selected entry when it still passes. When it is
+    /// gone the one now at its place is selected, and the first changed file
+    /// when nothing was selected yet.
+    fn refilter(&mut self) {
+        let before = self.items.state.selected();
+        let selected = self.items.cur().map(|i| i.entry.path().to_path_buf());
+        let items: Vec<_> = self
+            .comparison
+            .results
+            .iter()
+            .flat_map(|i| {
+                let inside = self.expanded.get(i.entry.path()).into_iter().flatten();
+                std::iter::once(i).chain(inside)
+            })
+            .filter(|i| self.state_filter.is_none_or(|s| i.state == s))
+            .filter(|i| {
+                !self.hide_reviewed || self.session.review(&self.engine.rel_path(i)).is_none()
+            })
+            .filter(|i| {
+                self.type_filter.is_none_or(|t| {
+                    self.comparison
+                        .types
+                        .get(i.entry.path())
+                        .is_some_and(|mime| t.matches(mime))
+                })
+            })
+            .cloned()
+            .collect();
+        let index = selected.and_then(|p| items.iter().position(|i| i.entry.path() == p));
+        self.items = StatefulList::with_items(items);
+        let len = self.items.items.len();
+        match (index, before) {
+            (Some(i), _) => self.items.state.select(Some(i)),
+            _ if len == 0 => self.cur_file_path = None,
+            (None, Some(before)) => {
+                self.cur_file_path = None;
+                self.items.state.select(Some(before.min(len - 1)));
+            }
+            (None, None) => {
+                // folders are passed over
+                self.cur_file_path = None;
+                let first = self.items.items.iter().position(|i| !i.entry.is_dir());
+                self.items.state.select(Some(first.unwrap_or(0)));
+            }
+        }
+        if self.cur_file_path.is_none() && self.items.cur().is_some_and(|i| !i.entry.is_dir()) {
+            self.open_selected();
+        }
+    }
+
+    /// Lists the files inside the selected new or deleted folder below it, or
+    /// folds them back in; other entries are opened.
+    fn expand_or_enter(&mut self) {
+        let dir = match self.items.cur() {
+            Some(cur)
+                if cur.entry.is_dir()
+                    && cur.state != crate::status::StatusItemType::Modified
+                    && cur.state != crate::status::StatusItemType::Normal =>
+            {
+                cur.clone()
+            }
+            _ => return self.enter(),
+        };
+        let path = dir.entry.path().to_path_buf();
+        if self.expanded.remove(&path).is_none() {
+            match self.engine.expand(&dir) {
+                Ok(files) => self.add_expanded(path, files),
+                Err(e) => {
+                    self.command_message = Some(format!("list {}: {}", path.display(), e));
+                    return;
+                }
+            }
+        }
+        self.refilter();
+    }
+
+    fn add_expanded(&mut self, dir: PathBuf, mut files: Vec<FolderStatefulList>) {
+        // moved files are listed on their own already
+        let listed: HashSet<&Path> = self
+            .comparison
+            .results
+            .iter()
+            .filter(|i| i.moved.is_some())
+            .map(|i| i.entry.path())
+            .collect();
+        files.retain(|f| !listed.contains(f.entry.path()));
+        self.untyped.extend(files.iter().cloned());
+        self.expanded.insert(dir, files);
+    }
+
+    /// Lists the opened folders again after a rescan, leaving out the ones
+    /// no longer new or deleted.
+    fn relist_expanded(&mut self) {
+        let dirs: Vec<_> = self
+            .comparison
+            .results
+            .iter()
+            .filter(|i| i.entry.is_dir() && self.expanded.contains_key(i.entry.path()))
+            .cloned()
+            .collect();
+        self.expanded.clear();
+        for dir in dirs {
+            match self.engine.expand(&dir) {
+                Ok(files) => self.add_expanded(dir.entry.path().to_path_buf(), files),
+                Err(e) => log::warn!("list {}: {}", dir.entry.path().display(), e),
+            }
+        }
+    }
+
+    /// Shows the diff of the selected entry, e.g. after a reload closed it,
+    /// without leaving the dashboard if it is up.
+    fn open_selected(&mut self) {
+        let show_dashboard = self.show_dashboard;
+        self.enter();
+        self.show_dashboard = show_dashboard;
+    }
+
     fn left(&mut self) {
         match self.tab {
+            // without wrapping, Left scrolls back first and only then leaves the diff window
+            WindowType::Right if !self.is_wrapped && self.h_scroll > 0 => {
+                self.h_scroll = self.h_scroll.saturating_sub(H_SCROLL_STEP)
+            }
             WindowType::Right => self.tab = WindowType::Left,
             _ => {}
         }
@@ -82,176 +648,892 @@ impl App {
     fn right(&mut self) {
         match self.tab {
             WindowType::Left => self.tab = WindowType::Right,
+            WindowType::Right if !self.is_wrapped => {
+                self.h_scroll = self.h_scroll.saturating_add(H_SCROLL_STEP)
+            }
             _ => {}
         }
     }
 
+    fn resize(&mut self, delta: i16) {
+        let split = self.split as i16 + delta;
+        self.split = split.clamp(MIN_SPLIT as i16, MAX_SPLIT as i16) as u16;
+    }
+
     fn up(&mut self) {
         match self.tab {
             WindowType::Left => {
+                let before = self.items.state.selected();
                 self.items.previous(1);
+                self.note_wrap(before);
                 self.enter();
             }
-            WindowType::Right => {
-                if self.scroll > 0 {
-                    self.scroll -= 1
-                }
-            }
+            WindowType::Right => self.scroll_to(self.scroll.saturating_sub(1)),
         }
     }
 
     fn down(&mut self) {
         match self.tab {
             WindowType::Left => {
+                let before = self.items.state.selected();
                 self.items.next(1);
+                self.note_wrap(before);
                 self.enter();
             }
-            WindowType::Right => {
-                let total = self.len_contents as u16;
-                if self.scroll >= total {
-                    self.scroll = total
-                } else {
-                    self.scroll += 1
-                }
-            }
+            WindowType::Right => self.scroll_to(self.scroll + 1),
+        }
+    }
+
+    /// Says so in the status bar when moving from `before` went round the
+    /// end of the list.
+    fn note_wrap(&mut self, before: Option<usize>) {
+        let (before, after) = match (before, self.items.state.selected()) {
+            (Some(before), Some(after)) if before != after => (before, after),
+            _ => return,
+        };
+        let last = self.items.items.len().saturating_sub(1);
+        if before == last && after == 0 {
+            self.notice = Some("wrapped to the top".to_string());
+        } else if before == 0 && after == last {
+            self.notice = Some("wrapped to the bottom".to_string());
         }
     }
 
     fn enter(&mut self) {
-        self.is_home = false;
+        self.show_credits = false;
+        self.show_dashboard = false;
+        let cur = match self.items.cur() {
+            Some(cur) => cur,
+            None => return,
+        };
         if let Some(file) = &self.cur_file_path {
-            if file.entry.path() == self.items.cur().entry.path() {
+            if file.entry.path() == cur.entry.path() {
                 // same file
                 return;
             }
         }
-        self.cur_file_path = Some(self.items.cur().clone());
-        self.scroll = 0
+        self.cur_file_path = Some(cur.clone());
+        self.scroll = 0;
+        self.h_scroll = 0;
+        self.load_hunks();
+    }
+
+    fn load_hunks(&mut self) {
+        self.hunks.clear();
+        self.ignored_hunks.clear();
+        self.hunk_cursor = 0;
+        if let Some(file) = &self.cur_file_path {
+            if file.state == crate::status::StatusItemType::Modified {
+                match self.engine.read_sides(file) {
+                    Ok((old, new)) => {
+                        let ignore = self.engine.ignore_lines();
+                        (self.hunks, self.ignored_hunks) =
+                            patch::partition_hunks(&old, &new, ignore);
+                    }
+                    Err(e) => log::error!("read {} failed: {}", file.entry.path().display(), e),
+                }
+            }
+        }
+        self.hunk_selected = vec![false; self.hunks.len()];
+    }
+
+    fn move_hunk(&mut self, step: isize) {
+        if self.hunks.is_empty() {
+            return;
+        }
+        let last = self.hunks.len() as isize - 1;
+        self.hunk_cursor = (self.hunk_cursor as isize + step).clamp(0, last) as usize;
+        self.scroll_to(self.hunks[self.hunk_cursor].display_start);
+    }
+
+    /// Scrolls the diff window to `line`, stopping where the last line reaches
+    /// the bottom so the last page stays filled.
+    fn scroll_to(&mut self, line: usize) {
+        let max = self.max_scroll(self.view.as_ref().map(|v| &v.content));
+        self.scroll = line.min(max);
+    }
+
+    /// The last line the diff window can start at. Wrapped lines take more
+    /// than one row, so the lines that fit at the bottom are counted in rows.
+    fn max_scroll(&self, content: Option<&Content>) -> usize {
+        let (lines, height) = (self.len_contents, self.view_height.max(1));
+        let content = match content {
+            Some(content) if self.wraps(content) => content,
+            _ => return lines.saturating_sub(height),
+        };
+        let tail = lines.saturating_sub(height)..lines;
+        let mut first = lines.saturating_sub(1);
+        let mut used = 0;
+        for (i, rows) in self
+            .line_rows(content, tail.clone())
+            .into_iter()
+            .enumerate()
+            .rev()
+        {
+            used += rows;
+            if used > height {
+                break;
+            }
+            first = tail.start + i;
+        }
+        first
+    }
+
+    /// Whether the diff window wraps the lines of `content`; columns never do.
+    fn wraps(&self, content: &Content) -> bool {
+        let columns = self.is_columns && !self.show_credits && matches!(content, Content::Diff(_));
+        self.is_wrapped && self.view_width > 0 && !columns
+    }
+
+    /// Rows each line in `range` of `content` takes in the diff window, with
+    /// the hunk gutter in front.
+    fn line_rows(&self, content: &Content, range: Range<usize>) -> Vec<usize> {
+        let gutter = !self.show_credits && !self.hunks.is_empty();
+        content
+            .spans(range, self.render)
+            .into_iter()
+            .map(|mut line| {
+                if gutter {
+                    line.0.insert(0, Span::raw(" "));
+                }
+                wrap_line(line, self.view_width).len()
+            })
+            .collect()
+    }
+
+    /// Lines below the top one, or above it going up, that fill a page of
+    /// the diff window; at least one.
+    fn page_lines(&self, down: bool) -> usize {
+        let content = match &self.view {
+            Some(view) if self.wraps(&view.content) => &view.content,
+            _ => return self.page_size,
+        };
+        let range = if down {
+            self.scroll..self.scroll + self.page_size
+        } else {
+            self.scroll.saturating_sub(self.page_size)..self.scroll
+        };
+        let mut rows = self.line_rows(content, range);
+        if !down {
+            rows.reverse();
+        }
+        let mut used = 0;
+        let lines = rows
+            .into_iter()
+            .take_while(|rows| {
+                used += rows;
+                used <= self.page_size
+            })
+            .count();
+        lines.max(1)
+    }
+
+    fn toggle_hunk(&mut self) {
+        if let Some(selected) = self.hunk_selected.get_mut(self.hunk_cursor) {
+            *selected = !*selected;
+        }
+    }
+
+    /// Asks before [`App::apply_hunks`], naming the file to be rewritten.
+    fn confirm_apply_hunks(&mut self) {
+        let file = match &self.cur_file_path {
+            Some(file) if self.hunk_selected.contains(&true) => file,
+            _ => return,
+        };
+        let rel = match self.engine.relative(file) {
+            Some(rel) if file.state == crate::status::StatusItemType::Modified => rel,
+            _ => return,
+        };
+        let target = match self.apply_to {
+            ApplyDirection::NewToOld => &self.engine.old,
+            ApplyDirection::OldToNew => &self.engine.new,
+        };
+        let count = self.hunk_selected.iter().filter(|s| **s).count();
+        let lines = vec![
+            format!("write {} selected hunks into", count),
+            format!("  {}", target.root().join(rel).display()),
+            format!("backup under {}", self.backup_dir.display()),
+        ];
+        self.modal = Some((Modal::confirm("Apply hunks", lines), Pending::ApplyHunks));
+    }
+
+    /// Writes the selected hunks of the displayed file into the other tree.
+    fn apply_hunks(&mut self) {
+        let file = match &self.cur_file_path {
+            Some(file) if self.hunk_selected.contains(&true) => file,
+            _ => return,
+        };
+        let rel = match self.engine.relative(file) {
+            Some(rel) if file.state == crate::status::StatusItemType::Modified => rel,
+            _ => return,
+        };
+        let to_old = self.apply_to == ApplyDirection::NewToOld;
+        let target = if to_old {
+            &self.engine.old
+        } else {
+            &self.engine.new
+        };
+        let dst = match target.local_path(rel) {
+            Some(dst) => dst,
+            None => {
+                log::error!("{} cannot be written to", target.root().display());
+                return;
+            }
+        };
+        let res = self.engine.read_sides(file).and_then(|(old, new)| {
+            let contents = patch::apply(&old, &new, &self.hunks, &self.hunk_selected, to_old);
+            self.applied += 1;
+            let backup = self.backup_dir.join(self.applied.to_string()).join(rel);
+            ops::write(&dst, &contents, &backup)
+        });
+        match res {
+            Ok(backup) => {
+                log::info!("applied hunks (backup: {})", backup.display());
+                self.journal.push(Record {
+                    kind: Kind::ApplyHunks,
+                    time: unix_now(),
+                    target: dst,
+                    backup: Some(backup),
+                });
+                self.reload();
+            }
+            Err(e) => log::error!("apply hunks failed: {}", e),
+        }
     }
 
+    /// First entry of the list or top of the diff, whichever has the focus.
     fn home(&mut self) {
-        self.cur_file_path = Some(self.items.cur().clone());
-        self.is_home = true;
+        match self.tab {
+            WindowType::Left => {
+                self.items.select_first();
+                self.enter();
+            }
+            WindowType::Right => self.scroll_to(0),
+        }
     }
 
-    fn page_up(&mut self) {
+    /// Last entry of the list or bottom of the diff.
+    fn end(&mut self) {
         match self.tab {
             WindowType::Left => {
-                self.items.previous(self.page_size as usize);
+                self.items.select_last();
                 self.enter();
             }
-            WindowType::Right => {
-                let mut page_size = self.page_size;
-                let content_length = self.len_contents as u16;
-                if page_size > content_length {
-                    page_size = content_length;
-                }
-                if self.scroll < page_size {
-                    self.scroll = 0
-                } else {
-                    self.scroll -= page_size
-                }
+            WindowType::Right => self.scroll_to(usize::MAX),
+        }
+    }
+
+    /// Shows or hides the authors' letter in the diff window.
+    fn credits(&mut self) {
+        if self.show_credits {
+            self.show_credits = false;
+        } else if let Some(cur) = self.items.cur() {
+            self.cur_file_path = Some(cur.clone());
+            self.show_credits = true;
+        }
+    }
+
+    fn copy_path(&mut self) {
+        if let Some(cur) = self.items.cur() {
+            let path = cur.entry.path().to_string_lossy().into_owned();
+            self.copied("path", copy_to_clipboard(path));
+        }
+    }
+
+    /// Asks for the selected pair to be opened in `--difftool`.
+    fn open_difftool(&mut self) {
+        let template = match &self.difftool {
+            Some(template) => template.clone(),
+            None => {
+                self.command_message = Some("no diff tool, set one with --difftool".to_string());
+                return;
             }
+        };
+        let rel = match self.items.cur().and_then(|cur| self.engine.relative(cur)) {
+            Some(rel) => rel.to_path_buf(),
+            None => return,
+        };
+        match (
+            self.engine.old.local_path(&rel),
+            self.engine.new.local_path(&rel),
+        ) {
+            (Some(old), Some(new)) if old.is_file() && new.is_file() => {
+                let name = template.split_whitespace().next().unwrap_or_default();
+                self.launch = Some(Launch {
+                    name: name.to_string(),
+                    command: external::command(&template, &old, &new),
+                });
+            }
+            _ => {
+                self.command_message = Some(format!(
+                    "{} is not a file on the local disk on both sides",
+                    text::slash_path(&rel)
+                ))
+            }
+        }
+    }
+
+    /// Asks for the selected file to be opened in `$VISUAL` or `$EDITOR`, the
+    /// new copy unless it was deleted.
+    fn open_editor(&mut self) {
+        let cur = match self.items.cur() {
+            Some(cur) if !cur.entry.is_dir() => cur,
+            _ => return,
+        };
+        let rel = self.engine.rel_path(cur);
+        let path = match cur.state {
+            crate::status::StatusItemType::Deleted => self.engine.old.local_path(&rel),
+            _ => self.engine.new.local_path(&rel),
+        };
+        let path = match path {
+            Some(path) => path,
+            None => {
+                self.command_message = Some(format!(
+                    "{} is not on the local disk",
+                    text::slash_path(&rel)
+                ));
+                return;
+            }
+        };
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+        self.launch = Some(Launch {
+            command: external::on_file(&editor, &path),
+            name: editor,
+        });
+    }
+
+    /// The program the last key asked for, to be run with [`crate::launch::run`]
+    /// while the TUI is suspended.
+    pub fn take_launch(&mut self) -> Option<Launch> {
+        self.launch.take()
+    }
+
+    /// Reports how the program `name` exited and reads the selected file
+    /// again, which it may have changed.
+    pub fn launched(&mut self, name: &str, status: io::Result<ExitStatus>) {
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => self.command_message = Some(format!("{} failed ({})", name, status)),
+            Err(e) => self.command_message = Some(format!("cannot start {}: {}", name, e)),
+        }
+        self.view = None;
+    }
+
+    fn copy_diff(&mut self) {
+        let cur = match self.items.cur() {
+            Some(cur) => cur,
+            None => return,
+        };
+        match self.engine.unified_diff(cur) {
+            Ok(diff) => self.copied("diff", copy_to_clipboard(diff)),
+            Err(e) => log::error!("build diff failed: {}", e),
+        }
+    }
+
+    fn toggle_bookmark(&mut self) {
+        let rel = match self.items.cur() {
+            Some(cur) => self.engine.rel_path(cur),
+            None => return,
+        };
+        let shown = text::slash_path(&rel);
+        self.notice = Some(if self.session.bookmarks.toggle(rel) {
+            format!("bookmarked {}", shown)
+        } else {
+            format!("removed the bookmark on {}", shown)
+        });
+        self.save_session();
+    }
+
+    /// Moves the selected entry on to its next review state. While reviewed
+    /// entries are hidden the one below it is selected next.
+    fn cycle_review(&mut self) {
+        let rel = match self.items.cur() {
+            Some(cur) => self.engine.rel_path(cur),
+            None => return,
+        };
+        let review = Review::next(self.session.review(&rel));
+        self.session.set_review(rel.clone(), review);
+        self.save_session();
+        self.notice = Some(format!(
+            "{} {}",
+            text::slash_path(&rel),
+            review.map_or("not reviewed", Review::name)
+        ));
+        if self.hide_reviewed {
+            self.refilter();
+        }
+    }
+
+    /// Reviewed entries out of all differences, for the list title.
+    fn review_progress(&self) -> (usize, usize) {
+        let results = &self.comparison.results;
+        let reviewed = results
+            .iter()
+            .filter(|i| self.session.review(&self.engine.rel_path(i)).is_some())
+            .count();
+        (reviewed, results.len())
+    }
+
+    /// Prompts for the note of the selected entry, holding the one it has.
+    fn edit_note(&mut self) {
+        let rel = match self.items.cur() {
+            Some(cur) => self.engine.rel_path(cur),
+            None => return,
+        };
+        let title = format!("Note on {} (empty removes it)", text::slash_path(&rel));
+        let note = self.session.note(&rel).unwrap_or("").to_string();
+        self.modal = Some((Modal::input_with(title, note), Pending::Note(rel)));
+    }
+
+    /// Writes bookmarks and notes to the `--session` file, if there is one.
+    fn save_session(&mut self) {
+        if let Err(e) = self.session.save() {
+            log::error!("save session failed: {}", e);
+            self.command_message = Some(format!("cannot save the session: {}", e));
+        }
+    }
+
+    /// Selects the next bookmarked entry in the list after the selected one,
+    /// going round to the top.
+    fn next_bookmark(&mut self) {
+        let len = self.items.items.len();
+        let start = self.items.state.selected().map_or(0, |i| i + 1);
+        let found = (0..len).map(|i| (start + i) % len).find(|&i| {
+            self.session
+                .bookmarks
+                .contains(&self.engine.rel_path(&self.items.items[i]))
+        });
+        match found {
+            Some(i) => {
+                self.items.state.select(Some(i));
+                self.tab = WindowType::Left;
+                self.enter();
+            }
+            None if self.session.bookmarks.is_empty() => {
+                self.command_message = Some("no bookmarks, m adds one".to_string())
+            }
+            None => self.command_message = Some("no bookmarks in the list".to_string()),
+        }
+    }
+
+    /// Reports how copying `what` went in the status bar.
+    fn copied(&mut self, what: &str, res: Result<(), String>) {
+        match res {
+            Ok(()) => self.notice = Some(format!("copied {} to the clipboard", what)),
+            Err(e) => self.command_message = Some(format!("copy failed: {}", e)),
+        }
+    }
+
+    /// Asks before [`App::apply`], previewing what is copied or deleted.
+    fn confirm_apply(&mut self) {
+        let item = match self.items.cur() {
+            Some(item) => item,
+            None => return,
+        };
+        if is_modified_dir(item) {
+            self.command_message =
+                Some("a modified folder is applied entry by entry, expand it".to_string());
+            return;
+        }
+        let rel = match self.engine.relative(item) {
+            Some(rel) => rel.to_path_buf(),
+            None => return,
+        };
+        let (src, dst) = match self.apply_to {
+            ApplyDirection::NewToOld => (&self.engine.new, &self.engine.old),
+            ApplyDirection::OldToNew => (&self.engine.old, &self.engine.new),
+        };
+        let target = dst.root().join(&rel);
+        let mut lines = if src.metadata(&rel).is_ok() {
+            vec![
+                format!("copy {}", src.root().join(&rel).display()),
+                format!("  to {}", target.display()),
+            ]
+        } else if self.permanent {
+            vec![format!("delete {}", target.display())]
+        } else {
+            vec![format!("move {} to the trash", target.display())]
+        };
+        lines.push(format!("backup under {}", self.backup_dir.display()));
+        self.modal = Some((Modal::confirm("Apply change", lines), Pending::Apply));
+    }
+
+    /// Applies the selected entry's change to the other tree and rescans.
+    fn apply(&mut self) {
+        let item = match self.items.cur() {
+            Some(item) if !is_modified_dir(item) => item,
+            _ => return,
+        };
+        let rel = match self.engine.relative(item) {
+            Some(rel) => rel.to_path_buf(),
+            None => return,
+        };
+        let (src, dst) = match self.apply_to {
+            ApplyDirection::NewToOld => (&self.engine.new, &self.engine.old),
+            ApplyDirection::OldToNew => (&self.engine.old, &self.engine.new),
+        };
+        let (src_path, dst_path) = match (src.local_path(&rel), dst.local_path(&rel)) {
+            (Some(src_path), Some(dst_path)) => (src_path, dst_path),
+            _ => {
+                log::error!("only local folders can be applied to each other");
+                return;
+            }
+        };
+        // a missing source means the entry was removed on that side
+        let src_path = src.metadata(&rel).ok().map(|_| src_path.as_path());
+        self.applied += 1;
+        let backup = self.backup_dir.join(self.applied.to_string()).join(&rel);
+        match ops::apply(src_path, &dst_path, Some(&backup), self.permanent) {
+            Ok(saved) => {
+                log::info!("applied {} (backup: {:?})", dst_path.display(), saved);
+                self.journal.push(Record {
+                    kind: Kind::Apply,
+                    time: unix_now(),
+                    target: dst_path,
+                    backup: saved,
+                });
+                self.reload();
+            }
+            Err(e) => log::error!("apply {} failed: {}", dst_path.display(), e),
+        }
+    }
+
+    /// Asks before [`App::undo`], naming what is put back.
+    fn confirm_undo(&mut self) {
+        let record = match self.journal.next_undo() {
+            Some(record) => record,
+            None => {
+                self.command_message = Some("nothing to undo".to_string());
+                return;
+            }
+        };
+        let lines = match &record.backup {
+            Some(backup) => vec![
+                format!("restore {}", record.target.display()),
+                format!("   from {}", backup.display()),
+            ],
+            None => vec![format!(
+                "remove {}, it did not exist before",
+                record.target.display()
+            )],
+        };
+        let title = format!("Undo {}", record.kind.name());
+        self.modal = Some((Modal::confirm(title, lines), Pending::Undo));
+    }
+
+    /// Reverts the last `A` or `a` from its backup and rescans.
+    fn undo(&mut self) {
+        match self.journal.undo(unix_now()) {
+            Some(Ok(record)) => {
+                log::info!(
+                    "undid {} of {}",
+                    record.kind.name(),
+                    record.target.display()
+                );
+                self.reload();
+            }
+            Some(Err(e)) => log::error!("undo failed: {}", e),
+            None => log::info!("nothing to undo"),
+        }
+    }
+
+    fn page_up(&mut self) {
+        match self.tab {
+            WindowType::Left => {
+                self.items.previous(self.page_size);
+                self.enter();
+            }
+            WindowType::Right => self.scroll_to(self.scroll.saturating_sub(self.page_lines(false))),
         }
     }
 
     fn page_down(&mut self) {
         match self.tab {
             WindowType::Left => {
-                self.items.next(self.page_size as usize);
+                self.items.next(self.page_size);
                 self.enter();
             }
-            WindowType::Right => {
-                let mut page_size = self.page_size;
-                let content_length = self.len_contents as u16;
-                if page_size > content_length {
-                    page_size = content_length;
+            WindowType::Right => self.scroll_to(self.scroll.saturating_add(self.page_lines(true))),
+        }
+    }
+
+    fn draw_gauge<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        let mut drawn = None;
+        let monochrome = self.monochrome;
+        self.compare(&mut move |p| {
+            // the scan reports once per file, only redraw when the bar or the
+            // time left moves
+            if drawn == Some(p) {
+                return !cancel_requested();
+            }
+            drawn = Some(p);
+            let left = match p.eta {
+                Some(eta) => format!(", about {} left", format::eta(eta)),
+                None => String::new(),
+            };
+            let progress = Modal::Progress {
+                title: format!("Loading files{} (Esc shows what was found so far)", left),
+                percent: p.percent,
+            };
+            // loading files
+            let _ = terminal.draw(|f| {
+                progress.draw(f);
+                if monochrome {
+                    f.render_widget(Monochrome, f.size());
                 }
-                if self.scroll + page_size >= content_length {
-                    self.scroll = content_length
-                } else {
-                    self.scroll += page_size
+            });
+            !cancel_requested()
+        })
+    }
+
+    /// Detects the types of what was listed since the last draw. The
+    /// progress bar only comes up when that takes a while, e.g. for files on
+    /// a remote host, and Esc leaves the rest without a type.
+    fn detect_types_with_gauge<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+    ) -> io::Result<()> {
+        if self.untyped.is_empty() {
+            return Ok(());
+        }
+        let started = Instant::now();
+        let mut drawn = None;
+        let monochrome = self.monochrome;
+        let mut error = None;
+        self.detect_types(&mut |p| {
+            if started.elapsed() < GAUGE_DELAY || drawn == Some(p) {
+                return !cancel_requested();
+            }
+            drawn = Some(p);
+            let progress = Modal::Progress {
+                title: "Detecting file types (Esc skips the rest)".to_string(),
+                percent: p.percent,
+            };
+            if let Err(e) = terminal.draw(|f| {
+                progress.draw(f);
+                if monochrome {
+                    f.render_widget(Monochrome, f.size());
                 }
+            }) {
+                error = Some(e);
             }
+            !cancel_requested()
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
     }
 
-    fn draw_gauge<B: Backend>(&mut self, terminal: &mut Terminal<B>) {
-        self.diff_list_dir(&mut move |p| {
-            let _ = terminal.draw(|f| {
-                let chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .margin(1)
-                    .constraints(
-                        [
-                            Constraint::Percentage(40),
-                            Constraint::Length(5),
-                            Constraint::Percentage(40),
-                        ]
-                        .as_ref(),
-                    )
-                    .split(f.size());
-                let gauge = Gauge::default()
-                    .block(
-                        Block::default()
-                            .title("Loading files")
-                            .borders(Borders::ALL),
-                    )
-                    .gauge_style(Style::default().fg(Color::White))
-                    .percent(p);
-                f.render_widget(gauge, chunks[1]);
-            }); // loading files
-        });
+    /// Detects the types of the files in `untyped`, then filters the list again
+    /// since `:type` goes by them.
+    fn detect_types(&mut self, progress: &mut impl FnMut(Progress) -> bool) {
+        let items = std::mem::take(&mut self.untyped);
+        if !self
+            .engine
+            .detect_types(&items, &mut self.comparison.types, progress)
+        {
+            log::warn!("type detection skipped, some files have no type");
+        }
+        self.refilter();
+    }
+
+    /// The view toggles as they are now, saved on exit for the next run.
+    pub fn layout(&self) -> config::Layout {
+        config::Layout {
+            split: Some(self.split),
+            columns: Some(self.is_columns),
+            wrap: Some(self.is_wrapped),
+            whitespace: Some(self.render.show_whitespace),
+            log: Some(self.show_log),
+            path_style: self.saved_path_style,
+        }
+    }
+
+    /// Drops the current results so the next draw rescans both trees.
+    fn reload(&mut self) {
+        self.cur_file_path = None;
+        self.view = None;
+        self.is_loaded = false;
+    }
+
+    /// Where custom views of files in the diff window are registered, see
+    /// [`crate::renderer`].
+    pub fn renderers_mut(&mut self) -> &mut crate::renderer::Renderers {
+        self.view = None;
+        self.engine.renderers_mut()
+    }
+
+    /// Runs the comparison without drawing the progress bar, for driving the
+    /// app without a terminal.
+    pub fn load(&mut self) -> io::Result<()> {
+        self.compare(&mut |_| true)?;
+        self.detect_types(&mut |_| true);
+        self.is_loaded = true;
+        Ok(())
+    }
+
+    /// Compares both trees again and rebuilds the list from what was found.
+    fn compare(&mut self, progress: &mut impl FnMut(Progress) -> bool) -> io::Result<()> {
+        self.comparison = self.engine.compare(progress)?;
+        self.untyped = self.comparison.results.clone();
+        self.relist_expanded();
+        self.refilter();
+        if let Some(limit) = self.comparison.truncated {
+            let lines = vec![
+                format!(
+                    "A tree has more than {} entries, the walk stopped there.",
+                    limit
+                ),
+                "The list holds what was found on both sides so far.".to_string(),
+            ];
+            let choices = vec![
+                ('c', "continue without a cap".to_string()),
+                ('p', "show partial results".to_string()),
+                ('f', "refine filters".to_string()),
+            ];
+            let modal = Modal::choice("Too many files", lines, choices);
+            self.modal = Some((modal, Pending::Limit));
+        }
+        Ok(())
     }
 
     pub fn draw_terminal<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        self.load_with_gauge(terminal)?;
+        terminal.draw(|f| self.draw(f))?;
+        return Ok(());
+    }
+
+    /// Compares both trees behind the progress bar unless that was done, and
+    /// detects the types of what was listed since.
+    pub fn load_with_gauge<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
         if !self.is_loaded {
-            self.draw_gauge(terminal);
+            self.draw_gauge(terminal)?;
             self.is_loaded = true;
         }
-        terminal.draw(|f| self.draw(f))?;
-        return Ok(());
+        self.detect_types_with_gauge(terminal)
     }
 
     pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
+        self.draw_in(f, f.size());
+    }
+
+    /// Draws the app into `area` of the frame, the rest of it being left to
+    /// the caller, e.g. for the tab bar.
+    pub fn draw_in<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        self.draw_main(f, area);
+        if let Some((modal, _)) = &self.modal {
+            modal.draw(f);
+        }
+        if self.monochrome {
+            f.render_widget(Monochrome, area);
+        }
+    }
+
+    /// The labels of the old and the new tree.
+    pub fn roots(&self) -> (&str, &str) {
+        (&self.old_dir, &self.new_dir)
+    }
+
+    /// How many differences were found, `None` until both trees were compared.
+    pub fn differences(&self) -> Option<usize> {
+        self.is_loaded.then_some(self.comparison.results.len())
+    }
+
+    /// Marks the app as one of several pairs open in tabs.
+    pub(crate) fn set_tabbed(&mut self) {
+        self.is_tabbed = true;
+    }
+
+    fn draw_main<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        // set again when the diff is drawn with one
+        self.minimap_area = None;
+        let area = self.draw_log(f, area);
+        let area = self.draw_status_bar(f, area);
+        if !self.comparison.has_differences() {
+            return self.draw_no_differences(f, area);
+        }
+        if self.show_dashboard {
+            return self.draw_dashboard(f, area);
+        }
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
             .margin(1)
             .constraints(
-                match self.tab {
-                    WindowType::Left => [Constraint::Percentage(70), Constraint::Percentage(30)],
-                    WindowType::Right => [Constraint::Percentage(30), Constraint::Percentage(70)],
+                match (self.is_zoomed, &self.tab) {
+                    (true, _) => [Constraint::Percentage(0), Constraint::Percentage(100)],
+                    (false, WindowType::Left) => [
+                        Constraint::Percentage(self.split),
+                        Constraint::Percentage(100 - self.split),
+                    ],
+                    (false, WindowType::Right) => [
+                        Constraint::Percentage(100 - self.split),
+                        Constraint::Percentage(self.split),
+                    ],
                 }
                 .as_ref(),
             )
-            .split(f.size());
+            .split(area);
 
-        self.page_size = chunks[0].height / 2;
+        self.page_size = (chunks[0].height / 2) as usize;
 
-        let items: Vec<ListItem> = self
+        let labels: Vec<String> = self
             .items
             .items
             .iter()
             .map(|i| {
-                let path = match i.entry.path().to_str() {
-                    Some(p) => {
-                        let cur_path = p.replace(&self.new_dir, ".");
-                        if i.entry.path().is_dir() {
-                            format!("d {}", cur_path)
-                        } else {
-                            format!("f {}", cur_path)
-                        }
-                    }
-                    None => "".to_owned(),
+                let cur_path = self.path_style.label(i.entry.path(), self.root_of(i));
+                let prefix = self.icons.prefix(i.state, i.entry.path(), i.entry.is_dir());
+                let mut path = if let Some(special) = i.entry.special {
+                    format!("{} {} [{}]", prefix, cur_path, special.name())
+                } else if i.entry.is_dir() || i.state == crate::status::StatusItemType::Normal {
+                    format!("{} {}", prefix, cur_path)
+                } else {
+                    format!(
+                        "{} {} ({})",
+                        prefix,
+                        cur_path,
+                        format::size_delta(i.size_delta)
+                    )
                 };
-                let lines = vec![Spans::from(path)];
-                ListItem::new(lines).style(match i.state {
-                    crate::status::StatusItemType::Deleted => Style::default().fg(Color::Red),
-                    crate::status::StatusItemType::Modified => {
-                        Style::default().fg(Color::LightYellow)
+                match (i.state, &i.moved) {
+                    (crate::status::StatusItemType::Deleted, Some(to)) => {
+                        path += &format!(" moved to {}", text::slash_path(to))
                     }
-                    crate::status::StatusItemType::New => Style::default().fg(Color::Green),
-                    crate::status::StatusItemType::Normal => Style::default(),
+                    (_, Some(from)) => path += &format!(" moved from {}", text::slash_path(from)),
+                    _ => {}
+                }
+                let rel = self.engine.rel_path(i);
+                if self.session.bookmarks.contains(&rel) {
+                    path += " *";
+                }
+                if self.session.note(&rel).is_some() {
+                    path += " [note]";
+                }
+                if let Some(review) = self.session.review(&rel) {
+                    path += &format!(" [{}]", review.name());
+                }
+                path
+            })
+            .collect();
+        // the type column starts after the longest label
+        let width = labels.iter().map(|l| text::width(l)).max().unwrap_or(0);
+        let items: Vec<ListItem> = self
+            .items
+            .items
+            .iter()
+            .zip(labels)
+            .map(|(i, label)| {
+                let mime = self.comparison.types.get(i.entry.path()).copied();
+                let lines = vec![Spans::from(vec![
+                    Span::raw(text::pad(&label, width + 2)),
+                    Span::styled(mime.unwrap_or(""), Style::default().fg(Color::DarkGray)),
+                ])];
+                ListItem::new(lines).style(match state_color(i.state, i.entry.special.is_some()) {
+                    Some(color) => Style::default().fg(color),
+                    None => Style::default(),
                 })
             })
             .collect();
@@ -263,7 +1545,7 @@ impl App {
                         WindowType::Left => Style::default().fg(Color::Gray),
                         WindowType::Right => Style::default().fg(Color::Black),
                     })
-                    .title(format!("folder {}", self.new_dir)),
+                    .title(self.list_title()),
             )
             .highlight_style(
                 Style::default()
@@ -271,257 +1553,634 @@ impl App {
                     .add_modifier(Modifier::BOLD)
                     .add_modifier(Modifier::ITALIC),
             );
-        f.render_stateful_widget(items, chunks[0], &mut self.items.state);
+        if !self.is_zoomed {
+            f.render_stateful_widget(items, chunks[0], &mut self.items.state);
+        }
 
-        if let Some(file) = &self.cur_file_path {
-            let (contents, title) =
-                Self::get_diff_spans(file, &self.new_dir, &self.old_dir, self.is_home);
-            self.len_contents = contents.len() as usize;
-            let paragraph = Paragraph::new(contents)
-                .style(Style::default())
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .border_style(match self.tab {
-                            WindowType::Left => Style::default().fg(Color::Black),
-                            WindowType::Right => Style::default().fg(Color::Gray),
-                        })
-                        .title(title),
-                )
-                .wrap(tui::widgets::Wrap { trim: false })
-                .scroll((self.scroll, 0));
-            f.render_widget(paragraph, chunks[1]);
-        }
-    }
-
-    fn get_diff_spans<'a>(
-        file: &FolderStatefulList,
-        new_dir: &'a str,
-        old_dir: &'a str,
-        is_home: bool,
-    ) -> (Vec<Spans<'a>>, String) {
-        if is_home {
-            return (
-                vec![Spans::from(String::from_utf8(MSG.to_vec()).unwrap())],
-                "letter".to_string(),
-            );
+        if let Some(file) = self.cur_file_path.clone() {
+            let view = match self.view.take() {
+                Some(view)
+                    if view.path == file.entry.path() && view.show_credits == self.show_credits =>
+                {
+                    view
+                }
+                _ => {
+                    let (content, title) = view::file_view(&self.engine, &file, self.show_credits);
+                    CachedView {
+                        path: file.entry.path().to_path_buf(),
+                        show_credits: self.show_credits,
+                        content,
+                        title,
+                        sides: view::sides(&self.engine, &file),
+                    }
+                }
+            };
+            self.draw_view(f, file, &view, chunks[1]);
+            self.view = Some(view);
+        } else {
+            self.draw_quick_start(f, chunks[1]);
         }
-        if file.entry.path().is_dir() {
-            return (
-                vec![Spans::from("\n\nthis is directory")],
-                "error".to_string(),
+    }
+
+    /// Fills the diff window while no file is selected, e.g. when only
+    /// folders changed or a filter lets nothing through: what was compared,
+    /// what changed and the keys to get going.
+    fn draw_quick_start<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let s = &self.comparison.summary;
+        let key = |key: &str, what: &str| {
+            Spans::from(vec![
+                Span::styled(
+                    text::pad(key, 11),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(what.to_string()),
+            ])
+        };
+        let lines = vec![
+            Spans::from(format!("{} → {}", self.old_dir, self.new_dir)),
+            Spans::from(format!(
+                "{} new, {} modified, {} deleted, {} on disk",
+                s.new.files + s.new.dirs,
+                s.modified.files + s.modified.dirs,
+                s.deleted.files + s.deleted.dirs,
+                format::size_delta(self.comparison.size_delta)
+            )),
+            Spans::default(),
+            key("Up/Down", "select, show its diff"),
+            key("Left/Right", "list or diff window"),
+            if self.is_tabbed {
+                key("Tab", "next pair of folders")
+            } else {
+                key("Tab", "dashboard")
+            },
+            key("/", "go to a file"),
+            key(":filter", "list one kind"),
+            key("n/p", "next/previous hunk"),
+            key("A", "apply to old folder"),
+            key("q", "quit"),
+        ];
+        let paragraph = Paragraph::new(lines)
+            .wrap(tui::widgets::Wrap { trim: false })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Black))
+                    .title("Quick start"),
             );
+        f.render_widget(paragraph, area);
+    }
+
+    /// Draws the diff window of `file` from its cached `view`.
+    fn draw_view<B: Backend>(
+        &mut self,
+        f: &mut Frame<B>,
+        file: FolderStatefulList,
+        view: &CachedView,
+        area: Rect,
+    ) {
+        let (content, title) = (&view.content, view.title.clone());
+        if let (true, Content::Diff(diff)) = (self.is_columns && !self.show_credits, content) {
+            return self.draw_columns(f, file, diff, area);
         }
-        let cur_file_path = match file.entry.path().to_str() {
-            Some(p) => p,
-            None => "",
-        };
-        if cur_file_path == "" {
-            return (
-                vec![Spans::from("please press 'enter', select file")],
-                "error".to_string(),
+        self.len_contents = content.len();
+        let mut diff_area = area;
+        self.minimap_area = None;
+        if !self.show_credits && !self.hunks.is_empty() && diff_area.width > MINIMAP_WIDTH {
+            diff_area.width -= MINIMAP_WIDTH;
+            // rows line up with the inside of the bordered diff window
+            let area = Rect::new(
+                diff_area.right(),
+                diff_area.y + 1,
+                MINIMAP_WIDTH,
+                diff_area.height.saturating_sub(2),
             );
+            f.render_widget(self.minimap(area.height), area);
+            self.minimap_area = Some(area);
         }
-        let mut buf_new = String::new();
-        let err = File::open(cur_file_path)
-            .expect(&format!("file not found: {}", cur_file_path))
-            .read_to_string(&mut buf_new);
-        if err.is_err() {
-            return (
-                vec![Spans::from(format!(
-                    "open file:{}, error: {}",
-                    cur_file_path,
-                    err.err().unwrap()
-                ))],
-                "error".to_string(),
+        // only the lines that fit are turned into spans; each takes at
+        // least one row, more when wrapped
+        self.view_height = diff_area.height.saturating_sub(2) as usize;
+        self.view_width = diff_area.width.saturating_sub(2) as usize;
+        // a resize or a shorter file may leave the old position past the end
+        self.scroll = self.scroll.min(self.max_scroll(Some(content)));
+        let (first, height) = (self.scroll, self.view_height);
+        let mut contents = content.spans(first..first + height, self.render);
+        if !self.show_credits {
+            dim_hunks(&mut contents, first, &self.ignored_hunks);
+        }
+        if !self.show_credits && !self.hunks.is_empty() {
+            mark_hunks(
+                &mut contents,
+                first,
+                &self.hunks,
+                &self.hunk_selected,
+                self.hunk_cursor,
             );
         }
+        // wrapped here rather than by the paragraph, so each line takes the
+        // rows `line_rows` counts for scrolling
+        let contents: Vec<Spans> = if self.wraps(content) {
+            contents
+                .into_iter()
+                .flat_map(|line| wrap_line(line, self.view_width))
+                .collect()
+        } else {
+            contents
+                .into_iter()
+                .map(|line| clip_line(line, self.h_scroll as usize))
+                .collect()
+        };
+        let paragraph = Paragraph::new(contents).style(Style::default()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(match self.tab {
+                    WindowType::Left => Style::default().fg(Color::Black),
+                    WindowType::Right => Style::default().fg(Color::Gray),
+                })
+                .title(title),
+        );
+        f.render_widget(paragraph, diff_area);
+    }
 
-        if file.state == crate::status::StatusItemType::Deleted
-            || file.state == crate::status::StatusItemType::New
-        {
-            let mut title = format!("Deleted: {}", cur_file_path);
-            let mut style = Color::Red;
-            if file.state == crate::status::StatusItemType::New {
-                title = format!("New File: {}", cur_file_path);
-                style = Color::Green;
-            }
-            let buf = io::BufReader::new(buf_new.as_bytes());
-            let contents: Vec<Spans> = buf
-                .lines()
+    /// Draws the old and the new contents of a modified file in two columns,
+    /// lined up row by row so one scroll position fits both.
+    fn draw_columns<B: Backend>(
+        &mut self,
+        f: &mut Frame<B>,
+        file: FolderStatefulList,
+        diff: &Diff,
+        area: Rect,
+    ) {
+        self.minimap_area = None;
+        self.len_contents = diff.rows();
+        self.view_height = area.height.saturating_sub(2) as usize;
+        self.scroll_to(self.scroll);
+        let (first, height) = (self.scroll, self.view_height);
+        let columns = diff.columns(first..first + height, self.render);
+        let (old_path, new_path) = self.engine.side_paths(&file);
+        let titles = [old_path, new_path].map(|p| {
+            p.map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(area);
+        for ((contents, title), area) in columns.into_iter().zip(titles).zip(chunks) {
+            // wrapping would let a long line on one side push the rows apart
+            let contents: Vec<Spans> = contents
                 .into_iter()
-                .map(|i| Spans::from(Span::styled(i.unwrap(), Style::default().fg(style))))
+                .map(|line| clip_line(line, self.h_scroll as usize))
                 .collect();
-            return (contents, title);
-        }
-
-        let old_file_path = cur_file_path.replace(new_dir, old_dir);
-        let mut buf_old = String::new();
-        let err = File::open(&old_file_path)
-            .expect(&format!("file not found: {}", old_file_path))
-            .read_to_string(&mut buf_old);
-        if err.is_err() {
-            return (
-                vec![Spans::from(format!(
-                    "open file:{}, error: {}",
-                    old_file_path,
-                    err.err().unwrap()
-                ))],
-                "error".to_string(),
+            let paragraph = Paragraph::new(contents).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(match self.tab {
+                        WindowType::Left => Style::default().fg(Color::Black),
+                        WindowType::Right => Style::default().fg(Color::Gray),
+                    })
+                    .title(title),
             );
+            f.render_widget(paragraph, area);
         }
+    }
 
-        let diff = TextDiff::from_lines(&buf_old, &buf_new);
-        let contents: Vec<Spans> = diff
-            .iter_all_changes()
-            .into_iter()
-            .map(|i| {
-                let (sign, color) = match i.tag() {
-                    ChangeTag::Delete => ("-", Color::Red),
-                    ChangeTag::Insert => ("+", Color::Green),
-                    ChangeTag::Equal => (" ", Color::White),
+    /// One cell per row showing whether the lines it stands for hold deletions
+    /// (red), insertions (green) or both (yellow); the visible part is shaded.
+    fn minimap(&self, height: u16) -> Paragraph<'static> {
+        let len = self.len_contents.max(1);
+        let rows = height.max(1) as usize;
+        // (has deletions, has insertions) per row
+        let mut marks = vec![(false, false); rows];
+        for hunk in &self.hunks {
+            let deleted = hunk.display_start..hunk.display_start + hunk.old_range.len();
+            let inserted = deleted.end..deleted.end + hunk.new_range.len();
+            for line in deleted {
+                marks[(line * rows / len).min(rows - 1)].0 = true;
+            }
+            for line in inserted {
+                marks[(line * rows / len).min(rows - 1)].1 = true;
+            }
+        }
+        let scroll = self.scroll;
+        let view = scroll * rows / len..=((scroll + rows) * rows / len).min(rows - 1);
+        let lines: Vec<Spans> = marks
+            .iter()
+            .enumerate()
+            .map(|(row, mark)| {
+                let mut style = Style::default();
+                if view.contains(&row) {
+                    style = style.bg(Color::DarkGray);
+                }
+                let (color, symbol) = match mark {
+                    (true, true) => (Color::Yellow, "~"),
+                    (true, false) => (Color::Red, "-"),
+                    (false, true) => (Color::Green, "+"),
+                    (false, false) => return Spans::from(Span::styled(" ", style)),
                 };
-                Spans::from(Span::styled(
-                    format!("{} {}", sign, i),
-                    Style::default().fg(color),
-                ))
+                // a block in one color says nothing without colors
+                let symbol = if self.monochrome { symbol } else { "█" };
+                Spans::from(Span::styled(symbol, style.fg(color)))
             })
             .collect();
-        let title = format!("Diff: {} and {}", cur_file_path, old_file_path);
-        (contents, title)
-    }
-
-    fn diff_list_dir(&mut self, progress: &mut impl FnMut(u16)) {
-        progress(10);
-        let old_dir = &self.old_dir;
-        let new_dir = &self.new_dir;
-        let old_files = list_dir(old_dir);
-        progress(20);
-        let new_files = list_dir(new_dir);
-        progress(30);
-        let mut res = Vec::new();
-
-        for (key, entry) in &old_files {
-            match new_files.get(key) {
-                None => {
-                    res.push(FolderStatefulList {
-                        entry: entry.clone(),
-                        state: crate::status::StatusItemType::Deleted,
-                    });
-                }
-                _ => {}
-            }
+        Paragraph::new(lines)
+    }
+
+    /// Called by the event loop when no key came for a while. Returns whether
+    /// something changed in the background that needs a redraw, so an idle
+    /// screen is left alone.
+    pub fn tick(&mut self) -> bool {
+        let written = crate::log::lines_written();
+        if self.show_log && written != self.log_seen {
+            self.log_seen = written;
+            return true;
+        }
+        false
+    }
+
+    /// Keeps paging and scrolling within a terminal that is now `height` rows
+    /// high, until the next draw works out the exact layout.
+    pub fn on_resize(&mut self, height: u16) {
+        // the panes sit above the status bar inside a one-row margin and have
+        // a border each
+        let rows = height.saturating_sub(3);
+        self.page_size = (rows / 2) as usize;
+        self.view_height = rows.saturating_sub(2) as usize;
+        self.scroll_to(self.scroll);
+    }
+
+    /// Whether the last draw showed a minimap to click. The mouse is only
+    /// captured then, so text can be selected in the terminal otherwise.
+    pub fn wants_mouse(&self) -> bool {
+        self.minimap_area.is_some()
+    }
+
+    /// Clicking the minimap scrolls the diff to the matching position.
+    pub fn mouse(&mut self, event: MouseEvent) {
+        let area = match (event.kind, self.minimap_area) {
+            (MouseEventKind::Down(MouseButton::Left), Some(area)) => area,
+            _ => return,
+        };
+        let inside = (area.left()..area.right()).contains(&event.column)
+            && (area.top()..area.bottom()).contains(&event.row);
+        if inside {
+            let row = (event.row - area.top()) as usize;
+            self.scroll_to(row * self.len_contents / area.height.max(1) as usize);
+        }
+    }
+
+    fn list_title(&self) -> String {
+        let mut title = format!("folder {}", self.new_dir);
+        if let (PathStyle::Name, Some(cur)) = (self.path_style, self.items.cur()) {
+            title += &format!(
+                " › {}",
+                text::breadcrumb(cur.entry.path(), self.root_of(cur))
+            );
         }
-        progress(40);
+        title += &format!(" ({})", format::size_delta(self.comparison.size_delta));
+        if self.session.has_reviews() || self.hide_reviewed {
+            let (reviewed, total) = self.review_progress();
+            title += &format!(
+                " reviewed {}/{} ({}%)",
+                reviewed,
+                total,
+                reviewed * 100 / total.max(1)
+            );
+        }
+        title
+    }
+
+    /// The root an entry lives under: deleted entries come from the old tree,
+    /// everything else from the new one.
+    fn root_of(&self, item: &FolderStatefulList) -> &str {
+        match item.state {
+            crate::status::StatusItemType::Deleted => &self.old_dir,
+            _ => &self.new_dir,
+        }
+    }
+
+    /// Draws the log viewer along the bottom of `area` when it is open and
+    /// returns the rest.
+    fn draw_log<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Rect {
+        if !self.show_log {
+            return area;
+        }
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Percentage(LOG_HEIGHT)].as_ref())
+            .split(area);
+        let lines = crate::log::recent_lines();
+        // keep the newest lines in view
+        let visible = chunks[1].height.saturating_sub(2) as usize;
+        let text: Vec<Spans> = lines[lines.len().saturating_sub(visible)..]
+            .iter()
+            .map(|line| {
+                let color = match line.split_whitespace().next() {
+                    Some("ERROR") => Color::Red,
+                    Some("WARN") => Color::Yellow,
+                    _ => Color::Gray,
+                };
+                Spans::from(Span::styled(line.clone(), Style::default().fg(color)))
+            })
+            .collect();
+        let paragraph = Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("log (L to close)"),
+        );
+        f.render_widget(paragraph, chunks[1]);
+        chunks[0]
+    }
 
-        for (key, entry) in &new_files {
-            match old_files.get(key) {
-                None => {
-                    res.push(FolderStatefulList {
-                        entry: entry.clone(),
-                        state: crate::status::StatusItemType::New,
-                    });
+    /// Totals per state, the size change and the largest files and deepest
+    /// directories among the changes.
+    fn draw_dashboard<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        // grows with the extension list, up to half the screen
+        let top_height = (self.comparison.summary.extensions.len() as u16 + 2)
+            .min(area.height / 2)
+            .max(8);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints([Constraint::Length(top_height), Constraint::Min(0)].as_ref())
+            .split(area);
+        let top = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunks[0]);
+        let s = &self.comparison.summary;
+        let count = |label: &str, c: crate::summary::Count, color| {
+            Spans::from(vec![
+                Span::styled(format!("{:<10}", label), Style::default().fg(color)),
+                Span::raw(format!("{} files, {} folders", c.files, c.dirs)),
+            ])
+        };
+        let totals = vec![
+            count("new", s.new, Color::Green),
+            count("modified", s.modified, Color::LightYellow),
+            count("deleted", s.deleted, Color::Red),
+            Spans::default(),
+            Spans::from(format!(
+                "disk usage {} (+{} added, -{} removed)",
+                format::size_delta(self.comparison.size_delta),
+                format::size(s.added),
+                format::size(s.removed)
+            )),
+        ];
+        let key = if self.is_tabbed { ":dashboard" } else { "Tab" };
+        let title = format!("{} → {} ({}: file list)", self.old_dir, self.new_dir, key);
+        f.render_widget(
+            Paragraph::new(totals).block(Block::default().borders(Borders::ALL).title(title)),
+            top[0],
+        );
+        let extensions: Vec<ListItem> = s
+            .extensions
+            .iter()
+            .map(|(ext, count)| {
+                let parts: Vec<String> = [
+                    (count.modified, "modified"),
+                    (count.new, "new"),
+                    (count.deleted, "deleted"),
+                ]
+                .iter()
+                .filter(|(n, _)| *n > 0)
+                .map(|(n, label)| format!("{} {}", n, label))
+                .collect();
+                let ext = if ext.is_empty() { "(none)" } else { ext };
+                ListItem::new(format!("{} {}", text::pad(ext, 10), parts.join(", ")))
+            })
+            .collect();
+        f.render_widget(
+            List::new(extensions)
+                .block(Block::default().borders(Borders::ALL).title("By extension")),
+            top[1],
+        );
+
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunks[1]);
+        let largest: Vec<ListItem> = s
+            .largest
+            .iter()
+            .map(|(rel, size, delta)| {
+                ListItem::new(format!(
+                    "{:>10}  {:>10}  {}",
+                    format::size(*size),
+                    format::size_delta(*delta),
+                    text::slash_path(rel)
+                ))
+            })
+            .collect();
+        let deepest: Vec<ListItem> = s
+            .deepest
+            .iter()
+            .map(|(rel, changes)| {
+                let dir = if rel.as_os_str().is_empty() {
+                    ".".to_string()
+                } else {
+                    text::slash_path(rel)
+                };
+                ListItem::new(format!("{:>5}  {}", changes, dir))
+            })
+            .collect();
+        let block = |title| Block::default().borders(Borders::ALL).title(title);
+        f.render_widget(
+            List::new(largest).block(block("Largest changed files")),
+            columns[0],
+        );
+        f.render_widget(
+            List::new(deepest).block(block("Deepest changed folders (changes inside)")),
+            columns[1],
+        );
+    }
+
+    /// Draws the status bar in the bottom row of `area` and returns the rest.
+    /// The left holds the `:` command line while it is typed, else the message
+    /// of the last command or key, else the selected entry; the right holds
+    /// the position in the list and the modes that change what is shown.
+    fn draw_status_bar<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Rect {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .split(area);
+        let bar = chunks[1];
+        if let Some(line) = &self.command {
+            f.render_widget(Paragraph::new(format!(":{}", line)), bar);
+            f.set_cursor(bar.x + 1 + text::width(line) as u16, bar.y);
+            return chunks[0];
+        }
+        let right = self.status_modes();
+        let left = match (&self.command_message, &self.notice, self.items.cur()) {
+            (Some(msg), _, _) => Span::styled(msg.clone(), Style::default().fg(Color::Red)),
+            (None, Some(notice), _) => {
+                Span::styled(notice.clone(), Style::default().fg(Color::Green))
+            }
+            (None, None, Some(cur)) => match &self.view {
+                // the diff window has the focus: which two files it shows
+                Some(view)
+                    if matches!(self.tab, WindowType::Right) && view.path == cur.entry.path() =>
+                {
+                    Span::raw(view.sides.clone())
                 }
-                Some(_) => {
-                    if entry.path().is_file() {
-                        let new_file_path = entry.path().canonicalize().unwrap();
-                        let old_file_path =
-                            new_file_path.to_str().unwrap().replace(new_dir, old_dir);
-                        let err = File::open(&old_file_path);
-                        match err {
-                            Ok(_) => {
-                                let is_same =
-                                    diff(new_file_path.to_str().unwrap(), old_file_path.as_str());
-                                if !is_same {
-                                    res.push(FolderStatefulList {
-                                        entry: entry.clone(),
-                                        state: crate::status::StatusItemType::Modified,
-                                    });
-                                }
-                                // * filter Normal
-                                // else {
-                                //     res.push(FolderStatefulList {
-                                //         entry: entry.clone(),
-                                //         state: crate::status::StatusItemType::Normal,
-                                //     });
-                                // }
-                            }
-                            _ => {}
+                _ => {
+                    let rel = self.engine.rel_path(cur);
+                    match self.session.note(&rel) {
+                        Some(note) => {
+                            Span::raw(format!("{}  note: {}", text::slash_path(&rel), note))
                         }
+                        None => Span::raw(text::slash_path(&rel)),
                     }
                 }
-            }
+            },
+            (None, None, None) => Span::raw(""),
+        };
+        let width = text::width(&right) as u16;
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(0), Constraint::Length(width)].as_ref())
+            .split(bar);
+        f.render_widget(Paragraph::new(Spans::from(left)), columns[0]);
+        f.render_widget(
+            Paragraph::new(Span::styled(right, Style::default().fg(Color::DarkGray))),
+            columns[1],
+        );
+        chunks[0]
+    }
+
+    /// The right side of the status bar, e.g. `[modified] [hidden] item 3/12`.
+    fn status_modes(&self) -> String {
+        let mut modes = Vec::new();
+        if self.comparison.is_partial {
+            modes.push("[partial]".to_string());
         }
-        progress(80);
-        delta_folder_stateful_list(&mut res);
-        self.items = StatefulList::with_items(res);
-        progress(100);
+        if let Some(state) = self.state_filter {
+            modes.push(format!("[{:?}]", state).to_lowercase());
+        }
+        if let Some(kind) = self.type_filter {
+            modes.push(format!("[{}]", kind.name()));
+        }
+        if self.engine.show_hidden() {
+            modes.push("[hidden]".to_string());
+        }
+        if self.engine.ignore_lines().is_some() {
+            modes.push("[ignoring lines]".to_string());
+        }
+        if self.is_columns {
+            modes.push("[columns]".to_string());
+        }
+        if !self.is_wrapped {
+            modes.push("[no wrap]".to_string());
+        }
+        if self.is_zoomed {
+            modes.push("[zoom]".to_string());
+        }
+        if self.hide_reviewed {
+            modes.push("[unreviewed]".to_string());
+        }
+        if !self.session.bookmarks.is_empty() {
+            modes.push(format!("[{} bookmarked]", self.session.bookmarks.len()));
+        }
+        match (self.items.state.selected(), self.items.items.len()) {
+            (_, 0) => modes.push("no items".to_string()),
+            (Some(i), len) => modes.push(format!("item {}/{}", i + 1, len)),
+            (None, len) => modes.push(format!("{} items", len)),
+        }
+        modes.join(" ")
     }
-}
 
-fn list_dir(path: &str) -> HashMap<String, DirEntry> {
-    let mut files = HashMap::new();
-    for f in walkdir::WalkDir::new(path) {
-        let entry = f.unwrap();
-        let key = entry
-            .path()
-            .canonicalize()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .replace(path, &"".to_string());
-        files.insert(key, entry);
-    }
-    files
+    fn draw_no_differences<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints(
+                [
+                    Constraint::Percentage(40),
+                    Constraint::Length(5),
+                    Constraint::Percentage(40),
+                ]
+                .as_ref(),
+            )
+            .split(area);
+        let text = if self.comparison.is_partial {
+            vec![
+                Spans::from(""),
+                Spans::from(Span::styled(
+                    "Scan cancelled",
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                )),
+                Spans::from("no differences were found before it stopped"),
+            ]
+        } else {
+            vec![
+                Spans::from(""),
+                Spans::from(Span::styled(
+                    "No differences found 🎉",
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::BOLD),
+                )),
+                Spans::from(format!(
+                    "{} and {} are identical",
+                    self.old_dir, self.new_dir
+                )),
+            ]
+        };
+        let paragraph = Paragraph::new(text)
+            .alignment(tui::layout::Alignment::Center)
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(paragraph, chunks[1]);
+    }
 }
 
-fn delta_folder_stateful_list(files: &mut Vec<FolderStatefulList>) {
-    files.sort_by(|x, y| {
-        x.entry
-            .path()
-            .canonicalize()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .cmp(y.entry.path().canonicalize().unwrap().to_str().unwrap())
-    });
-    let mut i = 1;
-    while i < files.len() - 1 {
-        // same directory
-        if files[i - 1].entry.path().is_dir()
-            && (files[i - 1].state == crate::status::StatusItemType::Deleted
-                || files[i - 1].state == crate::status::StatusItemType::New)
-        {
-            if files[i]
-                .entry
-                .path()
-                .to_str()
-                .unwrap()
-                .starts_with(files[i - 1].entry.path().to_str().unwrap())
-            {
-                files.remove(i);
-                continue;
+/// Drains pending input, returning whether Esc or Ctrl+C was pressed, the
+/// latter also asking the app to quit.
+fn cancel_requested() -> bool {
+    while let Ok(true) = event::poll(Duration::ZERO) {
+        if let Ok(Event::Key(key)) = event::read() {
+            if interrupt::is_ctrl_c(&key) {
+                interrupt::request();
+            }
+            if key.code == KeyCode::Esc {
+                return true;
             }
         }
-        i += 1;
     }
+    interrupt::requested()
+}
+
+/// A folder shown as modified by `--dir-changes`. Applying it would replace
+/// the whole folder, dropping what only the other side has.
+fn is_modified_dir(item: &FolderStatefulList) -> bool {
+    item.entry.is_dir() && item.state == crate::status::StatusItemType::Modified
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn copy_to_clipboard(text: String) -> Result<(), String> {
+    let res = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text));
+    res.map_err(|e| {
+        log::error!("copy to clipboard failed: {}", e);
+        e.to_string()
+    })
 }
 
-const MSG: [u8; 318] = [
-    84, 104, 105, 115, 32, 112, 114, 111, 106, 101, 99, 116, 32, 119, 97, 115, 32, 105, 110, 115,
-    112, 105, 114, 101, 100, 32, 98, 121, 32, 109, 121, 32, 103, 105, 114, 108, 102, 114, 105, 101,
-    110, 100, 44, 32, 119, 104, 111, 32, 114, 101, 113, 117, 101, 115, 116, 101, 100, 32, 97, 32,
-    116, 111, 111, 108, 32, 102, 111, 114, 32, 99, 111, 109, 112, 97, 114, 105, 110, 103, 32, 100,
-    105, 114, 101, 99, 116, 111, 114, 105, 101, 115, 59, 32, 97, 108, 116, 104, 111, 117, 103, 104,
-    32, 116, 104, 111, 117, 103, 104, 32, 86, 83, 32, 67, 111, 100, 101, 32, 97, 108, 114, 101, 97,
-    100, 121, 32, 111, 102, 102, 101, 114, 115, 32, 115, 117, 99, 104, 32, 97, 32, 112, 108, 117,
-    103, 45, 105, 110, 44, 32, 73, 32, 115, 116, 105, 108, 108, 32, 119, 97, 110, 116, 32, 116,
-    111, 32, 99, 114, 101, 97, 116, 101, 32, 111, 110, 101, 32, 102, 111, 114, 32, 104, 101, 114,
-    32, 40, 109, 111, 115, 116, 108, 121, 32, 115, 105, 110, 99, 101, 32, 73, 32, 100, 111, 110,
-    39, 116, 32, 104, 97, 118, 101, 32, 97, 110, 121, 32, 109, 111, 110, 101, 121, 32, 116, 111,
-    32, 112, 117, 114, 99, 104, 97, 115, 101, 32, 111, 116, 104, 101, 114, 32, 116, 104, 105, 110,
-    103, 115, 41, 59, 10, 73, 32, 119, 105, 115, 104, 32, 102, 111, 114, 32, 101, 118, 101, 114,
-    121, 111, 110, 101, 39, 115, 32, 104, 97, 112, 112, 105, 110, 101, 115, 115, 44, 32, 104, 101,
-    97, 108, 116, 104, 44, 32, 97, 110, 100, 32, 105, 110, 99, 114, 101, 97, 115, 105, 110, 103,
-    32, 119, 101, 97, 108, 116, 104, 59, 10, 50, 48, 50, 51, 48, 50, 49, 52,
-];
+const DEFAULT_SPLIT: u16 = 70;
+const MIN_SPLIT: u16 = 20;
+const MAX_SPLIT: u16 = 90;
+const SPLIT_STEP: u16 = 5;
+const H_SCROLL_STEP: u16 = 8;
+// how long type detection runs before its progress bar comes up
+const GAUGE_DELAY: Duration = Duration::from_millis(200);
+const MINIMAP_WIDTH: u16 = 1;
+// percentage of the height given to the log viewer
+const LOG_HEIGHT: u16 = 30;
+// when neither $VISUAL nor $EDITOR is set
+const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
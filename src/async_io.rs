@@ -0,0 +1,195 @@
+//! Tokio based walk and reads, built with the `async` feature.
+//!
+//! On network filesystems (NFS, SMB) nearly all the time goes into waiting on
+//! one metadata call or read after another. Here many directories are listed
+//! and many file pairs compared at once, while at most [`CONCURRENCY`] of them
+//! are in flight so a large tree does not flood the server or queue up
+//! unbounded work.
+
+use crate::{
+    filter::WalkFilter,
+    status::{Entry, Special},
+    throttle,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::{fs, io::AsyncReadExt, runtime::Runtime, task::JoinSet};
+
+/// Directory listings or file comparisons running at the same time.
+pub const CONCURRENCY: usize = 32;
+
+/// Bytes read per step when comparing, like [`crate::compare`].
+const CHUNK: usize = 1 << 20;
+
+/// A runtime whose blocking pool, where tokio runs file system calls, is
+/// sized to the concurrency limit, with timers for `--io-limit`.
+fn runtime() -> io::Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .max_blocking_threads(CONCURRENCY)
+        .enable_time()
+        .build()
+}
+
+/// Same result as the walkdir based [`crate::source::list_dir`]: every entry
+/// below `root`, keyed by its relative path, `root` itself under the empty
+/// path. Symlinks are not followed.
+pub fn list_dir(root: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+    runtime()?.block_on(walk(root, filter))
+}
+
+async fn walk(root: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+    let mut files = HashMap::new();
+    files.insert(
+        PathBuf::new(),
+        entry(root.to_path_buf(), fs::metadata(root).await?),
+    );
+    // directories wait here until a slot frees up
+    let mut pending = VecDeque::from([root.to_path_buf()]);
+    let mut tasks = JoinSet::new();
+    loop {
+        while tasks.len() < CONCURRENCY {
+            match pending.pop_front() {
+                Some(dir) => tasks.spawn(read_dir(dir)),
+                None => break,
+            }
+        }
+        let (dir, children) = match tasks.join_next().await {
+            Some(res) => res?,
+            None => break,
+        };
+        let children = match children {
+            Ok(children) => children,
+            Err(e) if dir == root => return Err(e),
+            Err(e) => {
+                log::warn!("skip {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        for (path, meta) in children {
+            let rel = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            if filter.is_ignored(&rel, meta.is_dir()) {
+                continue;
+            }
+            if meta.is_dir() {
+                pending.push_back(path.clone());
+            }
+            files.insert(rel, entry(path, meta));
+            if filter.is_full(files.len()) {
+                // dropping the set cancels the listings still running
+                return Ok(files);
+            }
+        }
+    }
+    Ok(files)
+}
+
+type Listing = (PathBuf, io::Result<Vec<(PathBuf, std::fs::Metadata)>>);
+
+/// Lists `dir` with the metadata of each child, so a task does all the round
+/// trips for one directory.
+async fn read_dir(dir: PathBuf) -> Listing {
+    let children = async {
+        let mut children = Vec::new();
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(child) = entries.next_entry().await? {
+            match child.metadata().await {
+                Ok(meta) => children.push((child.path(), meta)),
+                Err(e) => log::warn!("skip {}: {}", child.path().display(), e),
+            }
+        }
+        Ok(children)
+    }
+    .await;
+    (dir, children)
+}
+
+fn entry(path: PathBuf, meta: std::fs::Metadata) -> Entry {
+    let size = if meta.is_file() { meta.len() } else { 0 };
+    let mut entry = Entry::new(path, meta.is_dir(), size);
+    entry.mtime = crate::status::mtime_nanos(&meta);
+    entry.special = Special::of(meta.file_type());
+    entry.inode = crate::status::inode(&meta);
+    entry
+}
+
+/// Compares many `(key, old, new)` file pairs at once. `done` gets each
+/// pair's key, whether the files are equal and how long finding out took, as
+/// pairs finish, and stops the work by returning `false`, in which case
+/// `false` is returned.
+pub fn compare_files<K>(
+    pairs: Vec<(K, PathBuf, PathBuf)>,
+    done: &mut impl FnMut(K, io::Result<bool>, Duration) -> bool,
+) -> io::Result<bool>
+where
+    K: Send + 'static,
+{
+    runtime()?.block_on(async {
+        let mut pairs = pairs.into_iter();
+        let mut tasks = JoinSet::new();
+        loop {
+            while tasks.len() < CONCURRENCY {
+                match pairs.next() {
+                    Some((key, old, new)) => tasks.spawn(async move {
+                        let started = Instant::now();
+                        let same = same_file(&old, &new).await;
+                        (key, same, started.elapsed())
+                    }),
+                    None => break,
+                }
+            }
+            let (key, same, took) = match tasks.join_next().await {
+                Some(res) => res?,
+                None => break,
+            };
+            if !done(key, same, took) {
+                // dropping the set cancels what is still running
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    })
+}
+
+/// Async twin of [`crate::compare::same_file`]. A new side that is not a
+/// regular file counts as the same, as in the synchronous scan.
+async fn same_file(old: &Path, new: &Path) -> io::Result<bool> {
+    let new_meta = fs::metadata(new).await?;
+    if !new_meta.is_file() {
+        return Ok(true);
+    }
+    if fs::metadata(old).await?.len() != new_meta.len() {
+        return Ok(false);
+    }
+    let (mut old, mut new) = (fs::File::open(old).await?, fs::File::open(new).await?);
+    let (mut a, mut b) = (vec![0; CHUNK], vec![0; CHUNK]);
+    loop {
+        let n = read_full(&mut old, &mut a).await?;
+        let m = read_full(&mut new, &mut b).await?;
+        let wait = throttle::reserve(n + m);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        if n != m || a[..n] != b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fills `buf` unless the file ends first, returning the bytes read.
+async fn read_full(file: &mut fs::File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
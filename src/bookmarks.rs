@@ -0,0 +1,67 @@
+//! Entries marked with `m` to come back to. They are kept by relative path,
+//! so they outlive filters and rescans, and can be written out with
+//! `:bookmarks <file>` and read back with `--bookmarks <file>` next time.
+
+use crate::text;
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Relative paths of the bookmarked entries, in path order.
+#[derive(Clone, Default, Debug)]
+pub struct Bookmarks {
+    paths: BTreeSet<PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Bookmarks {
+            paths: paths.into_iter().collect(),
+        }
+    }
+
+    pub fn insert(&mut self, rel: PathBuf) {
+        self.paths.insert(rel);
+    }
+
+    /// Bookmarks `rel`, or drops it when it already was. Returns whether it
+    /// is bookmarked now.
+    pub fn toggle(&mut self, rel: PathBuf) -> bool {
+        if self.paths.remove(&rel) {
+            return false;
+        }
+        self.paths.insert(rel);
+        true
+    }
+
+    pub fn contains(&self, rel: &Path) -> bool {
+        self.paths.contains(rel)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PathBuf> {
+        self.paths.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// One path per line, the format `--bookmarks` and `--paths-from` read.
+    pub fn to_text(&self) -> String {
+        self.paths
+            .iter()
+            .map(|rel| text::slash_path(rel) + "\n")
+            .collect()
+    }
+
+    /// Writes the bookmarks to `file` as text, for `--bookmarks` to read.
+    pub fn export(&self, file: &Path) -> io::Result<()> {
+        fs::write(file, self.to_text())
+    }
+}
@@ -0,0 +1,227 @@
+//! `s3://bucket/prefix` and `gs://bucket/prefix` as one side of a
+//! comparison, behind the `cloud` feature. Objects are listed and fetched
+//! through the `aws` and `gcloud` commands, so the user's credentials,
+//! profiles and endpoints (`AWS_PROFILE`, `AWS_ENDPOINT_URL`, ...) apply.
+//! Only the listing is read up front; contents are fetched per file when the
+//! comparison needs them.
+
+use crate::filter::WalkFilter;
+use crate::source::{head_of, not_found, Source};
+use crate::status::Entry;
+use crate::text;
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Store {
+    S3,
+    Gcs,
+}
+
+/// The objects under a prefix of a bucket, as a tree split at `/`.
+pub struct Bucket {
+    store: Store,
+    bucket: String,
+    // empty, or ending in `/`
+    prefix: String,
+    label: PathBuf,
+}
+
+impl Bucket {
+    /// Parses `s3://bucket[/prefix]` or `gs://bucket[/prefix]`. The prefix is
+    /// taken as a folder: `s3://b/site` holds `site/index.html`, not
+    /// `site-old/index.html`.
+    pub fn parse(url: &str) -> io::Result<Self> {
+        let (store, rest) = match (url.strip_prefix("s3://"), url.strip_prefix("gs://")) {
+            (Some(rest), _) => (Store::S3, rest),
+            (_, Some(rest)) => (Store::Gcs, rest),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not an s3:// or gs:// URL", url),
+                ))
+            }
+        };
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("missing bucket in {}", url),
+            ));
+        }
+        let prefix = prefix.trim_matches('/');
+        Ok(Bucket {
+            store,
+            bucket: bucket.to_string(),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", prefix)
+            },
+            label: PathBuf::from(url.trim_end_matches('/')),
+        })
+    }
+
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<Vec<u8>> {
+        let out = Command::new(program).args(args).output()?;
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "{} {} failed: {}",
+                program,
+                args.join(" "),
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(out.stdout)
+    }
+
+    fn key(&self, rel: &Path) -> String {
+        format!("{}{}", self.prefix, text::slash_path(rel))
+    }
+
+    /// Key and size of every object whose key starts with `prefix`.
+    fn objects(&self, prefix: &str) -> io::Result<Vec<(String, u64)>> {
+        let out = match self.store {
+            // the CLI follows the continuation tokens itself
+            Store::S3 => self.run(
+                "aws",
+                &[
+                    "s3api",
+                    "list-objects-v2",
+                    "--bucket",
+                    &self.bucket,
+                    "--prefix",
+                    prefix,
+                    "--query",
+                    "Contents[].[Key,Size]",
+                    "--output",
+                    "text",
+                ],
+            )?,
+            Store::Gcs => self.run(
+                "gcloud",
+                &[
+                    "storage",
+                    "objects",
+                    "list",
+                    &format!("gs://{}/{}**", self.bucket, prefix),
+                    "--format=value(name,size)",
+                ],
+            )?,
+        };
+        Ok(parse_listing(&String::from_utf8_lossy(&out)))
+    }
+
+    /// The entries for `objects`, keyed by their path below the bucket's
+    /// prefix, with the folders their keys imply. Objects outside the prefix
+    /// are left out.
+    pub fn entries(&self, objects: Vec<(String, u64)>) -> HashMap<PathBuf, Entry> {
+        let mut files = HashMap::new();
+        for (key, size) in objects {
+            let rel = match key.strip_prefix(&self.prefix) {
+                Some(rel) if !rel.is_empty() => rel,
+                _ => continue,
+            };
+            // consoles create empty `folder/` objects to show folders
+            let is_dir = rel.ends_with('/');
+            let rel = PathBuf::from(rel.trim_end_matches('/'));
+            for dir in rel.ancestors().skip(1) {
+                if dir.as_os_str().is_empty() {
+                    break;
+                }
+                files
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| Entry::new(self.label.join(dir), true, 0));
+            }
+            let entry = Entry::new(self.label.join(&rel), is_dir, if is_dir { 0 } else { size });
+            files.insert(rel, entry);
+        }
+        files
+    }
+}
+
+/// Key and size of each object in what the listing commands print, one
+/// `key<TAB>size` line per object. Other lines, like the `None` the AWS CLI
+/// prints when there are no objects, are skipped.
+pub fn parse_listing(out: &str) -> Vec<(String, u64)> {
+    out.lines()
+        .filter_map(|line| {
+            let (key, size) = line.rsplit_once('\t')?;
+            Some((key.to_string(), size.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+impl Source for Bucket {
+    fn root(&self) -> &Path {
+        &self.label
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let mut files = self.entries(self.objects(&self.prefix)?);
+        files.retain(|rel, e| !filter.is_ignored(rel, e.is_dir()));
+        Ok(files)
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        let key = self.key(rel);
+        match self.store {
+            Store::S3 => self.run(
+                "aws",
+                &["s3", "cp", &format!("s3://{}/{}", self.bucket, key), "-"],
+            ),
+            Store::Gcs => self.run(
+                "gcloud",
+                &["storage", "cat", &format!("gs://{}/{}", self.bucket, key)],
+            ),
+        }
+    }
+
+    /// Only the range asked for is fetched from GCS; the AWS CLI has no
+    /// range for `cp`, so the download is stopped once it is in.
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let key = self.key(rel);
+        match self.store {
+            Store::S3 => {
+                let url = format!("s3://{}/{}", self.bucket, key);
+                let mut cmd = Command::new("aws");
+                cmd.args(["s3", "cp", &url, "-"]);
+                head_of(&mut cmd, len, &format!("aws s3 cp {} -", url))
+            }
+            Store::Gcs => self.run(
+                "gcloud",
+                &[
+                    "storage",
+                    "cat",
+                    &format!("--range=0-{}", len - 1),
+                    &format!("gs://{}/{}", self.bucket, key),
+                ],
+            ),
+        }
+    }
+
+    /// Only the objects under the folder `rel` are listed.
+    fn list_under(&self, rel: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        if rel.as_os_str().is_empty() {
+            return self.list(filter);
+        }
+        let mut files = self.entries(self.objects(&format!("{}/", self.key(rel)))?);
+        files.retain(|k, e| k.starts_with(rel) && k != rel && !filter.is_ignored(k, e.is_dir()));
+        Ok(files)
+    }
+
+    /// Lists `rel` and what is below it: the object itself for a file, the
+    /// objects under it for a folder.
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        self.entries(self.objects(&self.key(rel))?)
+            .remove(rel)
+            .ok_or_else(|| not_found(&self.label, rel))
+    }
+}
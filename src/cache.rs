@@ -0,0 +1,240 @@
+use crate::manifest;
+use crate::status::Entry;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// First line of the cache file, bumped whenever the record layout changes.
+const HEADER: &str = "# diff-folders cache v2";
+
+/// A file as it was when it was last looked at: path, size and mtime in
+/// nanoseconds. Any write changes the mtime, so a matching key means the
+/// cached answer still holds.
+type FileKey = (PathBuf, u64, u128);
+
+/// Hashes and comparison outcomes from earlier runs on the same pair of
+/// trees, so unchanged files are not read again.
+#[derive(Default)]
+pub struct Cache {
+    // None when caching is turned off
+    file: Option<PathBuf>,
+    // when the file was last written, as it was loaded
+    loaded: Option<SystemTime>,
+    hashes: HashMap<FileKey, String>,
+    // (old, new) -> whether the contents are the same
+    outcomes: HashMap<(FileKey, FileKey), bool>,
+    dirty: bool,
+}
+
+impl Cache {
+    /// `results/<hash>.tsv` under `dir`, named after both trees like
+    /// [`crate::checkpoint::Checkpoint::pair_file`], so every pair keeps a
+    /// cache of its own.
+    pub fn pair_file(dir: &Path, old: &Path, new: &Path) -> PathBuf {
+        let absolute = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+        let key = format!("{}\0{}", absolute(old).display(), absolute(new).display());
+        let hash = manifest::hash_bytes(key.as_bytes());
+        dir.join("results").join(format!("{}.tsv", &hash[..16]))
+    }
+
+    /// Loads the cache kept in `file`; a missing or unreadable file starts empty.
+    pub fn load(file: PathBuf) -> Self {
+        let mut cache = Cache::default();
+        if let Err(e) = cache.read(&file) {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("read cache {} failed: {}", file.display(), e);
+            }
+        }
+        cache.file = Some(file);
+        cache
+    }
+
+    /// A cache that remembers nothing, for `--no-cache`.
+    pub fn disabled() -> Self {
+        Cache::default()
+    }
+
+    fn read(&mut self, file: &Path) -> io::Result<()> {
+        let f = File::open(file)?;
+        self.loaded = f.metadata()?.modified().ok();
+        let mut lines = BufReader::new(f).lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            log::warn!("ignore cache {} of another version", file.display());
+            return Ok(());
+        }
+        for line in lines {
+            let line = line?;
+            let parts: Vec<&str> = line.split('\t').collect();
+            match parts.as_slice() {
+                ["h", hash, size, mtime, path] => {
+                    if let Some(key) = parse_key(path, size, mtime) {
+                        self.hashes.insert(key, hash.to_string());
+                    }
+                }
+                ["c", same, old_size, old_mtime, old, new_size, new_mtime, new] => {
+                    let old = parse_key(old, old_size, old_mtime);
+                    let new = parse_key(new, new_size, new_mtime);
+                    if let (Some(old), Some(new)) = (old, new) {
+                        self.outcomes.insert((old, new), *same == "1");
+                    }
+                }
+                _ => log::warn!("{}: skip malformed line {:?}", file.display(), line),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `old` and `new` were found to hold the same contents before.
+    pub fn outcome(&self, old: &Entry, new: &Entry) -> Option<bool> {
+        let key = (self.key(old)?, self.key(new)?);
+        self.outcomes.get(&key).copied()
+    }
+
+    pub fn set_outcome(&mut self, old: &Entry, new: &Entry, same: bool) {
+        if let (Some(old), Some(new)) = (self.key(old), self.key(new)) {
+            self.outcomes.insert((old, new), same);
+            self.dirty = true;
+        }
+    }
+
+    /// The cached hash of `entry`, or the one `compute` returns, remembered for
+    /// next time.
+    pub fn hash(
+        &mut self,
+        entry: &Entry,
+        compute: impl FnOnce() -> io::Result<String>,
+    ) -> io::Result<String> {
+        let key = match self.key(entry) {
+            Some(key) => key,
+            None => return compute(),
+        };
+        if let Some(hash) = self.hashes.get(&key) {
+            return Ok(hash.clone());
+        }
+        let hash = compute()?;
+        self.hashes.insert(key, hash.clone());
+        self.dirty = true;
+        Ok(hash)
+    }
+
+    /// Forgets every file that is not among `listed` as it is now, so files
+    /// that were deleted or changed since do not pile up. Only for a listing
+    /// of both trees in full.
+    pub fn keep_only<'a>(&mut self, listed: impl Iterator<Item = &'a Entry>) {
+        if self.file.is_none() {
+            return;
+        }
+        let live: HashSet<FileKey> = listed.filter_map(|e| self.key(e)).collect();
+        let (hashes, outcomes) = (self.hashes.len(), self.outcomes.len());
+        self.hashes.retain(|key, _| live.contains(key));
+        self.outcomes
+            .retain(|(old, new), _| live.contains(old) && live.contains(new));
+        self.dirty |= hashes != self.hashes.len() || outcomes != self.outcomes.len();
+    }
+
+    /// Entries without an mtime (snapshots, archives, remote trees) are never
+    /// cached, nor are paths that are not valid Unicode.
+    fn key(&self, entry: &Entry) -> Option<FileKey> {
+        self.file.as_ref()?;
+        entry.path().to_str()?;
+        Some((entry.path().to_path_buf(), entry.size, entry.mtime?))
+    }
+
+    /// Writes the cache back if anything changed. The file is replaced in one
+    /// rename so an interrupted run never leaves half a cache behind, and what
+    /// another run on the same pair saved in the meantime is kept.
+    pub fn save(&mut self) -> io::Result<()> {
+        let file = match &self.file {
+            Some(file) if self.dirty => file.clone(),
+            _ => return Ok(()),
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let modified = fs::metadata(&file).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != self.loaded {
+            let mut theirs = Cache::default();
+            if let Err(e) = theirs.read(&file) {
+                log::warn!("read cache {} failed: {}", file.display(), e);
+            }
+            for (key, hash) in theirs.hashes {
+                self.hashes.entry(key).or_insert(hash);
+            }
+            for (key, same) in theirs.outcomes {
+                self.outcomes.entry(key).or_insert(same);
+            }
+        }
+        let mut name = file.as_os_str().to_owned();
+        name.push(format!(".{}.tmp", std::process::id()));
+        let tmp = PathBuf::from(name);
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        writeln!(w, "{}", HEADER)?;
+        for ((path, size, mtime), hash) in &self.hashes {
+            writeln!(w, "h\t{}\t{}\t{}\t{}", hash, size, mtime, escape(path))?;
+        }
+        for ((old, new), same) in &self.outcomes {
+            writeln!(
+                w,
+                "c\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if *same { 1 } else { 0 },
+                old.1,
+                old.2,
+                escape(&old.0),
+                new.1,
+                new.2,
+                escape(&new.0)
+            )?;
+        }
+        w.flush()?;
+        drop(w);
+        fs::rename(&tmp, &file).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })?;
+        self.loaded = fs::metadata(&file).and_then(|m| m.modified()).ok();
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// A path as one field of a line: backslashes, tabs and line breaks are
+/// escaped. Only paths that are valid Unicode are cached, see [`Cache::key`].
+pub(crate) fn escape(path: &Path) -> String {
+    let mut out = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub(crate) fn unescape(field: &str) -> Option<PathBuf> {
+    let mut out = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(PathBuf::from(out))
+}
+
+fn parse_key(path: &str, size: &str, mtime: &str) -> Option<FileKey> {
+    Some((unescape(path)?, size.parse().ok()?, mtime.parse().ok()?))
+}
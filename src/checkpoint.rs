@@ -0,0 +1,202 @@
+//! Progress of a comparison kept on disk while it runs, so `--resume` picks
+//! up one that was interrupted (Ctrl+C, a dropped SSH session) instead of
+//! reading every file again. Each pair of files settled by reading is
+//! appended to `resume/<hash>.tsv` under the cache directory as soon as it is
+//! known, and the file is removed once the scan gets through.
+
+use crate::cache::{escape, unescape};
+use crate::manifest;
+use crate::status::Entry;
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, LineWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// First line of a checkpoint, bumped whenever the record layout changes.
+const HEADER: &str = "# diff-folders checkpoint v2";
+
+/// Size, mtime and recorded hash of a file when it was compared. Size alone
+/// says nothing about the contents, so a stamp with neither an mtime nor a
+/// hash is never trusted.
+type Stamp = (u64, Option<u128>, Option<String>);
+
+/// The outcomes of an earlier, interrupted run and the file this run adds to.
+#[derive(Default)]
+pub struct Checkpoint {
+    // None keeps no checkpoint
+    file: Option<PathBuf>,
+    // relative path -> old and new stamp, and whether the contents were the same
+    done: HashMap<PathBuf, (Stamp, Stamp, bool)>,
+    // opened on the first record
+    out: Option<LineWriter<File>>,
+}
+
+impl Checkpoint {
+    /// `resume/<hash>.tsv` under `dir`, named after both trees like
+    /// [`crate::session::Session::pair_file`].
+    pub fn pair_file(dir: &Path, old: &Path, new: &Path) -> PathBuf {
+        let absolute = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+        let key = format!("{}\0{}", absolute(old).display(), absolute(new).display());
+        let hash = manifest::hash_bytes(key.as_bytes());
+        dir.join("resume").join(format!("{}.tsv", &hash[..16]))
+    }
+
+    /// Keeps the progress of this run in `file`. With `resume`, the outcomes
+    /// an earlier run left there are taken up and added to, otherwise the
+    /// file is started over.
+    pub fn open(file: PathBuf, resume: bool) -> Self {
+        let mut checkpoint = Checkpoint::default();
+        if resume {
+            match checkpoint.read(&file) {
+                Ok(()) => log::info!(
+                    "resume with {} files compared before",
+                    checkpoint.done.len()
+                ),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    log::info!("no checkpoint to resume from, starting over")
+                }
+                Err(e) => log::warn!("read checkpoint {} failed: {}", file.display(), e),
+            }
+        }
+        checkpoint.file = Some(file);
+        checkpoint
+    }
+
+    /// A checkpoint that keeps nothing, for `--no-cache`.
+    pub fn disabled() -> Self {
+        Checkpoint::default()
+    }
+
+    fn read(&mut self, file: &Path) -> io::Result<()> {
+        let mut lines = BufReader::new(File::open(file)?).lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            log::warn!("ignore checkpoint {} of another version", file.display());
+            return Ok(());
+        }
+        for line in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            let record = match parts.as_slice() {
+                [same, old_size, old_mtime, old_hash, new_size, new_mtime, new_hash, rel] => {
+                    let old = parse_stamp(old_size, old_mtime, old_hash);
+                    let new = parse_stamp(new_size, new_mtime, new_hash);
+                    old.zip(new)
+                        .zip(unescape(rel))
+                        .map(|((old, new), rel)| (rel, (old, new, *same == "1")))
+                }
+                _ => None,
+            };
+            match record {
+                Some((rel, outcome)) => {
+                    self.done.insert(rel, outcome);
+                }
+                // the last line of a run killed mid-write
+                None => log::warn!("{}: skip malformed line {:?}", file.display(), line),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the earlier run found `old` and `new` at `rel` to hold the
+    /// same contents, when neither changed since.
+    pub fn outcome(&self, rel: &Path, old: &Entry, new: &Entry) -> Option<bool> {
+        let (old_stamp, new_stamp, same) = self.done.get(rel)?;
+        let (old, new) = (stamp(old)?, stamp(new)?);
+        (*old_stamp == old && *new_stamp == new).then_some(*same)
+    }
+
+    /// Appends an outcome right away, so it survives the process being
+    /// killed. A checkpoint that cannot be written is given up with a
+    /// warning, the comparison goes on without it. Files that could not be
+    /// told apart from a changed copy next time are not recorded.
+    pub fn record(&mut self, rel: &Path, old: &Entry, new: &Entry, same: bool) {
+        let (old, new) = match (stamp(old), stamp(new)) {
+            (Some(old), Some(new)) if self.file.is_some() && rel.to_str().is_some() => (old, new),
+            _ => return,
+        };
+        let written = self.writer().and_then(|out| {
+            let mtime = |m: Option<u128>| m.map_or("-".to_string(), |m| m.to_string());
+            let hash = |h: Option<String>| h.unwrap_or_else(|| "-".to_string());
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if same { 1 } else { 0 },
+                old.0,
+                mtime(old.1),
+                hash(old.2),
+                new.0,
+                mtime(new.1),
+                hash(new.2),
+                escape(rel)
+            )
+        });
+        if let Err(e) = written {
+            if let Some(file) = self.file.take() {
+                log::warn!("write checkpoint {} failed: {}", file.display(), e);
+            }
+        }
+    }
+
+    /// The checkpoint file opened for appending: after what was resumed from,
+    /// or emptied when starting over.
+    fn writer(&mut self) -> io::Result<&mut LineWriter<File>> {
+        let out = match self.out.take() {
+            Some(out) => out,
+            None => self.open_writer()?,
+        };
+        Ok(self.out.insert(out))
+    }
+
+    fn open_writer(&self) -> io::Result<LineWriter<File>> {
+        let file = self
+            .file
+            .as_ref()
+            .ok_or_else(|| io::Error::other("no checkpoint"))?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = if self.done.is_empty() {
+            let mut out = File::create(file)?;
+            writeln!(out, "{}", HEADER)?;
+            out
+        } else {
+            OpenOptions::new().append(true).open(file)?
+        };
+        // a line cut short by a crash ends here rather than spoiling the next
+        writeln!(out)?;
+        Ok(LineWriter::new(out))
+    }
+
+    /// The scan got through, nothing is left to resume.
+    pub fn finish(self) {
+        if let Some(file) = self.file {
+            if let Err(e) = fs::remove_file(&file) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    log::warn!("remove checkpoint {} failed: {}", file.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// `None` for an entry with neither an mtime nor a recorded hash.
+fn stamp(entry: &Entry) -> Option<Stamp> {
+    if entry.mtime.is_none() && entry.hash.is_none() {
+        return None;
+    }
+    Some((entry.size, entry.mtime, entry.hash.clone()))
+}
+
+fn parse_stamp(size: &str, mtime: &str, hash: &str) -> Option<Stamp> {
+    let mtime = match mtime {
+        "-" => None,
+        mtime => Some(mtime.parse().ok()?),
+    };
+    let hash = (hash != "-").then(|| hash.to_string());
+    Some((size.parse().ok()?, mtime, hash))
+}
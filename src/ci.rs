@@ -0,0 +1,108 @@
+//! `--output github-annotations` and `--output gitlab-codequality`: the
+//! differences in formats CI systems show next to the files, one annotation
+//! per changed entry.
+
+use crate::format;
+use crate::manifest;
+use crate::status::{FolderStatefulList, StatusItemType};
+use crate::text;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A workflow command per difference, e.g.
+/// `::warning file=dist/app.js,title=diff-folders%3A modified::app.js modified (+1.2 KB)`.
+/// GitHub Actions turns them into annotations on the run and the pull request.
+pub fn github(items: &[(PathBuf, &FolderStatefulList)]) -> String {
+    let mut out = String::new();
+    for (rel, item) in items {
+        let path = file_path(item);
+        let state = state_name(item.state);
+        out += &format!(
+            "::warning file={},title={}::{}\n",
+            escape_property(&path),
+            escape_property(&format!("diff-folders: {}", state)),
+            escape_data(&message(rel, item))
+        );
+    }
+    out
+}
+
+/// A GitLab Code Quality report: a JSON array with an issue per difference,
+/// shown in the merge request widget when saved as a `codequality` artifact.
+pub fn gitlab(items: &[(PathBuf, &FolderStatefulList)]) -> String {
+    let issues: Vec<_> = items
+        .iter()
+        .map(|(rel, item)| {
+            let path = file_path(item);
+            let state = state_name(item.state);
+            let severity = match item.state {
+                StatusItemType::Deleted => "major",
+                _ => "minor",
+            };
+            let fingerprint = manifest::hash_bytes(format!("{}\0{}", state, path).as_bytes());
+            format!(
+                "{{\"description\":{},\"check_name\":\"diff-folders-{}\",\"fingerprint\":\"{}\",\"severity\":\"{}\",\"location\":{{\"path\":{},\"lines\":{{\"begin\":1}}}}}}",
+                text::json_string(&message(rel, item)),
+                state,
+                fingerprint,
+                severity,
+                text::json_string(&path)
+            )
+        })
+        .collect();
+    format!("[{}]\n", issues.join(","))
+}
+
+/// The entry's path relative to the working directory, which is where CI
+/// checks out the workspace; a tree outside it keeps its absolute path.
+fn file_path(item: &FolderStatefulList) -> String {
+    let path = item.entry.path();
+    let cwd = std::env::current_dir()
+        .and_then(fs::canonicalize)
+        .map(text::strip_verbatim);
+    let path = match &cwd {
+        Ok(cwd) if path.is_absolute() => path.strip_prefix(cwd).unwrap_or(path),
+        _ => path,
+    };
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
+
+fn state_name(state: StatusItemType) -> String {
+    format!("{:?}", state).to_lowercase()
+}
+
+/// `src/app.rs modified (+1.2 KB)`, folders without a size.
+fn message(rel: &Path, item: &FolderStatefulList) -> String {
+    let rel = text::slash_path(rel);
+    let state = state_name(item.state);
+    if item.entry.is_dir() {
+        format!("{} {} (folder)", rel, state)
+    } else {
+        format!(
+            "{} {} ({})",
+            rel,
+            state,
+            format::size_delta(item.size_delta)
+        )
+    }
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a `key=value` property of a workflow command, where `:` and `,`
+/// separate the parts.
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
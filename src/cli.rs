@@ -0,0 +1,905 @@
+use crate::color::ColorChoice;
+use crate::config::{CompareRule, Config, Favorite, Layout};
+use crate::format::Format;
+use crate::icons::IconSet;
+use crate::mime::TypeFilter;
+use crate::model::Order;
+use crate::ops::ApplyDirection;
+use crate::recent;
+use crate::session::Session;
+use crate::source;
+use crate::text::{self, PathStyle, DEFAULT_TAB_WIDTH};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use glob::Pattern;
+use log::LevelFilter;
+use regex::RegexSet;
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Prefix of the environment variables that set options, e.g.
+/// `DIFF_FOLDERS_MAX_FILES=1000` for `--max-files 1000`.
+pub const ENV_PREFIX: &str = "DIFF_FOLDERS_";
+
+/// Options without a value, which settings turn on with `true` or `1` and
+/// leave off with `false`, `0` or nothing.
+const FLAGS: &[&str] = &[
+    "sync-plan",
+    "json",
+    "brief",
+    "no-color",
+    "contents",
+    "delete",
+    "dry-run",
+    "permanent",
+    "hidden",
+    "no-vcs-exclude",
+    "no-cache",
+    "resume",
+    "no-save-prefs",
+    "fail-on-diff",
+    "hard-links",
+    "dir-changes",
+    "renames",
+    "nice",
+    "duplicates",
+    "sizes",
+    "profile",
+    "no-log",
+];
+
+/// Options that take one value, which settings pass on as it is.
+const VALUED: &[&str] = &[
+    "oci",
+    "pairs-from",
+    "paths-from",
+    "since",
+    "apply-to",
+    "output",
+    "color",
+    "merge-out",
+    "token",
+    "listen",
+    "backup-dir",
+    "rename-threshold",
+    "modify-threshold",
+    "difftool",
+    "on-change",
+    "compare-cmd",
+    "ignore-matching-lines",
+    "io-limit",
+    "max-files",
+    "order",
+    "type",
+    "tab-width",
+    "path-style",
+    "icons",
+    "log-level",
+    "bookmarks",
+    "session",
+    "log-file",
+    "preset",
+];
+
+/// Entries a side may hold before the walk stops and asks, see `--max-files`.
+pub const DEFAULT_MAX_FILES: usize = 100_000;
+
+const USAGE: &str =
+    "diff-folders [options] <old_dir|old_file|manifest|checksum_file> <new_dir|new_file>
+       diff-folders snapshot [--contents] <dir> <file>
+       diff-folders sync [--delete] [--backup-dir <dir>] [--dry-run] <src_dir> <dst_dir>
+       diff-folders show <old_dir> <new_dir> <relative_path>
+       diff-folders serve [--listen <addr:port>] --token <secret> <dir>
+       diff-folders [options] --oci <image> <expected_dir>
+       diff-folders [options] --pair <old_dir> <new_dir> [--pair <old_dir> <new_dir>...]
+
+options:
+    --oci <image>           compare the root filesystem of a container image, an OCI layout
+                            folder, a docker save tarball or the name of a local docker or
+                            podman image, as the new side against expected_dir
+    --pair <old> <new>      also compare this pair of folders, in a tab of its own (repeatable);
+                            Tab and Shift+Tab switch between the pairs
+    --pairs-from <file>     open a tab for every pair listed in file, one `old new` per line
+                            (separated by a tab when the paths have spaces), relative paths
+                            being taken from the file's folder
+    --paths-from <file|->   only compare the relative paths listed in file (or stdin)
+    --since <when>          only compare entries modified after a date (2024-05-01,
+                            2024-05-01 12:00, RFC 3339) or within a duration (90s, 30m, 2h, 7d, 1w)
+    --apply-to <old|new>    tree rewritten by the apply key (default: old)
+    --sync-plan             list the copies and deletions that would make the --apply-to tree
+                            match the other one, like rsync -n, and exit
+    --json                  print --sync-plan as JSON
+    --output <format>       tui, or ndjson to print one JSON object per change found, progress
+                            and the totals while comparing, then exit (default: tui);
+                            github-annotations or gitlab-codequality print one CI annotation
+                            per difference and exit, with 1 if --fail-on-diff is also given
+    --brief                 list the differences, one status and path per line, and exit
+    --color <when>          color printed statuses: auto (when stdout is a terminal and
+                            NO_COLOR is unset), always or never (default: auto)
+    --merge-out <dir>       write conflict-marked copies of modified files to dir and exit
+    --token <secret>        serve: the token clients must send; when comparing, the token sent
+                            to the servers of host:port:/path sides
+    --listen <addr:port>    serve: where to wait for clients (default: 127.0.0.1:7873, use
+                            0.0.0.0:7873 for other machines; the protocol is plaintext, so
+                            the token and file contents can be read on the way)
+    --contents              snapshot: also store compressed file contents
+    --delete                sync: also delete what is in dst_dir but not in src_dir
+    --backup-dir <dir>      sync: save overwritten and deleted files here first
+    --dry-run               sync: only list what would be done
+    --permanent             delete for real instead of moving to the system trash
+    -H, --hidden            include dotfiles and dot-directories
+    --no-vcs-exclude        also compare .git, .hg and .svn directories
+    --no-cache              ignore and do not update the cache of earlier results
+    --resume                go on with a comparison of the same folders that was
+                            interrupted, without reading again the files it compared
+    --fail-on-diff          compare without the TUI and exit with 1 when anything differs
+    -I, --ignore-matching-lines <regex>
+                            changes whose lines all match regex do not count (repeatable)
+    --hard-links            files hard linked to each other count as equal without being
+                            read, equal files with a different link count as modified
+    --renames               pair deleted and new files with the same contents as moves, the
+                            diff window then shows them side by side
+    --rename-threshold <n>  also pair files at least this similar, from 0 to 1 (e.g. 0.5)
+    --modify-threshold <n>  list a changed file less similar than this as deleted and new
+    --dir-changes           list folders on both sides as modified when their entries differ,
+                            e.g. when one became empty
+    --compare-cmd <cmd>     decide whether differing files are equal by running cmd, with {old}
+                            and {new} replaced by the paths; exit 0 means equal, 1 different
+    --difftool <cmd>        D in the TUI opens the selected pair in cmd, with {old} and {new}
+                            replaced by the paths, e.g. \"meld {old} {new}\"
+    --on-change <cmd>       compare without the TUI and run cmd for every difference, with
+                            {path}, {status} (new, modified or deleted), {old} and {new}
+                            replaced; exits with 1 when a run fails
+    --io-limit <MB/s>       read file contents at most this fast, e.g. 20 or 0.5
+    --nice                  run at low CPU priority and, on Linux, idle IO priority
+    --duplicates            list files with the same contents under different paths and exit
+    --sizes                 list directories whose recursive size changed, biggest change
+                            first, and exit
+    --profile               compare without the TUI, list the files and folders that took
+                            longest and exit; add --no-cache to time every file
+    --max-files <n>         stop walking a tree after n entries and ask whether to go on, show
+                            what was found or refine the filters; 0 for no cap (default: 100000)
+    --preset <name>         use the options of [profile name] in the config file
+    --order <order>         list results depth first (dfs), breadth first (bfs) or by the
+                            whole path (alpha), the same on every run (default: dfs)
+    --type <kind>           list only text, binary, image, audio, video, font or archive
+                            files, told by their contents (:type in the TUI changes it)
+    --bookmarks <file>      start with the entries listed in file bookmarked, e.g. a list
+                            written with :bookmarks in an earlier session
+    --session <file>        keep bookmarks, notes and review states in file instead of the
+                            cache directory, where each pair of folders has its own
+    --tab-width <n>         columns per tab stop in the diff window (default: 4)
+    --path-style <style>    list labels: relative, full or name (default: relative)
+    --icons <set>           list icons: none, nerd (needs a Nerd Font), emoji or symbols (+, -
+                            and ~ for new, deleted and modified) (default: none)
+    --no-color              no colors anywhere: the TUI marks states with symbols and bold or
+                            underlined text, for monochrome terminals and colorblind users
+    --no-save-prefs         do not save the pane split, wrapping, columns and the other view
+                            toggles to the [layout] section of the config file on exit
+    --log-level <level>     off, error, warn, info, debug or trace (default: info)
+    --log-file <path>       log here instead of ~/.cache/diff-folders/diff-folders.log
+    --no-log                do not write a log file (same as --log-level off)
+
+Every option can also be set in the [defaults] section of the config file or with a
+DIFF_FOLDERS_<OPTION> environment variable (DIFF_FOLDERS_MAX_FILES=1000, DIFF_FOLDERS_HIDDEN=1);
+the command line beats the environment, which beats --preset, which beats [defaults].";
+
+/// What the process was asked to do.
+#[derive(Clone, Default)]
+pub enum Command {
+    /// compare two trees in the TUI
+    #[default]
+    Compare,
+    /// record `dir` into a snapshot file usable as the old side later
+    Snapshot {
+        dir: String,
+        out: PathBuf,
+        contents: bool,
+    },
+    /// make the old tree (`dst`) match the new one (`src`) without the TUI
+    Sync {
+        delete: bool,
+        backup_dir: Option<PathBuf>,
+        dry_run: bool,
+    },
+    /// print the diff of one file through `$PAGER` without the TUI
+    Show { rel: PathBuf },
+    /// answer other machines' requests for the files under `dir`, see
+    /// [`crate::agent`]
+    Serve { dir: String, listen: String },
+    /// no folders were named: pick a pair on the start screen, then compare
+    /// it in the TUI
+    Launch {
+        favorites: Vec<Favorite>,
+        recent: Vec<(String, String)>,
+    },
+}
+
+/// Where the results of a comparison go.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Output {
+    #[default]
+    Tui,
+    /// JSON lines on stdout while the scan runs, see [`crate::events`]
+    Ndjson,
+    /// GitHub Actions workflow commands, see [`crate::ci`]
+    GithubAnnotations,
+    /// a GitLab Code Quality report
+    GitlabCodequality,
+}
+
+impl std::str::FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tui" => Ok(Output::Tui),
+            "ndjson" => Ok(Output::Ndjson),
+            "github-annotations" => Ok(Output::GithubAnnotations),
+            "gitlab-codequality" => Ok(Output::GitlabCodequality),
+            _ => Err(format!(
+                "expected tui, ndjson, github-annotations or gitlab-codequality, got {}",
+                s
+            )),
+        }
+    }
+}
+
+/// Options collected from the command line.
+#[derive(Clone)]
+pub struct Options {
+    pub command: Command,
+    pub old_dir: String,
+    pub new_dir: String,
+    /// more pairs of trees to open in tabs of the TUI, after `old_dir` and
+    /// `new_dir`
+    pub pairs: Vec<(String, String)>,
+    /// relative paths to compare instead of walking both trees
+    pub paths: Option<Vec<PathBuf>>,
+    /// skip entries last modified before this on both sides
+    pub since: Option<SystemTime>,
+    pub apply_to: ApplyDirection,
+    /// delete files for good instead of moving them to the trash
+    pub permanent: bool,
+    /// print what syncing would do instead of starting the TUI
+    pub sync_plan: bool,
+    pub json: bool,
+    /// print one line per difference instead of starting the TUI
+    pub brief: bool,
+    pub output: Output,
+    pub color: ColorChoice,
+    /// write merge files here instead of starting the TUI
+    pub merge_out: Option<PathBuf>,
+    /// shared secret of `serve` and the `host:port:/path` sides
+    pub token: Option<String>,
+    /// include dotfiles and dot-directories
+    pub hidden: bool,
+    /// compare VCS metadata directories too
+    pub no_vcs_exclude: bool,
+    pub no_cache: bool,
+    /// take up the checkpoint an interrupted comparison left
+    pub resume: bool,
+    /// headless check, exit status tells whether the trees differ
+    pub fail_on_diff: bool,
+    /// changes made only of lines matching one of these do not count
+    pub ignore_lines: Option<RegexSet>,
+    /// compare inodes and hard link counts, for `rsync --link-dest` backups
+    pub hard_links: bool,
+    /// folders whose entries differ, emptied or filled ones too, are listed as
+    /// modified
+    pub dir_changes: bool,
+    /// deleted and new files with the same contents are paired as moves
+    pub renames: bool,
+    /// how similar, from 0 to 1, other files must be to be paired as moves;
+    /// setting it turns on `renames`
+    pub rename_threshold: Option<f32>,
+    /// changed files less similar than this are listed as deleted and new
+    pub modify_threshold: Option<f32>,
+    /// config file rules first, then `--compare-cmd` for everything else
+    pub compare_rules: Vec<CompareRule>,
+    /// the TUI's external diff tool, with `{old}` and `{new}` placeholders
+    pub difftool: Option<String>,
+    /// bytes of file contents read per second at most
+    pub io_limit: Option<u64>,
+    /// lower the process' CPU and IO priority
+    pub nice: bool,
+    pub duplicates: bool,
+    /// print recursive directory sizes instead of starting the TUI
+    pub sizes: bool,
+    /// print the slowest files and folders instead of starting the TUI
+    pub profile: bool,
+    /// run this for every difference instead of starting the TUI
+    pub on_change: Option<String>,
+    /// how results are listed, in the TUI and in every output
+    pub order: Order,
+    /// entries per side before the walk stops, `None` for no cap
+    pub max_files: Option<usize>,
+    /// list only files of this kind at first
+    pub type_filter: Option<TypeFilter>,
+    /// bookmarks, notes and reviews of an earlier session, from `--session`
+    /// and `--bookmarks`
+    pub session: Session,
+    pub tab_width: usize,
+    /// size units and date style, from the config file
+    pub format: Format,
+    pub path_style: PathStyle,
+    pub icons: IconSet,
+    /// the TUI shows states with symbols and bold or underlined text instead
+    /// of colors, and printed output is not colored
+    pub no_color: bool,
+    /// how the TUI was left last time, from the config file
+    pub layout: Layout,
+    /// leave the `[layout]` section of the config file as it is on exit
+    pub no_save_prefs: bool,
+    pub log_level: LevelFilter,
+    pub log_file: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            command: Command::default(),
+            old_dir: String::new(),
+            new_dir: String::new(),
+            pairs: Vec::new(),
+            paths: None,
+            since: None,
+            apply_to: ApplyDirection::default(),
+            permanent: false,
+            sync_plan: false,
+            json: false,
+            brief: false,
+            output: Output::default(),
+            color: ColorChoice::default(),
+            merge_out: None,
+            token: None,
+            hidden: false,
+            no_vcs_exclude: false,
+            no_cache: false,
+            resume: false,
+            fail_on_diff: false,
+            ignore_lines: None,
+            hard_links: false,
+            dir_changes: false,
+            renames: false,
+            rename_threshold: None,
+            modify_threshold: None,
+            compare_rules: Vec::new(),
+            difftool: None,
+            io_limit: None,
+            nice: false,
+            duplicates: false,
+            sizes: false,
+            profile: false,
+            on_change: None,
+            order: Order::default(),
+            max_files: Some(DEFAULT_MAX_FILES),
+            type_filter: None,
+            session: Session::default(),
+            tab_width: DEFAULT_TAB_WIDTH,
+            format: Format::default(),
+            path_style: PathStyle::default(),
+            icons: IconSet::default(),
+            no_color: false,
+            layout: Layout::default(),
+            no_save_prefs: false,
+            log_level: LevelFilter::Info,
+            log_file: None,
+        }
+    }
+}
+
+/// Parses the process arguments, `args` excludes the program name, on top of
+/// the settings of `config` and the `DIFF_FOLDERS_*` variables of `env`.
+pub fn parse_args(
+    args: impl Iterator<Item = String>,
+    config: Config,
+    env: impl Iterator<Item = (String, String)>,
+) -> Result<Options> {
+    let mut options = Options::default();
+    let mut dirs = Vec::new();
+    let mut contents = false;
+    let (mut delete, mut backup_dir, mut dry_run) = (false, None, false);
+    let mut ignore_lines = Vec::new();
+    let mut compare_cmd = None;
+    let mut oci = None;
+    let mut pairs = Vec::new();
+    let mut listen = None;
+    let mut args = layered_args(args.collect(), &config, env)?.into_iter();
+    options.compare_rules = config.compare;
+    options.format = config.format;
+    options.layout = config.layout;
+    options.path_style = config.layout.path_style.unwrap_or_default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--oci" => oci = Some(args.next().context("--oci needs a value")?),
+            "--pair" => {
+                let old = args.next().context("--pair needs two folders")?;
+                let new = args.next().context("--pair needs two folders")?;
+                pairs.push((old, new));
+            }
+            "--pairs-from" => {
+                let file = args.next().context("--pairs-from needs a value")?;
+                pairs.extend(read_pairs(Path::new(&file))?);
+            }
+            "--paths-from" => {
+                let src = args.next().context("--paths-from needs a value")?;
+                options.paths = Some(read_paths(&src)?);
+            }
+            "--since" => {
+                let when = args.next().context("--since needs a value")?;
+                options.since = Some(parse_since(&when)?);
+            }
+            "--apply-to" => {
+                let to = args.next().context("--apply-to needs a value")?;
+                options.apply_to = to.parse().map_err(|e| anyhow!("--apply-to: {}", e))?;
+            }
+            "--sync-plan" => options.sync_plan = true,
+            "--json" => options.json = true,
+            "--brief" => options.brief = true,
+            "--output" => {
+                let format = args.next().context("--output needs a value")?;
+                options.output = format.parse().map_err(|e| anyhow!("--output: {}", e))?;
+            }
+            "--no-color" => {
+                options.no_color = true;
+                options.color = ColorChoice::Never;
+            }
+            "--color" => {
+                let when = args.next().context("--color needs a value")?;
+                options.color = when.parse().map_err(|e| anyhow!("--color: {}", e))?;
+            }
+            "--merge-out" => {
+                let dir = args.next().context("--merge-out needs a value")?;
+                options.merge_out = Some(PathBuf::from(dir));
+            }
+            "--token" => options.token = Some(args.next().context("--token needs a value")?),
+            "--listen" => listen = Some(args.next().context("--listen needs a value")?),
+            "--contents" => contents = true,
+            "--delete" => delete = true,
+            "--backup-dir" => {
+                let dir = args.next().context("--backup-dir needs a value")?;
+                backup_dir = Some(PathBuf::from(dir));
+            }
+            "--dry-run" => dry_run = true,
+            "--permanent" => options.permanent = true,
+            "-H" | "--hidden" => options.hidden = true,
+            "--no-vcs-exclude" => options.no_vcs_exclude = true,
+            "--no-cache" => options.no_cache = true,
+            "--resume" => options.resume = true,
+            "--no-save-prefs" => options.no_save_prefs = true,
+            "--fail-on-diff" => options.fail_on_diff = true,
+            "--hard-links" => options.hard_links = true,
+            "--dir-changes" => options.dir_changes = true,
+            "--renames" => options.renames = true,
+            "--rename-threshold" => {
+                let n = args.next().context("--rename-threshold needs a value")?;
+                options.rename_threshold = Some(fraction("--rename-threshold", &n)?);
+            }
+            "--modify-threshold" => {
+                let n = args.next().context("--modify-threshold needs a value")?;
+                options.modify_threshold = Some(fraction("--modify-threshold", &n)?);
+            }
+            "--difftool" => {
+                options.difftool = Some(args.next().context("--difftool needs a command")?);
+            }
+            "--on-change" => {
+                options.on_change = Some(args.next().context("--on-change needs a command")?);
+            }
+            "--compare-cmd" => {
+                compare_cmd = Some(args.next().context("--compare-cmd needs a command")?);
+            }
+            "-I" | "--ignore-matching-lines" => {
+                let re = args
+                    .next()
+                    .context("--ignore-matching-lines needs a regex")?;
+                ignore_lines.push(re);
+            }
+            "--io-limit" => {
+                let limit = args.next().context("--io-limit needs a value")?;
+                let mb: f64 = limit.parse().context("--io-limit expects MB per second")?;
+                if !(mb > 0.0 && mb.is_finite()) {
+                    bail!("--io-limit must be above 0");
+                }
+                options.io_limit = Some((mb * 1_000_000.0) as u64);
+            }
+            "--nice" => options.nice = true,
+            "--duplicates" => options.duplicates = true,
+            "--sizes" => options.sizes = true,
+            "--profile" => options.profile = true,
+            "--max-files" => {
+                let n = args.next().context("--max-files needs a value")?;
+                let n: usize = n.parse().context("--max-files expects a number")?;
+                options.max_files = (n > 0).then_some(n);
+            }
+            "--order" => {
+                let order = args.next().context("--order needs a value")?;
+                options.order = order.parse().map_err(|e| anyhow!("--order: {}", e))?;
+            }
+            "--type" => {
+                let kind = args.next().context("--type needs a value")?;
+                options.type_filter = Some(kind.parse().map_err(|e| anyhow!("--type: {}", e))?);
+            }
+            "--tab-width" => {
+                let width = args.next().context("--tab-width needs a value")?;
+                options.tab_width = width.parse().context("--tab-width expects a number")?;
+            }
+            "--path-style" => {
+                let style = args.next().context("--path-style needs a value")?;
+                options.path_style = style.parse().map_err(|e| anyhow!("--path-style: {}", e))?;
+            }
+            "--icons" => {
+                let set = args.next().context("--icons needs a value")?;
+                options.icons = set.parse().map_err(|e| anyhow!("--icons: {}", e))?;
+            }
+            "--log-level" => {
+                let level = args.next().context("--log-level needs a value")?;
+                options.log_level = level
+                    .parse()
+                    .map_err(|_| anyhow!("--log-level: unknown level {}", level))?;
+            }
+            "--no-log" => options.log_level = LevelFilter::Off,
+            "--bookmarks" => {
+                let src = args.next().context("--bookmarks needs a value")?;
+                for rel in read_paths(&src)? {
+                    options.session.bookmarks.insert(rel);
+                }
+            }
+            "--session" => {
+                let file = args.next().context("--session needs a value")?;
+                let mut session = Session::open(Path::new(&file))
+                    .with_context(|| format!("cannot read {}", file))?;
+                session.merge(std::mem::take(&mut options.session));
+                options.session = session;
+            }
+            "--log-file" => {
+                let file = args.next().context("--log-file needs a value")?;
+                options.log_file = Some(PathBuf::from(file));
+            }
+            "--preset" => bail!("--preset only works on the command line or in the environment"),
+            _ if arg.starts_with('-') => bail!("unknown option {}\n\n{}", arg, USAGE),
+            _ => dirs.push(arg),
+        }
+    }
+    if !ignore_lines.is_empty() {
+        let set = RegexSet::new(&ignore_lines).context("--ignore-matching-lines")?;
+        options.ignore_lines = Some(set);
+    }
+    if options.resume && options.no_cache {
+        bail!("--resume reads the checkpoint kept in the cache directory, drop --no-cache");
+    }
+    if let Some(command) = compare_cmd {
+        options.compare_rules.push(CompareRule {
+            glob: Pattern::new("*")?,
+            command,
+        });
+    }
+    if !pairs.is_empty() {
+        // more names are a subcommand like sync, which works on one pair
+        if dirs.len() > 2 || !in_tui(&options) {
+            bail!("--pair and --pairs-from only open tabs in the TUI");
+        }
+        // without folders on the command line the first pair takes their place
+        if dirs.is_empty() && oci.is_none() {
+            let (old, new) = pairs.remove(0);
+            dirs = vec![old, new];
+        }
+        options.pairs = pairs
+            .into_iter()
+            .map(|(old, new)| Ok((normalize_spec(old)?, normalize_spec(new)?)))
+            .collect::<Result<_>>()?;
+    }
+    let is_sync = dirs.len() == 3 && dirs[0] == "sync";
+    if !is_sync && (delete || backup_dir.is_some() || dry_run) {
+        bail!("--delete, --backup-dir and --dry-run only work with sync");
+    }
+    // `manifest` is the older name of a snapshot without contents
+    if dirs.len() == 3 && (dirs[0] == "snapshot" || dirs[0] == "manifest") {
+        let out = PathBuf::from(dirs.pop().unwrap());
+        let dir = normalize_dir(dirs.pop().unwrap())?;
+        options.command = Command::Snapshot { dir, out, contents };
+        return Ok(options);
+    }
+    // the destination is the old side, rewritten to match the source
+    if is_sync {
+        let dst = dirs.pop().unwrap();
+        let src = dirs.pop().unwrap();
+        if !source::is_local(&src) || !source::is_local(&dst) {
+            bail!("sync needs two local folders");
+        }
+        // a missing destination is created when the sync runs
+        options.old_dir = if Path::new(&dst).exists() {
+            normalize_dir(dst)?
+        } else {
+            std::path::absolute(&dst)
+                .with_context(|| format!("cannot resolve {}", dst))?
+                .to_string_lossy()
+                .into_owned()
+        };
+        options.new_dir = normalize_dir(src)?;
+        options.apply_to = ApplyDirection::NewToOld;
+        options.command = Command::Sync {
+            delete,
+            backup_dir,
+            dry_run,
+        };
+        return Ok(options);
+    }
+    if dirs.len() == 2 && dirs[0] == "serve" {
+        if options.token.as_deref().unwrap_or("").is_empty() {
+            bail!("serve needs --token (or DIFF_FOLDERS_TOKEN) for clients to send");
+        }
+        options.command = Command::Serve {
+            dir: normalize_dir(dirs.pop().unwrap())?,
+            listen: listen.unwrap_or_else(|| crate::agent::DEFAULT_LISTEN.to_string()),
+        };
+        return Ok(options);
+    }
+    if dirs.len() == 4 && dirs[0] == "show" {
+        let rel = PathBuf::from(dirs.pop().unwrap());
+        options.new_dir = normalize_spec(dirs.pop().unwrap())?;
+        options.old_dir = normalize_spec(dirs.pop().unwrap())?;
+        options.command = Command::Show { rel };
+        return Ok(options);
+    }
+    // the image is the tree that was built, checked against the expected one
+    if let Some(image) = oci {
+        if dirs.len() != 1 {
+            bail!(USAGE);
+        }
+        options.new_dir = format!("oci:{}", image);
+        options.old_dir = normalize_spec(dirs.pop().unwrap())?;
+        return Ok(options);
+    }
+    // the start screen stands in for the folders when it has pairs to offer
+    if dirs.is_empty() && in_tui(&options) {
+        let recent = recent::load_from(&recent::default_file()).unwrap_or_else(|e| {
+            log::warn!("read the recent pairs failed: {}", e);
+            Vec::new()
+        });
+        if !config.favorites.is_empty() || !recent.is_empty() {
+            options.command = Command::Launch {
+                favorites: config.favorites,
+                recent,
+            };
+            return Ok(options);
+        }
+    }
+    if dirs.len() != 2 {
+        bail!(USAGE);
+    }
+    let new = dirs.pop().unwrap();
+    let old = dirs.pop().unwrap();
+    options.set_pair(old, new)?;
+    Ok(options)
+}
+
+impl Options {
+    /// Whether these options start the TUI, as opposed to a subcommand or an
+    /// output that exits.
+    pub fn is_tui(&self) -> bool {
+        matches!(self.command, Command::Compare | Command::Launch { .. }) && in_tui(self)
+    }
+
+    /// Compares `old` with `new` in the TUI, as if they were named on the
+    /// command line, e.g. after picking them on the start screen.
+    pub fn set_pair(&mut self, old: String, new: String) -> Result<()> {
+        self.old_dir = normalize_spec(old)?;
+        self.new_dir = normalize_spec(new)?;
+        self.command = Command::Compare;
+        Ok(())
+    }
+}
+
+/// The command line with every layer of settings in front of it, weakest
+/// first: the `[defaults]` section of the config file, the profile named by
+/// `--preset`, then `DIFF_FOLDERS_*` variables from `env`. Options that take
+/// one value keep the last one given, so the command line wins.
+fn layered_args(
+    cli: Vec<String>,
+    config: &Config,
+    env: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<String>> {
+    let mut env: Vec<(String, String)> = env
+        .filter_map(|(key, value)| {
+            let option = key
+                .strip_prefix(ENV_PREFIX)?
+                .to_lowercase()
+                .replace('_', "-");
+            // other programs' variables may share the prefix
+            if !FLAGS.contains(&option.as_str()) && !VALUED.contains(&option.as_str()) {
+                eprintln!("{} is not an option, ignored", key);
+                return None;
+            }
+            Some((option, value))
+        })
+        .collect();
+    env.sort();
+    let mut args = as_args(&config.defaults)?;
+    let mut rest = as_args(&env)?;
+    rest.extend(cli);
+    // the last --preset wins, so one on the command line beats the environment
+    let mut preset = None;
+    while let Some(at) = rest.iter().position(|a| a == "--preset") {
+        preset = Some(rest.get(at + 1).context("--preset needs a value")?.clone());
+        rest.drain(at..at + 2);
+    }
+    if let Some(name) = preset {
+        match config.profiles.get(&name) {
+            Some(settings) => args.extend(as_args(settings)?),
+            None => {
+                let mut known: Vec<_> = config.profiles.keys().map(String::as_str).collect();
+                known.sort();
+                bail!(
+                    "--preset: no profile {} in the config file (known: {})",
+                    name,
+                    known.join(", ")
+                );
+            }
+        }
+    }
+    args.extend(rest);
+    Ok(args)
+}
+
+/// Whether the options end in the TUI rather than in one of the outputs that
+/// compare a single pair and exit.
+fn in_tui(options: &Options) -> bool {
+    options.output == Output::Tui
+        && !(options.sync_plan
+            || options.brief
+            || options.merge_out.is_some()
+            || options.fail_on_diff
+            || options.on_change.is_some()
+            || options.duplicates
+            || options.sizes
+            || options.profile)
+}
+
+/// Reads `--pairs-from`: an `old new` pair per line, split at a tab when
+/// there is one and at the first run of spaces otherwise. Blank lines and
+/// `#` comments are skipped, and local paths are relative to the file.
+fn read_pairs(file: &Path) -> Result<Vec<(String, String)>> {
+    let text =
+        fs::read_to_string(file).with_context(|| format!("cannot read {}", file.display()))?;
+    let base = file.parent().unwrap_or(Path::new(""));
+    let resolve = |spec: &str| {
+        if source::is_local(spec) {
+            base.join(spec).to_string_lossy().into_owned()
+        } else {
+            spec.to_string()
+        }
+    };
+    let mut pairs = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let pair = line
+            .split_once('\t')
+            .or_else(|| line.split_once(char::is_whitespace))
+            .map(|(old, new)| (old.trim(), new.trim()))
+            .filter(|(old, new)| !old.is_empty() && !new.is_empty());
+        match pair {
+            Some((old, new)) => pairs.push((resolve(old), resolve(new))),
+            None => bail!(
+                "{}:{}: expected two folders, got {:?}",
+                file.display(),
+                n + 1,
+                line
+            ),
+        }
+    }
+    Ok(pairs)
+}
+
+/// A similarity from 0 to 1.
+fn fraction(flag: &str, value: &str) -> Result<f32> {
+    match value.parse::<f32>() {
+        Ok(n) if (0.0..=1.0).contains(&n) => Ok(n),
+        _ => bail!("{} expects a number from 0 to 1, got {}", flag, value),
+    }
+}
+
+/// Turns `option = value` settings into command line arguments. For a flag
+/// `true` or `1` stands for the bare flag and `false`, `0` or nothing leaves
+/// it out; any other option gets its value as it is.
+fn as_args(settings: &[(String, String)]) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for (key, value) in settings {
+        if FLAGS.contains(&key.as_str()) {
+            match value.as_str() {
+                "true" | "1" => args.push(format!("--{}", key)),
+                "false" | "0" | "" => {}
+                _ => bail!("{} is a flag, expected true or false, got {:?}", key, value),
+            }
+        } else if VALUED.contains(&key.as_str()) {
+            if value.is_empty() {
+                bail!("{} needs a value", key);
+            }
+            args.extend([format!("--{}", key), value.clone()]);
+        } else {
+            bail!("{:?} is not an option", key);
+        }
+    }
+    Ok(args)
+}
+
+/// Git revisions and remote folders are passed through as they are, see
+/// [`source::open`].
+fn normalize_spec(spec: String) -> Result<String> {
+    if source::is_local(&spec) {
+        normalize_dir(spec)
+    } else {
+        Ok(spec)
+    }
+}
+
+fn normalize_dir(dir: String) -> Result<String> {
+    // canonicalize copes with trailing separators itself; trimming them by hand
+    // would turn a drive root like `C:\` into the drive-relative `C:`
+    let path = Path::new(&dir)
+        .canonicalize()
+        .with_context(|| format!("cannot resolve {}", dir))?;
+    Ok(text::strip_verbatim(path).to_string_lossy().into_owned())
+}
+
+/// Parses `--since`: a duration back from now such as `30m` or `7d`, or a local
+/// date and time, or an RFC 3339 timestamp.
+fn parse_since(when: &str) -> Result<SystemTime> {
+    let unit = match when.chars().last() {
+        Some('s') => Some(1),
+        Some('m') => Some(60),
+        Some('h') => Some(60 * 60),
+        Some('d') => Some(24 * 60 * 60),
+        Some('w') => Some(7 * 24 * 60 * 60),
+        _ => None,
+    };
+    if let Some(unit) = unit {
+        // the unit is a single ASCII letter, so slicing it off is safe
+        if let Ok(n) = when[..when.len() - 1].parse::<u64>() {
+            return SystemTime::now()
+                .checked_sub(Duration::from_secs(n.saturating_mul(unit)))
+                .context("--since reaches before the epoch");
+        }
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(when) {
+        return Ok(time.into());
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(when, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(when, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .with_context(|| format!("--since: cannot read {:?} as a date or duration", when))?;
+    let local = Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .with_context(|| format!("--since: {} does not exist in the local time zone", when))?;
+    Ok(local.into())
+}
+
+/// Reads one relative path per line from `src`, `-` means stdin.
+fn read_paths(src: &str) -> Result<Vec<PathBuf>> {
+    let mut buf = String::new();
+    if src == "-" {
+        io::stdin().read_to_string(&mut buf)?;
+    } else {
+        buf = fs::read_to_string(src).with_context(|| format!("cannot read {}", src))?;
+    }
+    let paths = buf
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|l| PathBuf::from(text::strip_dot_prefix(l)))
+        .collect();
+    Ok(paths)
+}
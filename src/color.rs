@@ -0,0 +1,131 @@
+//! Colors for the plain text outputs, in the palette of the TUI.
+
+use crate::status::StatusItemType;
+use std::io::IsTerminal;
+
+/// The colors outputs use, named after the TUI colors they match.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Color {
+    Red,
+    Green,
+    LightYellow,
+    Magenta,
+    Cyan,
+    White,
+    DarkGray,
+}
+
+#[cfg(feature = "tui")]
+impl From<Color> for tui::style::Color {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Red => tui::style::Color::Red,
+            Color::Green => tui::style::Color::Green,
+            Color::LightYellow => tui::style::Color::LightYellow,
+            Color::Magenta => tui::style::Color::Magenta,
+            Color::Cyan => tui::style::Color::Cyan,
+            Color::White => tui::style::Color::White,
+            Color::DarkGray => tui::style::Color::DarkGray,
+        }
+    }
+}
+
+/// When to color what is printed, `--color`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum ColorChoice {
+    /// when stdout is a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether output to stdout gets colors. `always` beats `NO_COLOR`, like
+    /// any explicit flag should.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("expected auto, always or never, got {}", s)),
+        }
+    }
+}
+
+/// Color of an entry in `state`, in the list and in printed output; `special`
+/// is set for named pipes, sockets and devices. `None` keeps the default.
+pub fn state_color(state: StatusItemType, special: bool) -> Option<Color> {
+    match state {
+        _ if special => Some(Color::Magenta),
+        StatusItemType::Deleted => Some(Color::Red),
+        StatusItemType::Modified => Some(Color::LightYellow),
+        StatusItemType::New => Some(Color::Green),
+        StatusItemType::Normal => None,
+    }
+}
+
+/// `text` wrapped in the ANSI escapes for `color` when `on` is set.
+pub fn paint(text: &str, color: Color, on: bool) -> String {
+    let code = match color {
+        Color::Red => 31,
+        Color::Green => 32,
+        Color::Magenta => 35,
+        Color::Cyan => 36,
+        Color::LightYellow => 93,
+        Color::DarkGray => 90,
+        Color::White => 97,
+    };
+    if on {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Colors a unified diff like the diff window does: removed lines red, added
+/// ones green and hunk headers cyan.
+pub fn paint_diff(diff: &str, on: bool) -> String {
+    if !on {
+        return diff.to_string();
+    }
+    let mut out = String::new();
+    for line in diff.split_inclusive('\n') {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        let color = if text.starts_with("+++") || text.starts_with("---") {
+            Some(Color::White)
+        } else if text.starts_with('+') {
+            Some(Color::Green)
+        } else if text.starts_with('-') {
+            Some(Color::Red)
+        } else if text.starts_with("@@") {
+            Some(Color::Cyan)
+        } else {
+            None
+        };
+        match color {
+            Some(color) => out += &paint(text, color, true),
+            None => out += text,
+        }
+        out += newline;
+    }
+    out
+}
@@ -0,0 +1,244 @@
+use crate::mime;
+use crate::text;
+use crate::throttle::{self, Throttled};
+use memmap2::Mmap;
+use similar::TextDiff;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Most threads comparing files at once; past a few, reads compete for the
+/// same disk.
+const WORKERS: usize = 8;
+
+/// Bytes compared per step, small enough to stop early on a difference near
+/// the start of a large file.
+const CHUNK: usize = 1 << 20;
+
+/// How much of two texts is the same, from 0 for nothing to 1 for equal, by
+/// the lines a diff of both keeps. Binary contents are only similar when
+/// equal.
+pub fn similarity(old: &[u8], new: &[u8]) -> f32 {
+    if old == new {
+        return 1.0;
+    }
+    if mime::is_binary(old) || mime::is_binary(new) {
+        return 0.0;
+    }
+    let (old, new) = (text::decode_lossy(old), text::decode_lossy(new));
+    TextDiff::from_lines(&old, &new).ratio()
+}
+
+/// Checks whether two files hold the same bytes. Sizes are compared first, then
+/// both files are memory mapped and compared chunk by chunk, skipping the
+/// holes the two have in common when they are sparse.
+pub fn same_file(old: &Path, new: &Path) -> io::Result<bool> {
+    let (old, new) = (File::open(old)?, File::open(new)?);
+    let len = old.metadata()?.len();
+    if len != new.metadata()?.len() {
+        return Ok(false);
+    }
+    if len == 0 {
+        return Ok(true);
+    }
+    let ranges = data_ranges(&old, &new, len);
+    // SAFETY: the maps are only read while comparing; a file changed by another
+    // process at the same time can give a wrong answer, like any read would
+    let maps = unsafe { (Mmap::map(&old), Mmap::map(&new)) };
+    match maps {
+        (Ok(old_map), Ok(new_map)) => Ok(ranges.into_iter().all(|r| {
+            let r = r.start as usize..r.end as usize;
+            old_map[r.clone()]
+                .chunks(CHUNK)
+                .zip(new_map[r].chunks(CHUNK))
+                .all(|(a, b)| {
+                    // pages are read from disk as the comparison touches them
+                    throttle::consume(a.len() + b.len());
+                    a == b
+                })
+        })),
+        // some filesystems and special files cannot be mapped
+        _ => {
+            let (mut old, mut new) = (old, new);
+            for r in ranges {
+                old.seek(SeekFrom::Start(r.start))?;
+                new.seek(SeekFrom::Start(r.start))?;
+                let len = r.end - r.start;
+                let (old, new) = (Throttled((&old).take(len)), Throttled((&new).take(len)));
+                if !same_reader(old, new)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Compares many `(key, old, new)` file pairs on a pool of threads while
+/// the caller takes the results in. `done` gets each pair's key, whether the
+/// files are equal and how long finding out took, as pairs finish, and stops
+/// the work by returning `false`, in which case `false` is returned. A new
+/// side that is not a regular file counts as the same.
+pub fn compare_files<K: Send>(
+    pairs: Vec<(K, PathBuf, PathBuf)>,
+    done: &mut impl FnMut(K, io::Result<bool>, Duration) -> bool,
+) -> io::Result<bool> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(WORKERS)
+        .min(pairs.len());
+    let queue = Mutex::new(pairs.into_iter());
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let (queue, stop, tx) = (&queue, &stop, tx.clone());
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let next = queue.lock().unwrap().next();
+                    let (key, old, new) = match next {
+                        Some(pair) => pair,
+                        None => break,
+                    };
+                    let started = Instant::now();
+                    let same = if new.is_file() {
+                        same_file(&old, &new)
+                    } else {
+                        Ok(true)
+                    };
+                    if tx.send((key, same, started.elapsed())).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+        for (key, same, took) in rx {
+            if !done(key, same, took) {
+                // workers finish the pair they are on and take no other
+                stop.store(true, Ordering::Relaxed);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    })
+}
+
+/// Byte ranges holding data in at least one of the files, in order. A hole
+/// reads as zeros, so a range that is a hole in both files is equal without
+/// reading it; VM images and database files can be mostly holes. Files that
+/// are not sparse, or a filesystem that cannot tell, give the whole length.
+fn data_ranges(old: &File, new: &File, len: u64) -> Vec<Range<u64>> {
+    let whole = std::iter::once(0..len).collect();
+    if !is_sparse(old, len) && !is_sparse(new, len) {
+        return whole;
+    }
+    let (mut ranges, new_ranges) = match (extents(old, len), extents(new, len)) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return whole,
+    };
+    ranges.extend(new_ranges);
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match merged.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+
+/// Fewer blocks allocated than the length needs means the file has holes.
+#[cfg(unix)]
+fn is_sparse(file: &File, len: u64) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    file.metadata().is_ok_and(|m| m.blocks() * 512 < len)
+}
+
+#[cfg(not(unix))]
+fn is_sparse(_file: &File, _len: u64) -> bool {
+    false
+}
+
+/// The data extents of `file`, walked with `SEEK_DATA` and `SEEK_HOLE`.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos",
+    target_os = "ios"
+))]
+fn extents(file: &File, len: u64) -> Option<Vec<Range<u64>>> {
+    use std::os::unix::io::AsRawFd;
+    let fd = file.as_raw_fd();
+    let mut extents = Vec::new();
+    let mut pos = 0;
+    while pos < len {
+        // SAFETY: lseek only moves the offset of a descriptor owned by `file`
+        let start = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+        if start < 0 {
+            // ENXIO: only a hole is left up to the end
+            if io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+            return None;
+        }
+        // SAFETY: as above
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return None;
+        }
+        let (start, end) = (start as u64, (end as u64).min(len));
+        extents.push(start..end);
+        pos = end;
+    }
+    // SAFETY: as above; leaves the offset where a plain read would start
+    unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
+    Some(extents)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+fn extents(_file: &File, _len: u64) -> Option<Vec<Range<u64>>> {
+    None
+}
+
+fn same_reader(mut old: impl Read, mut new: impl Read) -> io::Result<bool> {
+    let (mut a, mut b) = (vec![0; CHUNK], vec![0; CHUNK]);
+    loop {
+        let n = read_full(&mut old, &mut a)?;
+        if n != read_full(&mut new, &mut b)? || a[..n] != b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+// `read` may return less than asked for before the end of the file
+fn read_full(r: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..])? {
+            0 => break,
+            m => n += m,
+        }
+    }
+    Ok(n)
+}
@@ -0,0 +1,299 @@
+use crate::format::Format;
+use crate::text::PathStyle;
+use anyhow::{anyhow, bail, Context, Result};
+use glob::Pattern;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Name of the config file inside [`config_dir`].
+pub const CONFIG_FILE: &str = "config";
+
+/// `$XDG_CONFIG_HOME/diff-folders` or `~/.config/diff-folders`.
+pub fn config_dir() -> PathBuf {
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute());
+    let base = match (xdg, directories::BaseDirs::new()) {
+        (Some(xdg), _) => xdg,
+        (None, Some(dirs)) => dirs.home_dir().join(".config"),
+        (None, None) => std::env::temp_dir(),
+    };
+    base.join("diff-folders")
+}
+
+/// Settings read from the config file, an INI-like list of sections:
+///
+/// ```text
+/// # sqlite files are equal when sqldiff finds nothing
+/// [compare *.sqlite]
+/// command = test -z "$(sqldiff {old} {new})"
+///
+/// [format]
+/// sizes = si
+/// dates = relative
+///
+/// # options for every run, `true` for a bare flag
+/// [defaults]
+/// max-files = 500000
+///
+/// # options for `--preset release-audit`
+/// [profile release-audit]
+/// type = binary
+/// hidden = true
+///
+/// # offered on the start screen when run without folders
+/// [favorite staging]
+/// old = /srv/prod/site
+/// new = /srv/staging/site
+///
+/// # rewritten when the TUI exits, see [`Layout`]
+/// [layout]
+/// split = 60
+/// wrap = false
+/// ```
+#[derive(Default)]
+pub struct Config {
+    pub compare: Vec<CompareRule>,
+    pub format: Format,
+    pub layout: Layout,
+    /// command line options applied to every run
+    pub defaults: Vec<(String, String)>,
+    /// named sets of command line options, by name
+    pub profiles: HashMap<String, Vec<(String, String)>>,
+    /// pairs of trees to pick from on the start screen, in file order
+    pub favorites: Vec<Favorite>,
+}
+
+/// A pair of trees named in a `[favorite name]` section.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Favorite {
+    pub name: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Files matching `glob` are compared by running `command`, see
+/// [`crate::external::same_by_command`].
+#[derive(Clone, Debug)]
+pub struct CompareRule {
+    pub glob: Pattern,
+    pub command: String,
+}
+
+impl CompareRule {
+    /// Patterns without a `/` match the file name anywhere in the tree, like
+    /// gitignore rules.
+    pub fn matches(&self, rel: &Path) -> bool {
+        if self.glob.as_str().contains('/') {
+            self.glob.matches_path(rel)
+        } else {
+            rel.file_name()
+                .is_some_and(|name| self.glob.matches(&name.to_string_lossy()))
+        }
+    }
+}
+
+/// How the TUI was laid out when it was last closed, kept in the `[layout]`
+/// section so the next run starts the same way. Unset fields keep the
+/// defaults.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Layout {
+    /// percentage of the width given to the focused pane
+    pub split: Option<u16>,
+    /// modified files side by side instead of a unified diff
+    pub columns: Option<bool>,
+    pub wrap: Option<bool>,
+    pub whitespace: Option<bool>,
+    /// the log viewer below the two windows
+    pub log: Option<bool>,
+    /// the list labels, which `--path-style` overrides
+    pub path_style: Option<PathStyle>,
+}
+
+impl Layout {
+    /// Writes the layout to the config file in [`config_dir`].
+    pub fn save(&self) -> Result<()> {
+        let dir = config_dir();
+        fs::create_dir_all(&dir).with_context(|| format!("cannot create {}", dir.display()))?;
+        self.save_to(&dir.join(CONFIG_FILE))
+    }
+
+    /// Replaces the `[layout]` section of `file`, keeping the rest of it as
+    /// it was, comments included.
+    pub fn save_to(&self, file: &Path) -> Result<()> {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("cannot read {}", file.display())),
+        };
+        let mut out = String::new();
+        let mut in_layout = false;
+        for line in text.lines() {
+            if let Some(header) = line
+                .trim()
+                .strip_prefix('[')
+                .and_then(|l| l.strip_suffix(']'))
+            {
+                in_layout = header.trim() == "layout";
+            }
+            if !in_layout {
+                out += line;
+                out.push('\n');
+            }
+        }
+        let mut out = out.trim_end().to_string();
+        if !out.is_empty() {
+            out += "\n\n";
+        }
+        out += "[layout]\n# written when diff-folders exits, --no-save-prefs leaves it alone\n";
+        let bools = [
+            ("columns", self.columns),
+            ("wrap", self.wrap),
+            ("whitespace", self.whitespace),
+            ("log", self.log),
+        ];
+        if let Some(split) = self.split {
+            out += &format!("split = {}\n", split);
+        }
+        for (key, value) in bools {
+            if let Some(value) = value {
+                out += &format!("{} = {}\n", key, value);
+            }
+        }
+        if let Some(style) = self.path_style {
+            out += &format!("path-style = {}\n", style.name());
+        }
+        fs::write(file, out).with_context(|| format!("cannot write {}", file.display()))
+    }
+}
+
+impl Config {
+    /// Reads the config file in [`config_dir`]; a missing file is an empty
+    /// config.
+    pub fn load() -> Result<Self> {
+        Self::load_from(&config_dir().join(CONFIG_FILE))
+    }
+
+    pub fn load_from(file: &Path) -> Result<Self> {
+        match fs::read_to_string(file) {
+            Ok(text) => Self::parse(&text).with_context(|| format!("in {}", file.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e).with_context(|| format!("cannot read {}", file.display())),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut config = Config::default();
+        // the section the following keys belong to
+        let mut section: Option<(String, String)> = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let header = header.trim();
+                // `[profile.name]` is read like `[profile name]`
+                let (kind, arg) = match header.split_once(' ') {
+                    Some(split) => split,
+                    None => header.split_once('.').unwrap_or((header, "")),
+                };
+                section = Some((kind.to_string(), arg.trim().to_string()));
+                if kind == "profile" {
+                    if arg.trim().is_empty() {
+                        bail!("line {}: profile needs a name", i + 1);
+                    }
+                    config.profiles.entry(arg.trim().to_string()).or_default();
+                }
+                if kind == "favorite" {
+                    config.favorites.push(Favorite {
+                        name: arg.trim().to_string(),
+                        ..Favorite::default()
+                    });
+                }
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => bail!("line {}: expected key = value", i + 1),
+            };
+            match (section.as_ref(), key) {
+                (Some((kind, glob)), "command") if kind == "compare" => {
+                    let glob = Pattern::new(glob)
+                        .with_context(|| format!("line {}: bad glob {:?}", i + 1, glob))?;
+                    config.compare.push(CompareRule {
+                        glob,
+                        command: value.to_string(),
+                    });
+                }
+                (Some((kind, _)), "sizes") if kind == "format" => {
+                    config.format.sizes = value
+                        .parse()
+                        .map_err(|e| anyhow!("line {}: sizes: {}", i + 1, e))?;
+                }
+                (Some((kind, _)), "dates") if kind == "format" => {
+                    config.format.dates = value
+                        .parse()
+                        .map_err(|e| anyhow!("line {}: dates: {}", i + 1, e))?;
+                }
+                (Some((kind, _)), _) if kind == "layout" => {
+                    let layout = &mut config.layout;
+                    let flag = || -> Result<Option<bool>> {
+                        match value {
+                            "true" => Ok(Some(true)),
+                            "false" => Ok(Some(false)),
+                            _ => bail!("line {}: {}: expected true or false", i + 1, key),
+                        }
+                    };
+                    match key {
+                        "split" => {
+                            layout.split = Some(value.parse().with_context(|| {
+                                format!("line {}: split expects a percentage", i + 1)
+                            })?)
+                        }
+                        "columns" => layout.columns = flag()?,
+                        "wrap" => layout.wrap = flag()?,
+                        "whitespace" => layout.whitespace = flag()?,
+                        "log" => layout.log = flag()?,
+                        "path-style" => {
+                            layout.path_style = Some(
+                                value
+                                    .parse()
+                                    .map_err(|e| anyhow!("line {}: path-style: {}", i + 1, e))?,
+                            )
+                        }
+                        _ => log::warn!("config line {}: unknown setting {}", i + 1, key),
+                    }
+                }
+                (Some((kind, _)), _) if kind == "defaults" => {
+                    config.defaults.push((key.to_string(), value.to_string()));
+                }
+                (Some((kind, _)), "old" | "new") if kind == "favorite" => {
+                    // the section pushed the favorite its keys belong to
+                    if let Some(favorite) = config.favorites.last_mut() {
+                        match key {
+                            "old" => favorite.old = value.to_string(),
+                            _ => favorite.new = value.to_string(),
+                        }
+                    }
+                }
+                (Some((kind, name)), _) if kind == "profile" => {
+                    let profile = config.profiles.entry(name.clone()).or_default();
+                    profile.push((key.to_string(), value.to_string()));
+                }
+                _ => log::warn!("config line {}: unknown setting {}", i + 1, key),
+            }
+        }
+        if let Some(favorite) = config
+            .favorites
+            .iter()
+            .find(|f| f.old.is_empty() || f.new.is_empty())
+        {
+            bail!("favorite {:?} needs both old and new", favorite.name);
+        }
+        Ok(config)
+    }
+}
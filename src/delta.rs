@@ -0,0 +1,263 @@
+//! Rsync style delta transfer, so that a remote file close to a local one is
+//! fetched as the few blocks that differ. The side holding the local copy
+//! sends a [`Signature`] of it: a weak rolling sum and an MD5 for every block.
+//! The side holding the file slides a window over it, looking each position's
+//! rolling sum up among the blocks, and answers with the [`Op`]s that rebuild
+//! it: runs of blocks the local copy already has and the bytes in between.
+
+use md5::{Digest, Md5};
+use std::{collections::HashMap, io};
+
+/// Blocks are never smaller than this, a sum costs 20 bytes to send.
+pub const MIN_BLOCK: usize = 700;
+
+/// Blocks a signature holds at most, bigger files get bigger blocks.
+const MAX_BLOCKS: usize = 1 << 19;
+
+/// Below this, a local copy is not worth a signature: the whole file is
+/// fetched about as fast.
+pub const MIN_BASIS: usize = 16 * 1024;
+
+/// Bytes of a signature's entry for one block: the weak sum, then the MD5.
+const SUM_LEN: usize = 4 + 16;
+
+/// What the local copy holds, block by block.
+pub struct Signature {
+    pub block_size: usize,
+    // weak and strong sum of every whole block; the short tail of the local
+    // copy is left out and comes over as bytes if it is still there
+    blocks: Vec<(u32, [u8; 16])>,
+}
+
+impl Signature {
+    /// Blocks about the square root of `basis` long, like rsync.
+    pub fn of(basis: &[u8]) -> Self {
+        let root = (basis.len() as f64).sqrt() as usize;
+        let block_size = root
+            .next_multiple_of(8)
+            .max(MIN_BLOCK)
+            .max(basis.len().div_ceil(MAX_BLOCKS));
+        let blocks = basis
+            .chunks_exact(block_size)
+            .map(|block| (Rolling::new(block).sum(), strong_sum(block)))
+            .collect();
+        Signature { block_size, blocks }
+    }
+
+    /// The block size as a big endian `u32`, then the sums of every block.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + self.blocks.len() * SUM_LEN);
+        out.extend((self.block_size as u32).to_be_bytes());
+        for (weak, strong) in &self.blocks {
+            out.extend(weak.to_be_bytes());
+            out.extend(strong);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let (size, sums) = bytes
+            .split_first_chunk::<4>()
+            .ok_or_else(|| invalid("truncated signature"))?;
+        let block_size = u32::from_be_bytes(*size) as usize;
+        if block_size < MIN_BLOCK || sums.len() % SUM_LEN != 0 {
+            return Err(invalid("malformed signature"));
+        }
+        let blocks = sums
+            .chunks_exact(SUM_LEN)
+            .map(|sum| {
+                let (weak, strong) = sum.split_at(4);
+                (
+                    u32::from_be_bytes(weak.try_into().unwrap()),
+                    strong.try_into().unwrap(),
+                )
+            })
+            .collect();
+        Ok(Signature { block_size, blocks })
+    }
+}
+
+/// A step in rebuilding a file from the local copy.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Op {
+    /// `count` blocks of the local copy, from block number `block` on.
+    Copy { block: usize, count: usize },
+    /// Bytes the local copy does not have.
+    Literal(Vec<u8>),
+}
+
+/// The steps that turn the copy `signature` was made of into `data`.
+pub fn delta(signature: &Signature, data: &[u8]) -> Vec<Op> {
+    let n = signature.block_size;
+    let mut by_weak: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, (weak, _)) in signature.blocks.iter().enumerate() {
+        by_weak.entry(*weak).or_default().push(i);
+    }
+    let mut ops = Vec::new();
+    if by_weak.is_empty() || data.len() < n {
+        push_literal(&mut ops, data);
+        return ops;
+    }
+    let (mut at, mut pending) = (0, 0);
+    let mut rolling = Rolling::new(&data[..n]);
+    loop {
+        let found = by_weak.get(&rolling.sum()).and_then(|blocks| {
+            let strong = strong_sum(&data[at..at + n]);
+            blocks
+                .iter()
+                .find(|b| signature.blocks[**b].1 == strong)
+                .copied()
+        });
+        if let Some(block) = found {
+            push_literal(&mut ops, &data[pending..at]);
+            match ops.last_mut() {
+                Some(Op::Copy {
+                    block: first,
+                    count,
+                }) if *first + *count == block => *count += 1,
+                _ => ops.push(Op::Copy { block, count: 1 }),
+            }
+            at += n;
+            pending = at;
+            if at + n > data.len() {
+                break;
+            }
+            rolling = Rolling::new(&data[at..at + n]);
+        } else {
+            if at + n == data.len() {
+                break;
+            }
+            rolling.roll(data[at], data[at + n], n);
+            at += 1;
+        }
+    }
+    push_literal(&mut ops, &data[pending..]);
+    ops
+}
+
+/// Rebuilds a file from the local copy `basis` and the steps of [`delta`].
+pub fn apply(basis: &[u8], block_size: usize, ops: &[Op]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for op in ops {
+        match op {
+            Op::Copy { block, count } => {
+                let range = block
+                    .checked_mul(block_size)
+                    .zip(count.checked_mul(block_size))
+                    .and_then(|(start, len)| Some(start..start.checked_add(len)?));
+                let blocks = range
+                    .and_then(|range| basis.get(range))
+                    .ok_or_else(|| invalid("block past the end of the local copy"))?;
+                out.extend_from_slice(blocks);
+            }
+            Op::Literal(bytes) => out.extend_from_slice(bytes),
+        }
+    }
+    Ok(out)
+}
+
+/// A tag byte per step: 0 then the first block and the count as big endian
+/// `u64`s, or 1 then the length as a `u64` and the bytes.
+pub fn encode(ops: &[Op]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for op in ops {
+        match op {
+            Op::Copy { block, count } => {
+                out.push(0);
+                out.extend((*block as u64).to_be_bytes());
+                out.extend((*count as u64).to_be_bytes());
+            }
+            Op::Literal(bytes) => {
+                out.push(1);
+                out.extend((bytes.len() as u64).to_be_bytes());
+                out.extend(bytes);
+            }
+        }
+    }
+    out
+}
+
+pub fn decode(mut bytes: &[u8]) -> io::Result<Vec<Op>> {
+    let mut ops = Vec::new();
+    while let Some((tag, rest)) = bytes.split_first() {
+        let (first, rest) = take_u64(rest)?;
+        match tag {
+            0 => {
+                let (count, rest) = take_u64(rest)?;
+                ops.push(Op::Copy {
+                    block: first as usize,
+                    count: count as usize,
+                });
+                bytes = rest;
+            }
+            1 => {
+                if rest.len() < first as usize {
+                    return Err(invalid("truncated delta"));
+                }
+                let (literal, rest) = rest.split_at(first as usize);
+                ops.push(Op::Literal(literal.to_vec()));
+                bytes = rest;
+            }
+            _ => return Err(invalid("malformed delta")),
+        }
+    }
+    Ok(ops)
+}
+
+fn take_u64(bytes: &[u8]) -> io::Result<(u64, &[u8])> {
+    let (value, rest) = bytes
+        .split_first_chunk::<8>()
+        .ok_or_else(|| invalid("truncated delta"))?;
+    Ok((u64::from_be_bytes(*value), rest))
+}
+
+fn push_literal(ops: &mut Vec<Op>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        ops.push(Op::Literal(bytes.to_vec()));
+    }
+}
+
+fn strong_sum(block: &[u8]) -> [u8; 16] {
+    let mut sum = [0; 16];
+    sum.copy_from_slice(&Md5::digest(block));
+    sum
+}
+
+/// The weak sum of rsync: cheap to move along by one byte, so every position
+/// of the file can be looked up.
+struct Rolling {
+    a: u32,
+    b: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Self {
+        let (mut a, mut b) = (0u32, 0u32);
+        for (i, x) in window.iter().enumerate() {
+            a = a.wrapping_add(u32::from(*x));
+            b = b.wrapping_add(((window.len() - i) as u32).wrapping_mul(u32::from(*x)));
+        }
+        Rolling { a, b }
+    }
+
+    /// Moves a window of `len` bytes one byte on, dropping `out` and taking
+    /// `into`.
+    fn roll(&mut self, out: u8, into: u8, len: usize) {
+        self.a = self
+            .a
+            .wrapping_sub(u32::from(out))
+            .wrapping_add(u32::from(into));
+        self.b = self
+            .b
+            .wrapping_sub((len as u32).wrapping_mul(u32::from(out)))
+            .wrapping_add(self.a);
+    }
+
+    fn sum(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,74 @@
+use crate::cache::Cache;
+use crate::source::Source;
+use crate::status::Entry;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Files from either tree holding the same contents under different paths.
+pub struct Duplicate {
+    pub size: u64,
+    pub hash: String,
+    /// entry paths, old tree first
+    pub files: Vec<PathBuf>,
+}
+
+/// A file of one side: (side, relative path, entry) with side 0 for the old tree.
+type Located<'a> = (usize, &'a Path, &'a Entry);
+
+/// Indexes the files of both trees by content and returns every group that is
+/// found under more than one relative path, largest files first.
+///
+/// Only files sharing a size with another file are hashed, so trees of unique
+/// sizes cost no reads at all.
+pub fn find<S: Source>(
+    sides: [(&S, &HashMap<PathBuf, Entry>); 2],
+    cache: &mut Cache,
+) -> Vec<Duplicate> {
+    // (side, source, rel, entry) with side 0 for the old tree
+    let mut by_size: HashMap<u64, Vec<(usize, &S, &Path, &Entry)>> = HashMap::new();
+    for (side, (source, files)) in sides.into_iter().enumerate() {
+        for (rel, entry) in files {
+            // empty files are all alike and not worth reporting
+            if !entry.is_dir() && entry.special.is_none() && entry.size > 0 {
+                by_size
+                    .entry(entry.size)
+                    .or_default()
+                    .push((side, source, rel, entry));
+            }
+        }
+    }
+    let mut groups: HashMap<(u64, String), Vec<Located>> = HashMap::new();
+    for (size, files) in by_size {
+        if files.len() < 2 {
+            continue;
+        }
+        for (side, source, rel, entry) in files {
+            match cache.hash(entry, || source.hash(rel)) {
+                Ok(hash) => groups
+                    .entry((size, hash))
+                    .or_default()
+                    .push((side, rel, entry)),
+                Err(e) => log::warn!("skip {}: {}", entry.path().display(), e),
+            }
+        }
+    }
+    let mut dupes: Vec<Duplicate> = groups
+        .into_iter()
+        .filter(|(_, files)| files.iter().any(|(_, rel, _)| *rel != files[0].1))
+        .map(|((size, hash), mut files)| {
+            files.sort_by_key(|(side, rel, _)| (*side, *rel));
+            Duplicate {
+                size,
+                hash,
+                files: files
+                    .iter()
+                    .map(|(_, _, e)| e.path().to_path_buf())
+                    .collect(),
+            }
+        })
+        .collect();
+    dupes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.files.cmp(&b.files)));
+    dupes
+}
@@ -0,0 +1,1141 @@
+//! The filesystem side of a comparison: listing both trees, deciding which
+//! entries differ and reading the two sides of a file, with no terminal
+//! involved, so the same engine serves the TUI, the headless modes and
+//! library users.
+
+#[cfg(feature = "async")]
+use crate::async_io::compare_files;
+use crate::cache::Cache;
+use crate::checkpoint::Checkpoint;
+use crate::cli::Options;
+use crate::compare;
+#[cfg(not(feature = "async"))]
+use crate::compare::compare_files;
+use crate::config::CompareRule;
+use crate::dupes::{self, Duplicate};
+use crate::external;
+use crate::filter::{WalkFilter, IGNORE_FILE};
+use crate::merge;
+use crate::mime;
+use crate::model::{self, Comparison, Order};
+use crate::ops::ApplyDirection;
+use crate::patch;
+use crate::profile::Timing;
+use crate::renderer::Renderers;
+use crate::sizes::{self, DirSize};
+use crate::source::{self, Source};
+use crate::status::{Entry, FolderStatefulList};
+use crate::summary::Summary;
+use crate::sync::Plan;
+use crate::text;
+use regex::RegexSet;
+use similar::TextDiff;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Files larger than this are only paired as moves when equal and are never
+/// split by `--modify-threshold`, their lines are not diffed.
+const SIMILAR_MAX: u64 = 1 << 20;
+
+/// How far a comparison is, handed to its `progress` callback.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Progress {
+    pub percent: u16,
+    /// time left reading file contents, in whole seconds, estimated from how
+    /// fast the bytes read so far went; `None` until there is enough to go by
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    pub fn at(percent: u16) -> Self {
+        Progress { percent, eta: None }
+    }
+}
+
+/// The bytes the content comparison has read out of all it has to, for the
+/// gauge from 40 to 80 percent and the time left.
+struct Meter {
+    total: u64,
+    done: u64,
+    started: Instant,
+}
+
+impl Meter {
+    fn new(total: u64) -> Self {
+        Meter {
+            total,
+            done: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Counts the `bytes` of a file that was just compared.
+    fn add(&mut self, bytes: u64) -> Progress {
+        self.done += bytes;
+        let share = match self.total {
+            0 => 1.0,
+            total => (self.done as f64 / total as f64).min(1.0),
+        };
+        let elapsed = self.started.elapsed();
+        // a second of reading is the least to go by
+        let eta = (share > 0.0 && elapsed >= Duration::from_secs(1)).then(|| {
+            let left = elapsed.mul_f64((1.0 - share) / share);
+            Duration::from_secs(left.as_secs())
+        });
+        Progress {
+            percent: 40 + (share * 40.0) as u16,
+            eta,
+        }
+    }
+}
+
+/// Two trees and the settings that decide what counts as a difference.
+pub struct Engine<S: Source = Box<dyn Source>> {
+    pub old: S,
+    pub new: S,
+    // restricts the comparison to these relative paths
+    paths: Option<Vec<PathBuf>>,
+    // nanoseconds since the epoch, entries older than this on both sides are skipped
+    since: Option<u128>,
+    show_hidden: bool,
+    vcs_exclude: bool,
+    // reuse hashes and outcomes from earlier runs
+    use_cache: bool,
+    // where the progress of a scan is kept, `None` for nowhere
+    checkpoint: Option<PathBuf>,
+    // take up what an interrupted scan left in the checkpoint
+    resume: bool,
+    ignore_lines: Option<RegexSet>,
+    // same inode means equal, a different link count means modified
+    hard_links: bool,
+    // folders on both sides holding different entries are modified
+    dir_changes: bool,
+    // deleted and new files at least this similar are paired as moves, 1 for
+    // the same contents only
+    renames: Option<f32>,
+    // modified files less similar than this are a deleted and a new file
+    modify_threshold: Option<f32>,
+    // commands deciding equality for matching paths
+    compare_rules: Vec<CompareRule>,
+    order: Order,
+    // entries a side may hold before its walk stops, `None` for no cap
+    max_files: Option<usize>,
+    // gitignore patterns added on top of the ignore files
+    excludes: Vec<String>,
+    // custom diff window views by glob or MIME type
+    renderers: Renderers,
+}
+
+impl Engine {
+    /// Opens both sides named in `options`, see [`source::open`].
+    pub fn new(options: &Options) -> io::Result<Self> {
+        let old = source::open(&options.old_dir)?;
+        let new = source::open(&options.new_dir)?;
+        Ok(Self::with_sources(old, new, options))
+    }
+}
+
+impl<S: Source> Engine<S> {
+    pub fn with_sources(old: S, new: S, options: &Options) -> Self {
+        let checkpoint = (!options.no_cache)
+            .then(|| Checkpoint::pair_file(&crate::log::cache_dir(), old.root(), new.root()));
+        Engine {
+            old,
+            new,
+            paths: options.paths.clone(),
+            since: options.since.and_then(|t| {
+                let since = t.duration_since(std::time::UNIX_EPOCH).ok()?;
+                Some(since.as_nanos())
+            }),
+            show_hidden: options.hidden,
+            vcs_exclude: !options.no_vcs_exclude,
+            use_cache: !options.no_cache,
+            checkpoint,
+            resume: options.resume,
+            ignore_lines: options.ignore_lines.clone(),
+            hard_links: options.hard_links,
+            dir_changes: options.dir_changes,
+            renames: (options.renames || options.rename_threshold.is_some())
+                .then(|| options.rename_threshold.unwrap_or(1.0)),
+            modify_threshold: options.modify_threshold,
+            compare_rules: options.compare_rules.clone(),
+            order: options.order,
+            max_files: options.max_files,
+            excludes: Vec::new(),
+            renderers: Renderers::default(),
+        }
+    }
+
+    pub fn show_hidden(&self) -> bool {
+        self.show_hidden
+    }
+
+    /// Includes dotfiles from the next comparison on, or leaves them out.
+    pub fn set_show_hidden(&mut self, show_hidden: bool) {
+        self.show_hidden = show_hidden;
+    }
+
+    pub fn max_files(&self) -> Option<usize> {
+        self.max_files
+    }
+
+    /// Changes the cap on entries per side from the next comparison on.
+    pub fn set_max_files(&mut self, max_files: Option<usize>) {
+        self.max_files = max_files;
+    }
+
+    /// Keeps the progress of the next comparisons in `file` instead of under
+    /// the cache directory, or nowhere.
+    pub fn set_checkpoint(&mut self, file: Option<PathBuf>) {
+        self.checkpoint = file;
+    }
+
+    /// Leaves paths matching `pattern`, in gitignore syntax, out of the next
+    /// comparison, as if it were in `.diffignore`.
+    pub fn add_exclude(&mut self, pattern: String) {
+        self.excludes.push(pattern);
+    }
+
+    pub fn renderers(&self) -> &Renderers {
+        &self.renderers
+    }
+
+    /// Where custom views of files in the diff window are registered.
+    pub fn renderers_mut(&mut self) -> &mut Renderers {
+        &mut self.renderers
+    }
+
+    /// The `--ignore-matching-lines` patterns.
+    pub fn ignore_lines(&self) -> Option<&RegexSet> {
+        self.ignore_lines.as_ref()
+    }
+
+    /// Compares both trees. `progress` gets a percentage now and then and
+    /// stops the scan early by returning `false`, which leaves a partial
+    /// result.
+    pub fn compare(&self, progress: &mut impl FnMut(Progress) -> bool) -> io::Result<Comparison> {
+        self.compare_with(progress, &mut |_| {})
+    }
+
+    /// Like [`Engine::compare`], also handing each difference to `found` as
+    /// soon as the scan comes across it. Those are not collapsed yet: every
+    /// entry inside a new or deleted folder comes on its own, in no
+    /// particular order.
+    pub fn compare_with(
+        &self,
+        progress: &mut impl FnMut(Progress) -> bool,
+        found: &mut impl FnMut(&FolderStatefulList),
+    ) -> io::Result<Comparison> {
+        let mut cache = self.cache();
+        let mut checkpoint = match &self.checkpoint {
+            Some(file) => Checkpoint::open(file.clone(), self.resume),
+            None => Checkpoint::disabled(),
+        };
+        let mut comparison = Comparison::default();
+        let is_complete = self.scan(
+            &mut comparison,
+            &mut cache,
+            &mut checkpoint,
+            progress,
+            found,
+        )?;
+        if let Err(e) = cache.save() {
+            log::warn!("save cache failed: {}", e);
+        }
+        // a scan stopped early keeps its checkpoint for --resume
+        if is_complete {
+            checkpoint.finish();
+        }
+        let res = &mut comparison.results;
+        // sum before collapsing so files inside new/deleted folders still count
+        comparison.size_delta = res.iter().map(|i| i.size_delta).sum();
+        if let Some(threshold) = self.renames {
+            self.pair_moves(res, threshold);
+        }
+        model::collapse_dirs(res);
+        self.order.sort(res, |i| self.rel_path(i));
+        comparison.summary = Summary::new(res, |i| self.rel_path(i));
+        comparison.is_partial = !is_complete || comparison.truncated.is_some();
+        progress(Progress::at(100));
+        Ok(comparison)
+    }
+
+    /// Marks each new file holding the same bytes as a deleted one as moved
+    /// from there, and the deleted file as moved to it. Both stay in the
+    /// results, a move still deletes one path and adds another. Only files of
+    /// equal size are hashed; among equal ones the same file name wins. Below
+    /// a `threshold` of 1 the files left over are paired with the most
+    /// similar deleted text file at least that similar.
+    fn pair_moves(&self, res: &mut [FolderStatefulList], threshold: f32) {
+        let is_file = |i: &FolderStatefulList| {
+            !i.entry.is_dir() && i.entry.special.is_none() && i.entry.size > 0
+        };
+        let mut deleted: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, item) in res.iter().enumerate() {
+            if item.state == crate::status::StatusItemType::Deleted && is_file(item) {
+                deleted.entry(item.entry.size).or_default().push(i);
+            }
+        }
+        let hash = |source: &S, item: &FolderStatefulList| {
+            let rel = self.relative(item)?;
+            source
+                .hash(rel)
+                .inspect_err(|e| log::warn!("hash {}: {}", item.entry.path().display(), e))
+                .ok()
+        };
+        let mut old_hashes: HashMap<usize, Option<String>> = HashMap::new();
+        for i in 0..res.len() {
+            if res[i].state != crate::status::StatusItemType::New || !is_file(&res[i]) {
+                continue;
+            }
+            let candidates = match deleted.get_mut(&res[i].entry.size) {
+                Some(candidates) if !candidates.is_empty() => candidates,
+                _ => continue,
+            };
+            let new_hash = match hash(&self.new, &res[i]) {
+                Some(new_hash) => new_hash,
+                None => continue,
+            };
+            // a file split by `--modify-threshold` does not move onto itself
+            let rel = self.rel_path(&res[i]);
+            let same: Vec<usize> = candidates
+                .iter()
+                .copied()
+                .filter(|&j| {
+                    self.rel_path(&res[j]) != rel
+                        && old_hashes
+                            .entry(j)
+                            .or_insert_with(|| hash(&self.old, &res[j]))
+                            .as_ref()
+                            == Some(&new_hash)
+                })
+                .collect();
+            let name = res[i].entry.path().file_name();
+            let j = match same
+                .iter()
+                .find(|&&j| res[j].entry.path().file_name() == name)
+                .or(same.first())
+            {
+                Some(&j) => j,
+                None => continue,
+            };
+            candidates.retain(|&c| c != j);
+            res[i].moved = Some(self.rel_path(&res[j]));
+            res[j].moved = Some(rel);
+        }
+        if threshold >= 1.0 {
+            return;
+        }
+
+        let read = |source: &S, item: &FolderStatefulList| {
+            let rel = self.relative(item)?;
+            let bytes = source
+                .read(rel)
+                .inspect_err(|e| log::warn!("read {}: {}", item.entry.path().display(), e))
+                .ok()?;
+            (!mime::is_binary(&bytes)).then_some(bytes)
+        };
+        let unpaired = |res: &[FolderStatefulList], state| -> Vec<usize> {
+            (0..res.len())
+                .filter(|&i| {
+                    let item = &res[i];
+                    item.state == state
+                        && is_file(item)
+                        && item.moved.is_none()
+                        && item.entry.size <= SIMILAR_MAX
+                })
+                .collect()
+        };
+        let deleted = unpaired(res, crate::status::StatusItemType::Deleted);
+        let mut old_texts: HashMap<usize, Option<Vec<u8>>> = HashMap::new();
+        for i in unpaired(res, crate::status::StatusItemType::New) {
+            let new_text = match read(&self.new, &res[i]) {
+                Some(text) => text,
+                None => continue,
+            };
+            let (rel, name) = (self.rel_path(&res[i]), res[i].entry.path().file_name());
+            // the most similar, then the one with the same name
+            let mut best: Option<(f32, bool, usize)> = None;
+            for &j in &deleted {
+                let (a, b) = (res[i].entry.size, res[j].entry.size);
+                // files that far apart in size cannot be similar enough
+                if res[j].moved.is_some() || (a.min(b) as f32) < threshold * a.max(b) as f32 {
+                    continue;
+                }
+                if self.rel_path(&res[j]) == rel {
+                    continue;
+                }
+                let old_text = match old_texts
+                    .entry(j)
+                    .or_insert_with(|| read(&self.old, &res[j]))
+                {
+                    Some(text) => text,
+                    None => continue,
+                };
+                let score = compare::similarity(old_text, &new_text);
+                let same_name = res[j].entry.path().file_name() == name;
+                let better = match best {
+                    None => true,
+                    Some((s, n, _)) => score > s || (score == s && same_name && !n),
+                };
+                if score >= threshold && better {
+                    best = Some((score, same_name, j));
+                }
+            }
+            if let Some((_, _, j)) = best {
+                res[i].moved = Some(self.rel_path(&res[j]));
+                res[j].moved = Some(rel);
+            }
+        }
+    }
+
+    /// Whether the two sides of the differing text file at `rel` are less
+    /// similar than `--modify-threshold`. Binary files and files past
+    /// [`SIMILAR_MAX`] stay modified.
+    fn is_rewrite(&self, rel: &Path, old_entry: &Entry, entry: &Entry) -> bool {
+        let threshold = match self.modify_threshold {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+        if old_entry.size > SIMILAR_MAX || entry.size > SIMILAR_MAX {
+            return false;
+        }
+        match (self.old.read(rel), self.new.read(rel)) {
+            (Ok(old), Ok(new)) if mime::is_binary(&old) || mime::is_binary(&new) => false,
+            (Ok(old), Ok(new)) => compare::similarity(&old, &new) < threshold,
+            (Err(e), _) | (_, Err(e)) => {
+                log::warn!("read {}: {}", rel.display(), e);
+                false
+            }
+        }
+    }
+
+    /// Second opinion on files whose bytes differ: a `--compare-cmd` or config
+    /// rule matching the path decides, otherwise `--ignore-matching-lines`.
+    /// Failures count as different.
+    fn recheck(&self, rel: &Path) -> bool {
+        let res = match self.compare_rules.iter().find(|r| r.matches(rel)) {
+            Some(rule) => match (self.old.local_path(rel), self.new.local_path(rel)) {
+                (Some(old), Some(new)) => external::same_by_command(&rule.command, &old, &new),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{:?} needs files on the local disk", rule.command),
+                )),
+            },
+            None if self.ignore_lines.is_some() => self.only_ignored_changes(rel),
+            None => Ok(false),
+        };
+        res.unwrap_or_else(|e| {
+            log::warn!("compare {}: {}", rel.display(), e);
+            false
+        })
+    }
+
+    /// Whether every difference of the file at `rel` is in lines matching
+    /// `--ignore-matching-lines`.
+    fn only_ignored_changes(&self, rel: &Path) -> io::Result<bool> {
+        let old = text::decode_lossy(&self.old.read(rel)?);
+        let new = text::decode_lossy(&self.new.read(rel)?);
+        let (significant, _) = patch::partition_hunks(&old, &new, self.ignore_lines.as_ref());
+        Ok(significant.is_empty())
+    }
+
+    fn walk_filter(&self) -> WalkFilter {
+        // ignore files are read through the sources so remote trees have them too
+        let rules: Vec<(PathBuf, String)> = [&self.old, &self.new]
+            .iter()
+            .filter_map(|source| {
+                let bytes = source.read(Path::new(IGNORE_FILE)).ok()?;
+                let file = source.root().join(IGNORE_FILE);
+                Some((file, String::from_utf8_lossy(&bytes).into_owned()))
+            })
+            .collect();
+        let mut rules = rules;
+        if !self.excludes.is_empty() {
+            rules.push((PathBuf::from("excludes"), self.excludes.join("\n")));
+        }
+        WalkFilter::from_rules(&rules)
+            .with_hidden(self.show_hidden)
+            .with_vcs_exclude(self.vcs_exclude)
+            .with_limit(self.max_files)
+    }
+
+    /// Files with the same contents under different paths in either tree,
+    /// see [`dupes::find`].
+    pub fn duplicates(&self) -> io::Result<Vec<Duplicate>> {
+        let (old_files, new_files) = self.list_sides()?;
+        let mut cache = self.cache();
+        let dupes = dupes::find(
+            [(&self.old, &old_files), (&self.new, &new_files)],
+            &mut cache,
+        );
+        if let Err(e) = cache.save() {
+            log::warn!("save cache failed: {}", e);
+        }
+        Ok(dupes)
+    }
+
+    /// The cache of earlier runs on this pair of trees, unless `--no-cache`.
+    fn cache(&self) -> Cache {
+        if !self.use_cache {
+            return Cache::disabled();
+        }
+        let dir = crate::log::cache_dir();
+        Cache::load(Cache::pair_file(&dir, self.old.root(), self.new.root()))
+    }
+
+    /// Recursive directory sizes of both trees, for `--sizes`.
+    pub fn dir_sizes(&self) -> io::Result<Vec<DirSize>> {
+        let (old_files, new_files) = self.list_sides()?;
+        Ok(sizes::compare(&old_files, &new_files))
+    }
+
+    /// Lists both trees in full, or only `paths` when given; more entries than
+    /// `--max-files` on either side is an error.
+    fn list_sides(&self) -> io::Result<(HashMap<PathBuf, Entry>, HashMap<PathBuf, Entry>)> {
+        let filter = self.walk_filter();
+        let sides = match &self.paths {
+            Some(paths) => (
+                self.old.list_paths(paths, &filter)?,
+                self.new.list_paths(paths, &filter)?,
+            ),
+            None => (self.old.list(&filter)?, self.new.list(&filter)?),
+        };
+        if filter.is_full(sides.0.len()) || filter.is_full(sides.1.len()) {
+            return Err(too_many(self.max_files.unwrap_or_default()));
+        }
+        Ok(sides)
+    }
+
+    /// Collects the differences into `comparison.results` and notes a walk cut
+    /// short by `--max-files`. Returns `false` when `progress` asked to stop
+    /// early, leaving the results found so far.
+    fn scan(
+        &self,
+        comparison: &mut Comparison,
+        cache: &mut Cache,
+        checkpoint: &mut Checkpoint,
+        progress: &mut impl FnMut(Progress) -> bool,
+        found: &mut impl FnMut(&FolderStatefulList),
+    ) -> io::Result<bool> {
+        if !progress(Progress::at(10)) {
+            return Ok(false);
+        }
+        let filter = self.walk_filter();
+        let (mut old_files, mut new_files) = match &self.paths {
+            Some(paths) => (
+                self.old.list_paths(paths, &filter)?,
+                self.new.list_paths(paths, &filter)?,
+            ),
+            None => {
+                let old_files = self.old.list(&filter)?;
+                if !progress(Progress::at(20)) {
+                    return Ok(false);
+                }
+                (old_files, self.new.list(&filter)?)
+            }
+        };
+        // a walk cut short cannot tell what its side lacks, so entries only the
+        // other side has are left out rather than listed as new or deleted
+        let (old_full, new_full) = (
+            filter.is_full(old_files.len()),
+            filter.is_full(new_files.len()),
+        );
+        if old_full {
+            new_files.retain(|k, _| old_files.contains_key(k));
+        }
+        if new_full {
+            old_files.retain(|k, _| new_files.contains_key(k));
+        }
+        if old_full || new_full {
+            comparison.truncated = self.max_files;
+        }
+        if self.paths.is_none() && !old_full && !new_full {
+            // both trees listed in full, whatever else the cache holds is stale
+            cache.keep_only(old_files.values().chain(new_files.values()));
+        }
+        // local walks list the root itself under an empty path, which trees
+        // read from archives and images leave out
+        old_files.remove(Path::new(""));
+        new_files.remove(Path::new(""));
+        let res = &mut comparison.results;
+        let mut timings = Vec::new();
+        if let Some(since) = self.since {
+            // unknown mtimes (snapshots, archives, remote trees) count as recent
+            let is_recent =
+                |e: Option<&Entry>| e.is_some_and(|e| e.mtime.is_none_or(|m| m >= since));
+            let recent: HashSet<PathBuf> = old_files
+                .keys()
+                .chain(new_files.keys())
+                .filter(|k| is_recent(old_files.get(*k)) || is_recent(new_files.get(*k)))
+                .cloned()
+                .collect();
+            old_files.retain(|k, _| recent.contains(k));
+            new_files.retain(|k, _| recent.contains(k));
+        }
+        if !progress(Progress::at(30)) {
+            return Ok(false);
+        }
+
+        for (key, entry) in &old_files {
+            if !new_files.contains_key(key) {
+                record(
+                    res,
+                    found,
+                    FolderStatefulList {
+                        entry: entry.clone(),
+                        state: crate::status::StatusItemType::Deleted,
+                        size_delta: -(entry.size as i64),
+                        moved: None,
+                    },
+                );
+            }
+        }
+
+        // files in both trees wait in path order, neighbours one after another,
+        // while the directories and the entries that need no reading are settled
+        let children = self
+            .dir_changes
+            .then(|| (child_names(&old_files), child_names(&new_files)));
+        let mut jobs = Vec::new();
+        for (key, entry) in &new_files {
+            let old_entry = match old_files.get(key) {
+                Some(old_entry) => old_entry,
+                None => {
+                    record(
+                        res,
+                        found,
+                        FolderStatefulList {
+                            entry: entry.clone(),
+                            state: crate::status::StatusItemType::New,
+                            size_delta: entry.size as i64,
+                            moved: None,
+                        },
+                    );
+                    continue;
+                }
+            };
+            if entry.is_dir() && old_entry.is_dir() {
+                // an empty folder is missing from the map, so emptied and
+                // filled ones differ too
+                let changed = children
+                    .as_ref()
+                    .is_some_and(|(old, new)| old.get(key.as_path()) != new.get(key.as_path()));
+                if changed {
+                    record(
+                        res,
+                        found,
+                        FolderStatefulList {
+                            entry: entry.clone(),
+                            state: crate::status::StatusItemType::Modified,
+                            size_delta: 0,
+                            moved: None,
+                        },
+                    );
+                }
+                continue;
+            }
+            if entry.is_dir() || old_entry.is_dir() {
+                continue;
+            }
+            // only the kind of a special file is compared, never its contents
+            if entry.special.is_some() || old_entry.special.is_some() {
+                if entry.special != old_entry.special {
+                    record(
+                        res,
+                        found,
+                        FolderStatefulList {
+                            entry: entry.clone(),
+                            state: crate::status::StatusItemType::Modified,
+                            size_delta: entry.size as i64 - old_entry.size as i64,
+                            moved: None,
+                        },
+                    );
+                }
+                continue;
+            }
+            jobs.push((key, old_entry, entry));
+        }
+        jobs.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut settle = |key: &PathBuf,
+                          old_entry: &Entry,
+                          entry: &Entry,
+                          same: io::Result<bool>,
+                          took: Duration| {
+            // the cache holds byte equality, comparators and ignored lines are checked on top
+            let same = match same {
+                Ok(false) => Ok(self.recheck(key)),
+                same => same,
+            };
+            timings.push(Timing {
+                rel: key.clone(),
+                bytes: entry.size,
+                took,
+            });
+            // the same bytes held by a different number of links is a metadata change
+            let same = match (same, old_entry.inode, entry.inode) {
+                (Ok(true), Some(a), Some(b)) if self.hard_links => Ok(a.links == b.links),
+                (same, _, _) => same,
+            };
+            match same {
+                Ok(true) => {}
+                // too different to be the same file, like a file deleted and
+                // another added under its name
+                Ok(false) if self.is_rewrite(key, old_entry, entry) => {
+                    record(
+                        res,
+                        found,
+                        FolderStatefulList {
+                            entry: old_entry.clone(),
+                            state: crate::status::StatusItemType::Deleted,
+                            size_delta: -(old_entry.size as i64),
+                            moved: None,
+                        },
+                    );
+                    record(
+                        res,
+                        found,
+                        FolderStatefulList {
+                            entry: entry.clone(),
+                            state: crate::status::StatusItemType::New,
+                            size_delta: entry.size as i64,
+                            moved: None,
+                        },
+                    );
+                }
+                Ok(false) => record(
+                    res,
+                    found,
+                    FolderStatefulList {
+                        entry: entry.clone(),
+                        state: crate::status::StatusItemType::Modified,
+                        size_delta: entry.size as i64 - old_entry.size as i64,
+                        moved: None,
+                    },
+                ),
+                Err(e) => log::warn!("skip {}: {}", entry.path().display(), e),
+            }
+        };
+        let mut pool = Vec::new();
+        let mut rest = Vec::new();
+        for (key, old_entry, entry) in jobs {
+            let earlier = cache
+                .outcome(old_entry, entry)
+                .or_else(|| checkpoint.outcome(key, old_entry, entry));
+            let known = match earlier {
+                _ if self.hard_links && is_hard_link(old_entry, entry) => Some(true),
+                // listed sizes that differ settle it without opening either
+                // file; links list with no size and are left to the readers
+                _ if old_entry.hash.is_none()
+                    && entry.hash.is_none()
+                    && old_entry.size > 0
+                    && entry.size > 0
+                    && old_entry.size != entry.size =>
+                {
+                    Some(false)
+                }
+                known => known,
+            };
+            if let Some(same) = known {
+                settle(key, old_entry, entry, Ok(same), Duration::ZERO);
+                continue;
+            }
+            // files both trees hold on the local disk go to the pool of readers
+            let local = match (self.old.local_path(key), self.new.local_path(key)) {
+                (Some(old), Some(new)) if entry.hash.is_none() && old_entry.hash.is_none() => {
+                    Some((old, new))
+                }
+                _ => None,
+            };
+            match local {
+                Some((old, new)) => pool.push((key.clone(), old, new)),
+                None => rest.push((key, old_entry, entry)),
+            }
+        }
+
+        // the gauge follows the bytes to read, and so does the estimate of
+        // the time left
+        let mut meter = Meter::new(
+            pool.iter()
+                .map(|(key, ..)| new_files[key].size)
+                .sum::<u64>()
+                + rest.iter().map(|(_, _, e)| e.size).sum::<u64>(),
+        );
+        let stopped = !compare_files(pool, &mut |key, same, took| {
+            let (old_entry, entry) = (&old_files[&key], &new_files[&key]);
+            if let Ok(same) = same {
+                cache.set_outcome(old_entry, entry, same);
+                checkpoint.record(&key, old_entry, entry, same);
+            }
+            settle(&key, old_entry, entry, same, took);
+            progress(meter.add(entry.size))
+        })?;
+        if stopped {
+            return Ok(false);
+        }
+        // compressed archives are read front to back, in one pass
+        rest.sort_by_key(|(key, ..)| (self.old.position(key), self.new.position(key)));
+        for (key, old_entry, entry) in rest {
+            let started = Instant::now();
+            let same =
+                same_contents(&self.old, &self.new, key, old_entry, entry, cache).inspect(|same| {
+                    cache.set_outcome(old_entry, entry, *same);
+                    checkpoint.record(key, old_entry, entry, *same);
+                });
+            settle(key, old_entry, entry, same, started.elapsed());
+            if !progress(meter.add(entry.size)) {
+                return Ok(false);
+            }
+        }
+        comparison.timings = timings;
+        progress(Progress::at(80));
+        Ok(true)
+    }
+
+    /// The tree `item` was listed from.
+    pub fn root_of(&self, item: &FolderStatefulList) -> &Path {
+        match item.state {
+            crate::status::StatusItemType::Deleted => self.old.root(),
+            _ => self.new.root(),
+        }
+    }
+
+    /// Path of `file` relative to the tree it was listed from.
+    pub fn relative<'a>(&self, file: &'a FolderStatefulList) -> Option<&'a Path> {
+        file.entry.path().strip_prefix(self.root_of(file)).ok()
+    }
+
+    /// Like [`Engine::relative`], the full path for entries outside both trees.
+    pub fn rel_path(&self, file: &FolderStatefulList) -> PathBuf {
+        self.relative(file)
+            .unwrap_or(file.entry.path())
+            .to_path_buf()
+    }
+
+    /// Paths of `file` relative to the old and the new tree; the missing side
+    /// of a new or deleted file is `None`, and the other end of a move stands
+    /// in for it.
+    pub fn side_rels<'a>(
+        &self,
+        file: &'a FolderStatefulList,
+    ) -> (Option<&'a Path>, Option<&'a Path>) {
+        let rel = match self.relative(file) {
+            Some(rel) => rel,
+            None => return (None, None),
+        };
+        match (file.state, &file.moved) {
+            (crate::status::StatusItemType::New, Some(from)) => (Some(from.as_path()), Some(rel)),
+            (crate::status::StatusItemType::Deleted, Some(to)) => (Some(rel), Some(to.as_path())),
+            (crate::status::StatusItemType::New, None) => (None, Some(rel)),
+            (crate::status::StatusItemType::Deleted, None) => (Some(rel), None),
+            _ => (Some(rel), Some(rel)),
+        }
+    }
+
+    /// Paths of `file` in the old and the new tree, see [`Engine::side_rels`].
+    pub fn side_paths(&self, file: &FolderStatefulList) -> (Option<PathBuf>, Option<PathBuf>) {
+        let (old_rel, new_rel) = self.side_rels(file);
+        (
+            old_rel.map(|rel| self.old.root().join(rel)),
+            new_rel.map(|rel| self.new.root().join(rel)),
+        )
+    }
+
+    /// Reads the old and the new contents of `file`, a missing side reads as
+    /// empty. A moved file is read from the path at the other end of the move.
+    pub fn read_sides(&self, file: &FolderStatefulList) -> io::Result<(String, String)> {
+        if self.relative(file).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is outside both trees", file.entry.path().display()),
+            ));
+        }
+        let (old_rel, new_rel) = self.side_rels(file);
+        let read = |source: &S, rel: Option<&Path>, basis: &[u8]| match rel {
+            Some(rel) => source.read_like(rel, basis),
+            None => Ok(Vec::new()),
+        };
+        // a remote side is read last, against the other side's copy, so only
+        // the blocks that differ come over the network
+        let (old, new) = if self.old.reads_deltas() && !self.new.reads_deltas() {
+            let new = read(&self.new, new_rel, &[])?;
+            (read(&self.old, old_rel, &new)?, new)
+        } else {
+            let old = read(&self.old, old_rel, &[])?;
+            let new = read(&self.new, new_rel, &old)?;
+            (old, new)
+        };
+        let text = |bytes| {
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+        Ok((text(old)?, text(new)?))
+    }
+
+    /// MIME type of `file` from its leading bytes, read from the tree it was
+    /// listed from. `None` for special files and files that cannot be read.
+    pub fn mime_type(&self, file: &FolderStatefulList) -> Option<&'static str> {
+        if file.entry.is_dir() {
+            return Some(mime::DIRECTORY);
+        }
+        if file.entry.special.is_some() {
+            return None;
+        }
+        let rel = self.relative(file)?;
+        let source = match file.state {
+            crate::status::StatusItemType::Deleted => &self.old,
+            _ => &self.new,
+        };
+        match source.head(rel, mime::SNIFF_LEN) {
+            Ok(bytes) => Some(mime::detect(&bytes)),
+            Err(e) => {
+                log::warn!("type of {}: {}", file.entry.path().display(), e);
+                None
+            }
+        }
+    }
+
+    /// The files inside the new or deleted folder `item`, which the results
+    /// fold into it, each with the folder's state. Only listed when asked
+    /// for, so a large folder costs nothing until it is opened.
+    pub fn expand(&self, item: &FolderStatefulList) -> io::Result<Vec<FolderStatefulList>> {
+        let rel = match self.relative(item) {
+            Some(rel) if item.entry.is_dir() => rel,
+            _ => return Ok(Vec::new()),
+        };
+        let (source, sign) = match item.state {
+            crate::status::StatusItemType::New => (&self.new, 1),
+            crate::status::StatusItemType::Deleted => (&self.old, -1),
+            _ => return Ok(Vec::new()),
+        };
+        let mut files: Vec<_> = source
+            .list_under(rel, &self.walk_filter())?
+            .into_values()
+            .filter(|e| !e.is_dir())
+            .map(|entry| FolderStatefulList {
+                state: item.state,
+                size_delta: sign * entry.size as i64,
+                moved: None,
+                entry,
+            })
+            .collect();
+        self.order.sort(&mut files, |i| self.rel_path(i));
+        Ok(files)
+    }
+
+    /// The names directly inside the folder `rel` in the old and the new
+    /// tree, to tell how a folder modified under `--dir-changes` changed.
+    pub fn dir_entries(&self, rel: &Path) -> io::Result<(BTreeSet<String>, BTreeSet<String>)> {
+        let filter = self.walk_filter();
+        let names = |source: &S| -> io::Result<BTreeSet<String>> {
+            Ok(source
+                .list_under(rel, &filter)?
+                .keys()
+                .filter(|k| k.parent() == Some(rel))
+                .filter_map(|k| Some(k.file_name()?.to_string_lossy().into_owned()))
+                .collect())
+        };
+        Ok((names(&self.old)?, names(&self.new)?))
+    }
+
+    /// Adds the MIME type of each of `items` to `types`. Left to callers that
+    /// show types, since it reads the start of every file. `progress` gets
+    /// the share of `items` done and stops early by returning `false`, which
+    /// leaves the rest without a type; the return value tells whether all of
+    /// them were done.
+    pub fn detect_types(
+        &self,
+        items: &[FolderStatefulList],
+        types: &mut HashMap<PathBuf, &'static str>,
+        progress: &mut impl FnMut(Progress) -> bool,
+    ) -> bool {
+        for (done, item) in items.iter().enumerate() {
+            if !progress(Progress::at((done * 100 / items.len()) as u16)) {
+                return false;
+            }
+            if let Some(mime) = self.mime_type(item) {
+                types.insert(item.entry.path().to_path_buf(), mime);
+            }
+        }
+        progress(Progress::at(100));
+        true
+    }
+
+    /// Builds a unified diff of `file` against its counterpart in the other tree,
+    /// treating the missing side of a new or deleted file as empty.
+    pub fn unified_diff(&self, file: &FolderStatefulList) -> io::Result<String> {
+        let (old_path, new_path) = self.side_paths(file);
+        let name = |p: &Option<PathBuf>| match p {
+            Some(p) => p.to_string_lossy().into_owned(),
+            None => "/dev/null".to_string(),
+        };
+        let (old_text, new_text) = self.read_sides(file)?;
+        let diff = TextDiff::from_lines(&old_text, &new_text);
+        let text = diff
+            .unified_diff()
+            .header(&name(&old_path), &name(&new_path))
+            .to_string();
+        Ok(text)
+    }
+
+    /// Like [`Engine::unified_diff`] for the file at `rel`, which has to be in
+    /// at least one of the trees.
+    pub fn unified_diff_at(&self, rel: &Path) -> io::Result<String> {
+        let (entry, state) = match (self.old.metadata(rel), self.new.metadata(rel)) {
+            (Ok(_), Ok(entry)) => (entry, crate::status::StatusItemType::Modified),
+            (Err(_), Ok(entry)) => (entry, crate::status::StatusItemType::New),
+            (Ok(entry), Err(_)) => (entry, crate::status::StatusItemType::Deleted),
+            (Err(e), Err(_)) => return Err(e),
+        };
+        if entry.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is a folder", rel.display()),
+            ));
+        }
+        self.unified_diff(&FolderStatefulList {
+            entry,
+            state,
+            size_delta: 0,
+            moved: None,
+        })
+    }
+
+    /// The steps that would make the `direction` target match the other tree.
+    pub fn sync_plan(&self, comparison: &Comparison, direction: ApplyDirection) -> Plan {
+        Plan::new(
+            &comparison.results,
+            direction,
+            &self.old.root().to_string_lossy(),
+            &self.new.root().to_string_lossy(),
+            |i| self.rel_path(i),
+        )
+    }
+
+    /// Writes a conflict-marked copy of every modified file under `out_dir`,
+    /// returning how many files were written.
+    pub fn export_merge(&self, comparison: &Comparison, out_dir: &Path) -> io::Result<usize> {
+        let mut written = 0;
+        for file in &comparison.results {
+            // a move is written once, at its new path
+            let is_moved = file.state == crate::status::StatusItemType::New && file.moved.is_some();
+            if file.state != crate::status::StatusItemType::Modified && !is_moved {
+                continue;
+            }
+            let (old_path, new_path) = self.side_paths(file);
+            let (rel, old_path, new_path) = match (self.relative(file), old_path, new_path) {
+                (Some(rel), Some(old), Some(new)) => (rel, old, new),
+                _ => continue,
+            };
+            let (old, new) = match self.read_sides(file) {
+                Ok(sides) if is_moved && sides.0 == sides.1 => continue,
+                Ok(sides) => sides,
+                Err(e) => {
+                    log::warn!("skip {}: {}", new_path.display(), e);
+                    continue;
+                }
+            };
+            let contents = merge::conflict_text(
+                &old,
+                &new,
+                &old_path.to_string_lossy(),
+                &new_path.to_string_lossy(),
+            );
+            let dst = out_dir.join(rel);
+            if let Some(parent) = dst.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(dst, contents)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
+
+/// The names directly inside each folder of a listing, by the folder's path.
+fn child_names(files: &HashMap<PathBuf, Entry>) -> HashMap<&Path, BTreeSet<&OsStr>> {
+    let mut children: HashMap<&Path, BTreeSet<&OsStr>> = HashMap::new();
+    for key in files.keys() {
+        if let (Some(parent), Some(name)) = (key.parent(), key.file_name()) {
+            children.entry(parent).or_default().insert(name);
+        }
+    }
+    children
+}
+
+/// Whether both entries are hard links to one file, known without reading it.
+fn is_hard_link(old: &Entry, new: &Entry) -> bool {
+    match (old.inode, new.inode) {
+        (Some(a), Some(b)) => a.same_file(&b),
+        _ => false,
+    }
+}
+
+/// Adds a difference to `res` and tells `found` about it.
+fn record(
+    res: &mut Vec<FolderStatefulList>,
+    found: &mut impl FnMut(&FolderStatefulList),
+    item: FolderStatefulList,
+) {
+    found(&item);
+    res.push(item);
+}
+
+/// Compares the file at `rel` on both sides: recorded hashes are used when a
+/// snapshot has them, two local files are memory mapped, and anything
+/// else is read and compared in full.
+fn same_contents<S: Source>(
+    old: &S,
+    new: &S,
+    rel: &Path,
+    old_entry: &Entry,
+    new_entry: &Entry,
+    cache: &mut Cache,
+) -> io::Result<bool> {
+    // checksum files record no sizes, the other side is hashed the same way
+    let checksum = match (
+        crate::sums::checksum(old_entry),
+        crate::sums::checksum(new_entry),
+    ) {
+        (Some(recorded), _) => Some((recorded, new, new_entry)),
+        (_, Some(recorded)) => Some((recorded, old, old_entry)),
+        _ => None,
+    };
+    if let Some(((algorithm, hex), other, entry)) = checksum {
+        let hash = match (algorithm, other.local_path(rel)) {
+            (crate::sums::Algorithm::Sha256, _) => cache.hash(entry, || other.hash(rel))?,
+            (_, Some(path)) => algorithm.hash_file(&path)?,
+            (_, None) => algorithm.hash_bytes(&other.read(rel)?),
+        };
+        return Ok(hash == hex);
+    }
+    if old_entry.hash.is_some() || new_entry.hash.is_some() {
+        if old_entry.size != new_entry.size {
+            return Ok(false);
+        }
+        // a recorded hash is taken as it is, only the other side is hashed
+        let mut hash = |source: &S, entry: &Entry| match &entry.hash {
+            Some(hash) => Ok(hash.clone()),
+            None => cache.hash(entry, || source.hash(rel)),
+        };
+        return Ok(hash(old, old_entry)? == hash(new, new_entry)?);
+    }
+    if let (Some(old_path), Some(new_path)) = (old.local_path(rel), new.local_path(rel)) {
+        if !new_path.is_file() {
+            return Ok(true);
+        }
+        return compare::same_file(&old_path, &new_path);
+    }
+    Ok(old_entry.size == new_entry.size && old.read(rel)? == new.read(rel)?)
+}
+
+fn too_many(limit: usize) -> io::Error {
+    io::Error::other(format!(
+        "more than {} entries, raise --max-files or leave paths out with .diffignore",
+        limit
+    ))
+}
@@ -0,0 +1,54 @@
+//! `--output ndjson`: one JSON object per line, written while the scan runs
+//! so wrappers can follow along or pipe into `jq`.
+
+use crate::engine::Progress;
+use crate::model::Comparison;
+use crate::status::FolderStatefulList;
+use crate::text;
+use std::path::Path;
+
+/// How far the scan got, in percent, with the seconds left once they can be
+/// estimated.
+pub fn progress(progress: Progress) -> String {
+    match progress.eta {
+        Some(eta) => format!(
+            "{{\"event\":\"progress\",\"percent\":{},\"eta_secs\":{}}}",
+            progress.percent,
+            eta.as_secs()
+        ),
+        None => format!(
+            "{{\"event\":\"progress\",\"percent\":{}}}",
+            progress.percent
+        ),
+    }
+}
+
+/// A difference the scan just found at `rel`.
+pub fn change(rel: &Path, item: &FolderStatefulList) -> String {
+    format!(
+        "{{\"event\":\"change\",\"state\":\"{}\",\"path\":{},\"dir\":{},\"size_delta\":{}}}",
+        format!("{:?}", item.state).to_lowercase(),
+        text::json_string(&text::slash_path(rel)),
+        item.entry.is_dir(),
+        item.size_delta
+    )
+}
+
+/// Totals once the scan is over. `truncated` holds the `--max-files` cap a
+/// walk stopped at.
+pub fn done(comparison: &Comparison) -> String {
+    let s = &comparison.summary;
+    let truncated = match comparison.truncated {
+        Some(limit) => limit.to_string(),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"event\":\"done\",\"new\":{},\"modified\":{},\"deleted\":{},\"size_delta\":{},\"partial\":{},\"truncated\":{}}}",
+        s.new.files + s.new.dirs,
+        s.modified.files + s.modified.dirs,
+        s.deleted.files + s.deleted.dirs,
+        comparison.size_delta,
+        comparison.is_partial,
+        truncated
+    )
+}
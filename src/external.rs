@@ -0,0 +1,126 @@
+use crate::engine::Engine;
+use crate::model::Comparison;
+use crate::source::Source;
+use std::{
+    io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Builds a shell command from `template`, replacing `{old}` and `{new}` with
+/// the quoted paths.
+pub fn command(template: &str, old: &Path, new: &Path) -> Command {
+    shell(&substitute(
+        template,
+        &[("old", quote(old)), ("new", quote(new))],
+    ))
+}
+
+/// Replaces every `{name}` of `values` in `template` in one pass, so a
+/// placeholder spelled inside a value (a file named `{new};rm -rf ~`) is
+/// left as it is.
+fn substitute(template: &str, values: &[(&str, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let found = values.iter().find(|(name, _)| {
+            rest[1..].starts_with(name) && rest[1 + name.len()..].starts_with('}')
+        });
+        match found {
+            Some((name, value)) => {
+                out.push_str(value);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Runs `line` with the platform's shell, `sh -c` or `cmd /C`.
+pub fn shell(line: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(line);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(line);
+        cmd
+    }
+}
+
+/// Runs `program` on `path`, quoted and appended to it, e.g. `$EDITOR` on a
+/// file.
+pub fn on_file(program: &str, path: &Path) -> Command {
+    shell(&format!("{} {}", program, quote(path)))
+}
+
+/// Runs a `--compare-cmd` style `template` on two files: exit status 0 means
+/// equal and 1 means different, like `cmp` and `diff`. Anything else is an
+/// error.
+pub fn same_by_command(template: &str, old: &Path, new: &Path) -> io::Result<bool> {
+    let out = command(template, old, new)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    match out.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(io::Error::other(format!(
+            "{:?} failed ({}): {}",
+            template,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ))),
+    }
+}
+
+/// Runs the `--on-change` `template` once for every difference in
+/// `comparison`, one after another, with `{path}` replaced by the quoted
+/// relative path, `{status}` by `new`, `modified` or `deleted` and `{old}` and
+/// `{new}` by the quoted paths on each side, whether the file is there or not.
+/// Returns what went wrong with the runs that did not exit with 0.
+pub fn on_change<S: Source>(
+    template: &str,
+    engine: &Engine<S>,
+    comparison: &Comparison,
+) -> Vec<String> {
+    let mut failed = Vec::new();
+    for item in &comparison.results {
+        let rel = engine.rel_path(item);
+        let line = substitute(
+            template,
+            &[
+                ("path", quote(&rel)),
+                ("status", format!("{:?}", item.state).to_lowercase()),
+                ("old", quote(&engine.old.root().join(&rel))),
+                ("new", quote(&engine.new.root().join(&rel))),
+            ],
+        );
+        match shell(&line).stdin(Stdio::null()).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => failed.push(format!("{} ({})", line, status)),
+            Err(e) => failed.push(format!("{} ({})", line, e)),
+        }
+    }
+    failed
+}
+
+fn quote(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        // cmd expands %VAR% even between quotes, so a % is escaped outside
+        // them; quotes cannot be part of a Windows file name
+        format!("\"{}\"", path.replace('"', "").replace('%', "\"^%\""))
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
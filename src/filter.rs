@@ -0,0 +1,106 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the per-tree ignore file, in gitignore syntax.
+pub const IGNORE_FILE: &str = ".diffignore";
+
+/// Version control metadata directories skipped unless asked otherwise.
+pub const VCS_DIRS: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// Decides which relative paths are left out of the comparison.
+pub struct WalkFilter {
+    ignore: Gitignore,
+    // include dotfiles and dot-directories
+    hidden: bool,
+    // skip VCS_DIRS even when hidden entries are shown
+    vcs_exclude: bool,
+    // walks stop once they hold more entries than this
+    limit: Option<usize>,
+}
+
+impl WalkFilter {
+    /// Loads `.diffignore` from both roots; rules from either side apply to both
+    /// trees so an ignored path never shows up as created or deleted.
+    pub fn new(old_dir: &str, new_dir: &str) -> Self {
+        let rules: Vec<(PathBuf, String)> = [old_dir, new_dir]
+            .iter()
+            .filter_map(|dir| {
+                let file = Path::new(dir).join(IGNORE_FILE);
+                fs::read_to_string(&file).ok().map(|c| (file, c))
+            })
+            .collect();
+        Self::from_rules(&rules)
+    }
+
+    /// Builds the filter from already read ignore files, given as (file, contents)
+    /// pairs, for trees that are not on the local filesystem.
+    pub fn from_rules(rules: &[(PathBuf, String)]) -> Self {
+        let mut builder = GitignoreBuilder::new("");
+        for (file, contents) in rules {
+            for line in contents.lines() {
+                if let Err(e) = builder.add_line(None, line) {
+                    log::warn!("{}: skip rule {:?}: {}", file.display(), line, e);
+                }
+            }
+        }
+        let ignore = builder.build().unwrap_or_else(|e| {
+            log::warn!("build ignore rules failed: {}", e);
+            Gitignore::empty()
+        });
+        Self {
+            ignore,
+            hidden: false,
+            vcs_exclude: true,
+            limit: None,
+        }
+    }
+
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn with_vcs_exclude(mut self, vcs_exclude: bool) -> Self {
+        self.vcs_exclude = vcs_exclude;
+        self
+    }
+
+    /// Caps the entries a walk collects, so a tree far bigger than expected
+    /// (say `/`) does not fill the memory. A walk over the cap returns `limit + 1`
+    /// entries, which tells the caller it was cut short.
+    pub fn with_limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Whether a walk holding `entries` entries should stop.
+    pub fn is_full(&self, entries: usize) -> bool {
+        self.limit.is_some_and(|limit| entries > limit)
+    }
+
+    /// Checks a path relative to the compared roots.
+    pub fn is_ignored(&self, rel: &Path, is_dir: bool) -> bool {
+        if !self.hidden && is_hidden(rel) {
+            return true;
+        }
+        if self.vcs_exclude && is_vcs(rel) {
+            return true;
+        }
+        self.ignore
+            .matched_path_or_any_parents(rel, is_dir)
+            .is_ignore()
+    }
+}
+
+fn is_hidden(rel: &Path) -> bool {
+    rel.components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+fn is_vcs(rel: &Path) -> bool {
+    rel.components()
+        .any(|c| VCS_DIRS.iter().any(|d| c.as_os_str() == *d))
+}
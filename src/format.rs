@@ -0,0 +1,152 @@
+//! Sizes and dates as people read them, the same in every view. How they
+//! look is set once from the `[format]` section of the config file.
+
+use chrono::{Local, TimeZone};
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The style every function here uses.
+static STYLE: Mutex<Format> = Mutex::new(Format {
+    sizes: SizeUnits::Binary,
+    dates: DateStyle::Iso,
+});
+
+/// How sizes and dates are written.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Format {
+    pub sizes: SizeUnits,
+    pub dates: DateStyle,
+}
+
+/// Steps between size units.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum SizeUnits {
+    /// 1024 bytes to a KB, like `du -h`
+    #[default]
+    Binary,
+    /// 1000 bytes to a kB, like file managers on macOS
+    Si,
+}
+
+/// How modification times are written.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum DateStyle {
+    /// `2023-02-14 09:30:00` in local time
+    #[default]
+    Iso,
+    /// `3 days ago`
+    Relative,
+}
+
+impl std::str::FromStr for SizeUnits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(SizeUnits::Binary),
+            "si" => Ok(SizeUnits::Si),
+            _ => Err(format!("expected binary or si, got {}", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for DateStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iso" => Ok(DateStyle::Iso),
+            "relative" => Ok(DateStyle::Relative),
+            _ => Err(format!("expected iso or relative, got {}", s)),
+        }
+    }
+}
+
+/// Uses `format` for every size and date from now on.
+pub fn set_style(format: Format) {
+    *STYLE.lock().unwrap() = format;
+}
+
+fn style() -> Format {
+    *STYLE.lock().unwrap()
+}
+
+/// Formats a byte count, e.g. `1.2 KB` or `340 B`.
+pub fn size(bytes: u64) -> String {
+    size_in(bytes, style().sizes)
+}
+
+/// Formats a byte delta with its sign, e.g. `+1.2 KB` or `-340 B`.
+pub fn size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, size(delta.unsigned_abs()))
+}
+
+/// Like [`size`], in the given units.
+pub fn size_in(bytes: u64, units: SizeUnits) -> String {
+    let (step, names) = match units {
+        SizeUnits::Binary => (1024.0, ["B", "KB", "MB", "GB", "TB"]),
+        SizeUnits::Si => (1000.0, ["B", "kB", "MB", "GB", "TB"]),
+    };
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= step && unit < names.len() - 1 {
+        size /= step;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, names[unit])
+    } else {
+        format!("{:.1} {}", size, names[unit])
+    }
+}
+
+/// Formats a modification time in nanoseconds since the epoch.
+pub fn date(mtime: u128) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    date_in(mtime, now, style().dates)
+}
+
+/// Like [`date`], in the given style and with `now` for relative times.
+/// Times after `now` are always written out in full.
+pub fn date_in(mtime: u128, now: u128, style: DateStyle) -> String {
+    const UNITS: [(u64, &str); 6] = [
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+    if style == DateStyle::Relative && mtime <= now {
+        let secs = ((now - mtime) / 1_000_000_000) as u64;
+        return match UNITS.iter().find(|(unit, _)| secs >= *unit) {
+            Some((unit, name)) => {
+                let n = secs / unit;
+                format!("{} {}{} ago", n, name, if n == 1 { "" } else { "s" })
+            }
+            None => "just now".to_string(),
+        };
+    }
+    let secs = (mtime / 1_000_000_000) as i64;
+    let nanos = (mtime % 1_000_000_000) as u32;
+    match Local.timestamp_opt(secs, nanos).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Time left in the two largest units, e.g. `1h 5m`, `1m 20s` or `40s`.
+pub fn eta(left: Duration) -> String {
+    let secs = left.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
@@ -0,0 +1,103 @@
+use crate::status::StatusItemType;
+use std::path::Path;
+
+/// Which glyphs label entries in the file list.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum IconSet {
+    /// the `d`/`f` letters, safe on any terminal
+    #[default]
+    Plain,
+    /// glyphs from a patched Nerd Font
+    Nerd,
+    /// emoji, wide on most terminals
+    Emoji,
+    /// the letters of `Plain` after `+`, `-` or `~`, for terminals without
+    /// colors; `--no-color` picks it
+    Symbols,
+}
+
+impl std::str::FromStr for IconSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "plain" => Ok(IconSet::Plain),
+            "nerd" => Ok(IconSet::Nerd),
+            "emoji" => Ok(IconSet::Emoji),
+            "symbols" => Ok(IconSet::Symbols),
+            _ => Err(format!("expected none, nerd, emoji or symbols, got {}", s)),
+        }
+    }
+}
+
+impl IconSet {
+    /// Full list prefix for an entry: its status glyph, if any, then its type.
+    pub fn prefix(self, state: StatusItemType, path: &Path, is_dir: bool) -> String {
+        match self.status(state) {
+            "" => self.entry(path, is_dir).to_string(),
+            status => format!("{} {}", status, self.entry(path, is_dir)),
+        }
+    }
+
+    /// Glyph for the kind of entry, chosen by extension for files.
+    pub fn entry(self, path: &Path, is_dir: bool) -> &'static str {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match (self, is_dir) {
+            (IconSet::Plain | IconSet::Symbols, true) => "d",
+            (IconSet::Plain | IconSet::Symbols, false) => "f",
+            (IconSet::Nerd, true) => "\u{f07b}",
+            (IconSet::Nerd, false) => match ext.as_str() {
+                "rs" => "\u{e7a8}",
+                "md" | "markdown" => "\u{e73e}",
+                "toml" | "yaml" | "yml" | "ini" | "conf" => "\u{e615}",
+                "json" => "\u{e60b}",
+                "js" | "mjs" | "cjs" => "\u{e74e}",
+                "ts" => "\u{e628}",
+                "py" => "\u{e73c}",
+                "go" => "\u{e626}",
+                "c" | "h" => "\u{e61e}",
+                "cpp" | "cc" | "hpp" => "\u{e61d}",
+                "sh" | "bash" | "zsh" => "\u{f489}",
+                "html" | "htm" => "\u{e736}",
+                "css" => "\u{e749}",
+                "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" => "\u{f1c5}",
+                "zip" | "tar" | "gz" | "xz" | "zst" => "\u{f410}",
+                "lock" => "\u{f023}",
+                _ => "\u{f15b}",
+            },
+            (IconSet::Emoji, true) => "📁",
+            (IconSet::Emoji, false) => match ext.as_str() {
+                "rs" => "🦀",
+                "md" | "markdown" | "txt" => "📝",
+                "toml" | "yaml" | "yml" | "ini" | "conf" | "json" => "⚙️",
+                "py" => "🐍",
+                "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" => "🖼️",
+                "zip" | "tar" | "gz" | "xz" | "zst" => "📦",
+                "lock" => "🔒",
+                _ => "📄",
+            },
+        }
+    }
+
+    /// Glyph for how an entry changed; plain terminals rely on colour alone.
+    pub fn status(self, state: StatusItemType) -> &'static str {
+        match (self, state) {
+            (IconSet::Plain, _) => "",
+            (IconSet::Nerd, StatusItemType::New) => "\u{f457}",
+            (IconSet::Nerd, StatusItemType::Modified) => "\u{f459}",
+            (IconSet::Nerd, StatusItemType::Deleted) => "\u{f458}",
+            (IconSet::Nerd, StatusItemType::Normal) => " ",
+            (IconSet::Emoji, StatusItemType::New) => "➕",
+            (IconSet::Emoji, StatusItemType::Modified) => "✏️",
+            (IconSet::Emoji, StatusItemType::Deleted) => "➖",
+            (IconSet::Emoji, StatusItemType::Normal) => "  ",
+            (IconSet::Symbols, StatusItemType::New) => "+",
+            (IconSet::Symbols, StatusItemType::Modified) => "~",
+            (IconSet::Symbols, StatusItemType::Deleted) => "-",
+            (IconSet::Symbols, StatusItemType::Normal) => " ",
+        }
+    }
+}
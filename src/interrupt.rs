@@ -0,0 +1,47 @@
+//! Ctrl+C in the TUI. Raw mode turns it into a key rather than SIGINT, so it
+//! is looked for among the keys, and SIGINT sent from elsewhere (`kill -INT`)
+//! is caught too. Either way the scan stops at the next file, the terminal is
+//! restored and the process exits with [`EXIT_CODE`], like a shell command
+//! killed by SIGINT.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 128 + SIGINT.
+pub const EXIT_CODE: i32 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Asks everything running to stop and the process to exit.
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether Ctrl+C was pressed or SIGINT received.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Whether `key` is Ctrl+C.
+#[cfg(feature = "tui")]
+pub fn is_ctrl_c(key: &crossterm::event::KeyEvent) -> bool {
+    use crossterm::event::{KeyCode, KeyModifiers};
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Turns SIGINT into a [`request`]. A second one exits at once, for a scan
+/// stuck waiting on a slow read.
+#[cfg(unix)]
+pub fn catch_sigint() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        if REQUESTED.swap(true, Ordering::Relaxed) {
+            // SAFETY: _exit is async-signal-safe
+            unsafe { libc::_exit(EXIT_CODE) };
+        }
+    }
+    let handler: extern "C" fn(libc::c_int) = on_sigint;
+    // SAFETY: the handler only touches an atomic and calls _exit
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+}
+
+#[cfg(not(unix))]
+pub fn catch_sigint() {}
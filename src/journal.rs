@@ -0,0 +1,108 @@
+use crate::ops;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// What an entry of the journal did.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// `A`: an entry copied over or deleted
+    Apply,
+    /// `a`: selected hunks written into a file
+    ApplyHunks,
+    /// `u`: an earlier operation reverted
+    Undo,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Apply => "apply",
+            Kind::ApplyHunks => "apply-hunks",
+            Kind::Undo => "undo",
+        }
+    }
+}
+
+/// One file operation of the session.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub kind: Kind,
+    /// seconds since the epoch
+    pub time: u64,
+    /// the file or directory that was changed
+    pub target: PathBuf,
+    /// copy of what was at `target` before, `None` when nothing was there
+    pub backup: Option<PathBuf>,
+}
+
+/// The file operations performed in the TUI, newest last, so they can be
+/// undone one by one and exported as a session log.
+#[derive(Default)]
+pub struct Journal {
+    // every record in order, undos included
+    log: Vec<Record>,
+    // indices into `log` of operations that can still be undone
+    undoable: Vec<usize>,
+}
+
+impl Journal {
+    pub fn push(&mut self, record: Record) {
+        self.undoable.push(self.log.len());
+        self.log.push(record);
+    }
+
+    /// The operation [`Journal::undo`] would revert.
+    pub fn next_undo(&self) -> Option<&Record> {
+        self.undoable.last().map(|&i| &self.log[i])
+    }
+
+    /// Reverts the last operation not undone yet: `target` is put back from
+    /// its backup, or removed when it did not exist before. Returns the
+    /// reverted record, or `None` when there is nothing left to undo.
+    pub fn undo(&mut self, time: u64) -> Option<io::Result<Record>> {
+        let index = *self.undoable.last()?;
+        let record = self.log[index].clone();
+        let res = (|| {
+            if record.target.symlink_metadata().is_ok() {
+                ops::remove_all(&record.target)?;
+            }
+            if let Some(backup) = &record.backup {
+                ops::copy_all(backup, &record.target)?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = res {
+            return Some(Err(e));
+        }
+        self.undoable.pop();
+        self.log.push(Record {
+            kind: Kind::Undo,
+            time,
+            target: record.target.clone(),
+            backup: record.backup.clone(),
+        });
+        Some(Ok(record))
+    }
+
+    /// Writes one tab separated line per record: time, kind, target and the
+    /// backup (`-` for none).
+    pub fn export(&self, file: &Path) -> io::Result<()> {
+        let mut out = String::new();
+        for record in &self.log {
+            let backup = match &record.backup {
+                Some(backup) => backup.to_string_lossy(),
+                None => "-".into(),
+            };
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                record.time,
+                record.kind.name(),
+                record.target.display(),
+                backup
+            ));
+        }
+        fs::write(file, out)
+    }
+}
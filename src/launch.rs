@@ -0,0 +1,65 @@
+//! Programs the TUI hands the terminal to: the `--difftool` on the selected
+//! pair and `$VISUAL` or `$EDITOR` on the selected file. The TUI is suspended
+//! while one runs, leaving the alternate screen with raw mode and mouse
+//! capture off, and restored once it exits, whatever its exit status; mouse
+//! capture comes back with the next draw that shows a minimap.
+//! They run with the priority of diff-folders, so under `--nice` an expensive
+//! diff tool stays out of the way too.
+
+use crossterm::{
+    cursor::{Hide, Show},
+    event::DisableMouseCapture,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use std::{
+    io,
+    process::{Command, ExitStatus},
+};
+
+/// A program the app asked to run, see [`crate::app::App::take_launch`].
+pub struct Launch {
+    /// what to call it in messages, e.g. `meld`
+    pub name: String,
+    pub command: Command,
+}
+
+/// The terminal as the TUI uses it, given up and taken back around a program.
+pub trait Screen {
+    fn suspend(&mut self) -> io::Result<()>;
+    fn resume(&mut self) -> io::Result<()>;
+}
+
+/// The terminal on stdout, as set up by the binary.
+pub struct Stdout;
+
+impl Screen for Stdout {
+    fn suspend(&mut self) -> io::Result<()> {
+        let mut out = io::stdout();
+        out.execute(DisableMouseCapture)?
+            .execute(LeaveAlternateScreen)?
+            .execute(Show)?;
+        disable_raw_mode()
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        enable_raw_mode()?;
+        let mut out = io::stdout();
+        out.execute(EnterAlternateScreen)?.execute(Hide)?;
+        Ok(())
+    }
+}
+
+/// Runs `command` on the plain terminal and waits for it. The screen is
+/// resumed even when the program fails or cannot be started; a screen that
+/// cannot be resumed is the error then.
+pub fn run(screen: &mut impl Screen, command: &mut Command) -> io::Result<ExitStatus> {
+    if let Err(e) = screen.suspend() {
+        // take back whatever was given up before it failed
+        let _ = screen.resume();
+        return Err(e);
+    }
+    let status = command.status();
+    screen.resume()?;
+    status
+}
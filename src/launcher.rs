@@ -0,0 +1,126 @@
+//! The start screen shown when diff-folders is run without folders: the
+//! favorites of the config file, then the pairs compared lately, one of which
+//! is picked with Enter and compared as if it was named on the command line.
+
+use crate::config::Favorite;
+use crossterm::event::KeyCode;
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use tui::{backend::Backend, Frame};
+
+/// A pair to pick, with the favorite's name when it is one.
+struct Choice {
+    name: Option<String>,
+    old: String,
+    new: String,
+}
+
+pub struct Launcher {
+    choices: Vec<Choice>,
+    state: ListState,
+    // why the last pick could not be opened, shown until the next key
+    message: Option<String>,
+}
+
+impl Launcher {
+    /// Favorites first in file order, then recent pairs newest first, leaving
+    /// out those already listed as a favorite.
+    pub fn new(favorites: &[Favorite], recent: &[(String, String)]) -> Self {
+        let mut choices: Vec<Choice> = favorites
+            .iter()
+            .map(|f| Choice {
+                name: Some(f.name.clone()),
+                old: f.old.clone(),
+                new: f.new.clone(),
+            })
+            .collect();
+        for (old, new) in recent {
+            if !choices.iter().any(|c| (&c.old, &c.new) == (old, new)) {
+                choices.push(Choice {
+                    name: None,
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+            }
+        }
+        let mut state = ListState::default();
+        state.select((!choices.is_empty()).then_some(0));
+        Launcher {
+            choices,
+            state,
+            message: None,
+        }
+    }
+
+    /// Moves the selection; Enter gives the old and new tree of the pair
+    /// picked.
+    pub fn event(&mut self, key_code: KeyCode) -> Option<(String, String)> {
+        self.message = None;
+        let len = self.choices.len();
+        let selected = self.state.selected()?;
+        match key_code {
+            KeyCode::Up => self.state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => self.state.select(Some((selected + 1).min(len - 1))),
+            KeyCode::Home => self.state.select(Some(0)),
+            KeyCode::End => self.state.select(Some(len - 1)),
+            KeyCode::Enter => {
+                let choice = &self.choices[selected];
+                return Some((choice.old.clone(), choice.new.clone()));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Shows why the pair picked last cannot be compared, e.g. a folder that
+    /// is gone.
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .split(f.size());
+        let items: Vec<ListItem> = self
+            .choices
+            .iter()
+            .map(|c| {
+                let pair = format!("{} → {}", c.old, c.new);
+                ListItem::new(Spans::from(match &c.name {
+                    Some(name) => vec![
+                        Span::styled(
+                            format!("{} ", name),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(pair),
+                    ],
+                    None => vec![Span::raw(pair)],
+                }))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Favorites and recent pairs"),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(Color::LightBlue)
+                    .add_modifier(Modifier::BOLD),
+            );
+        f.render_stateful_widget(list, chunks[0], &mut self.state);
+        let bar = match &self.message {
+            Some(message) => Span::styled(message.clone(), Style::default().fg(Color::Red)),
+            None => Span::styled(
+                "Enter compares the pair, q quits",
+                Style::default().fg(Color::DarkGray),
+            ),
+        };
+        f.render_widget(Paragraph::new(Spans::from(bar)), chunks[1]);
+    }
+}
@@ -1,3 +1,56 @@
+pub mod agent;
+#[cfg(feature = "tui")]
 pub mod app;
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod bookmarks;
+#[cfg(feature = "cloud")]
+pub mod bucket;
+pub mod cache;
+pub mod checkpoint;
+pub mod ci;
+pub mod cli;
+pub mod color;
+pub mod compare;
+pub mod config;
+pub mod delta;
+pub mod dupes;
+pub mod engine;
+pub mod events;
+pub mod external;
+pub mod filter;
+pub mod format;
+pub mod icons;
+pub mod interrupt;
+pub mod journal;
+#[cfg(feature = "tui")]
+pub mod launch;
+#[cfg(feature = "tui")]
+pub mod launcher;
 pub mod log;
+pub mod manifest;
+pub mod merge;
+pub mod mime;
+pub mod model;
+pub mod oci;
+pub mod ops;
+pub mod pager;
+pub mod patch;
+pub mod profile;
+pub mod recent;
+pub mod renderer;
+pub mod session;
+pub mod sizes;
+pub mod source;
 pub mod status;
+pub mod summary;
+pub mod sums;
+pub mod sync;
+#[cfg(feature = "tui")]
+pub mod tabs;
+pub mod text;
+pub mod throttle;
+#[cfg(feature = "tui")]
+pub mod ui;
+#[cfg(feature = "tui")]
+pub mod view;
@@ -1,62 +1,121 @@
-use anyhow::Result;
-use flexi_logger::writers::LogWriter;
+use anyhow::{Context, Result};
+use flexi_logger::{
+    writers::LogWriter, Cleanup, Criterion, FileSpec, Logger, LoggerHandle, Naming,
+};
+use log::LevelFilter;
 use std::{
-    fs::{self, File, OpenOptions},
-    io::{Error, ErrorKind},
-    sync::{Arc, Mutex},
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
-pub fn init_logger() -> Result<()> {
-    let dir = directories::BaseDirs::new()
-        .unwrap()
-        .home_dir()
-        .join(".cache")
-        .join("diff-folders");
-    if !dir.exists() {
-        fs::create_dir_all(&dir)?;
-    }
-    let logfile = dir.clone().join("diff-folders.log");
-    if !logfile.exists() {
-        File::create(&logfile)?;
-    }
-    let fd = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .open(logfile)
-        .unwrap();
-    let my_writer = FileWriter {
-        file: Arc::new(Mutex::new(fd)),
+/// `$XDG_CACHE_HOME/diff-folders` or `~/.cache/diff-folders`, home of the
+/// log files, apply backups and the results cache. Falls back to the temp dir
+/// when there is no home directory.
+pub fn cache_dir() -> PathBuf {
+    // the spec says relative values are invalid and must be ignored
+    let xdg = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute());
+    let base = match (xdg, directories::BaseDirs::new()) {
+        (Some(xdg), _) => xdg,
+        (None, Some(dirs)) => dirs.home_dir().join(".cache"),
+        (None, None) => std::env::temp_dir(),
     };
-    flexi_logger::Logger::try_with_str("info")
-        .unwrap()
-        .log_to_writer(Box::new(my_writer))
-        .write_mode(flexi_logger::WriteMode::BufferAndFlush)
-        .start()?;
-    Ok(())
+    base.join("diff-folders")
 }
 
-struct FileWriter<F> {
-    file: Arc<Mutex<F>>,
+/// Default log file inside [`cache_dir`].
+pub fn default_log_file() -> PathBuf {
+    cache_dir().join("diff-folders.log")
 }
 
-impl<F: std::io::Write + Send + Sync> LogWriter for FileWriter<F> {
+/// Size at which the log file is rotated.
+const LOG_ROTATE_SIZE: u64 = 5 * 1024 * 1024;
+/// Rotated log files kept next to the current one.
+const LOG_KEEP_FILES: usize = 3;
+/// Lines kept for the in-TUI log viewer.
+const RECENT_LINES: usize = 1000;
+
+/// The latest log lines, newest last, for the log viewer.
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Lines logged since the start, to tell whether the viewer is behind.
+static WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// How many lines were logged so far; it changes whenever a line comes in.
+pub fn lines_written() -> usize {
+    WRITTEN.load(Ordering::Relaxed)
+}
+
+/// Copies of the most recent log lines, oldest first.
+pub fn recent_lines() -> Vec<String> {
+    match RECENT.lock() {
+        Ok(recent) => recent.iter().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Starts logging at `level` to `file`, or to [`default_log_file`] when none
+/// is given. The file is rotated once it reaches [`LOG_ROTATE_SIZE`], keeping
+/// [`LOG_KEEP_FILES`] old ones. `LevelFilter::Off` starts nothing and leaves no
+/// file behind.
+///
+/// With rotation the lines go to `<stem>_rCURRENT.<ext>` next to `file`, and
+/// the old ones are `<stem>_r00000.<ext>` and up; on Unix `file` itself is a
+/// symlink to the current one.
+///
+/// File logging stops when the returned handle is dropped, keep it until exit.
+pub fn init_logger(level: LevelFilter, file: Option<&Path>) -> Result<Option<LoggerHandle>> {
+    if level == LevelFilter::Off {
+        return Ok(None);
+    }
+    let logfile = file.map_or_else(default_log_file, Path::to_path_buf);
+    if let Some(dir) = logfile.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let spec = FileSpec::try_from(&logfile)
+        .with_context(|| format!("cannot log to {}", logfile.display()))?;
+    let logger = Logger::try_with_str(level.as_str().to_lowercase())?
+        .log_to_file_and_writer(spec, Box::new(RecentWriter))
+        .format(flexi_logger::detailed_format)
+        .rotate(
+            Criterion::Size(LOG_ROTATE_SIZE),
+            Naming::Numbers,
+            Cleanup::KeepLogFiles(LOG_KEEP_FILES),
+        )
+        .append()
+        .write_mode(flexi_logger::WriteMode::BufferAndFlush);
+    #[cfg(unix)]
+    let logger = logger.create_symlink(&logfile);
+    Ok(Some(logger.start()?))
+}
+
+/// Feeds the log viewer.
+struct RecentWriter;
+
+impl LogWriter for RecentWriter {
     fn write(
         &self,
-        now: &mut flexi_logger::DeferredNow,
+        _now: &mut flexi_logger::DeferredNow,
         record: &flexi_logger::Record,
     ) -> std::io::Result<()> {
-        let mut file = self
-            .file
+        let mut recent = RECENT
             .lock()
-            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
-        flexi_logger::detailed_format(&mut *file, now, record)
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        if recent.len() == RECENT_LINES {
+            recent.pop_front();
+        }
+        recent.push_back(format!("{:<5} {}", record.level(), record.args()));
+        WRITTEN.fetch_add(1, Ordering::Relaxed);
+        Ok(())
     }
 
     fn flush(&self) -> std::io::Result<()> {
-        let mut file = self
-            .file
-            .lock()
-            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
-        file.flush()
+        Ok(())
     }
 }
@@ -1,90 +1,470 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
-use diff_folders::{app::App, log::init_logger};
+use diff_folders::{
+    agent, ci,
+    cli::{parse_args, Command, Options, Output},
+    color,
+    config::Config,
+    engine::Engine,
+    events, external,
+    filter::WalkFilter,
+    format, interrupt, launch,
+    launcher::Launcher,
+    log::init_logger,
+    manifest,
+    model::Comparison,
+    pager, profile, recent,
+    source::list_dir,
+    sync,
+    tabs::Tabs,
+    text, throttle,
+    ui::Monochrome,
+};
+use flexi_logger::LoggerHandle;
 use scopeguard::defer;
 use std::{
     env::args,
+    fs,
     io::{self, Write},
-    path::{self, Path},
-    str::FromStr,
+    net::TcpListener,
+    path::Path,
+    time::Duration,
 };
 use tui::{
     backend::{Backend, CrosstermBackend},
+    layout::Rect,
     Terminal,
 };
 
+/// How long the event loop waits for input before giving background work a
+/// turn, see [`Tabs::tick`].
+const TICK_RATE: Duration = Duration::from_millis(250);
+
+/// Files and folders listed by `--profile`.
+const PROFILE_ROWS: usize = 20;
+
 fn main() -> Result<()> {
-    if args().len() != 3 {
-        panic!(
-            "{} <old_dir|new_file> <new_dir|new_file>",
-            args().next().unwrap()
-        )
+    // a broken config file stops the TUI, which saves its layout there; the
+    // commands that exit go on without it
+    let (config, config_error) = match Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
+    };
+    let mut options = parse_args(args().skip(1), config, std::env::vars())?;
+    if let Some(e) = config_error {
+        if options.is_tui() {
+            return Err(e);
+        }
+        eprintln!("config file ignored: {:#}", e);
+    }
+    format::set_style(options.format);
+    if let Some(limit) = options.io_limit {
+        throttle::set_limit(limit);
+    }
+    if options.nice {
+        if let Err(e) = throttle::be_nice() {
+            eprintln!("--nice: {}", e);
+        }
+    }
+    if let Some(token) = &options.token {
+        agent::set_token(token);
+    }
+    if let Command::Serve { dir, listen } = &options.command {
+        let listener = TcpListener::bind(listen)?;
+        let _logger = init_logger(options.log_level, options.log_file.as_deref())?;
+        println!("serving {} on {}", dir, listener.local_addr()?);
+        agent::serve(
+            listener,
+            Path::new(dir),
+            options.token.as_deref().unwrap_or(""),
+        )?;
+        return Ok(());
+    }
+    if let Command::Snapshot { dir, out, contents } = &options.command {
+        let filter = WalkFilter::new(dir, dir)
+            .with_hidden(options.hidden)
+            .with_vcs_exclude(!options.no_vcs_exclude);
+        let files = list_dir(Path::new(dir), &filter)?;
+        let written = manifest::write(&files, out, *contents)?;
+        println!(
+            "recorded {} entries of {} in {}",
+            written,
+            dir,
+            out.display()
+        );
+        return Ok(());
+    }
+    if let Command::Show { rel } = &options.command {
+        let diff = match Engine::new(&options)?.unified_diff_at(rel) {
+            Ok(diff) => diff,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                println!("binary files differ: {}", rel.display());
+                return Ok(());
+            }
+            Err(e) => bail!("{}: {}", rel.display(), e),
+        };
+        // like diff, equal files print nothing
+        if !diff.is_empty() {
+            pager::page(&color::paint_diff(&diff, options.color.enabled()))?;
+        }
+        return Ok(());
+    }
+    if let Command::Sync {
+        delete,
+        backup_dir,
+        dry_run,
+    } = &options.command
+    {
+        let (delete, backup_dir, dry_run) = (*delete, backup_dir.clone(), *dry_run);
+        let permanent = options.permanent;
+        // like rsync, a missing destination is created
+        if !dry_run && !Path::new(&options.old_dir).exists() {
+            fs::create_dir_all(&options.old_dir)
+                .with_context(|| format!("cannot create {}", options.old_dir))?;
+        }
+        let engine = Engine::new(&options)?;
+        let comparison = compare_all(&engine)?;
+        let mut plan = engine.sync_plan(&comparison, options.apply_to);
+        if !delete {
+            plan.ops.retain(|op| op.action != sync::Action::Delete);
+        }
+        if dry_run {
+            print!("{}", plan.to_text(options.color.enabled()));
+            return Ok(());
+        }
+        let failed = plan.apply(backup_dir.as_deref(), permanent, &mut |op, res| match res {
+            Ok(_) => println!("{}", op.describe()),
+            Err(e) => eprintln!("{}: {}", op.describe(), e),
+        });
+        if failed > 0 {
+            bail!("{} of {} operations failed", failed, plan.ops.len());
+        }
+        println!(
+            "{} operations, {} bytes copied",
+            plan.ops.len(),
+            plan.bytes()
+        );
+        return Ok(());
+    }
+    if options.duplicates {
+        let dupes = Engine::new(&options)?.duplicates()?;
+        for dupe in &dupes {
+            println!("{} bytes, sha256 {}", dupe.size, dupe.hash);
+            for file in &dupe.files {
+                println!("    {}", file.display());
+            }
+        }
+        println!("{} groups of duplicated files", dupes.len());
+        return Ok(());
+    }
+    if options.sizes {
+        let dirs = Engine::new(&options)?.dir_sizes()?;
+        println!("{:>14} {:>14} {:>14}  path", "old", "new", "delta");
+        for dir in dirs {
+            let rel = if dir.rel.as_os_str().is_empty() {
+                ".".to_string()
+            } else {
+                text::slash_path(&dir.rel)
+            };
+            println!(
+                "{:>14} {:>14} {:>+14}  {}",
+                dir.old,
+                dir.new,
+                dir.delta(),
+                rel
+            );
+        }
+        return Ok(());
+    }
+    if options.profile {
+        let comparison = compare_all(&Engine::new(&options)?)?;
+        print!("{}", profile::report(&comparison.timings, PROFILE_ROWS));
+        return Ok(());
+    }
+    if let Some(template) = &options.on_change {
+        let engine = Engine::new(&options)?;
+        let comparison = compare_all(&engine)?;
+        let failed = external::on_change(template, &engine, &comparison);
+        for run in &failed {
+            eprintln!("--on-change failed: {}", run);
+        }
+        if !failed.is_empty() {
+            bail!(
+                "{} of {} --on-change runs failed",
+                failed.len(),
+                comparison.results.len()
+            );
+        }
+        return Ok(());
+    }
+    if let Output::GithubAnnotations | Output::GitlabCodequality = options.output {
+        let engine = Engine::new(&options)?;
+        let comparison = compare_all(&engine)?;
+        let items: Vec<_> = comparison
+            .results
+            .iter()
+            .map(|item| (engine.rel_path(item), item))
+            .collect();
+        if options.output == Output::GithubAnnotations {
+            print!("{}", ci::github(&items));
+        } else {
+            print!("{}", ci::gitlab(&items));
+        }
+        if options.fail_on_diff && comparison.has_differences() {
+            // exit runs no destructors, and the engine's remove saved images
+            drop(engine);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if options.output == Output::Ndjson {
+        let engine = Engine::new(&options)?;
+        let mut last = None;
+        let comparison = engine.compare_with(
+            &mut |progress| {
+                if last != Some(progress) {
+                    last = Some(progress);
+                    // a reader that went away stops the scan
+                    return writeln!(io::stdout(), "{}", events::progress(progress)).is_ok();
+                }
+                true
+            },
+            &mut |item| {
+                let _ = writeln!(
+                    io::stdout(),
+                    "{}",
+                    events::change(&engine.rel_path(item), item)
+                );
+            },
+        )?;
+        writeln!(io::stdout(), "{}", events::done(&comparison))?;
+        return Ok(());
+    }
+    if options.brief {
+        let engine = Engine::new(&options)?;
+        let color = options.color.enabled();
+        for item in compare_all(&engine)?.results {
+            let label = match item.entry.special {
+                Some(special) => format!("{:<8}", special.name()),
+                None => format!("{:<8}", format!("{:?}", item.state).to_lowercase()),
+            };
+            let label = match color::state_color(item.state, item.entry.special.is_some()) {
+                Some(c) => color::paint(&label, c, color),
+                None => label,
+            };
+            println!("{} {}", label, text::slash_path(&engine.rel_path(&item)));
+        }
+        return Ok(());
+    }
+    if options.sync_plan {
+        let engine = Engine::new(&options)?;
+        let comparison = compare_all(&engine)?;
+        let plan = engine.sync_plan(&comparison, options.apply_to);
+        if options.json {
+            println!("{}", plan.to_json());
+        } else {
+            print!("{}", plan.to_text(options.color.enabled()));
+        }
+        return Ok(());
+    }
+    if options.fail_on_diff {
+        let engine = Engine::new(&options)?;
+        if compare_all(&engine)?.has_differences() {
+            println!("differences found");
+            drop(engine);
+            std::process::exit(1);
+        }
+        println!("no differences found");
+        return Ok(());
+    }
+    if let Some(out_dir) = &options.merge_out {
+        let engine = Engine::new(&options)?;
+        let comparison = compare_all(&engine)?;
+        let written = engine.export_merge(&comparison, out_dir)?;
+        println!("wrote {} merge files to {}", written, out_dir.display());
+        return Ok(());
     }
-    let (old_dir, new_dir) = parse_args();
 
-    init_logger()?;
+    let logger = init_logger(options.log_level, options.log_file.as_deref())?;
     setup_terminal()?;
+    install_panic_hook();
+    interrupt::catch_sigint();
 
     defer! {
         shutdown_terminal();
     }
     let mut terminal = start_terminal(io::stdout())?;
 
-    let app = App::new(old_dir, new_dir);
-    let res = run_app(&mut terminal, app);
+    if let Command::Launch { favorites, recent } = &options.command {
+        let mut launcher = Launcher::new(favorites, recent);
+        if !run_launcher(&mut terminal, &mut launcher, &mut options)? {
+            return exit_if_interrupted(logger);
+        }
+    }
+    record_recent(&options);
+    let save_prefs = !options.no_save_prefs;
+    let mut tabs = Tabs::open(options)?;
+    let res = run_app(&mut terminal, &mut tabs);
 
     if let Err(err) = res {
         log::error!("{:?}", err)
     }
+    if save_prefs {
+        if let Err(e) = tabs.layout().save() {
+            log::warn!("save the layout failed: {:#}", e);
+        }
+    }
+    drop(tabs);
+    exit_if_interrupted(logger)
+}
+
+/// Exits with [`interrupt::EXIT_CODE`] after Ctrl+C, once the terminal is
+/// restored and the log written out.
+fn exit_if_interrupted(logger: Option<LoggerHandle>) -> Result<()> {
+    if interrupt::requested() {
+        shutdown_terminal();
+        if let Some(logger) = logger {
+            logger.flush();
+        }
+        std::process::exit(interrupt::EXIT_CODE);
+    }
     Ok(())
 }
 
-fn parse_args() -> (String, String) {
-    let mut args = args();
-    args.next();
-    let mut old_dir = args.next().unwrap();
-    let mut new_dir = args.next().unwrap();
-    if old_dir.ends_with(path::MAIN_SEPARATOR) {
-        old_dir = old_dir[0..old_dir.len() - 1].to_string();
-    }
-    if new_dir.ends_with(path::MAIN_SEPARATOR) {
-        new_dir = new_dir[0..new_dir.len() - 1].to_string();
-    }
-    let new_dir = String::from_str(
-        Path::new(&new_dir)
-            .canonicalize()
-            .unwrap()
-            .to_str()
-            .unwrap(),
-    )
-    .unwrap();
-    let old_dir = String::from_str(
-        Path::new(&old_dir)
-            .canonicalize()
-            .unwrap()
-            .to_str()
-            .unwrap(),
-    )
-    .unwrap();
-    (old_dir, new_dir)
+/// Compares without the TUI. A walk cut short by `--max-files` is an error
+/// here, a partial answer would be a wrong one.
+fn compare_all(engine: &Engine) -> Result<Comparison> {
+    let comparison = engine.compare(&mut |_| true)?;
+    if let Some(limit) = comparison.truncated {
+        bail!(
+            "more than {} entries in a tree, raise --max-files or leave paths out with .diffignore",
+            limit
+        );
+    }
+    Ok(comparison)
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, tabs: &mut Tabs) -> io::Result<()> {
+    let mut redraw = true;
+    // captured only while a minimap is shown, see App::wants_mouse
+    let mut mouse = false;
     loop {
-        app.draw_terminal(terminal)?;
-        if let Event::Key(key) = event::read()? {
-            match key.code {
-                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
-                _ => app.event(key.code),
+        if redraw {
+            tabs.draw_terminal(terminal)?;
+            if tabs.wants_mouse() != mouse {
+                mouse = !mouse;
+                if mouse {
+                    io::stdout().execute(EnableMouseCapture)?;
+                } else {
+                    io::stdout().execute(DisableMouseCapture)?;
+                }
+            }
+        }
+        // Ctrl+C while loading, or SIGINT
+        if interrupt::requested() {
+            return Ok(());
+        }
+        redraw = true;
+        if !event::poll(TICK_RATE)? {
+            redraw = tabs.tick();
+            continue;
+        }
+        match event::read()? {
+            Event::Key(key) if interrupt::is_ctrl_c(&key) => {
+                interrupt::request();
+                return Ok(());
+            }
+            Event::Key(key) => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc if !tabs.is_typing() => return Ok(()),
+                _ => {
+                    tabs.event(key.code);
+                    if let Some(mut launch) = tabs.take_launch() {
+                        let status = launch::run(&mut launch::Stdout, &mut launch.command);
+                        // the program drew over the screen, draw all of it again
+                        terminal.clear()?;
+                        // and gave the terminal back without mouse capture
+                        mouse = false;
+                        tabs.launched(&launch.name, status);
+                    }
+                }
+            },
+            Event::Mouse(mouse) => tabs.mouse(mouse),
+            // without a fresh full draw the old frame stays garbled until the next key
+            Event::Resize(width, height) => {
+                terminal.resize(Rect::new(0, 0, width, height))?;
+                tabs.on_resize(height);
             }
+            _ => {}
         }
     }
 }
 
+/// Shows the start screen until a pair that can be opened is picked, which
+/// `options` then compares. Returns false when the user quit instead.
+fn run_launcher<B: Backend>(
+    terminal: &mut Terminal<B>,
+    launcher: &mut Launcher,
+    options: &mut Options,
+) -> io::Result<bool> {
+    let monochrome = options.no_color;
+    loop {
+        terminal.draw(|f| {
+            launcher.draw(f);
+            if monochrome {
+                f.render_widget(Monochrome, f.size());
+            }
+        })?;
+        match event::read()? {
+            Event::Key(key) if interrupt::is_ctrl_c(&key) => {
+                interrupt::request();
+                return Ok(false);
+            }
+            Event::Key(key) => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                code => {
+                    if let Some((old, new)) = launcher.event(code) {
+                        match options.set_pair(old, new) {
+                            Ok(()) => return Ok(true),
+                            Err(e) => launcher.set_message(format!("{:#}", e)),
+                        }
+                    }
+                }
+            },
+            Event::Resize(width, height) => terminal.resize(Rect::new(0, 0, width, height))?,
+            _ => {}
+        }
+    }
+}
+
+/// Puts the pairs about to be compared on top of the start screen's recent
+/// list, the first one topmost.
+fn record_recent(options: &Options) {
+    let file = recent::default_file();
+    let pairs = std::iter::once((&options.old_dir, &options.new_dir))
+        .chain(options.pairs.iter().map(|(old, new)| (old, new)));
+    for (old, new) in pairs.rev() {
+        if let Err(e) = recent::record_to(&file, old, new) {
+            log::warn!("remember the recent pairs failed: {}", e);
+            return;
+        }
+    }
+}
+
+/// Restores the terminal before the panic message is printed, otherwise it is
+/// lost in the alternate screen and the shell is left in raw mode.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        shutdown_terminal();
+        log::error!("{}", info);
+        default_hook(info);
+    }));
+}
+
 fn setup_terminal() -> Result<()> {
     enable_raw_mode()?;
     io::stdout().execute(EnterAlternateScreen)?;
@@ -101,6 +481,12 @@ fn start_terminal<W: Write>(buf: W) -> io::Result<Terminal<CrosstermBackend<W>>>
 }
 
 fn shutdown_terminal() {
+    let disable_mouse = io::stdout().execute(DisableMouseCapture).map(|_f| ());
+
+    if let Err(e) = disable_mouse {
+        log::error!("disable_mouse_capture failed:\n{e}\n");
+    }
+
     let leave_screen = io::stdout().execute(LeaveAlternateScreen).map(|_f| ());
 
     if let Err(e) = leave_screen {
@@ -0,0 +1,255 @@
+use crate::status::{Entry, Special};
+use crate::text;
+use crate::throttle::Throttled;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// First line of a manifest holding sizes and hashes only.
+const HEADER: &str = "# diff-folders manifest v1";
+/// First line of a snapshot, whose index may point at compressed contents.
+const SNAPSHOT_HEADER: &str = "# diff-folders snapshot v2";
+/// Separates a snapshot's index from the compressed contents that follow it.
+const DATA_MARKER: &str = "# data";
+
+/// Checks whether `path` is a manifest or snapshot written by [`write`].
+pub fn is_manifest(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    let mut first = String::new();
+    match File::open(path) {
+        Ok(f) => {
+            BufReader::new(f).read_line(&mut first).is_ok()
+                && matches!(first.trim_end(), HEADER | SNAPSHOT_HEADER)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Records every entry of `files` (keyed by relative path) with its size and
+/// content hash, one tab separated line each. Paths are written with `/` so a
+/// snapshot taken on Windows reads back anywhere. With `contents` every file is
+/// also stored deflate-compressed after the index.
+pub fn write(files: &HashMap<PathBuf, Entry>, out: &Path, contents: bool) -> io::Result<usize> {
+    let mut keys: Vec<&PathBuf> = files.keys().filter(|k| !k.as_os_str().is_empty()).collect();
+    keys.sort();
+    let mut w = BufWriter::new(File::create(out)?);
+    writeln!(w, "{}", SNAPSHOT_HEADER)?;
+    // the index in front needs the offsets of the blobs, so they are streamed
+    // to a file of their own and appended once the index is written
+    let mut name = out.as_os_str().to_owned();
+    name.push(format!(".{}.data", std::process::id()));
+    let data_file = scopeguard::guard(PathBuf::from(name), |file| {
+        let _ = fs::remove_file(file);
+    });
+    let mut data = BufWriter::new(
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&*data_file)?,
+    );
+    for key in &keys {
+        let entry = &files[*key];
+        if entry.is_dir() {
+            writeln!(w, "d\t0\t-\t-\t-\t{}", text::slash_path(key))?;
+            continue;
+        }
+        // special files keep only their type, in the hash column
+        if let Some(special) = entry.special {
+            writeln!(
+                w,
+                "s\t0\t{}\t-\t-\t{}",
+                special.name(),
+                text::slash_path(key)
+            )?;
+            continue;
+        }
+        let hash = hash_file(entry.path())?;
+        if contents {
+            let offset = data.stream_position()?;
+            let mut encoder = DeflateEncoder::new(&mut data, Compression::default());
+            io::copy(&mut Throttled(File::open(entry.path())?), &mut encoder)?;
+            encoder.finish()?;
+            writeln!(
+                w,
+                "f\t{}\t{}\t{}\t{}\t{}",
+                entry.size,
+                hash,
+                offset,
+                data.stream_position()? - offset,
+                text::slash_path(key)
+            )?;
+        } else {
+            writeln!(
+                w,
+                "f\t{}\t{}\t-\t-\t{}",
+                entry.size,
+                hash,
+                text::slash_path(key)
+            )?;
+        }
+    }
+    writeln!(w, "{}", DATA_MARKER)?;
+    let mut data = data.into_inner().map_err(|e| e.into_error())?;
+    data.rewind()?;
+    io::copy(&mut data, &mut w)?;
+    w.flush()?;
+    Ok(keys.len())
+}
+
+/// One parsed index line.
+struct Record {
+    rel: PathBuf,
+    is_dir: bool,
+    size: u64,
+    hash: Option<String>,
+    special: Option<Special>,
+    /// offset and length of the compressed contents in the data section
+    blob: Option<(u64, u64)>,
+}
+
+/// Parses the index of a manifest or snapshot, returning its records and the
+/// file offset where the data section starts.
+fn read_index(file: &Path) -> io::Result<(Vec<Record>, u64)> {
+    let mut reader = BufReader::new(File::open(file)?);
+    let mut line = String::new();
+    let mut pos = reader.read_line(&mut line)? as u64;
+    let fields = if line.trim_end() == SNAPSHOT_HEADER {
+        6
+    } else {
+        4
+    };
+    let mut records = Vec::new();
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line)?;
+        pos += n as u64;
+        let text = line.trim_end_matches('\n');
+        if n == 0 || text == DATA_MARKER {
+            break;
+        }
+        let parts: Vec<&str> = text.splitn(fields, '\t').collect();
+        if parts.len() != fields {
+            log::warn!("{}: skip malformed line {:?}", file.display(), text);
+            continue;
+        }
+        let is_dir = parts[0] == "d";
+        let special = match parts[0] {
+            "s" => parts[2].parse().ok(),
+            _ => None,
+        };
+        let blob = match (fields, parts.get(3), parts.get(4)) {
+            (6, Some(offset), Some(len)) => offset.parse().ok().zip(len.parse().ok()),
+            _ => None,
+        };
+        records.push(Record {
+            rel: PathBuf::from(parts[fields - 1]),
+            is_dir,
+            size: parts[1].parse().unwrap_or_default(),
+            hash: (!is_dir && special.is_none()).then(|| parts[2].to_string()),
+            special,
+            blob,
+        });
+    }
+    Ok((records, pos))
+}
+
+/// A manifest or snapshot whose index was read once, for looking up entries
+/// and reading recorded contents without parsing it again.
+pub struct Manifest {
+    file: PathBuf,
+    entries: HashMap<PathBuf, Entry>,
+    // offset and length of the compressed contents of files that have them
+    blobs: HashMap<PathBuf, (u64, u64)>,
+    // where the data section starts in the file
+    data_start: u64,
+}
+
+impl Manifest {
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let (records, data_start) = read_index(file)?;
+        let mut entries = HashMap::new();
+        let mut blobs = HashMap::new();
+        for record in records {
+            let mut entry = Entry::new(file.join(&record.rel), record.is_dir, record.size);
+            entry.hash = record.hash;
+            entry.special = record.special;
+            if let Some(blob) = record.blob {
+                blobs.insert(record.rel.clone(), blob);
+            }
+            entries.insert(record.rel, entry);
+        }
+        Ok(Manifest {
+            file: file.to_path_buf(),
+            entries,
+            blobs,
+            data_start,
+        })
+    }
+
+    /// The recorded entries keyed by relative path, placed under the manifest
+    /// path itself so they render like a directory's entries.
+    pub fn entries(&self) -> &HashMap<PathBuf, Entry> {
+        &self.entries
+    }
+
+    /// The recorded contents of the file at `rel`.
+    pub fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        let (offset, len) = *self.blobs.get(rel).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "contents were not recorded in the snapshot",
+            )
+        })?;
+        let mut file = File::open(&self.file)?;
+        file.seek(SeekFrom::Start(self.data_start + offset))?;
+        let mut bytes = Vec::new();
+        DeflateDecoder::new(file.take(len)).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Loads a manifest as entries keyed by relative path, see
+/// [`Manifest::entries`].
+pub fn read(file: &Path) -> io::Result<HashMap<PathBuf, Entry>> {
+    Ok(Manifest::open(file)?.entries)
+}
+
+/// Reads a file's text; when `path` points inside a snapshot, the contents
+/// recorded in the snapshot are returned instead.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read_bytes(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Like [`read_to_string`] without requiring UTF-8.
+pub fn read_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    let err = match fs::read(path) {
+        Ok(bytes) => return Ok(bytes),
+        Err(e) => e,
+    };
+    let snapshot = match path.ancestors().skip(1).find(|p| is_manifest(p)) {
+        Some(snapshot) => snapshot,
+        None => return Err(err),
+    };
+    Manifest::open(snapshot)?.read(path.strip_prefix(snapshot).unwrap())
+}
+
+/// Hex encoded SHA-256 of a file's contents.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut Throttled(File::open(path)?), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hex encoded SHA-256 of `bytes`, matching [`hash_file`].
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
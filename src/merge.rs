@@ -0,0 +1,32 @@
+use crate::patch;
+use similar::TextDiff;
+
+/// Renders `old` and `new` as one text where every hunk becomes a conflict
+/// block, ready to be resolved in an editor.
+pub fn conflict_text(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let (old_lines, new_lines) = (diff.old_slices(), diff.new_slices());
+    let mut out = String::with_capacity(old.len() + new.len());
+    let mut pos = 0;
+    for hunk in patch::hunks(old, new) {
+        push_lines(&mut out, &old_lines[pos..hunk.old_range.start]);
+        out.push_str(&format!("<<<<<<< {}\n", old_label));
+        push_lines(&mut out, &old_lines[hunk.old_range.clone()]);
+        out.push_str("=======\n");
+        push_lines(&mut out, &new_lines[hunk.new_range.clone()]);
+        out.push_str(&format!(">>>>>>> {}\n", new_label));
+        pos = hunk.old_range.end;
+    }
+    out.extend(old_lines[pos..].iter().copied());
+    out
+}
+
+// markers must start on their own line even when the file lacks a final newline
+fn push_lines(out: &mut String, lines: &[&str]) {
+    for line in lines {
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+}
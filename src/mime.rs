@@ -0,0 +1,135 @@
+/// Bytes looked at to tell text from binary, the same amount git uses. They
+/// also hold every magic number below.
+pub const SNIFF_LEN: usize = 8000;
+
+/// The type given to folders.
+pub const DIRECTORY: &str = "inode/directory";
+
+/// Known leading bytes, with the offset they start at, and their MIME type.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"BM", "image/bmp"),
+    (0, b"II*\0", "image/tiff"),
+    (0, b"MM\0*", "image/tiff"),
+    (0, b"\0\0\x01\0", "image/x-icon"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\0", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"\x7fELF", "application/x-elf"),
+    (0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"MZ", "application/vnd.microsoft.portable-executable"),
+    (0, b"\0asm", "application/wasm"),
+    (0, b"\xca\xfe\xba\xbe", "application/java-vm"),
+    (0, b"SQLite format 3\0", "application/vnd.sqlite3"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"OTTO", "font/otf"),
+    (0, b"\0\x01\0\0", "font/ttf"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1a\x45\xdf\xa3", "video/webm"),
+];
+
+/// Whether `bytes` look binary: a NUL byte near the start, like git decides.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_LEN)].contains(&0)
+}
+
+/// MIME type guessed from the leading bytes of a file.
+pub fn detect(bytes: &[u8]) -> &'static str {
+    if let Some((_, _, mime)) = MAGIC
+        .iter()
+        .find(|(offset, magic, _)| bytes.get(*offset..).is_some_and(|b| b.starts_with(magic)))
+    {
+        return mime;
+    }
+    // RIFF containers name their format after the size field
+    if bytes.starts_with(b"RIFF") {
+        match bytes.get(8..12) {
+            Some(b"WEBP") => return "image/webp",
+            Some(b"WAVE") => return "audio/wav",
+            Some(b"AVI ") => return "video/x-msvideo",
+            _ => {}
+        }
+    }
+    if is_binary(bytes) {
+        "application/octet-stream"
+    } else {
+        "text/plain"
+    }
+}
+
+/// A family of MIME types the file list can be narrowed to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TypeFilter {
+    Text,
+    /// anything that is not text
+    Binary,
+    Image,
+    Audio,
+    Video,
+    Font,
+    /// zip, tar and compressed files
+    Archive,
+}
+
+impl TypeFilter {
+    pub fn matches(self, mime: &str) -> bool {
+        let (kind, sub) = mime.split_once('/').unwrap_or((mime, ""));
+        match self {
+            TypeFilter::Text => kind == "text",
+            TypeFilter::Binary => kind != "text" && mime != DIRECTORY,
+            TypeFilter::Image => kind == "image",
+            TypeFilter::Audio => kind == "audio",
+            TypeFilter::Video => kind == "video",
+            TypeFilter::Font => kind == "font",
+            TypeFilter::Archive => matches!(
+                sub,
+                "zip" | "gzip" | "x-bzip2" | "x-xz" | "zstd" | "x-7z-compressed" | "x-tar"
+            ),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TypeFilter::Text => "text",
+            TypeFilter::Binary => "binary",
+            TypeFilter::Image => "image",
+            TypeFilter::Audio => "audio",
+            TypeFilter::Video => "video",
+            TypeFilter::Font => "font",
+            TypeFilter::Archive => "archive",
+        }
+    }
+}
+
+impl std::str::FromStr for TypeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(TypeFilter::Text),
+            "binary" => Ok(TypeFilter::Binary),
+            "image" => Ok(TypeFilter::Image),
+            "audio" => Ok(TypeFilter::Audio),
+            "video" => Ok(TypeFilter::Video),
+            "font" => Ok(TypeFilter::Font),
+            "archive" => Ok(TypeFilter::Archive),
+            _ => Err(format!(
+                "expected text, binary, image, audio, video, font or archive, got {}",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Results of a comparison, independent of how they were produced or shown.
+
+use crate::profile::Timing;
+use crate::status::FolderStatefulList;
+use crate::summary::Summary;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// What [`crate::engine::Engine::compare`] found.
+#[derive(Default)]
+pub struct Comparison {
+    /// one entry per difference, contents of new and deleted folders folded
+    /// into the folder, except for moved files
+    pub results: Vec<FolderStatefulList>,
+    pub summary: Summary,
+    /// bytes gained or lost in the new tree
+    pub size_delta: i64,
+    /// the scan was stopped before it finished
+    pub is_partial: bool,
+    /// a side had more entries than this `--max-files` cap, so its walk
+    /// stopped early and only part of the trees was compared
+    pub truncated: Option<usize>,
+    /// how long deciding each file pair took, for `--profile`
+    pub timings: Vec<Timing>,
+    /// MIME type of each result by its path, once [`Engine::detect_types`]
+    /// ran
+    ///
+    /// [`Engine::detect_types`]: crate::engine::Engine::detect_types
+    pub types: HashMap<PathBuf, &'static str>,
+}
+
+impl Comparison {
+    /// Whether any difference was found.
+    pub fn has_differences(&self) -> bool {
+        !self.results.is_empty()
+    }
+}
+
+/// The order results are listed in, `--order`. Every order compares the
+/// relative paths component by component as text, so the same trees list the
+/// same way on every run and platform.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum Order {
+    /// a folder, then everything inside it
+    #[default]
+    Dfs,
+    /// everything at one depth before anything deeper
+    Bfs,
+    /// the whole relative path as a string, so `a.txt` comes before `a/b`
+    Alpha,
+}
+
+impl Order {
+    /// Sorts `files`, `rel` gives an item's path relative to its tree.
+    pub fn sort(
+        self,
+        files: &mut [FolderStatefulList],
+        rel: impl Fn(&FolderStatefulList) -> PathBuf,
+    ) {
+        files.sort_by_cached_key(|f| {
+            let parts = components(&rel(f));
+            let depth = match self {
+                Order::Bfs => parts.len(),
+                _ => 0,
+            };
+            let key = match self {
+                Order::Alpha => vec![parts.join("/")],
+                _ => parts,
+            };
+            (depth, key)
+        });
+    }
+}
+
+impl std::str::FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dfs" => Ok(Order::Dfs),
+            "bfs" => Ok(Order::Bfs),
+            "alpha" => Ok(Order::Alpha),
+            _ => Err(format!("expected dfs, bfs or alpha, got {}", s)),
+        }
+    }
+}
+
+// `./` and the separator style of the platform do not count
+fn components(rel: &Path) -> Vec<String> {
+    rel.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Sorts `files` by path and drops entries inside new or deleted folders, the
+/// folder alone stands for them. Files paired as moves are kept, they tell
+/// where a file went.
+pub fn collapse_dirs(files: &mut Vec<FolderStatefulList>) {
+    files.sort_by(|x, y| x.entry.path().cmp(y.entry.path()));
+    // the new or deleted folder the entries are inside of
+    let mut folded: Option<PathBuf> = None;
+    files.retain(|f| {
+        if let Some(dir) = &folded {
+            if f.entry.path().starts_with(dir) {
+                return f.moved.is_some();
+            }
+        }
+        folded = (f.entry.is_dir()
+            && (f.state == crate::status::StatusItemType::Deleted
+                || f.state == crate::status::StatusItemType::New))
+            .then(|| f.entry.path().to_path_buf());
+        true
+    });
+}
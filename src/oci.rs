@@ -0,0 +1,642 @@
+//! Container images as one side of a comparison, `oci:<image>` or `--oci`.
+//! The layers are streamed out of the image and stacked in memory, with
+//! their whiteouts applied, so the root filesystem is never unpacked. Where
+//! each blob and each file starts is indexed while doing so, and reads seek
+//! straight there.
+
+use crate::filter::WalkFilter;
+use crate::manifest;
+use crate::source::{clean_rel, not_found, Source};
+use crate::status::{Entry, Special};
+use crate::text;
+use flate2::read::GzDecoder;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+/// The root filesystem of a container image.
+pub struct Oci {
+    store: Store,
+    // blob names of the layers, bottom first
+    layers: Vec<String>,
+    label: PathBuf,
+    files: HashMap<PathBuf, Member>,
+    // the layer the last read was from
+    stream: Mutex<Option<LayerStream>>,
+    // a tarball saved from the image store for this run, removed on drop
+    saved: Option<PathBuf>,
+}
+
+/// An entry of the stacked filesystem.
+#[derive(Clone)]
+struct Member {
+    // the layer that added it
+    layer: usize,
+    entry: Entry,
+    data: Data,
+}
+
+#[derive(Clone)]
+enum Data {
+    Dir,
+    /// a member of a layer, whose contents start at `offset` in the
+    /// decompressed layer
+    File {
+        layer: usize,
+        offset: u64,
+    },
+    /// symlink targets and the empty contents of devices
+    Inline(Vec<u8>),
+}
+
+/// Which layer, its decompressed stream, and how far into it the last read
+/// got.
+type LayerStream = (usize, Box<dyn Read + Send>, u64);
+
+impl Oci {
+    /// Opens `image`: an OCI layout folder, a tarball written by
+    /// `docker save` or `podman save`, or the name of an image in the local
+    /// docker (or podman) store, which is saved to the cache directory first
+    /// and removed again when the image is dropped.
+    pub fn open(image: &str) -> io::Result<Self> {
+        let path = Path::new(image);
+        let (store, saved) = if path.is_dir() {
+            (Store::Dir(path.to_path_buf()), None)
+        } else if path.is_file() {
+            (Store::tar(path.to_path_buf())?, None)
+        } else {
+            let file = save(image)?;
+            let store = Store::tar(file.clone()).inspect_err(|_| {
+                let _ = fs::remove_file(&file);
+            })?;
+            (store, Some(file))
+        };
+        // made before the layers are read, so a failure still drops it and
+        // removes a saved tarball
+        let mut oci = Oci {
+            store,
+            layers: Vec::new(),
+            label: PathBuf::from(format!("oci:{}", image)),
+            files: HashMap::new(),
+            stream: Mutex::new(None),
+            saved,
+        };
+        oci.layers = layers(&oci.store)?;
+        for layer in 0..oci.layers.len() {
+            oci.apply(layer)?;
+        }
+        Ok(oci)
+    }
+
+    /// Puts the entries of `layer` over the ones below it, and drops what its
+    /// whiteouts hide.
+    fn apply(&mut self, layer: usize) -> io::Result<()> {
+        let Oci {
+            store,
+            layers,
+            label,
+            files,
+            ..
+        } = self;
+        let mut archive = tar::Archive::new(layer_reader(store.blob(&layers[layer])?)?);
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let name = clean_rel(&entry.path()?);
+            let file_name = name
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let dir = name.parent().unwrap_or(Path::new("")).to_path_buf();
+            // whiteouts only ever hide entries of lower layers
+            if file_name == ".wh..wh..opq" {
+                files.retain(|rel, m| m.layer == layer || rel == &dir || !rel.starts_with(&dir));
+                continue;
+            }
+            if let Some(hidden) = file_name.strip_prefix(".wh.") {
+                let hidden = dir.join(hidden);
+                files.retain(|rel, m| m.layer == layer || !rel.starts_with(&hidden));
+                continue;
+            }
+            if name.as_os_str().is_empty() {
+                continue;
+            }
+            // layers may leave out the entries of their directories
+            for dir in name.ancestors().skip(1) {
+                if dir.as_os_str().is_empty() {
+                    break;
+                }
+                files.entry(dir.to_path_buf()).or_insert_with(|| Member {
+                    layer,
+                    entry: Entry::new(label.join(dir), true, 0),
+                    data: Data::Dir,
+                });
+            }
+            let path = label.join(&name);
+            let header = entry.header();
+            let inline = |entry: Entry, bytes: Vec<u8>| Member {
+                layer,
+                entry,
+                data: Data::Inline(bytes),
+            };
+            let member = match header.entry_type() {
+                tar::EntryType::Directory => Member {
+                    layer,
+                    entry: Entry::new(path, true, 0),
+                    data: Data::Dir,
+                },
+                tar::EntryType::Symlink => {
+                    let target = entry.link_name()?.unwrap_or_default();
+                    let target = target.to_string_lossy().into_owned().into_bytes();
+                    inline(Entry::new(path, false, target.len() as u64), target)
+                }
+                tar::EntryType::Link => {
+                    let target = clean_rel(&entry.link_name()?.unwrap_or_default());
+                    match files.get(&target) {
+                        Some(target) => Member {
+                            layer,
+                            entry: Entry::new(path, false, target.entry.size),
+                            data: target.data.clone(),
+                        },
+                        None => {
+                            log::warn!(
+                                "skip {}: hard link to missing {}",
+                                path.display(),
+                                target.display()
+                            );
+                            continue;
+                        }
+                    }
+                }
+                kind @ (tar::EntryType::Char | tar::EntryType::Block | tar::EntryType::Fifo) => {
+                    let mut entry = Entry::new(path, false, 0);
+                    entry.special = Some(match kind {
+                        tar::EntryType::Char => Special::CharDevice,
+                        tar::EntryType::Block => Special::BlockDevice,
+                        _ => Special::Fifo,
+                    });
+                    inline(entry, Vec::new())
+                }
+                _ => Member {
+                    layer,
+                    entry: Entry::new(path, false, header.size()?),
+                    data: Data::File {
+                        layer,
+                        offset: entry.raw_file_position(),
+                    },
+                },
+            };
+            files.insert(name, member);
+        }
+        Ok(())
+    }
+
+    /// At most `len` bytes of the file at `rel`.
+    fn contents(&self, rel: &Path, len: u64) -> io::Result<Vec<u8>> {
+        let member = self
+            .files
+            .get(rel)
+            .ok_or_else(|| not_found(&self.label, rel))?;
+        let (layer, offset) = match &member.data {
+            Data::Dir => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is a directory", member.entry.path().display()),
+                ))
+            }
+            Data::Inline(bytes) => return Ok(bytes[..bytes.len().min(len as usize)].to_vec()),
+            Data::File { layer, offset } => (*layer, *offset),
+        };
+        let len = len.min(member.entry.size);
+        let mut stream = self.stream.lock().unwrap();
+        // only a file behind the stream, or in another layer, starts it over
+        let (mut reader, pos) = match stream.take() {
+            Some((at, reader, pos)) if at == layer && pos <= offset => (reader, pos),
+            _ => (layer_reader(self.store.blob(&self.layers[layer])?)?, 0),
+        };
+        io::copy(&mut (&mut reader).take(offset - pos), &mut io::sink())?;
+        let mut bytes = Vec::with_capacity(len as usize);
+        (&mut reader).take(len).read_to_end(&mut bytes)?;
+        *stream = Some((layer, reader, offset + bytes.len() as u64));
+        if (bytes.len() as u64) < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} is cut short", member.entry.path().display()),
+            ));
+        }
+        Ok(bytes)
+    }
+}
+
+impl Drop for Oci {
+    fn drop(&mut self) {
+        if let Some(file) = &self.saved {
+            if let Err(e) = fs::remove_file(file) {
+                log::warn!("remove {} failed: {}", file.display(), e);
+            }
+        }
+    }
+}
+
+impl Source for Oci {
+    fn root(&self) -> &Path {
+        &self.label
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        Ok(self
+            .files
+            .iter()
+            .filter(|(rel, m)| !filter.is_ignored(rel, m.entry.is_dir()))
+            .map(|(rel, m)| (rel.clone(), m.entry.clone()))
+            .collect())
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        self.contents(rel, u64::MAX)
+    }
+
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        self.contents(rel, len as u64)
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        self.files
+            .get(rel)
+            .map(|m| m.entry.clone())
+            .ok_or_else(|| not_found(&self.label, rel))
+    }
+
+    /// Layers are read bottom first, each front to back.
+    fn position(&self, rel: &Path) -> Option<u64> {
+        match self.files.get(rel)?.data {
+            // no layer comes near 2^48 bytes
+            Data::File { layer, offset } => Some(((layer as u64) << 48) + offset),
+            _ => None,
+        }
+    }
+}
+
+/// Where the blobs of an image are: an OCI layout folder, or a tarball of
+/// one or of `docker save` output.
+enum Store {
+    Dir(PathBuf),
+    /// the tarball, and where the contents of each of its members start in
+    /// it and how long they are
+    Tar(PathBuf, HashMap<String, (u64, u64)>),
+}
+
+impl Store {
+    /// Reads the headers of every member of the tarball `file` once.
+    fn tar(file: PathBuf) -> io::Result<Self> {
+        let mut blobs = HashMap::new();
+        let mut archive = tar::Archive::new(File::open(&file)?);
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let name = text::slash_path(&clean_rel(&entry.path()?));
+            blobs.insert(name, (entry.raw_file_position(), entry.header().size()?));
+        }
+        Ok(Store::Tar(file, blobs))
+    }
+
+    /// A reader of `name`, a `/` separated path in the store.
+    fn blob(&self, name: &str) -> io::Result<io::Take<File>> {
+        match self {
+            Store::Dir(dir) => Ok(File::open(dir.join(name))?.take(u64::MAX)),
+            Store::Tar(file, blobs) => {
+                let (offset, size) = blobs
+                    .get(name)
+                    .ok_or_else(|| not_found(file, Path::new(name)))?;
+                let mut reader = File::open(file)?;
+                reader.seek(SeekFrom::Start(*offset))?;
+                Ok(reader.take(*size))
+            }
+        }
+    }
+
+    fn json(&self, name: &str) -> io::Result<Json> {
+        let mut text = String::new();
+        self.blob(name)?.read_to_string(&mut text)?;
+        Json::parse(&text)
+    }
+}
+
+/// The blob names of the image's layers in `store`, bottom first.
+fn layers(store: &Store) -> io::Result<Vec<String>> {
+    // `docker save` lists them in manifest.json, also when it writes an OCI
+    // layout next to it
+    if let Ok(manifest) = store.json("manifest.json") {
+        let layers = manifest
+            .items()
+            .first()
+            .and_then(|image| image.get("Layers"));
+        return layers
+            .map(|layers| {
+                layers
+                    .items()
+                    .iter()
+                    .filter_map(Json::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .ok_or_else(|| invalid("manifest.json lists no layers"));
+    }
+    let manifest = image_manifest(store, store.json("index.json")?)?;
+    manifest
+        .get("layers")
+        .map_or(&[][..], Json::items)
+        .iter()
+        .map(|layer| match layer.get("digest").and_then(Json::as_str) {
+            Some(digest) => blob_name(digest),
+            None => Err(invalid("a layer of the image manifest has no digest")),
+        })
+        .collect()
+}
+
+/// Follows `index` down to the image manifest for this platform, or to the
+/// first one when none is for it.
+fn image_manifest(store: &Store, index: Json) -> io::Result<Json> {
+    let mut json = index;
+    // an index lists manifests, possibly one per platform, possibly indexes
+    loop {
+        let next = match json.get("manifests") {
+            Some(manifests) => {
+                let manifests = manifests.items();
+                let manifest = manifests
+                    .iter()
+                    .find(|m| is_this_platform(m))
+                    .or_else(|| manifests.first())
+                    .ok_or_else(|| invalid("the image index lists no manifests"))?;
+                match manifest.get("digest").and_then(Json::as_str) {
+                    Some(digest) => blob_name(digest)?,
+                    None => return Err(invalid("a manifest of the image index has no digest")),
+                }
+            }
+            None => return Ok(json),
+        };
+        json = store.json(&next)?;
+    }
+}
+
+fn is_this_platform(manifest: &Json) -> bool {
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    let platform = |key: &str| {
+        manifest
+            .get("platform")
+            .and_then(|p| p.get(key))
+            .and_then(Json::as_str)
+    };
+    platform("os") == Some("linux") && platform("architecture") == Some(arch)
+}
+
+/// `blobs/sha256/<hex>` for the digest `sha256:<hex>`.
+fn blob_name(digest: &str) -> io::Result<String> {
+    match digest.split_once(':') {
+        Some((algorithm, hex))
+            if !algorithm.is_empty()
+                && !hex.is_empty()
+                && [algorithm, hex]
+                    .iter()
+                    .all(|part| part.chars().all(|c| c.is_ascii_alphanumeric())) =>
+        {
+            Ok(format!("blobs/{}/{}", algorithm, hex))
+        }
+        _ => Err(invalid(&format!("bad digest {}", digest))),
+    }
+}
+
+/// A layer blob as the tar archive in it, decompressed when it is gzipped.
+fn layer_reader(blob: io::Take<File>) -> io::Result<Box<dyn Read + Send>> {
+    let mut blob = BufReader::new(blob);
+    let magic = blob.fill_buf()?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(GzDecoder::new(blob)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Err(invalid("zstd compressed layers are not supported"))
+    } else {
+        Ok(Box::new(blob))
+    }
+}
+
+/// Saves `image` from the local image store with `docker save`, or with
+/// `podman save` where there is no docker. Pull images from a registry first.
+fn save(image: &str) -> io::Result<PathBuf> {
+    let dir = crate::log::cache_dir().join("images");
+    fs::create_dir_all(&dir)?;
+    // one per run, so runs on the same image do not write over each other
+    let file = dir.join(format!(
+        "{}-{}.tar",
+        &manifest::hash_bytes(image.as_bytes())[..16],
+        std::process::id()
+    ));
+    for tool in ["docker", "podman"] {
+        let out = match Command::new(tool)
+            .arg("save")
+            .arg("-o")
+            .arg(&file)
+            .arg(image)
+            .output()
+        {
+            Ok(out) => out,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "{} save {} failed: {}",
+                tool,
+                image,
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        return Ok(file);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "{} is no file or folder, and there is no docker or podman to save it with",
+            image
+        ),
+    ))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Just enough JSON for image indexes and manifests.
+enum Json {
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+    /// numbers, booleans and null, which no lookup needs
+    Other,
+}
+
+impl Json {
+    fn parse(text: &str) -> io::Result<Json> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(parser.error()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array, none for anything else.
+    fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self) -> io::Error {
+        invalid(&format!("bad JSON at byte {}", self.pos))
+    }
+
+    /// The next byte that is not white space, left unread.
+    fn peek(&mut self) -> Option<u8> {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        if self.peek() != Some(byte) {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> io::Result<Json> {
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value()?));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(_) => {
+                let start = self.pos;
+                while self
+                    .text
+                    .get(self.pos)
+                    .is_some_and(|b| b.is_ascii_alphanumeric() || b"+-.".contains(b))
+                {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(self.error());
+                }
+                Ok(Json::Other)
+            }
+            None => Err(self.error()),
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let byte = *self.text.get(self.pos).ok_or_else(|| self.error())?;
+            self.pos += 1;
+            match byte {
+                b'"' => return Ok(String::from_utf8_lossy(&out).into_owned()),
+                b'\\' => {
+                    let escaped = *self.text.get(self.pos).ok_or_else(|| self.error())?;
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let code = self
+                                .text
+                                .get(self.pos..self.pos + 4)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| self.error())?;
+                            self.pos += 4;
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        other => other as char,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => out.push(byte),
+            }
+        }
+    }
+}
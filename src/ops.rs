@@ -0,0 +1,101 @@
+use crate::status::ensure_not_special;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Which tree is rewritten when a change is applied.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum ApplyDirection {
+    /// make the old tree match the new one
+    #[default]
+    NewToOld,
+    /// make the new tree match the old one
+    OldToNew,
+}
+
+impl std::str::FromStr for ApplyDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "old" => Ok(ApplyDirection::NewToOld),
+            "new" => Ok(ApplyDirection::OldToNew),
+            _ => Err(format!("expected old or new, got {}", s)),
+        }
+    }
+}
+
+/// Makes `dst` match `src`, deleting `dst` when `src` is `None`. A deleted
+/// `dst` goes to the system trash unless `permanent` is set.
+///
+/// Whatever was at `dst` is first copied to `backup`, when given; the backup
+/// path is returned when there was something to save.
+pub fn apply(
+    src: Option<&Path>,
+    dst: &Path,
+    backup: Option<&Path>,
+    permanent: bool,
+) -> io::Result<Option<PathBuf>> {
+    let mut saved = None;
+    if dst.symlink_metadata().is_ok() {
+        if let Some(backup) = backup {
+            copy_all(dst, backup)?;
+            saved = Some(backup.to_path_buf());
+        }
+        match src {
+            None if !permanent => trash(dst)?,
+            _ => remove_all(dst)?,
+        }
+    }
+    if let Some(src) = src {
+        copy_all(src, dst)?;
+    }
+    Ok(saved)
+}
+
+/// Overwrites the file `dst` with `contents`, saving the previous file to `backup`.
+pub fn write(dst: &Path, contents: &str, backup: &Path) -> io::Result<PathBuf> {
+    copy_all(dst, backup)?;
+    fs::write(dst, contents)?;
+    Ok(backup.to_path_buf())
+}
+
+/// Copies a file, or a directory recursively, creating missing parents of `dst`.
+pub fn copy_all(src: &Path, dst: &Path) -> io::Result<()> {
+    if !src.is_dir() {
+        ensure_not_special(src)?;
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(src, dst)?;
+        return Ok(());
+    }
+    for entry in walkdir::WalkDir::new(src) {
+        let entry = entry?;
+        let target = dst.join(entry.path().strip_prefix(src).unwrap());
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            ensure_not_special(entry.path())?;
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves a file or a whole directory to the system trash, where it can be
+/// restored from.
+pub fn trash(path: &Path) -> io::Result<()> {
+    trash::delete(path)
+        .map_err(|e| io::Error::other(format!("move {} to the trash: {}", path.display(), e)))
+}
+
+/// Removes a file or a whole directory.
+pub fn remove_all(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
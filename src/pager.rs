@@ -0,0 +1,42 @@
+//! Long plain text output through the user's pager, like `git diff` does.
+
+use crate::external;
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::Stdio,
+};
+
+/// Used when `$PAGER` is unset: `-R` passes colors through, and with `LESS`
+/// unset `FRX` quits right away when the text fits on one screen.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Writes `text` through `$PAGER` when stdout is a terminal, straight to
+/// stdout otherwise or when the pager cannot be started. `cat` or an empty
+/// `$PAGER` turn paging off.
+pub fn page(text: &str) -> io::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    if !io::stdout().is_terminal() || pager.trim().is_empty() || pager.trim() == "cat" {
+        return io::stdout().write_all(text.as_bytes());
+    }
+    let mut cmd = external::shell(&pager);
+    if env::var_os("LESS").is_none() {
+        cmd.env("LESS", "FRX");
+    }
+    let mut child = match cmd.stdin(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn!("cannot start pager {:?}: {}", pager, e);
+            return io::stdout().write_all(text.as_bytes());
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // quitting the pager early closes the pipe, that is no error
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use regex::RegexSet;
+use similar::{DiffOp, TextDiff};
+use std::ops::Range;
+
+/// A run of adjacent changed lines between the old and the new text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_range: Range<usize>,
+    pub new_range: Range<usize>,
+    /// index of the hunk's first line in the rendered diff, which lists every
+    /// change in order with deletions before insertions
+    pub display_start: usize,
+}
+
+impl Hunk {
+    /// Number of rendered diff lines the hunk covers.
+    pub fn display_len(&self) -> usize {
+        self.old_range.len() + self.new_range.len()
+    }
+}
+
+/// Splits the line diff of `old` and `new` into hunks.
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut display = 0;
+    // whether the previous op was a change, so adjacent changes merge
+    let mut in_hunk = false;
+    for op in diff.ops() {
+        let (old_range, new_range) = (op.old_range(), op.new_range());
+        if let DiffOp::Equal { len, .. } = op {
+            display += len;
+            in_hunk = false;
+            continue;
+        }
+        let len = old_range.len() + new_range.len();
+        match hunks.last_mut() {
+            Some(last) if in_hunk => {
+                last.old_range.end = old_range.end;
+                last.new_range.end = new_range.end;
+            }
+            _ => hunks.push(Hunk {
+                old_range,
+                new_range,
+                display_start: display,
+            }),
+        }
+        display += len;
+        in_hunk = true;
+    }
+    hunks
+}
+
+/// Whether every line `hunk` removes or adds matches one of `patterns`, the
+/// rule GNU diff uses for `-I`.
+pub fn is_ignorable(
+    hunk: &Hunk,
+    old_lines: &[&str],
+    new_lines: &[&str],
+    patterns: &RegexSet,
+) -> bool {
+    old_lines[hunk.old_range.clone()]
+        .iter()
+        .chain(&new_lines[hunk.new_range.clone()])
+        .all(|line| patterns.is_match(line.trim_end_matches(['\n', '\r'])))
+}
+
+/// Like [`hunks`], split into the hunks that matter and the ones that only
+/// touch lines matching `patterns`.
+pub fn partition_hunks(
+    old: &str,
+    new: &str,
+    patterns: Option<&RegexSet>,
+) -> (Vec<Hunk>, Vec<Hunk>) {
+    let all = hunks(old, new);
+    let patterns = match patterns {
+        Some(patterns) => patterns,
+        None => return (all, Vec::new()),
+    };
+    let diff = TextDiff::from_lines(old, new);
+    let (old_lines, new_lines) = (diff.old_slices(), diff.new_slices());
+    all.into_iter()
+        .partition(|h| !is_ignorable(h, old_lines, new_lines, patterns))
+}
+
+/// Rebuilds one side with the `selected` hunks taken from the other side.
+///
+/// With `to_old` the result is the old text with the chosen changes applied,
+/// otherwise it is the new text with the chosen changes reverted.
+pub fn apply(old: &str, new: &str, hunks: &[Hunk], selected: &[bool], to_old: bool) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let (old_lines, new_lines) = (diff.old_slices(), diff.new_slices());
+    let (base, other) = if to_old {
+        (old_lines, new_lines)
+    } else {
+        (new_lines, old_lines)
+    };
+    let mut out = String::with_capacity(old.len().max(new.len()));
+    let mut pos = 0;
+    for (hunk, &take) in hunks.iter().zip(selected) {
+        let (base_range, other_range) = if to_old {
+            (hunk.old_range.clone(), hunk.new_range.clone())
+        } else {
+            (hunk.new_range.clone(), hunk.old_range.clone())
+        };
+        out.extend(base[pos..base_range.start].iter().copied());
+        if take {
+            out.extend(other[other_range].iter().copied());
+        } else {
+            out.extend(base[base_range.clone()].iter().copied());
+        }
+        pos = base_range.end;
+    }
+    out.extend(base[pos..].iter().copied());
+    out
+}
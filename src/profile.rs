@@ -0,0 +1,89 @@
+//! Where a comparison spent its time, for `--profile`.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Time spent deciding whether one file differs.
+#[derive(Clone, Debug)]
+pub struct Timing {
+    /// relative to the tree roots
+    pub rel: PathBuf,
+    /// size on the new side
+    pub bytes: u64,
+    pub took: Duration,
+}
+
+/// Time spent on all the files below one directory.
+pub struct DirTiming {
+    pub rel: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+    pub took: Duration,
+}
+
+/// The `n` slowest files, slowest first.
+pub fn slowest_files(timings: &[Timing], n: usize) -> Vec<&Timing> {
+    let mut files: Vec<_> = timings.iter().collect();
+    files.sort_by(|a, b| b.took.cmp(&a.took).then_with(|| a.rel.cmp(&b.rel)));
+    files.truncate(n);
+    files
+}
+
+/// The `n` directories whose files took longest altogether, slowest first.
+/// The roots are left out, they hold everything.
+pub fn slowest_dirs(timings: &[Timing], n: usize) -> Vec<DirTiming> {
+    let mut dirs: HashMap<&Path, DirTiming> = HashMap::new();
+    for timing in timings {
+        for dir in timing.rel.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            let dir = dirs.entry(dir).or_insert_with(|| DirTiming {
+                rel: dir.to_path_buf(),
+                files: 0,
+                bytes: 0,
+                took: Duration::ZERO,
+            });
+            dir.files += 1;
+            dir.bytes += timing.bytes;
+            dir.took += timing.took;
+        }
+    }
+    let mut dirs: Vec<_> = dirs.into_values().collect();
+    dirs.sort_by(|a, b| b.took.cmp(&a.took).then_with(|| a.rel.cmp(&b.rel)));
+    dirs.truncate(n);
+    dirs
+}
+
+/// The slowest `n` files and directories as text, for the terminal.
+pub fn report(timings: &[Timing], n: usize) -> String {
+    let total: Duration = timings.iter().map(|t| t.took).sum();
+    let mut out = format!("{:>10} {:>14}  slowest files\n", "seconds", "bytes");
+    for file in slowest_files(timings, n) {
+        out += &format!(
+            "{:>10.3} {:>14}  {}\n",
+            file.took.as_secs_f64(),
+            file.bytes,
+            crate::text::slash_path(&file.rel)
+        );
+    }
+    out += &format!("\n{:>10} {:>14}  slowest folders\n", "seconds", "bytes");
+    for dir in slowest_dirs(timings, n) {
+        out += &format!(
+            "{:>10.3} {:>14}  {} ({} files)\n",
+            dir.took.as_secs_f64(),
+            dir.bytes,
+            crate::text::slash_path(&dir.rel),
+            dir.files
+        );
+    }
+    out += &format!(
+        "\ncompared {} files in {:.3} s\n",
+        timings.len(),
+        total.as_secs_f64()
+    );
+    out
+}
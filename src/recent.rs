@@ -0,0 +1,47 @@
+//! Pairs of trees compared in the TUI lately, newest first, offered on the
+//! start screen when diff-folders is run without folders. They are kept as
+//! `old<TAB>new` lines in `recent` under the cache directory.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Pairs kept at most; older ones are forgotten.
+pub const MAX_RECENT: usize = 20;
+
+/// `recent` under [`crate::log::cache_dir`].
+pub fn default_file() -> PathBuf {
+    crate::log::cache_dir().join("recent")
+}
+
+/// The pairs recorded in `file`, newest first; a missing file has none.
+pub fn load_from(file: &Path) -> io::Result<Vec<(String, String)>> {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(old, new)| (old.to_string(), new.to_string()))
+        .collect())
+}
+
+/// Puts `old` and `new` at the top of `file`, moving the pair up when it was
+/// there already.
+pub fn record_to(file: &Path, old: &str, new: &str) -> io::Result<()> {
+    let mut pairs = load_from(file)?;
+    pairs.retain(|(o, n)| (o.as_str(), n.as_str()) != (old, new));
+    pairs.insert(0, (old.to_string(), new.to_string()));
+    pairs.truncate(MAX_RECENT);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text: String = pairs
+        .iter()
+        .map(|(old, new)| format!("{}\t{}\n", old, new))
+        .collect();
+    fs::write(file, text)
+}
@@ -0,0 +1,109 @@
+//! Custom views of a file in the diff window. A [`DiffRenderer`] turns both
+//! sides of a file into styled lines, e.g. decoded protobuf messages instead
+//! of their raw bytes, and [`Renderers`] picks one per file by glob or MIME
+//! type. Library users register theirs with [`crate::engine::Engine::renderers_mut`].
+
+use crate::color::Color;
+use glob::Pattern;
+use std::{io, path::Path};
+
+/// One line of a rendered file, in the color of the TUI to draw it with;
+/// `None` keeps the default.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StyledLine {
+    pub text: String,
+    pub color: Option<Color>,
+}
+
+impl StyledLine {
+    pub fn plain(text: impl Into<String>) -> Self {
+        StyledLine {
+            text: text.into(),
+            color: None,
+        }
+    }
+
+    pub fn colored(text: impl Into<String>, color: Color) -> Self {
+        StyledLine {
+            text: text.into(),
+            color: Some(color),
+        }
+    }
+}
+
+/// Shows a file of some kind in the diff window.
+pub trait DiffRenderer {
+    /// Shown in the diff window title.
+    fn name(&self) -> &str;
+
+    /// The lines to show for the file at `rel`. `old` is `None` for a new
+    /// file and `new` for a deleted one.
+    fn render(
+        &self,
+        rel: &Path,
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> io::Result<Vec<StyledLine>>;
+}
+
+/// Which files a renderer is for.
+enum Matcher {
+    /// like the config file's compare rules: without a `/` the pattern
+    /// matches the file name anywhere in the tree
+    Glob(Pattern),
+    /// a MIME type, or a family such as `image/*`
+    Mime(String),
+}
+
+impl Matcher {
+    fn matches(&self, rel: &Path, mime: Option<&str>) -> bool {
+        match self {
+            Matcher::Glob(glob) if glob.as_str().contains('/') => glob.matches_path(rel),
+            Matcher::Glob(glob) => rel
+                .file_name()
+                .is_some_and(|name| glob.matches(&name.to_string_lossy())),
+            Matcher::Mime(want) => mime.is_some_and(|mime| match want.strip_suffix("/*") {
+                Some(family) => mime.split('/').next() == Some(family),
+                None => mime == want,
+            }),
+        }
+    }
+}
+
+/// The registered renderers; the first one matching a file is used.
+#[derive(Default)]
+pub struct Renderers {
+    entries: Vec<(Matcher, Box<dyn DiffRenderer>)>,
+}
+
+impl Renderers {
+    /// Uses `renderer` for files whose path matches `glob`.
+    pub fn add_glob(
+        &mut self,
+        glob: &str,
+        renderer: impl DiffRenderer + 'static,
+    ) -> Result<(), glob::PatternError> {
+        let glob = Pattern::new(glob)?;
+        self.entries.push((Matcher::Glob(glob), Box::new(renderer)));
+        Ok(())
+    }
+
+    /// Uses `renderer` for files of type `mime`, e.g. `application/x-protobuf`
+    /// or `image/*`, as told by their first bytes.
+    pub fn add_mime(&mut self, mime: &str, renderer: impl DiffRenderer + 'static) {
+        self.entries
+            .push((Matcher::Mime(mime.to_string()), Box::new(renderer)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The renderer for the file at `rel` of type `mime`, if one matches.
+    pub fn find(&self, rel: &Path, mime: Option<&str>) -> Option<&dyn DiffRenderer> {
+        self.entries
+            .iter()
+            .find(|(matcher, _)| matcher.matches(rel, mime))
+            .map(|(_, renderer)| renderer.as_ref())
+    }
+}
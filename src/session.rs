@@ -0,0 +1,285 @@
+//! What a review left on the entries, bookmarks, notes and review states,
+//! kept in a session file so it survives quitting, and exported with
+//! `:export` as a record of the review. The file is the one `--session`
+//! names, or one per pair of folders under the cache directory.
+
+use crate::bookmarks::Bookmarks;
+use crate::format;
+use crate::manifest;
+use crate::status::FolderStatefulList;
+use crate::text;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Where an entry is in a review, cycled with `r`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Review {
+    /// looked at, no verdict
+    Reviewed,
+    Accepted,
+    Rejected,
+}
+
+impl Review {
+    pub fn name(self) -> &'static str {
+        match self {
+            Review::Reviewed => "reviewed",
+            Review::Accepted => "accepted",
+            Review::Rejected => "rejected",
+        }
+    }
+
+    /// The state after `state` in the cycle: none, reviewed, accepted,
+    /// rejected and none again.
+    pub fn next(state: Option<Review>) -> Option<Review> {
+        match state {
+            None => Some(Review::Reviewed),
+            Some(Review::Reviewed) => Some(Review::Accepted),
+            Some(Review::Accepted) => Some(Review::Rejected),
+            Some(Review::Rejected) => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Review {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reviewed" => Ok(Review::Reviewed),
+            "accepted" => Ok(Review::Accepted),
+            "rejected" => Ok(Review::Rejected),
+            _ => Err(format!(
+                "expected reviewed, accepted or rejected, got {}",
+                s
+            )),
+        }
+    }
+}
+
+/// Bookmarks, notes and review states by relative path, and the file they
+/// are saved to.
+#[derive(Clone, Default, Debug)]
+pub struct Session {
+    // `None` keeps them for this run only
+    file: Option<PathBuf>,
+    pub bookmarks: Bookmarks,
+    notes: BTreeMap<PathBuf, String>,
+    reviews: BTreeMap<PathBuf, Review>,
+}
+
+impl Session {
+    /// Reads the session saved in `file`; a file that does not exist yet
+    /// starts an empty one. Changes go to `file` with [`Session::save`].
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let mut session = match fs::read_to_string(file) {
+            Ok(text) => Session::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Session::default(),
+            Err(e) => return Err(e),
+        };
+        session.file = Some(file.to_path_buf());
+        Ok(session)
+    }
+
+    /// The session kept for comparing `old` with `new` under the cache
+    /// directory, see [`Session::open`].
+    pub fn open_for_pair(old: &str, new: &str) -> io::Result<Self> {
+        let file = Session::pair_file(&crate::log::cache_dir(), old, new);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Session::open(&file)
+    }
+
+    /// `sessions/<hash>.tsv` under `dir`, named after both folders so every
+    /// pair keeps its own.
+    pub fn pair_file(dir: &Path, old: &str, new: &str) -> PathBuf {
+        let absolute = |dir: &str| {
+            fs::canonicalize(dir)
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| dir.to_string())
+        };
+        let key = format!("{}\0{}", absolute(old), absolute(new));
+        let hash = manifest::hash_bytes(key.as_bytes());
+        dir.join("sessions").join(format!("{}.tsv", &hash[..16]))
+    }
+
+    pub fn has_file(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Adds what `other` holds, keeping this session's notes and reviews
+    /// where both have one.
+    pub fn merge(&mut self, other: Session) {
+        for rel in other.bookmarks.iter() {
+            self.bookmarks.insert(rel.clone());
+        }
+        for (rel, note) in other.notes {
+            self.notes.entry(rel).or_insert(note);
+        }
+        for (rel, review) in other.reviews {
+            self.reviews.entry(rel).or_insert(review);
+        }
+    }
+
+    /// Reads the lines [`Session::to_text`] writes, skipping any it does not
+    /// know.
+    pub fn parse(text: &str) -> Self {
+        let mut session = Session::default();
+        for line in text.lines() {
+            match line.split('\t').collect::<Vec<_>>()[..] {
+                ["bookmark", rel] => {
+                    session.bookmarks.insert(PathBuf::from(rel));
+                }
+                ["note", rel, note] => session.set_note(PathBuf::from(rel), note),
+                ["review", rel, review] => {
+                    if let Ok(review) = review.parse() {
+                        session.set_review(PathBuf::from(rel), Some(review));
+                    }
+                }
+                _ => {}
+            }
+        }
+        session
+    }
+
+    /// One tab-separated line per bookmark, note and review.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for rel in self.bookmarks.iter() {
+            out += &format!("bookmark\t{}\n", text::slash_path(rel));
+        }
+        for (rel, note) in &self.notes {
+            out += &format!("note\t{}\t{}\n", text::slash_path(rel), note);
+        }
+        for (rel, review) in &self.reviews {
+            out += &format!("review\t{}\t{}\n", text::slash_path(rel), review.name());
+        }
+        out
+    }
+
+    /// Writes the session to its file, if it has one.
+    pub fn save(&self) -> io::Result<()> {
+        match &self.file {
+            Some(file) => fs::write(file, self.to_text()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn note(&self, rel: &Path) -> Option<&str> {
+        self.notes.get(rel).map(String::as_str)
+    }
+
+    /// Attaches `note` to `rel`, replacing the one it had; a blank note
+    /// removes it. Line breaks and tabs become spaces.
+    pub fn set_note(&mut self, rel: PathBuf, note: &str) {
+        let note = note.replace(['\t', '\n', '\r'], " ");
+        if note.trim().is_empty() {
+            self.notes.remove(&rel);
+        } else {
+            self.notes.insert(rel, note.trim().to_string());
+        }
+    }
+
+    pub fn review(&self, rel: &Path) -> Option<Review> {
+        self.reviews.get(rel).copied()
+    }
+
+    /// Sets the review state of `rel`, `None` for not reviewed.
+    pub fn set_review(&mut self, rel: PathBuf, review: Option<Review>) {
+        match review {
+            Some(review) => self.reviews.insert(rel, review),
+            None => self.reviews.remove(&rel),
+        };
+    }
+
+    pub fn has_reviews(&self) -> bool {
+        !self.reviews.is_empty()
+    }
+
+    /// Writes `entries` with their bookmarks, notes and reviews to `file`, as JSON when
+    /// it ends in `.json` and as Markdown otherwise.
+    pub fn export(
+        &self,
+        file: &Path,
+        old: &str,
+        new: &str,
+        entries: &[(PathBuf, &FolderStatefulList)],
+    ) -> io::Result<()> {
+        let report = if file.extension() == Some("json".as_ref()) {
+            self.to_json(old, new, entries)
+        } else {
+            self.to_markdown(old, new, entries)
+        };
+        fs::write(file, report)
+    }
+
+    /// A Markdown table of `entries`, each given with its relative path,
+    /// with their bookmarks, notes and reviews.
+    pub fn to_markdown(
+        &self,
+        old: &str,
+        new: &str,
+        entries: &[(PathBuf, &FolderStatefulList)],
+    ) -> String {
+        let mut out = format!("# Review of {} against {}\n\n", new, old);
+        out += "| state | path | size | review | bookmarked | note |\n";
+        out += "| --- | --- | --- | --- | --- | --- |\n";
+        for (rel, item) in entries {
+            let cell = |s: &str| s.replace('|', "\\|");
+            out += &format!(
+                "| {} | {} | {} | {} | {} | {} |\n",
+                format!("{:?}", item.state).to_lowercase(),
+                cell(&text::slash_path(rel)),
+                format::size_delta(item.size_delta),
+                self.review(rel).map_or("", Review::name),
+                if self.bookmarks.contains(rel) {
+                    "yes"
+                } else {
+                    ""
+                },
+                cell(self.note(rel).unwrap_or("")),
+            );
+        }
+        out
+    }
+
+    /// The same as [`Session::to_markdown`] as one JSON object.
+    pub fn to_json(
+        &self,
+        old: &str,
+        new: &str,
+        entries: &[(PathBuf, &FolderStatefulList)],
+    ) -> String {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(rel, item)| {
+                format!(
+                    "{{\"state\":\"{}\",\"path\":{},\"dir\":{},\"size_delta\":{},\"review\":{},\"bookmarked\":{},\"note\":{}}}",
+                    format!("{:?}", item.state).to_lowercase(),
+                    text::json_string(&text::slash_path(rel)),
+                    item.entry.is_dir(),
+                    item.size_delta,
+                    match self.review(rel) {
+                        Some(review) => format!("\"{}\"", review.name()),
+                        None => "null".to_string(),
+                    },
+                    self.bookmarks.contains(rel),
+                    match self.note(rel) {
+                        Some(note) => text::json_string(note),
+                        None => "null".to_string(),
+                    }
+                )
+            })
+            .collect();
+        format!(
+            "{{\"old\":{},\"new\":{},\"entries\":[{}]}}\n",
+            text::json_string(old),
+            text::json_string(new),
+            entries.join(",")
+        )
+    }
+}
@@ -0,0 +1,60 @@
+use crate::status::Entry;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Recursive size of one directory on both sides, like `du`.
+pub struct DirSize {
+    /// relative to the tree roots, empty for the roots themselves
+    pub rel: PathBuf,
+    pub old: u64,
+    pub new: u64,
+}
+
+impl DirSize {
+    pub fn delta(&self) -> i64 {
+        self.new as i64 - self.old as i64
+    }
+}
+
+/// Bytes of all files below each directory of `files`, the tree root under
+/// the empty path.
+pub fn dir_sizes(files: &HashMap<PathBuf, Entry>) -> HashMap<PathBuf, u64> {
+    let mut sizes = HashMap::new();
+    for (rel, entry) in files {
+        if entry.is_dir() {
+            sizes.entry(rel.clone()).or_insert(0);
+            continue;
+        }
+        for dir in rel.ancestors().skip(1) {
+            *sizes.entry(dir.to_path_buf()).or_insert(0) += entry.size;
+        }
+    }
+    sizes
+}
+
+/// Directories whose recursive size differs between the two trees, biggest
+/// change first. A directory missing on one side counts as empty there.
+pub fn compare(old: &HashMap<PathBuf, Entry>, new: &HashMap<PathBuf, Entry>) -> Vec<DirSize> {
+    let (old, new) = (dir_sizes(old), dir_sizes(new));
+    let mut dirs: Vec<DirSize> = old
+        .keys()
+        .chain(new.keys().filter(|k| !old.contains_key(*k)))
+        .map(|rel| DirSize {
+            rel: rel.clone(),
+            old: old.get(rel).copied().unwrap_or(0),
+            new: new.get(rel).copied().unwrap_or(0),
+        })
+        .filter(|d| d.old != d.new)
+        .collect();
+    dirs.sort_by(|a, b| {
+        let depth = |p: &Path| p.components().count();
+        b.delta()
+            .unsigned_abs()
+            .cmp(&a.delta().unsigned_abs())
+            .then_with(|| depth(&a.rel).cmp(&depth(&b.rel)))
+            .then_with(|| a.rel.cmp(&b.rel))
+    });
+    dirs
+}
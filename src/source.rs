@@ -0,0 +1,935 @@
+use crate::filter::WalkFilter;
+use crate::manifest;
+use crate::status::{Entry, Special};
+use crate::text;
+use crate::throttle::Throttled;
+use flate2::read::GzDecoder;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+/// A tree that can be compared: a local folder, a snapshot, an archive, a git
+/// revision, a container image, a remote host, a bucket or a tree built in
+/// memory.
+///
+/// Entries are keyed by their path relative to the tree, and every entry's own
+/// path is that relative path joined onto [`Source::root`].
+pub trait Source {
+    /// Label of the tree, shown in titles and used as the root of entry paths.
+    fn root(&self) -> &Path;
+
+    /// Every entry not rejected by `filter`, keyed by relative path.
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>>;
+
+    /// Only the given relative `paths`; missing ones are left out.
+    fn list_paths(
+        &self,
+        paths: &[PathBuf],
+        filter: &WalkFilter,
+    ) -> io::Result<HashMap<PathBuf, Entry>> {
+        let mut files = self.list(filter)?;
+        files.retain(|k, _| paths.contains(k));
+        Ok(files)
+    }
+
+    /// The entries below the folder `rel`, without the folder itself. By
+    /// default the whole tree is listed and cut down, which trees that list
+    /// over a network or a command do better themselves.
+    fn list_under(&self, rel: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let mut files = self.list(filter)?;
+        files.retain(|k, _| k.starts_with(rel) && k != rel);
+        Ok(files)
+    }
+
+    /// Contents of the file at `rel`.
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>>;
+
+    /// Contents of the file at `rel`, where `basis` is likely close to them,
+    /// e.g. the other side's copy. Only a tree that [`Source::reads_deltas`]
+    /// makes use of it.
+    fn read_like(&self, rel: &Path, _basis: &[u8]) -> io::Result<Vec<u8>> {
+        self.read(rel)
+    }
+
+    /// Whether [`Source::read_like`] fetches only what differs from the
+    /// basis, so the other side is better read first.
+    fn reads_deltas(&self) -> bool {
+        false
+    }
+
+    /// At most the first `len` bytes of the file at `rel`.
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = self.read(rel)?;
+        bytes.truncate(len);
+        Ok(bytes)
+    }
+
+    /// The entry at `rel`, `NotFound` when the tree has none.
+    fn metadata(&self, rel: &Path) -> io::Result<Entry>;
+
+    /// Hex encoded SHA-256 of the file at `rel`.
+    fn hash(&self, rel: &Path) -> io::Result<String> {
+        Ok(manifest::hash_bytes(&self.read(rel)?))
+    }
+
+    /// Where `rel` lives on the local filesystem, for trees that can be
+    /// written to.
+    fn local_path(&self, _rel: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Where the contents of `rel` start in a tree that can only be read
+    /// front to back, so files are best read in this order.
+    fn position(&self, _rel: &Path) -> Option<u64> {
+        None
+    }
+}
+
+impl<S: Source + ?Sized> Source for Box<S> {
+    fn root(&self) -> &Path {
+        (**self).root()
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        (**self).list(filter)
+    }
+
+    fn list_paths(
+        &self,
+        paths: &[PathBuf],
+        filter: &WalkFilter,
+    ) -> io::Result<HashMap<PathBuf, Entry>> {
+        (**self).list_paths(paths, filter)
+    }
+
+    fn list_under(&self, rel: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        (**self).list_under(rel, filter)
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        (**self).read(rel)
+    }
+
+    fn read_like(&self, rel: &Path, basis: &[u8]) -> io::Result<Vec<u8>> {
+        (**self).read_like(rel, basis)
+    }
+
+    fn reads_deltas(&self) -> bool {
+        (**self).reads_deltas()
+    }
+
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        (**self).head(rel, len)
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        (**self).metadata(rel)
+    }
+
+    fn hash(&self, rel: &Path) -> io::Result<String> {
+        (**self).hash(rel)
+    }
+
+    fn local_path(&self, rel: &Path) -> Option<PathBuf> {
+        (**self).local_path(rel)
+    }
+
+    fn position(&self, rel: &Path) -> Option<u64> {
+        (**self).position(rel)
+    }
+}
+
+/// Picks the backend for a command line argument:
+///
+/// - `git:<rev>` or `git:<repo>#<rev>` for a git revision
+/// - `sftp://[user@]host[:port]/path` (or `ssh://`) for a remote folder
+/// - `host:port:/path` for a folder served by `diff-folders serve`, see
+///   [`crate::agent`]
+/// - `oci:<image>` for the root filesystem of a container image, see
+///   [`crate::oci::Oci::open`]
+/// - `s3://bucket/prefix` or `gs://bucket/prefix` for objects in a bucket,
+///   with the `cloud` feature
+/// - a manifest or snapshot file written by `diff-folders snapshot`
+/// - a checksum file such as `SHA256SUMS` or `app.md5`, see [`crate::sums`]
+/// - a `.tar`, `.tar.gz` or `.tgz` file
+/// - anything else is a local folder
+pub fn open(spec: &str) -> io::Result<Box<dyn Source>> {
+    if let Some(rest) = spec.strip_prefix("git:") {
+        let (repo, rev) = rest.rsplit_once('#').unwrap_or((".", rest));
+        return Ok(Box::new(Git::open(Path::new(repo), rev)?));
+    }
+    if let Some(image) = spec.strip_prefix("oci:") {
+        return Ok(Box::new(crate::oci::Oci::open(image)?));
+    }
+    if let Some(rest) = spec
+        .strip_prefix("sftp://")
+        .or_else(|| spec.strip_prefix("ssh://"))
+    {
+        return Ok(Box::new(Sftp::parse(spec, rest)?));
+    }
+    if crate::agent::parse_spec(spec).is_some() {
+        return Ok(Box::new(crate::agent::Agent::open_with_global_token(spec)?));
+    }
+    if spec.starts_with("s3://") || spec.starts_with("gs://") {
+        #[cfg(feature = "cloud")]
+        return Ok(Box::new(crate::bucket::Bucket::parse(spec)?));
+        #[cfg(not(feature = "cloud"))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{}: buckets need a build with the cloud feature", spec),
+        ));
+    }
+    let path = Path::new(spec);
+    if manifest::is_manifest(path) {
+        return Ok(Box::new(Snapshot::open(path)?));
+    }
+    if crate::sums::is_sums(path) {
+        return Ok(Box::new(crate::sums::Sums::open(path)?));
+    }
+    if path.is_file() && Archive::is_archive(path) {
+        return Ok(Box::new(Archive::open(path)?));
+    }
+    Ok(Box::new(LocalDir {
+        root: path.to_path_buf(),
+    }))
+}
+
+/// Whether `spec` names something on the local filesystem, as opposed to a
+/// git revision, a container image or a remote host.
+pub fn is_local(spec: &str) -> bool {
+    !spec.starts_with("git:")
+        && !spec.starts_with("oci:")
+        && !spec.contains("://")
+        && crate::agent::parse_spec(spec).is_none()
+}
+
+/// A folder on the local filesystem.
+pub struct LocalDir {
+    pub root: PathBuf,
+}
+
+impl Source for LocalDir {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        list_dir(&self.root, filter)
+    }
+
+    fn list_paths(
+        &self,
+        paths: &[PathBuf],
+        filter: &WalkFilter,
+    ) -> io::Result<HashMap<PathBuf, Entry>> {
+        let mut files = HashMap::new();
+        for rel in paths {
+            let path = self.root.join(rel);
+            if filter.is_ignored(rel, path.is_dir()) {
+                continue;
+            }
+            // a zero-depth walk yields the DirEntry of the path itself
+            let entry = walkdir::WalkDir::new(path).max_depth(0).into_iter().next();
+            if let Some(Ok(entry)) = entry {
+                files.insert(rel.clone(), Entry::from(&entry));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Walks the folder alone rather than the whole tree.
+    fn list_under(&self, rel: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let dir = self.root.join(rel);
+        let mut files = HashMap::new();
+        // the filter's patterns are relative to the root of the tree
+        let walker = walkdir::WalkDir::new(&dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| {
+                let below = e.path().strip_prefix(&dir).unwrap_or(e.path());
+                !filter.is_ignored(&rel.join(below), e.file_type().is_dir())
+            });
+        for f in walker {
+            let entry = match f {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::warn!("skip {}", e);
+                    continue;
+                }
+            };
+            let below = entry.path().strip_prefix(&dir).unwrap_or(entry.path());
+            files.insert(rel.join(below), Entry::from(&entry));
+        }
+        Ok(files)
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        let path = self.root.join(rel);
+        crate::status::ensure_not_special(&path)?;
+        let mut bytes = Vec::new();
+        Throttled(File::open(path)?).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        let path = self.root.join(rel);
+        crate::status::ensure_not_special(&path)?;
+        let mut bytes = Vec::new();
+        Throttled(File::open(path)?)
+            .take(len as u64)
+            .read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        let path = self.root.join(rel);
+        let meta = path.symlink_metadata()?;
+        let mut entry = Entry::new(path, meta.is_dir(), meta.len());
+        entry.mtime = crate::status::mtime_nanos(&meta);
+        entry.special = crate::status::Special::of(meta.file_type());
+        entry.inode = crate::status::inode(&meta);
+        Ok(entry)
+    }
+
+    fn hash(&self, rel: &Path) -> io::Result<String> {
+        let path = self.root.join(rel);
+        crate::status::ensure_not_special(&path)?;
+        manifest::hash_file(&path)
+    }
+
+    fn local_path(&self, rel: &Path) -> Option<PathBuf> {
+        Some(self.root.join(rel))
+    }
+}
+
+/// Walks `path`, keying every entry by its path relative to `path`.
+///
+/// Only an unreadable root is an error; entries that cannot be read further
+/// down are logged and skipped.
+pub fn list_dir(path: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+    #[cfg(feature = "async")]
+    return crate::async_io::list_dir(path, filter);
+    #[cfg(not(feature = "async"))]
+    walk_dir(path, filter)
+}
+
+#[cfg_attr(feature = "async", allow(dead_code))]
+fn walk_dir(path: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+    let mut files = HashMap::new();
+    let walker = walkdir::WalkDir::new(path).into_iter().filter_entry(|e| {
+        // walkdir yields paths rooted at `path`, so the key is the relative remainder
+        let rel = e.path().strip_prefix(path).unwrap_or(e.path());
+        e.depth() == 0 || !filter.is_ignored(rel, e.file_type().is_dir())
+    });
+    for f in walker {
+        let entry = match f {
+            Ok(entry) => entry,
+            Err(e) if e.depth() == 0 => return Err(e.into()),
+            Err(e) => {
+                log::warn!("skip {}", e);
+                continue;
+            }
+        };
+        let key = entry.path().strip_prefix(path).unwrap_or(entry.path());
+        files.insert(key.to_path_buf(), Entry::from(&entry));
+        if filter.is_full(files.len()) {
+            break;
+        }
+    }
+    Ok(files)
+}
+
+/// A manifest or snapshot written by `diff-folders snapshot`.
+pub struct Snapshot {
+    pub file: PathBuf,
+    // the index, read once when opened
+    manifest: manifest::Manifest,
+}
+
+impl Snapshot {
+    pub fn open(file: &Path) -> io::Result<Self> {
+        Ok(Snapshot {
+            file: file.to_path_buf(),
+            manifest: manifest::Manifest::open(file)?,
+        })
+    }
+}
+
+impl Source for Snapshot {
+    fn root(&self) -> &Path {
+        &self.file
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let mut files = self.manifest.entries().clone();
+        files.retain(|rel, e| !filter.is_ignored(rel, e.is_dir()));
+        Ok(files)
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        self.manifest.read(rel)
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        self.manifest
+            .entries()
+            .get(rel)
+            .cloned()
+            .ok_or_else(|| not_found(&self.file, rel))
+    }
+
+    /// The recorded hash, the contents may not be stored at all.
+    fn hash(&self, rel: &Path) -> io::Result<String> {
+        self.metadata(rel)?
+            .hash
+            .ok_or_else(|| not_found(&self.file, rel))
+    }
+}
+
+/// A tar archive, optionally gzip compressed. Its members are indexed once,
+/// when it is opened, and contents are read on demand: a plain tar seeks
+/// straight to them, a compressed one is decompressed onwards from the last
+/// member read, so members read in archive order (see [`Source::position`])
+/// take one pass. Nothing is extracted to disk.
+pub struct Archive {
+    pub file: PathBuf,
+    // entries by relative path, with where their contents start in the
+    // uncompressed archive
+    index: HashMap<PathBuf, (Entry, u64)>,
+    // the decompressed stream of a compressed archive and how far into it
+    // the last read got
+    stream: Mutex<Option<(GzDecoder<File>, u64)>>,
+}
+
+impl Archive {
+    fn is_archive(path: &Path) -> bool {
+        let name = path.to_string_lossy();
+        [".tar", ".tar.gz", ".tgz"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+    }
+
+    /// Reads the headers of every member of `file`, skipping over their
+    /// contents.
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let mut index = HashMap::new();
+        let mut archive = tar::Archive::new(Archive::reader(file)?);
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let rel = clean_rel(&entry.path()?);
+            if rel.as_os_str().is_empty() {
+                continue;
+            }
+            // archives may leave out the entries of their directories
+            for dir in rel.ancestors().skip(1) {
+                if dir.as_os_str().is_empty() {
+                    break;
+                }
+                index
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| (Entry::new(file.join(dir), true, 0), 0));
+            }
+            let is_dir = entry.header().entry_type().is_dir();
+            let size = if is_dir { 0 } else { entry.header().size()? };
+            // a member given twice is extracted as the later one
+            let member = Entry::new(file.join(&rel), is_dir, size);
+            index.insert(rel, (member, entry.raw_file_position()));
+        }
+        Ok(Archive {
+            file: file.to_path_buf(),
+            index,
+            stream: Mutex::new(None),
+        })
+    }
+
+    fn is_compressed(&self) -> bool {
+        !self.file.to_string_lossy().ends_with(".tar")
+    }
+
+    fn reader(file: &Path) -> io::Result<Box<dyn Read>> {
+        let reader = File::open(file)?;
+        if file.to_string_lossy().ends_with(".tar") {
+            Ok(Box::new(reader))
+        } else {
+            Ok(Box::new(GzDecoder::new(reader)))
+        }
+    }
+
+    /// At most `len` bytes of the member at `rel`.
+    fn contents(&self, rel: &Path, len: u64) -> io::Result<Vec<u8>> {
+        let (entry, offset) = self
+            .index
+            .get(rel)
+            .ok_or_else(|| not_found(&self.file, rel))?;
+        if entry.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is a directory", entry.path().display()),
+            ));
+        }
+        let len = len.min(entry.size);
+        let mut bytes = Vec::with_capacity(len as usize);
+        if self.is_compressed() {
+            let mut stream = self.stream.lock().unwrap();
+            // only a member behind the stream starts it over
+            let (mut decoder, pos) = match stream.take() {
+                Some((decoder, pos)) if pos <= *offset => (decoder, pos),
+                _ => (GzDecoder::new(File::open(&self.file)?), 0),
+            };
+            io::copy(&mut (&mut decoder).take(offset - pos), &mut io::sink())?;
+            (&mut decoder).take(len).read_to_end(&mut bytes)?;
+            *stream = Some((decoder, offset + bytes.len() as u64));
+        } else {
+            let mut file = File::open(&self.file)?;
+            file.seek(SeekFrom::Start(*offset))?;
+            file.take(len).read_to_end(&mut bytes)?;
+        }
+        if (bytes.len() as u64) < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} is cut short", entry.path().display()),
+            ));
+        }
+        Ok(bytes)
+    }
+}
+
+impl Source for Archive {
+    fn root(&self) -> &Path {
+        &self.file
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        Ok(self
+            .index
+            .iter()
+            .filter(|(rel, (e, _))| !filter.is_ignored(rel, e.is_dir()))
+            .map(|(rel, (e, _))| (rel.clone(), e.clone()))
+            .collect())
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        self.contents(rel, u64::MAX)
+    }
+
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        self.contents(rel, len as u64)
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        self.index
+            .get(rel)
+            .map(|(e, _)| e.clone())
+            .ok_or_else(|| not_found(&self.file, rel))
+    }
+
+    fn position(&self, rel: &Path) -> Option<u64> {
+        let (_, offset) = self.index.get(rel)?;
+        self.is_compressed().then_some(*offset)
+    }
+}
+
+/// A tree held in memory, for tests and for callers that build the sides of
+/// a comparison themselves rather than reading them from disk.
+#[derive(Clone, Default)]
+pub struct Memory {
+    pub root: PathBuf,
+    // contents by relative path, `None` for directories
+    files: HashMap<PathBuf, Option<Vec<u8>>>,
+}
+
+impl Memory {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Memory {
+            root: root.into(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Adds a file holding `contents`, and the directories above it.
+    pub fn file(mut self, rel: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> Self {
+        let rel = clean_rel(rel.as_ref());
+        self.add_parents(&rel);
+        self.files.insert(rel, Some(contents.into()));
+        self
+    }
+
+    /// Adds an empty directory, and the directories above it.
+    pub fn dir(mut self, rel: impl AsRef<Path>) -> Self {
+        let rel = clean_rel(rel.as_ref());
+        self.add_parents(&rel);
+        self.files.insert(rel, None);
+        self
+    }
+
+    fn add_parents(&mut self, rel: &Path) {
+        for dir in rel.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            self.files.insert(dir.to_path_buf(), None);
+        }
+    }
+
+    fn entry(&self, rel: &Path, contents: &Option<Vec<u8>>) -> Entry {
+        let size = contents.as_ref().map_or(0, |c| c.len() as u64);
+        Entry::new(self.root.join(rel), contents.is_none(), size)
+    }
+}
+
+impl Source for Memory {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let mut files = HashMap::new();
+        for (rel, contents) in &self.files {
+            // an ignored directory hides everything below it, as in a walk
+            let ignored = rel
+                .ancestors()
+                .take_while(|a| !a.as_os_str().is_empty())
+                .any(|a| filter.is_ignored(a, a != rel || contents.is_none()));
+            if !ignored {
+                files.insert(rel.clone(), self.entry(rel, contents));
+            }
+        }
+        Ok(files)
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        match self.files.get(rel) {
+            Some(Some(contents)) => Ok(contents.clone()),
+            Some(None) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is a directory", self.root.join(rel).display()),
+            )),
+            None => Err(not_found(&self.root, rel)),
+        }
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        self.files
+            .get(rel)
+            .map(|contents| self.entry(rel, contents))
+            .ok_or_else(|| not_found(&self.root, rel))
+    }
+}
+
+/// Drops the `./` archivers like to put in front of member names.
+pub(crate) fn clean_rel(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect()
+}
+
+/// A revision of a git repository, read through the `git` command.
+pub struct Git {
+    repo: PathBuf,
+    rev: String,
+    label: PathBuf,
+}
+
+impl Git {
+    /// Checks that `rev` names a commit in `repo`.
+    pub fn open(repo: &Path, rev: &str) -> io::Result<Self> {
+        let git = Git {
+            repo: repo.to_path_buf(),
+            rev: rev.to_string(),
+            label: PathBuf::from(format!("git:{}#{}", repo.display(), rev)),
+        };
+        git.run(&[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", rev),
+        ])?;
+        Ok(git)
+    }
+
+    fn run(&self, args: &[&str]) -> io::Result<Vec<u8>> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(args)
+            .output()?;
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(out.stdout)
+    }
+
+    /// Parses `git ls-tree -l -z` output, skipping submodules.
+    fn parse_tree(&self, out: &[u8]) -> Vec<(PathBuf, Entry)> {
+        let mut entries = Vec::new();
+        for record in out.split(|b| *b == 0).filter(|r| !r.is_empty()) {
+            let record = String::from_utf8_lossy(record);
+            // <mode> <type> <object> <size>\t<path>
+            let (info, path) = match record.split_once('\t') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            let fields: Vec<&str> = info.split_whitespace().collect();
+            let is_dir = match fields.get(1) {
+                Some(&"tree") => true,
+                Some(&"blob") => false,
+                _ => continue,
+            };
+            let size = fields.get(3).and_then(|s| s.parse().ok()).unwrap_or(0);
+            let rel = PathBuf::from(path);
+            let entry = Entry::new(self.label.join(&rel), is_dir, size);
+            entries.push((rel, entry));
+        }
+        entries
+    }
+}
+
+impl Source for Git {
+    fn root(&self) -> &Path {
+        &self.label
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let out = self.run(&["ls-tree", "-r", "-t", "-l", "-z", &self.rev])?;
+        Ok(self
+            .parse_tree(&out)
+            .into_iter()
+            .filter(|(rel, e)| !filter.is_ignored(rel, e.is_dir()))
+            .collect())
+    }
+
+    /// Only the tree at `rel` is listed.
+    fn list_under(&self, rel: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let out = self.run(&[
+            "ls-tree",
+            "-r",
+            "-t",
+            "-l",
+            "-z",
+            &self.rev,
+            "--",
+            &text::slash_path(rel),
+        ])?;
+        // the tree itself and the ones above it come along with -t
+        Ok(self
+            .parse_tree(&out)
+            .into_iter()
+            .filter(|(k, e)| k.starts_with(rel) && k != rel && !filter.is_ignored(k, e.is_dir()))
+            .collect())
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        let object = format!("{}:{}", self.rev, text::slash_path(rel));
+        self.run(&["cat-file", "blob", &object])
+    }
+
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        let object = format!("{}:{}", self.rev, text::slash_path(rel));
+        let mut cmd = Command::new("git");
+        cmd.arg("-C")
+            .arg(&self.repo)
+            .args(["cat-file", "blob", &object]);
+        head_of(&mut cmd, len, &format!("git cat-file blob {}", object))
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        let out = self.run(&[
+            "ls-tree",
+            "-l",
+            "-z",
+            &self.rev,
+            "--",
+            &text::slash_path(rel),
+        ])?;
+        self.parse_tree(&out)
+            .into_iter()
+            .find(|(r, _)| r == rel)
+            .map(|(_, e)| e)
+            .ok_or_else(|| not_found(&self.label, rel))
+    }
+}
+
+/// A folder on a remote host, read by running `find` and `cat` over the `ssh`
+/// command so the user's keys, agent and `~/.ssh/config` all apply.
+pub struct Sftp {
+    // [user@]host
+    host: String,
+    port: Option<u16>,
+    path: String,
+    label: PathBuf,
+}
+
+impl Sftp {
+    /// Parses the part of `url` after the scheme, `[user@]host[:port]/path`.
+    fn parse(url: &str, rest: &str) -> io::Result<Self> {
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "."),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("bad port in {}", url))
+                })?;
+                (host, Some(port))
+            }
+            None => (authority, None),
+        };
+        if host.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("missing host in {}", url),
+            ));
+        }
+        Ok(Sftp {
+            host: host.to_string(),
+            port,
+            path: path.trim_end_matches('/').to_string(),
+            label: PathBuf::from(url.trim_end_matches('/')),
+        })
+    }
+
+    fn run(&self, script: &str) -> io::Result<Vec<u8>> {
+        let mut cmd = Command::new("ssh");
+        if let Some(port) = self.port {
+            cmd.arg("-p").arg(port.to_string());
+        }
+        let out = cmd.arg("--").arg(&self.host).arg(script).output()?;
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "ssh {} failed: {}",
+                self.host,
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+        Ok(out.stdout)
+    }
+
+    fn remote_path(&self, rel: &Path) -> String {
+        shell_quote(&format!("{}/{}", self.path, text::slash_path(rel)))
+    }
+
+    /// Parses `find -printf '%y %s %P\0'` output. Pipes, sockets and devices
+    /// are listed as special files, so their contents are never asked for;
+    /// symlinks are read through, as in a local folder. `%P` is relative to
+    /// the folder `base` of the tree.
+    fn parse_find(&self, base: &Path, out: &[u8]) -> Vec<(PathBuf, Entry)> {
+        let mut entries = Vec::new();
+        for record in out.split(|b| *b == 0).filter(|r| !r.is_empty()) {
+            let record = String::from_utf8_lossy(record);
+            let mut parts = record.splitn(3, ' ');
+            let (kind, size, rel) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(kind), Some(size), Some(rel)) => (kind, size, rel),
+                _ => continue,
+            };
+            let rel = base.join(rel);
+            let special = match kind {
+                "p" => Some(Special::Fifo),
+                "s" => Some(Special::Socket),
+                "b" => Some(Special::BlockDevice),
+                "c" => Some(Special::CharDevice),
+                _ => None,
+            };
+            let is_dir = kind == "d";
+            let size = match (is_dir, special) {
+                (false, None) => size.parse().unwrap_or(0),
+                _ => 0,
+            };
+            let mut entry = Entry::new(self.label.join(&rel), is_dir, size);
+            entry.special = special;
+            entries.push((rel, entry));
+        }
+        entries
+    }
+}
+
+impl Source for Sftp {
+    fn root(&self) -> &Path {
+        &self.label
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        self.list_under(Path::new(""), filter)
+    }
+
+    /// Only the folder `rel` is walked on the remote host.
+    fn list_under(&self, rel: &Path, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let script = format!(
+            "find {} -mindepth 1 -printf '%y %s %P\\0'",
+            self.remote_path(rel)
+        );
+        Ok(self
+            .parse_find(rel, &self.run(&script)?)
+            .into_iter()
+            .filter(|(rel, e)| !filter.is_ignored(rel, e.is_dir()))
+            .collect())
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        self.run(&format!("cat -- {}", self.remote_path(rel)))
+    }
+
+    fn head(&self, rel: &Path, len: usize) -> io::Result<Vec<u8>> {
+        self.run(&format!("head -c {} -- {}", len, self.remote_path(rel)))
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        let script = format!(
+            "find {} -maxdepth 0 -printf '%y %s x\\0'",
+            self.remote_path(rel)
+        );
+        let (_, entry) = self
+            .parse_find(Path::new(""), &self.run(&script)?)
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_found(&self.label, rel))?;
+        let mut found = Entry::new(self.label.join(rel), entry.is_dir(), entry.size);
+        found.special = entry.special;
+        Ok(found)
+    }
+}
+
+/// Wraps `s` in single quotes for the remote shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// At most the first `len` bytes `cmd` prints; it is killed once they are in,
+/// so a large file is not fetched in full. `what` names it in errors.
+pub(crate) fn head_of(cmd: &mut Command, len: usize, what: &str) -> io::Result<Vec<u8>> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let mut bytes = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        stdout.take(len as u64).read_to_end(&mut bytes)?;
+    }
+    if bytes.len() == len {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Ok(bytes);
+    }
+    let out = child.wait_with_output()?;
+    if !out.status.success() {
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            what,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn not_found(root: &Path, rel: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found in {}", rel.display(), root.display()),
+    )
+}
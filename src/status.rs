@@ -1,3 +1,8 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "tui")]
 use tui::widgets::ListState;
 
 ///
@@ -13,20 +18,200 @@ pub enum StatusItemType {
     Normal,
 }
 
+/// A file or directory found on one side of the comparison.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    path: PathBuf,
+    is_dir: bool,
+    /// size in bytes, 0 for directories
+    pub size: u64,
+    /// content hash, only known for entries read from a manifest (SHA-256) or
+    /// a checksum file (`<algorithm>:<hex>`, see [`crate::sums::parse_tagged`])
+    pub hash: Option<String>,
+    /// modification time in nanoseconds since the epoch, only known for local files
+    pub mtime: Option<u128>,
+    /// set for named pipes, sockets and devices, whose contents are never read
+    pub special: Option<Special>,
+    /// device, inode and link count, only known for local files on unix
+    pub inode: Option<Inode>,
+}
+
+impl Entry {
+    pub fn new(path: PathBuf, is_dir: bool, size: u64) -> Self {
+        Entry {
+            path,
+            is_dir,
+            size,
+            hash: None,
+            mtime: None,
+            special: None,
+            inode: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+}
+
+impl From<&walkdir::DirEntry> for Entry {
+    fn from(entry: &walkdir::DirEntry) -> Self {
+        let meta = entry.metadata().ok();
+        let size = match &meta {
+            Some(m) if m.is_file() => m.len(),
+            _ => 0,
+        };
+        let mut e = Entry::new(entry.path().to_path_buf(), entry.file_type().is_dir(), size);
+        e.mtime = meta.as_ref().and_then(mtime_nanos);
+        e.special = Special::of(entry.file_type());
+        e.inode = meta.as_ref().and_then(inode);
+        e
+    }
+}
+
+/// Where a file's data lives. Backup trees made with `rsync --link-dest`
+/// hard link unchanged files to the previous backup, so both sides can be the
+/// very same file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Inode {
+    pub dev: u64,
+    pub ino: u64,
+    /// number of hard links to the file
+    pub links: u64,
+}
+
+impl Inode {
+    /// Whether both are links to the same file, which then needs no reading.
+    pub fn same_file(&self, other: &Inode) -> bool {
+        self.dev == other.dev && self.ino == other.ino
+    }
+}
+
+#[cfg(unix)]
+pub fn inode(meta: &fs::Metadata) -> Option<Inode> {
+    use std::os::unix::fs::MetadataExt;
+    Some(Inode {
+        dev: meta.dev(),
+        ino: meta.ino(),
+        links: meta.nlink(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn inode(_meta: &fs::Metadata) -> Option<Inode> {
+    None
+}
+
+/// File types that are not regular files, directories or symlinks. Opening a
+/// named pipe blocks until something writes to it and a device has no
+/// contents worth comparing, so only the type of these is compared.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Special {
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl Special {
+    /// The special type behind `file_type`, `None` for ordinary entries.
+    #[cfg(unix)]
+    pub fn of(file_type: fs::FileType) -> Option<Self> {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            Some(Special::Fifo)
+        } else if file_type.is_socket() {
+            Some(Special::Socket)
+        } else if file_type.is_block_device() {
+            Some(Special::BlockDevice)
+        } else if file_type.is_char_device() {
+            Some(Special::CharDevice)
+        } else {
+            None
+        }
+    }
+
+    /// Windows has none of these in a directory tree.
+    #[cfg(not(unix))]
+    pub fn of(_file_type: fs::FileType) -> Option<Self> {
+        None
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Special::Fifo => "fifo",
+            Special::Socket => "socket",
+            Special::BlockDevice => "block-device",
+            Special::CharDevice => "char-device",
+        }
+    }
+}
+
+impl std::str::FromStr for Special {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(Special::Fifo),
+            "socket" => Ok(Special::Socket),
+            "block-device" => Ok(Special::BlockDevice),
+            "char-device" => Ok(Special::CharDevice),
+            _ => Err(format!("unknown special file type {}", s)),
+        }
+    }
+}
+
+/// Fails when `path` (after following symlinks) is a special file, before
+/// anything opens it. Other errors are left to the read that follows.
+pub fn ensure_not_special(path: &Path) -> io::Result<()> {
+    match fs::metadata(path)
+        .ok()
+        .and_then(|m| Special::of(m.file_type()))
+    {
+        Some(special) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is a {}, its contents are not read",
+                path.display(),
+                special.name()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Modification time of `meta` in nanoseconds since the epoch.
+pub fn mtime_nanos(meta: &std::fs::Metadata) -> Option<u128> {
+    let modified = meta.modified().ok()?;
+    let since = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since.as_nanos())
+}
+
 ///
 
 #[derive(Clone)]
 pub struct FolderStatefulList {
-    pub entry: walkdir::DirEntry,
+    pub entry: Entry,
     pub state: StatusItemType,
+    /// size change in bytes from the old file to the new one
+    pub size_delta: i64,
+    /// the relative path at the other end of a detected move: where a new
+    /// file was in the old tree, or where a deleted one went in the new tree
+    pub moved: Option<PathBuf>,
 }
 
-///
+/// Items of a list widget and which one is selected.
+#[cfg(feature = "tui")]
 pub struct StatefulList<T> {
     pub state: ListState,
     pub items: Vec<T>,
 }
 
+#[cfg(feature = "tui")]
 impl<T> StatefulList<T> {
     pub fn with_items(items: Vec<T>) -> StatefulList<T> {
         StatefulList {
@@ -36,6 +221,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn next(&mut self, dist: usize) {
+        if self.items.is_empty() {
+            return self.unselect();
+        }
         let mut page_size = dist;
         if page_size > self.items.len() {
             page_size = self.items.len();
@@ -54,6 +242,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn previous(&mut self, dist: usize) {
+        if self.items.is_empty() {
+            return self.unselect();
+        }
         let mut page_size = dist;
         if page_size > self.items.len() {
             page_size = self.items.len();
@@ -71,15 +262,24 @@ impl<T> StatefulList<T> {
         self.state.select(Some(i));
     }
 
+    pub fn select_first(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn select_last(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(self.items.len() - 1));
+        }
+    }
+
     pub fn unselect(&mut self) {
         self.state.select(None);
     }
 
-    pub fn cur(&self) -> &T {
-        let i = match self.state.selected() {
-            Some(i) => i,
-            None => 0,
-        };
-        &self.items[i]
+    /// The selected item, `None` when nothing is.
+    pub fn cur(&self) -> Option<&T> {
+        self.items.get(self.state.selected()?)
     }
 }
@@ -0,0 +1,108 @@
+use crate::status::{FolderStatefulList, StatusItemType};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Rows in each of the top lists.
+pub const TOP_ROWS: usize = 10;
+
+/// Files and directories found in one state.
+#[derive(Default, Clone, Copy)]
+pub struct Count {
+    pub files: usize,
+    pub dirs: usize,
+}
+
+/// Changed files of one extension, by state.
+#[derive(Default, Clone, Copy)]
+pub struct ExtensionCount {
+    pub new: usize,
+    pub modified: usize,
+    pub deleted: usize,
+}
+
+impl ExtensionCount {
+    pub fn total(&self) -> usize {
+        self.new + self.modified + self.deleted
+    }
+}
+
+/// The big picture of a comparison, shown on the dashboard before the list.
+#[derive(Default)]
+pub struct Summary {
+    pub new: Count,
+    pub modified: Count,
+    pub deleted: Count,
+    /// bytes gained by new and grown files
+    pub added: u64,
+    /// bytes lost by deleted and shrunk files
+    pub removed: u64,
+    /// (relative path, size, size change), largest first
+    pub largest: Vec<(PathBuf, u64, i64)>,
+    /// (relative directory, changed entries directly inside), deepest first
+    pub deepest: Vec<(PathBuf, usize)>,
+    /// changed files per extension such as `.rs`, most changed first; files
+    /// without one are counted under an empty string
+    pub extensions: Vec<(String, ExtensionCount)>,
+}
+
+impl Summary {
+    /// Sums up `items`; `rel` gives an entry's path relative to its tree.
+    pub fn new(items: &[FolderStatefulList], rel: impl Fn(&FolderStatefulList) -> PathBuf) -> Self {
+        let mut summary = Summary::default();
+        let mut dirs: HashMap<PathBuf, usize> = HashMap::new();
+        let mut extensions: HashMap<String, ExtensionCount> = HashMap::new();
+        for item in items {
+            let count = match item.state {
+                StatusItemType::New => &mut summary.new,
+                StatusItemType::Modified => &mut summary.modified,
+                StatusItemType::Deleted => &mut summary.deleted,
+                StatusItemType::Normal => continue,
+            };
+            if item.entry.is_dir() {
+                count.dirs += 1;
+            } else {
+                count.files += 1;
+            }
+            if item.size_delta > 0 {
+                summary.added += item.size_delta as u64;
+            } else {
+                summary.removed += item.size_delta.unsigned_abs();
+            }
+            let rel = rel(item);
+            let parent = rel.parent().unwrap_or(Path::new("")).to_path_buf();
+            *dirs.entry(parent).or_default() += 1;
+            if !item.entry.is_dir() {
+                let ext = rel
+                    .extension()
+                    .map(|e| format!(".{}", e.to_string_lossy().to_lowercase()))
+                    .unwrap_or_default();
+                let by_ext = extensions.entry(ext).or_default();
+                match item.state {
+                    StatusItemType::New => by_ext.new += 1,
+                    StatusItemType::Modified => by_ext.modified += 1,
+                    _ => by_ext.deleted += 1,
+                }
+                summary
+                    .largest
+                    .push((rel, item.entry.size, item.size_delta));
+            }
+        }
+        summary
+            .largest
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        summary.largest.truncate(TOP_ROWS);
+        summary.deepest = dirs.into_iter().collect();
+        summary.deepest.sort_by(|a, b| {
+            let depth = |p: &Path| p.components().count();
+            depth(&b.0).cmp(&depth(&a.0)).then_with(|| a.0.cmp(&b.0))
+        });
+        summary.deepest.truncate(TOP_ROWS);
+        summary.extensions = extensions.into_iter().collect();
+        summary
+            .extensions
+            .sort_by(|a, b| b.1.total().cmp(&a.1.total()).then_with(|| a.0.cmp(&b.0)));
+        summary
+    }
+}
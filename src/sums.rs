@@ -0,0 +1,222 @@
+//! Checksum files as one side of a comparison: `SHA256SUMS`, `app.sha256`,
+//! `MD5SUMS` and the like, as written by `sha256sum`, `sha512sum` and
+//! `md5sum`, or in their BSD `--tag` form. The tree they describe has the
+//! listed files and their folders with a hash each, but no sizes and no
+//! contents, so files on the other side are hashed to be compared: a missing
+//! file shows as deleted, an extra one as new and a mismatch as modified.
+
+use crate::filter::WalkFilter;
+use crate::source::{clean_rel, not_found, Source};
+use crate::status::Entry;
+use crate::throttle::Throttled;
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// File names checksum tools are commonly run to write.
+const NAMES: [&str; 6] = [
+    "SHA256SUMS",
+    "SHA512SUMS",
+    "MD5SUMS",
+    "sha256sums.txt",
+    "sha512sums.txt",
+    "md5sums.txt",
+];
+const EXTENSIONS: [&str; 6] = [
+    "sha256",
+    "sha256sum",
+    "sha512",
+    "sha512sum",
+    "md5",
+    "md5sum",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// The algorithm of a hex digest, told by its length.
+    fn of_digest(hex: &str) -> Option<Self> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        match hex.len() {
+            32 => Some(Algorithm::Md5),
+            64 => Some(Algorithm::Sha256),
+            128 => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Hex encoded digest of the file at `path`.
+    pub fn hash_file(self, path: &Path) -> io::Result<String> {
+        let mut file = Throttled(File::open(path)?);
+        Ok(match self {
+            Algorithm::Md5 => hex_digest::<Md5>(&mut file)?,
+            Algorithm::Sha256 => hex_digest::<Sha256>(&mut file)?,
+            Algorithm::Sha512 => hex_digest::<Sha512>(&mut file)?,
+        })
+    }
+
+    /// Hex encoded digest of `bytes`.
+    pub fn hash_bytes(self, bytes: &[u8]) -> String {
+        match self {
+            Algorithm::Md5 => format!("{:x}", Md5::digest(bytes)),
+            Algorithm::Sha256 => format!("{:x}", Sha256::digest(bytes)),
+            Algorithm::Sha512 => format!("{:x}", Sha512::digest(bytes)),
+        }
+    }
+}
+
+fn hex_digest<D: Digest + io::Write>(reader: &mut impl io::Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Splits a hash read from a checksum file, kept in [`Entry::hash`] as
+/// `<algorithm>:<hex>`. Hashes from manifests are bare SHA-256 hex and give
+/// `None`.
+pub fn parse_tagged(hash: &str) -> Option<(Algorithm, &str)> {
+    let (name, hex) = hash.split_once(':')?;
+    let algorithm = Algorithm::of_digest(hex)?;
+    (algorithm.name() == name).then_some((algorithm, hex))
+}
+
+/// The hash a checksum file recorded for `entry`.
+pub fn checksum(entry: &Entry) -> Option<(Algorithm, &str)> {
+    entry.hash.as_deref().and_then(parse_tagged)
+}
+
+/// Whether `path` is a checksum file, told by its name.
+pub fn is_sums(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    path.is_file()
+        && (NAMES.contains(&name.as_ref())
+            || extension.is_some_and(|e| EXTENSIONS.contains(&e.as_str())))
+}
+
+/// The files listed in a checksum file, with the folders above them.
+pub struct Sums {
+    pub file: PathBuf,
+    files: HashMap<PathBuf, Entry>,
+}
+
+impl Sums {
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(file)?;
+        Ok(Sums {
+            file: file.to_path_buf(),
+            files: Sums::parse(file, &text),
+        })
+    }
+
+    /// Reads `<hex>  <path>` (`<hex> *<path>` for binary mode) and
+    /// `SHA256 (<path>) = <hex>` lines; others are logged and skipped. Paths
+    /// are relative to the folder compared against.
+    fn parse(file: &Path, text: &str) -> HashMap<PathBuf, Entry> {
+        let mut files = HashMap::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            // a leading backslash says the name has `\\` and `\n` escapes
+            let (escaped, line) = match line.strip_prefix('\\') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let tagged = ["MD5 (", "SHA256 (", "SHA512 ("]
+                .iter()
+                .find_map(|tag| line.strip_prefix(tag));
+            let parsed = match tagged {
+                Some(rest) => rest.rsplit_once(") = ").map(|(name, hex)| (hex, name)),
+                None => line
+                    .split_once(' ')
+                    .map(|(hex, name)| (hex, name.strip_prefix([' ', '*']).unwrap_or(name))),
+            };
+            let (hex, name, algorithm) = match parsed
+                .and_then(|(hex, name)| Some((hex, name, Algorithm::of_digest(hex)?)))
+            {
+                Some(parsed) => parsed,
+                None => {
+                    log::warn!("{}: skip malformed line {:?}", file.display(), line);
+                    continue;
+                }
+            };
+            let name = if escaped {
+                name.replace("\\n", "\n").replace("\\\\", "\\")
+            } else {
+                name.to_string()
+            };
+            let rel = clean_rel(Path::new(&name));
+            if rel.as_os_str().is_empty() || rel.is_absolute() {
+                log::warn!("{}: skip {:?}, not a relative path", file.display(), name);
+                continue;
+            }
+            for dir in rel.ancestors().skip(1) {
+                if dir.as_os_str().is_empty() {
+                    break;
+                }
+                files
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| Entry::new(file.join(dir), true, 0));
+            }
+            let mut entry = Entry::new(file.join(&rel), false, 0);
+            entry.hash = Some(format!("{}:{}", algorithm.name(), hex.to_lowercase()));
+            files.insert(rel, entry);
+        }
+        files
+    }
+}
+
+impl Source for Sums {
+    fn root(&self) -> &Path {
+        &self.file
+    }
+
+    fn list(&self, filter: &WalkFilter) -> io::Result<HashMap<PathBuf, Entry>> {
+        let mut files = self.files.clone();
+        files.retain(|rel, e| !filter.is_ignored(rel, e.is_dir()));
+        Ok(files)
+    }
+
+    fn read(&self, rel: &Path) -> io::Result<Vec<u8>> {
+        self.metadata(rel)?;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "a checksum file records no contents",
+        ))
+    }
+
+    fn metadata(&self, rel: &Path) -> io::Result<Entry> {
+        self.files
+            .get(rel)
+            .cloned()
+            .ok_or_else(|| not_found(&self.file, rel))
+    }
+
+    /// The recorded hash, tagged with its algorithm.
+    fn hash(&self, rel: &Path) -> io::Result<String> {
+        self.metadata(rel)?
+            .hash
+            .ok_or_else(|| not_found(&self.file, rel))
+    }
+}
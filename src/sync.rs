@@ -0,0 +1,210 @@
+use crate::color;
+use crate::ops::{self, ApplyDirection};
+use crate::status::{FolderStatefulList, StatusItemType};
+use crate::text;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// What a step of a sync does to the target tree.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// copy a file or a whole directory that the target lacks
+    Create,
+    /// overwrite a file whose contents differ
+    Update,
+    /// remove a file or a whole directory the source lacks
+    Delete,
+}
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+/// One step of a sync.
+pub struct Op {
+    pub action: Action,
+    /// relative to both tree roots
+    pub rel: PathBuf,
+    pub is_dir: bool,
+    /// bytes copied, 0 for deletions and directories
+    pub bytes: u64,
+}
+
+impl Op {
+    /// `update  src/main.rs (120 bytes)`, the line listing this step.
+    pub fn describe(&self) -> String {
+        let path = text::slash_path(&self.rel);
+        match (self.action, self.is_dir) {
+            (_, true) => format!("{}  {}/ (recursive)", self.action.name(), path),
+            (Action::Delete, false) => format!("delete  {}", path),
+            (_, false) => format!("{}  {} ({} bytes)", self.action.name(), path, self.bytes),
+        }
+    }
+}
+
+/// Every step that makes `target` match `source`, like `rsync -n` lists them.
+pub struct Plan {
+    pub source: String,
+    pub target: String,
+    pub ops: Vec<Op>,
+}
+
+impl Plan {
+    /// Turns the differences found by a comparison into steps, in the order of
+    /// `items`.
+    /// `items` are the collapsed results, so a new or deleted directory is one
+    /// step; `rel` gives an item's path relative to its tree. Named pipes and
+    /// devices are left out, they cannot be copied, and so are modified
+    /// folders.
+    pub fn new(
+        items: &[FolderStatefulList],
+        direction: ApplyDirection,
+        old_dir: &str,
+        new_dir: &str,
+        rel: impl Fn(&FolderStatefulList) -> PathBuf,
+    ) -> Self {
+        let to_old = direction == ApplyDirection::NewToOld;
+        let ops: Vec<Op> = items
+            .iter()
+            .filter(|item| {
+                if item.entry.special.is_some() {
+                    log::warn!("sync skips special file {}", item.entry.path().display());
+                }
+                item.entry.special.is_none()
+            })
+            .filter_map(|item| {
+                let action = match (item.state, to_old) {
+                    // a folder whose entries differ (--dir-changes): its
+                    // entries come as steps of their own, replacing it whole
+                    // would drop what only the target has
+                    (StatusItemType::Modified, _) if item.entry.is_dir() => return None,
+                    (StatusItemType::Modified, _) => Action::Update,
+                    (StatusItemType::New, true) | (StatusItemType::Deleted, false) => {
+                        Action::Create
+                    }
+                    (StatusItemType::New, false) | (StatusItemType::Deleted, true) => {
+                        Action::Delete
+                    }
+                    (StatusItemType::Normal, _) => return None,
+                };
+                let is_dir = item.entry.is_dir();
+                let bytes = match action {
+                    Action::Delete => 0,
+                    _ if is_dir => 0,
+                    // a modified item holds the new file, the old one is smaller by the delta
+                    Action::Update if !to_old => (item.entry.size as i64 - item.size_delta) as u64,
+                    _ => item.entry.size,
+                };
+                Some(Op {
+                    action,
+                    rel: rel(item),
+                    is_dir,
+                    bytes,
+                })
+            })
+            .collect();
+        let (source, target) = if to_old {
+            (new_dir, old_dir)
+        } else {
+            (old_dir, new_dir)
+        };
+        Plan {
+            source: source.to_string(),
+            target: target.to_string(),
+            ops,
+        }
+    }
+
+    /// Bytes of files to copy; whole directories are not counted.
+    pub fn bytes(&self) -> u64 {
+        self.ops.iter().map(|op| op.bytes).sum()
+    }
+
+    /// One line per step and a count of each action at the end. With `color`
+    /// steps take the color of the matching state in the file list.
+    pub fn to_text(&self, color: bool) -> String {
+        let mut out = String::new();
+        for op in &self.ops {
+            let state = match op.action {
+                Action::Create => StatusItemType::New,
+                Action::Update => StatusItemType::Modified,
+                Action::Delete => StatusItemType::Deleted,
+            };
+            let line = op.describe();
+            match color::state_color(state, false) {
+                Some(c) => out.push_str(&color::paint(&line, c, color)),
+                None => out.push_str(&line),
+            }
+            out.push('\n');
+        }
+        let count = |action| self.ops.iter().filter(|op| op.action == action).count();
+        out.push_str(&format!(
+            "would create {}, update {} and delete {} in {}, copying {} bytes from {}\n",
+            count(Action::Create),
+            count(Action::Update),
+            count(Action::Delete),
+            self.target,
+            self.bytes(),
+            self.source,
+        ));
+        out
+    }
+
+    /// Performs every step in order with [`ops::apply`], saving what is
+    /// overwritten or deleted under `backup_dir` when given. Deleted entries go
+    /// to the system trash unless `permanent` is set. A failed step is
+    /// reported to `done` and the rest still run; the number of failures is
+    /// returned.
+    pub fn apply(
+        &self,
+        backup_dir: Option<&Path>,
+        permanent: bool,
+        done: &mut impl FnMut(&Op, &io::Result<Option<PathBuf>>),
+    ) -> usize {
+        let (source, target) = (Path::new(&self.source), Path::new(&self.target));
+        let mut failed = 0;
+        for op in &self.ops {
+            let src = source.join(&op.rel);
+            let src = (op.action != Action::Delete).then_some(src.as_path());
+            let backup = backup_dir.map(|dir| dir.join(&op.rel));
+            let res = ops::apply(src, &target.join(&op.rel), backup.as_deref(), permanent);
+            if res.is_err() {
+                failed += 1;
+            }
+            done(op, &res);
+        }
+        failed
+    }
+
+    /// The plan as one JSON object.
+    pub fn to_json(&self) -> String {
+        let ops: Vec<String> = self
+            .ops
+            .iter()
+            .map(|op| {
+                format!(
+                    "{{\"action\":\"{}\",\"path\":{},\"dir\":{},\"bytes\":{}}}",
+                    op.action.name(),
+                    text::json_string(&text::slash_path(&op.rel)),
+                    op.is_dir,
+                    op.bytes
+                )
+            })
+            .collect();
+        format!(
+            "{{\"source\":{},\"target\":{},\"bytes\":{},\"operations\":[{}]}}",
+            text::json_string(&self.source),
+            text::json_string(&self.target),
+            self.bytes(),
+            ops.join(",")
+        )
+    }
+}
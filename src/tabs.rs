@@ -0,0 +1,158 @@
+//! Several pairs of folders open at once, from `--pair` and `--pairs-from`,
+//! each in its own [`App`] under a row of tabs. Tab and Shift+Tab switch
+//! between them; a pair is only compared once its tab is first shown.
+
+use crate::app::App;
+use crate::cli::Options;
+use crate::config;
+use crate::launch::Launch;
+use crate::session::Session;
+use crate::source::Source;
+use crossterm::event::{KeyCode, MouseEvent};
+use std::io;
+use std::path::Path;
+use std::process::ExitStatus;
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::Paragraph;
+use tui::{backend::Backend, Frame, Terminal};
+
+pub struct Tabs<S: Source = Box<dyn Source>> {
+    apps: Vec<App<S>>,
+    active: usize,
+}
+
+impl Tabs {
+    /// Opens the pair named on the command line and one tab for every other
+    /// pair. `--session` and `--bookmarks` go with the first pair, the others
+    /// keep their review under the cache directory.
+    pub fn open(mut options: Options) -> io::Result<Self> {
+        let pairs = std::mem::take(&mut options.pairs);
+        let mut apps = Vec::with_capacity(pairs.len() + 1);
+        for (old_dir, new_dir) in pairs {
+            let mut pair = options.clone();
+            pair.old_dir = old_dir;
+            pair.new_dir = new_dir;
+            pair.session = Session::default();
+            apps.push(App::new(pair)?);
+        }
+        apps.insert(0, App::new(options)?);
+        Ok(Self::new(apps))
+    }
+}
+
+impl<S: Source> Tabs<S> {
+    pub fn new(mut apps: Vec<App<S>>) -> Self {
+        assert!(!apps.is_empty(), "tabs need at least one pair");
+        if apps.len() > 1 {
+            apps.iter_mut().for_each(App::set_tabbed);
+        }
+        Tabs { apps, active: 0 }
+    }
+
+    /// The pair shown now.
+    pub fn active(&mut self) -> &mut App<S> {
+        &mut self.apps[self.active]
+    }
+
+    pub fn event(&mut self, key_code: KeyCode) {
+        let count = self.apps.len();
+        match key_code {
+            KeyCode::Tab if count > 1 && !self.active().is_typing() => {
+                self.active = (self.active + 1) % count;
+            }
+            KeyCode::BackTab if count > 1 && !self.active().is_typing() => {
+                self.active = (self.active + count - 1) % count;
+            }
+            _ => self.active().event(key_code),
+        }
+    }
+
+    /// See [`App::take_launch`].
+    pub fn take_launch(&mut self) -> Option<Launch> {
+        self.active().take_launch()
+    }
+
+    pub fn launched(&mut self, name: &str, status: io::Result<ExitStatus>) {
+        self.active().launched(name, status)
+    }
+
+    pub fn is_typing(&self) -> bool {
+        self.apps[self.active].is_typing()
+    }
+
+    pub fn tick(&mut self) -> bool {
+        self.active().tick()
+    }
+
+    /// See [`App::wants_mouse`].
+    pub fn wants_mouse(&self) -> bool {
+        self.apps[self.active].wants_mouse()
+    }
+
+    pub fn mouse(&mut self, event: MouseEvent) {
+        self.active().mouse(event)
+    }
+
+    /// Like [`App::on_resize`] for every pair, less the row of tabs.
+    pub fn on_resize(&mut self, height: u16) {
+        let height = height.saturating_sub(self.bar_height());
+        self.apps.iter_mut().for_each(|app| app.on_resize(height));
+    }
+
+    /// The view toggles of the pair shown now, saved on exit.
+    pub fn layout(&self) -> config::Layout {
+        self.apps[self.active].layout()
+    }
+
+    pub fn draw_terminal<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        self.active().load_with_gauge(terminal)?;
+        terminal.draw(|f| self.draw(f))?;
+        Ok(())
+    }
+
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
+        if self.bar_height() == 0 {
+            return self.active().draw(f);
+        }
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+            .split(f.size());
+        f.render_widget(Paragraph::new(self.bar()), chunks[0]);
+        self.active().draw_in(f, chunks[1]);
+    }
+
+    fn bar_height(&self) -> u16 {
+        u16::from(self.apps.len() > 1)
+    }
+
+    /// ` 1 staging → prod (12)  2 qa → prod `, the pair shown now reversed.
+    /// Pairs not compared yet have no count.
+    fn bar(&self) -> Spans<'static> {
+        let spans = self.apps.iter().enumerate().map(|(i, app)| {
+            let (old, new) = app.roots();
+            let mut label = format!(" {} {} → {} ", i + 1, name(old), name(new));
+            if let Some(n) = app.differences() {
+                label.insert_str(label.len() - 1, &format!(" ({})", n));
+            }
+            let style = if i == self.active {
+                Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Span::styled(label, style)
+        });
+        Spans::from(spans.collect::<Vec<_>>())
+    }
+}
+
+/// The last part of a tree's label, which tells pairs apart in little room.
+fn name(root: &str) -> String {
+    let trimmed = root.trim_end_matches(['/', '\\']);
+    match Path::new(trimmed).file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => root.to_string(),
+    }
+}
@@ -0,0 +1,279 @@
+use std::path::{Path, PathBuf};
+use unicode_width::UnicodeWidthStr;
+
+/// How entries are labelled in the file list.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum PathStyle {
+    /// `./src/app.rs`
+    #[default]
+    Relative,
+    /// the absolute path
+    Full,
+    /// `app.rs` indented by depth, with the selection's breadcrumb in the title
+    Name,
+}
+
+impl PathStyle {
+    /// The style after this one, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            PathStyle::Relative => PathStyle::Full,
+            PathStyle::Full => PathStyle::Name,
+            PathStyle::Name => PathStyle::Relative,
+        }
+    }
+
+    /// The name `--path-style` takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            PathStyle::Relative => "relative",
+            PathStyle::Full => "full",
+            PathStyle::Name => "name",
+        }
+    }
+
+    /// Labels `path`, which lives under `root`.
+    pub fn label(self, path: &Path, root: &str) -> String {
+        let rel = match (self, path.strip_prefix(root)) {
+            (PathStyle::Full, _) | (_, Err(_)) => return path.to_string_lossy().into_owned(),
+            (_, Ok(rel)) => rel,
+        };
+        match self {
+            PathStyle::Name => {
+                let depth = rel.components().count().saturating_sub(1);
+                let name = rel.file_name().unwrap_or(rel.as_os_str());
+                format!("{}{}", "  ".repeat(depth), name.to_string_lossy())
+            }
+            _ => Path::new(".").join(rel).to_string_lossy().into_owned(),
+        }
+    }
+}
+
+impl std::str::FromStr for PathStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relative" => Ok(PathStyle::Relative),
+            "full" => Ok(PathStyle::Full),
+            "name" => Ok(PathStyle::Name),
+            _ => Err(format!("expected relative, full or name, got {}", s)),
+        }
+    }
+}
+
+/// Spells a relative path with `/` on every platform, as git, remote hosts
+/// and manifests expect.
+pub fn slash_path(rel: &Path) -> String {
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `./src/app.rs` (or `.\src\app.rs` on Windows) names the same path as
+/// `src/app.rs`.
+pub fn strip_dot_prefix(mut line: &str) -> &str {
+    loop {
+        let rest = match line.strip_prefix("./") {
+            Some(rest) => rest,
+            None if cfg!(windows) => match line.strip_prefix(".\\") {
+                Some(rest) => rest,
+                None => return line,
+            },
+            None => return line,
+        };
+        line = rest;
+    }
+}
+
+/// Drops the `\\?\` prefix Windows' canonicalize puts in front of every path,
+/// which other programs and users do not expect: `\\?\C:\dir` becomes
+/// `C:\dir` and `\\?\UNC\server\share` becomes `\\server\share`. Paths too
+/// long to work without the prefix keep it.
+pub fn strip_verbatim(path: PathBuf) -> PathBuf {
+    const MAX_PATH: usize = 260;
+    let text = path.to_string_lossy();
+    if text.len() >= MAX_PATH {
+        return path;
+    }
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{}", rest));
+    }
+    match text.strip_prefix(r"\\?\") {
+        // only plain drive paths, not volume GUIDs or devices
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+        _ => path,
+    }
+}
+
+/// `s` as a quoted JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `src › app › mod.rs` for a `path` under `root`.
+pub fn breadcrumb(path: &Path, root: &str) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" › ")
+}
+
+/// Tab stop distance used when `--tab-width` is not given.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Decodes `bytes` as UTF-8, spelling every invalid byte as `\xNN` instead of
+/// failing, so any file can be shown.
+pub fn decode_lossy(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    let mut rest = bytes;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                out.push_str(valid);
+                return out;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                out.push_str(&String::from_utf8_lossy(valid));
+                let bad = e.error_len().unwrap_or(after.len());
+                for b in &after[..bad] {
+                    out.push_str(&format!("\\x{:02x}", b));
+                }
+                rest = &after[bad..];
+            }
+        }
+    }
+}
+
+/// `hexdump -C` style lines for the first `limit` bytes: offset, 16 bytes in
+/// hex and their printable ASCII.
+pub fn hexdump(bytes: &[u8], limit: usize) -> Vec<String> {
+    bytes[..bytes.len().min(limit)]
+        .chunks(16)
+        .enumerate()
+        .map(|(i, row)| {
+            let mut hex = String::new();
+            for (j, b) in row.iter().enumerate() {
+                hex.push_str(if j == 8 { "  " } else { " " });
+                hex.push_str(&format!("{:02x}", b));
+            }
+            let ascii: String = row
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x} {:<49}  |{}|", i * 16, hex, ascii)
+        })
+        .collect()
+}
+
+/// How diff lines are turned into display text.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderOptions {
+    pub tab_width: usize,
+    /// mark trailing whitespace and mixed tab/space indentation
+    pub show_whitespace: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            tab_width: DEFAULT_TAB_WIDTH,
+            show_whitespace: false,
+        }
+    }
+}
+
+/// Columns `s` takes on a terminal: two for CJK characters and most emoji,
+/// none for combining marks.
+pub fn width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+/// `s` followed by the spaces that make it `width` columns wide; `{:<n}`
+/// counts characters, which leaves wide ones out of line.
+pub fn pad(s: &str, width: usize) -> String {
+    format!("{}{}", s, " ".repeat(width.saturating_sub(self::width(s))))
+}
+
+/// Marks that reorder the text after them on screen, so a line would not
+/// read the way it is stored.
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Prepares one line for display: drops the line ending, expands tabs to the
+/// next multiple of `tab_width` and makes control and bidi characters
+/// visible.
+pub fn render_line(line: &str, tab_width: usize) -> String {
+    let options = RenderOptions {
+        tab_width,
+        show_whitespace: false,
+    };
+    render_segments(line, options)
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect()
+}
+
+/// Like [`render_line`], split into runs flagged `true` where whitespace is
+/// worth pointing out. Flagged spaces show as `·` and tabs as `→`.
+pub fn render_segments(line: &str, options: RenderOptions) -> Vec<(String, bool)> {
+    let line = line
+        .strip_suffix('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .unwrap_or(line);
+    let tab_width = options.tab_width.max(1);
+    let body = line.trim_start_matches([' ', '\t']);
+    let indent = &line[..line.len() - body.len()];
+    let mixed_indent = indent.contains(' ') && indent.contains('\t');
+    let trailing_start = line.trim_end_matches([' ', '\t']).len();
+
+    let mut segments: Vec<(String, bool)> = Vec::new();
+    let mut col = 0;
+    for (i, c) in line.char_indices() {
+        let flagged =
+            options.show_whitespace && ((i < indent.len() && mixed_indent) || i >= trailing_start);
+        let text = match c {
+            '\t' => {
+                let width = tab_width - col % tab_width;
+                let fill = if flagged { "→" } else { " " };
+                fill.to_string() + &" ".repeat(width - 1)
+            }
+            ' ' if flagged => "·".to_string(),
+            '\0' => "␀".to_string(),
+            c if c.is_control() => format!("\\x{:02x}", c as u32),
+            c if is_bidi_control(c) => format!("\\u{{{:x}}}", c as u32),
+            c => c.to_string(),
+        };
+        // tab stops count columns, which wide characters take two of
+        col += width(&text);
+        match segments.last_mut() {
+            Some((last, last_flagged)) if *last_flagged == flagged => last.push_str(&text),
+            _ => segments.push((text, flagged)),
+        }
+    }
+    segments
+}
@@ -0,0 +1,105 @@
+//! Keeping a comparison from starving other workloads on a shared disk or a
+//! production NFS mount: `--io-limit` caps the bytes read per second and
+//! `--nice` lowers the process' CPU and IO priority.
+
+use std::{
+    io::{self, Read},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The read budget shared by every thread, `None` when reads are unlimited.
+static BUCKET: Mutex<Option<Bucket>> = Mutex::new(None);
+
+/// A token bucket refilled at `rate` bytes per second and holding at most one
+/// second worth of bytes, so a burst after a pause stays short.
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+/// Limits reads of file contents to `bytes_per_sec` from now on.
+pub fn set_limit(bytes_per_sec: u64) {
+    let rate = bytes_per_sec.max(1) as f64;
+    *BUCKET.lock().unwrap() = Some(Bucket {
+        rate,
+        tokens: rate,
+        last: Instant::now(),
+    });
+}
+
+/// Takes `bytes` from the budget and returns how long to wait before reading
+/// them. The budget may go negative, so callers queue up behind each other.
+pub fn reserve(bytes: usize) -> Duration {
+    let mut bucket = BUCKET.lock().unwrap();
+    let bucket = match bucket.as_mut() {
+        Some(bucket) => bucket,
+        None => return Duration::ZERO,
+    };
+    let now = Instant::now();
+    let refill = now.duration_since(bucket.last).as_secs_f64() * bucket.rate;
+    bucket.tokens = (bucket.tokens + refill).min(bucket.rate) - bytes as f64;
+    bucket.last = now;
+    if bucket.tokens >= 0.0 {
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+    }
+}
+
+/// Blocks until `bytes` may be read.
+pub fn consume(bytes: usize) {
+    let wait = reserve(bytes);
+    if !wait.is_zero() {
+        std::thread::sleep(wait);
+    }
+}
+
+/// A reader that pays for what it read before handing it out.
+pub struct Throttled<R>(pub R);
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        consume(n);
+        Ok(n)
+    }
+}
+
+/// Lowers the CPU priority like `nice -n 10` and, on Linux, moves the process
+/// to the idle IO class like `ionice -c 3`.
+#[cfg(unix)]
+pub fn be_nice() -> io::Result<()> {
+    // SAFETY: plain system calls on the current process
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 10) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+        const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+        const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+        // SAFETY: as above
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                0,
+                IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+            )
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn be_nice() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--nice is only supported on unix",
+    ))
+}
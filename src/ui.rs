@@ -0,0 +1,361 @@
+use crate::patch::Hunk;
+use crate::status::StatusItemType;
+use crossterm::event::KeyCode;
+use tui::{
+    backend::Backend,
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Gauge, Paragraph, Widget, Wrap},
+    Frame,
+};
+
+/// Share of the screen width a popup takes.
+const WIDTH_PERCENT: u16 = 60;
+
+/// A popup drawn over the rest of the screen. While one is open it gets every
+/// key, so actions, prompts and progress all look and behave the same.
+pub enum Modal {
+    /// a yes/no question, with a preview of what is about to happen
+    Confirm { title: String, lines: Vec<String> },
+    /// a question answered with one of a few keys, each with a label
+    Choice {
+        title: String,
+        lines: Vec<String>,
+        choices: Vec<(char, String)>,
+    },
+    /// a one-line text prompt
+    Input { title: String, value: String },
+    /// a progress bar at `percent`
+    Progress { title: String, percent: u16 },
+}
+
+/// What a key did to a modal.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// still open
+    Open,
+    /// closed with Esc or `n`
+    Cancelled,
+    /// a confirmation accepted with Enter or `y`
+    Confirmed,
+    /// a prompt closed with Enter, holding what was typed
+    Submitted(String),
+    /// the key of the choice picked
+    Chosen(char),
+}
+
+impl Modal {
+    pub fn confirm(title: impl Into<String>, lines: Vec<String>) -> Self {
+        Modal::Confirm {
+            title: title.into(),
+            lines,
+        }
+    }
+
+    pub fn choice(
+        title: impl Into<String>,
+        lines: Vec<String>,
+        choices: Vec<(char, String)>,
+    ) -> Self {
+        Modal::Choice {
+            title: title.into(),
+            lines,
+            choices,
+        }
+    }
+
+    pub fn input(title: impl Into<String>) -> Self {
+        Modal::Input {
+            title: title.into(),
+            value: String::new(),
+        }
+    }
+
+    /// A prompt that starts out holding `value`, to edit an earlier answer.
+    pub fn input_with(title: impl Into<String>, value: impl Into<String>) -> Self {
+        Modal::Input {
+            title: title.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn key(&mut self, key_code: KeyCode) -> Outcome {
+        match self {
+            Modal::Confirm { .. } => match key_code {
+                KeyCode::Enter | KeyCode::Char('y') => Outcome::Confirmed,
+                KeyCode::Esc | KeyCode::Char('n') => Outcome::Cancelled,
+                _ => Outcome::Open,
+            },
+            Modal::Choice { choices, .. } => match key_code {
+                KeyCode::Char(c) if choices.iter().any(|(k, _)| *k == c) => Outcome::Chosen(c),
+                KeyCode::Esc => Outcome::Cancelled,
+                _ => Outcome::Open,
+            },
+            Modal::Input { value, .. } => match key_code {
+                KeyCode::Char(c) => {
+                    value.push(c);
+                    Outcome::Open
+                }
+                KeyCode::Backspace => {
+                    value.pop();
+                    Outcome::Open
+                }
+                KeyCode::Enter => Outcome::Submitted(std::mem::take(value)),
+                KeyCode::Esc => Outcome::Cancelled,
+                _ => Outcome::Open,
+            },
+            Modal::Progress { .. } => Outcome::Open,
+        }
+    }
+
+    /// The keys a question takes, shown below it.
+    fn help(&self) -> String {
+        match self {
+            Modal::Choice { choices, .. } => choices
+                .iter()
+                .map(|(key, label)| format!("{}: {}", key, label))
+                .chain(["Esc: close".to_string()])
+                .collect::<Vec<_>>()
+                .join("    "),
+            _ => "y / Enter: confirm    n / Esc: cancel".to_string(),
+        }
+    }
+
+    /// Draws the popup centered over whatever is already in the frame.
+    pub fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        // rows a wrapped line takes, roughly: word wrapping may need one more
+        let inner = (f.size().width * WIDTH_PERCENT / 100)
+            .saturating_sub(2)
+            .max(1) as usize;
+        let height = match self {
+            Modal::Confirm { lines, .. } | Modal::Choice { lines, .. } => {
+                let help = self.help();
+                let rows: usize = lines
+                    .iter()
+                    .chain([&help])
+                    .map(|l| crate::text::width(l).max(1).div_ceil(inner))
+                    .sum();
+                rows as u16 + 3
+            }
+            Modal::Input { .. } => 3,
+            Modal::Progress { .. } => 3,
+        };
+        let area = centered(f.size(), height);
+        f.render_widget(Clear, area);
+        match self {
+            Modal::Confirm { title, lines } | Modal::Choice { title, lines, .. } => {
+                let mut text: Vec<Spans> = lines.iter().map(|l| Spans::from(l.clone())).collect();
+                text.push(Spans::default());
+                text.push(Spans::from(Span::styled(
+                    self.help(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )));
+                let paragraph = Paragraph::new(text)
+                    .wrap(Wrap { trim: false })
+                    .block(block(title).border_style(Style::default().fg(Color::Yellow)));
+                f.render_widget(paragraph, area);
+            }
+            Modal::Input { title, value } => {
+                f.render_widget(Paragraph::new(value.as_str()).block(block(title)), area);
+                let x = area.x + 1 + crate::text::width(value) as u16;
+                f.set_cursor(x.min(area.right().saturating_sub(2)), area.y + 1);
+            }
+            Modal::Progress { title, percent } => {
+                let gauge = Gauge::default()
+                    .block(block(title))
+                    .gauge_style(Style::default().fg(Color::White))
+                    .percent((*percent).min(100));
+                f.render_widget(gauge, area);
+            }
+        }
+    }
+}
+
+fn block(title: &str) -> Block<'_> {
+    Block::default().title(title).borders(Borders::ALL)
+}
+
+/// A box `height` rows high in the middle of `area`, [`WIDTH_PERCENT`] wide.
+pub fn centered(area: Rect, height: u16) -> Rect {
+    let height = height.min(area.height);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length((area.height - height) / 2),
+                Constraint::Length(height),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - WIDTH_PERCENT) / 2),
+                Constraint::Percentage(WIDTH_PERCENT),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(rows[1])[1]
+}
+
+/// [`crate::color::state_color`] for the list.
+pub fn state_color(state: StatusItemType, special: bool) -> Option<Color> {
+    crate::color::state_color(state, special).map(Color::from)
+}
+
+/// Takes the colors out of everything drawn below it, for `--no-color`. The
+/// colors that carry a meaning become text attributes: green (new, inserted)
+/// turns bold, red (deleted) underlined, yellow (modified) both, magenta
+/// (special files) italic, grey dim, and a background, like the selection,
+/// reverses the cell.
+pub struct Monochrome;
+
+impl Widget for Monochrome {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let cell = buf.get_mut(x, y);
+                let mut modifier = match cell.fg {
+                    Color::Green | Color::LightGreen => Modifier::BOLD,
+                    Color::Red | Color::LightRed => Modifier::UNDERLINED,
+                    Color::Yellow | Color::LightYellow => Modifier::BOLD | Modifier::UNDERLINED,
+                    Color::Magenta | Color::LightMagenta => Modifier::ITALIC,
+                    Color::DarkGray => Modifier::DIM,
+                    _ => Modifier::empty(),
+                };
+                if cell.bg != Color::Reset {
+                    modifier |= Modifier::REVERSED;
+                }
+                cell.modifier |= modifier;
+                cell.fg = Color::Reset;
+                cell.bg = Color::Reset;
+            }
+        }
+    }
+}
+
+/// Splits `line` into rows of at most `width` columns, the way the diff window
+/// shows it wrapped. Breaking at the width rather than between words keeps the
+/// rows a line takes easy to count, which scrolling relies on.
+pub fn wrap_line(line: Spans<'static>, width: usize) -> Vec<Spans<'static>> {
+    if width == 0 {
+        return vec![line];
+    }
+    let mut rows = Vec::new();
+    let mut row: Vec<Span<'static>> = Vec::new();
+    let mut used = 0;
+    for span in &line.0 {
+        for grapheme in span.styled_graphemes(Style::default()) {
+            let w = Span::raw(grapheme.symbol).width();
+            if used + w > width && used > 0 {
+                rows.push(Spans::from(std::mem::take(&mut row)));
+                used = 0;
+            }
+            match row.last_mut() {
+                Some(last) if last.style == grapheme.style => {
+                    last.content.to_mut().push_str(grapheme.symbol)
+                }
+                _ => row.push(Span::styled(grapheme.symbol.to_string(), grapheme.style)),
+            }
+            used += w;
+        }
+    }
+    rows.push(Spans::from(row));
+    rows
+}
+
+/// `line` without its first `skip` columns, for scrolling sideways. A wide
+/// character cut in half by the edge leaves a space, so the rest of the line
+/// stays in the columns it had.
+pub fn clip_line(line: Spans<'static>, skip: usize) -> Spans<'static> {
+    if skip == 0 {
+        return line;
+    }
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut col = 0;
+    for span in &line.0 {
+        for grapheme in span.styled_graphemes(Style::default()) {
+            let w = Span::raw(grapheme.symbol).width();
+            let end = col + w;
+            col = end;
+            let symbol = if end <= skip {
+                continue;
+            } else if end - w < skip {
+                " ".repeat(end - skip)
+            } else {
+                grapheme.symbol.to_string()
+            };
+            match spans.last_mut() {
+                Some(last) if last.style == grapheme.style => {
+                    last.content.to_mut().push_str(&symbol)
+                }
+                _ => spans.push(Span::styled(symbol, grapheme.style)),
+            }
+        }
+    }
+    Spans::from(spans)
+}
+
+/// Greys out the lines of hunks that only differ in ignored lines; `contents`
+/// holds the diff lines from `first` on.
+pub fn dim_hunks(contents: &mut [Spans], first: usize, hunks: &[Hunk]) {
+    let visible = first..first + contents.len();
+    for hunk in hunks {
+        let start = hunk.display_start.max(visible.start);
+        let end = (hunk.display_start + hunk.display_len()).min(visible.end);
+        for line in contents
+            .iter_mut()
+            .take(end.saturating_sub(first))
+            .skip(start - first)
+        {
+            for span in &mut line.0 {
+                span.style = span.style.fg(Color::DarkGray);
+            }
+        }
+    }
+}
+
+/// Puts a gutter in front of the diff lines from `first` on: `|` for lines in
+/// a hunk, `*` once the hunk is selected, bright for the hunk under the cursor.
+pub fn mark_hunks(
+    contents: &mut [Spans],
+    first: usize,
+    hunks: &[Hunk],
+    selected: &[bool],
+    cursor: usize,
+) {
+    let visible = first..first + contents.len();
+    let mut marks = vec![" "; contents.len()];
+    for (i, hunk) in hunks.iter().enumerate() {
+        let mark = if selected[i] { "*" } else { "|" };
+        let start = hunk.display_start.max(visible.start);
+        let end = (hunk.display_start + hunk.display_len()).min(visible.end);
+        for m in marks
+            .iter_mut()
+            .take(end.saturating_sub(first))
+            .skip(start - first)
+        {
+            *m = mark;
+        }
+    }
+    let (start, end) = match hunks.get(cursor) {
+        Some(h) => (h.display_start, h.display_start + h.display_len()),
+        None => (0, 0),
+    };
+    for (i, line) in contents.iter_mut().enumerate() {
+        let style = if (start..end).contains(&(first + i)) {
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        line.0.insert(0, Span::styled(marks[i], style));
+    }
+}
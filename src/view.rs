@@ -0,0 +1,548 @@
+use crate::compare;
+use crate::engine::Engine;
+use crate::format;
+use crate::mime;
+use crate::renderer::DiffRenderer;
+use crate::source::Source;
+use crate::status::{FolderStatefulList, Special};
+use crate::text::{self, RenderOptions};
+use similar::{ChangeTag, DiffOp, DiffTag, TextDiff};
+use std::ops::Range;
+use std::path::Path;
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+// bytes of a binary file shown in its preview
+const PREVIEW_BYTES: usize = 256;
+
+/// What the diff window shows. Texts are held once and lines only become
+/// spans when they are on screen, so a file with millions of lines costs a
+/// few offsets per line rather than a styled string each.
+pub enum Content {
+    /// a handful of prepared lines: messages, binary previews
+    Lines(Vec<Spans<'static>>),
+    /// every line of a new or deleted file in one color
+    Whole(Lines, Color),
+    /// line diff of a modified file
+    Diff(Diff),
+}
+
+impl Content {
+    /// Number of lines, the unit `scroll` counts in.
+    pub fn len(&self) -> usize {
+        match self {
+            Content::Lines(lines) => lines.len(),
+            Content::Whole(lines, _) => lines.len(),
+            Content::Diff(diff) => diff.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The lines in `range`, ready to draw.
+    pub fn spans(&self, range: Range<usize>, render: RenderOptions) -> Vec<Spans<'static>> {
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        match self {
+            Content::Lines(lines) => lines[range].to_vec(),
+            Content::Whole(lines, color) => range
+                .map(|i| styled_line("", lines.line(i), Style::default().fg(*color), render))
+                .collect(),
+            Content::Diff(diff) => range
+                .map(|i| {
+                    let (tag, line) = diff.line(i);
+                    let (sign, color) = match tag {
+                        ChangeTag::Delete => ("-", Color::Red),
+                        ChangeTag::Insert => ("+", Color::Green),
+                        ChangeTag::Equal => (" ", Color::White),
+                    };
+                    styled_line(
+                        &format!("{} ", sign),
+                        line,
+                        Style::default().fg(color),
+                        render,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A text split into lines the way [`TextDiff::from_lines`] does, each line
+/// keeping its line ending.
+pub struct Lines {
+    text: String,
+    // byte offset where each line starts
+    starts: Vec<usize>,
+}
+
+impl Lines {
+    pub fn new(text: String) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .filter(|&i| i < text.len())
+            .collect();
+        Lines { text, starts }
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    pub fn line(&self, i: usize) -> &str {
+        let end = self.starts.get(i + 1).copied().unwrap_or(self.text.len());
+        &self.text[self.starts[i]..end]
+    }
+}
+
+/// The line diff of two texts, stored as the texts and the diff operations.
+/// The unified view lists every change in order with deletions before
+/// insertions, like [`crate::patch::Hunk::display_start`] counts; the column
+/// view puts old and new lines side by side.
+pub struct Diff {
+    old: Lines,
+    new: Lines,
+    ops: Vec<DiffOp>,
+    // unified line where each op starts, plus the total at the end
+    line_starts: Vec<usize>,
+    // side by side row where each op starts, plus the total at the end
+    row_starts: Vec<usize>,
+}
+
+impl Diff {
+    pub fn new(old: String, new: String) -> Self {
+        let ops = TextDiff::from_lines(&old, &new).ops().to_vec();
+        let mut line_starts = vec![0];
+        let mut row_starts = vec![0];
+        for op in &ops {
+            let (old_len, new_len) = (op.old_range().len(), op.new_range().len());
+            let (lines, rows) = match op.tag() {
+                DiffTag::Equal => (old_len, old_len),
+                _ => (old_len + new_len, old_len.max(new_len)),
+            };
+            line_starts.push(line_starts[line_starts.len() - 1] + lines);
+            row_starts.push(row_starts[row_starts.len() - 1] + rows);
+        }
+        Diff {
+            old: Lines::new(old),
+            new: Lines::new(new),
+            ops,
+            line_starts,
+            row_starts,
+        }
+    }
+
+    /// Lines of the unified view.
+    pub fn len(&self) -> usize {
+        self.line_starts[self.ops.len()]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Rows of the column view.
+    pub fn rows(&self) -> usize {
+        self.row_starts[self.ops.len()]
+    }
+
+    /// Line `i` of the unified view.
+    pub fn line(&self, i: usize) -> (ChangeTag, &str) {
+        let k = self.line_starts.partition_point(|&s| s <= i) - 1;
+        let (op, offset) = (&self.ops[k], i - self.line_starts[k]);
+        let (old_range, new_range) = (op.old_range(), op.new_range());
+        match op.tag() {
+            DiffTag::Equal => (ChangeTag::Equal, self.old.line(old_range.start + offset)),
+            _ if offset < old_range.len() => {
+                (ChangeTag::Delete, self.old.line(old_range.start + offset))
+            }
+            _ => (
+                ChangeTag::Insert,
+                self.new.line(new_range.start + offset - old_range.len()),
+            ),
+        }
+    }
+
+    /// The rows in `range` of the old and the new column: equal lines share a
+    /// row, replaced lines are paired up and the shorter side of a change is
+    /// padded with blank rows.
+    pub fn columns(&self, range: Range<usize>, render: RenderOptions) -> [Vec<Spans<'static>>; 2] {
+        let (mut left, mut right) = (Vec::new(), Vec::new());
+        for row in range.start..range.end.min(self.rows()) {
+            let k = self.row_starts.partition_point(|&s| s <= row) - 1;
+            let (op, offset) = (&self.ops[k], row - self.row_starts[k]);
+            let (old_color, new_color) = match op.tag() {
+                DiffTag::Equal => (Color::White, Color::White),
+                _ => (Color::Red, Color::Green),
+            };
+            let cell = |lines: &Lines, range: Range<usize>, color| {
+                if offset < range.len() {
+                    styled_line(
+                        "",
+                        lines.line(range.start + offset),
+                        Style::default().fg(color),
+                        render,
+                    )
+                } else {
+                    Spans::default()
+                }
+            };
+            left.push(cell(&self.old, op.old_range(), old_color));
+            right.push(cell(&self.new, op.new_range(), new_color));
+        }
+        [left, right]
+    }
+}
+
+/// `line` after `prefix`, both in `style`, with flagged whitespace on red.
+pub fn styled_line(
+    prefix: &str,
+    line: &str,
+    style: Style,
+    render: RenderOptions,
+) -> Spans<'static> {
+    let mut spans = vec![Span::styled(prefix.to_string(), style)];
+    for (text, flagged) in text::render_segments(line, render) {
+        let style = if flagged { style.bg(Color::Red) } else { style };
+        spans.push(Span::styled(text, style));
+    }
+    Spans::from(spans)
+}
+
+/// What the diff window shows for `file`, and its title: the two sides diffed
+/// line by line, a new or deleted file whole, or a message when there is
+/// nothing to diff.
+pub fn file_view<S: Source>(
+    engine: &Engine<S>,
+    file: &FolderStatefulList,
+    show_credits: bool,
+) -> (Content, String) {
+    let (old, new) = (&engine.old, &engine.new);
+    if show_credits {
+        let letter = String::from_utf8(MSG.to_vec()).unwrap();
+        let lines = letter.lines().map(|l| Spans::from(l.to_string())).collect();
+        return (Content::Lines(lines), "letter".to_string());
+    }
+    if file.entry.is_dir() && file.state == crate::status::StatusItemType::Modified {
+        if let Some(rel) = engine.relative(file) {
+            return dir_changes(engine, rel);
+        }
+    }
+    if file.entry.is_dir() {
+        return (
+            Content::Lines(vec![Spans::from("\n\nthis is directory")]),
+            "error".to_string(),
+        );
+    }
+    let cur_file_path = file.entry.path().to_string_lossy().into_owned();
+    let rel = match engine.relative(file) {
+        Some(rel) => rel,
+        None => {
+            return (
+                Content::Lines(vec![Spans::from("please press 'enter', select file")]),
+                "error".to_string(),
+            )
+        }
+    };
+    // pipes and devices only show what they are, opening one could block
+    let kinds = [old, new].map(|s| s.metadata(rel).ok().and_then(|e| e.special));
+    if file.entry.special.or(kinds[0]).or(kinds[1]).is_some() {
+        let kind = |k: Option<Special>| k.map_or("regular file", Special::name);
+        let mut lines = vec![
+            Spans::from("special file, contents are not read"),
+            Spans::default(),
+        ];
+        if file.state != crate::status::StatusItemType::New {
+            lines.push(Spans::from(format!("old: {}", kind(kinds[0]))));
+        }
+        if file.state != crate::status::StatusItemType::Deleted {
+            lines.push(Spans::from(format!("new: {}", kind(kinds[1]))));
+        }
+        return (Content::Lines(lines), format!("Special: {}", cur_file_path));
+    }
+    if !engine.renderers().is_empty() {
+        if let Some(renderer) = engine.renderers().find(rel, engine.mime_type(file)) {
+            return rendered(engine, file, rel, renderer, &cur_file_path);
+        }
+    }
+    if let Some(other) = &file.moved {
+        if let Some(moved) = moved_view(engine, file, rel, other) {
+            return moved;
+        }
+    }
+    // deleted files are read from the old side, everything else from the new one
+    let source = match file.state {
+        crate::status::StatusItemType::Deleted => old,
+        _ => new,
+    };
+    let bytes_new = match source.read(rel) {
+        Ok(buf) => buf,
+        Err(e) => {
+            return (
+                Content::Lines(vec![Spans::from(format!(
+                    "open file:{}, error: {}",
+                    cur_file_path, e
+                ))]),
+                "error".to_string(),
+            )
+        }
+    };
+
+    if file.state == crate::status::StatusItemType::Deleted
+        || file.state == crate::status::StatusItemType::New
+    {
+        if mime::is_binary(&bytes_new) {
+            return binary_preview(file, &cur_file_path, &bytes_new);
+        }
+        let buf_new = text::decode_lossy(&bytes_new);
+        let mut title = format!("Deleted: {}", cur_file_path);
+        let mut style = Color::Red;
+        if file.state == crate::status::StatusItemType::New {
+            title = format!("New File: {}", cur_file_path);
+            style = Color::Green;
+        }
+        return (Content::Whole(Lines::new(buf_new), style), title);
+    }
+
+    let old_file_path = old.root().join(rel).to_string_lossy().into_owned();
+    let buf_old = match old.read(rel) {
+        Ok(buf) => text::decode_lossy(&buf),
+        Err(e) => {
+            return (
+                Content::Lines(vec![Spans::from(format!(
+                    "open file:{}, error: {}",
+                    old_file_path, e
+                ))]),
+                "error".to_string(),
+            )
+        }
+    };
+
+    let diff = Diff::new(buf_old, text::decode_lossy(&bytes_new));
+    let mut title = format!("Diff: {} and {}", cur_file_path, old_file_path);
+    let links = |s: &S| s.metadata(rel).ok().and_then(|e| e.inode).map(|i| i.links);
+    if let (Some(old_links), Some(new_links)) = (links(old), links(new)) {
+        if old_links != new_links {
+            title = format!("{} (hard links: {} -> {})", title, old_links, new_links);
+        }
+    }
+    (Content::Diff(diff), title)
+}
+
+/// Both files being compared with their sizes and mtimes, e.g.
+/// `old: /src/a.txt (12 B, 3 days ago)  new: /dst/a.txt (20 B, 2 hours ago)`,
+/// so it is plain which two files the diff window shows.
+pub fn sides<S: Source>(engine: &Engine<S>, file: &FolderStatefulList) -> String {
+    let (old_path, new_path) = engine.side_paths(file);
+    let (old_rel, new_rel) = engine.side_rels(file);
+    let side = |name: &str, source: &S, path: Option<std::path::PathBuf>, rel: Option<&Path>| {
+        let (path, rel) = match (path, rel) {
+            (Some(path), Some(rel)) => (path, rel),
+            _ => return format!("{}: none", name),
+        };
+        let details = match source.metadata(rel) {
+            Ok(entry) => match entry.mtime {
+                Some(mtime) => format!("{}, {}", format::size(entry.size), format::date(mtime)),
+                None => format::size(entry.size),
+            },
+            Err(e) => e.to_string(),
+        };
+        format!("{}: {} ({})", name, path.display(), details)
+    };
+    format!(
+        "{}  {}",
+        side("old", &engine.old, old_path, old_rel),
+        side("new", &engine.new, new_path, new_rel)
+    )
+}
+
+/// The line diff between both ends of a move, `None` to show a binary file
+/// like any other new or deleted one.
+fn moved_view<S: Source>(
+    engine: &Engine<S>,
+    file: &FolderStatefulList,
+    rel: &Path,
+    other: &Path,
+) -> Option<(Content, String)> {
+    let (old_rel, new_rel) = match file.state {
+        crate::status::StatusItemType::Deleted => (rel, other),
+        _ => (other, rel),
+    };
+    let (old_path, new_path) = (
+        engine.old.root().join(old_rel),
+        engine.new.root().join(new_rel),
+    );
+    let (old, new) = match (engine.old.read(old_rel), engine.new.read(new_rel)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            let line = format!(
+                "open file:{} or {}, error: {}",
+                old_path.display(),
+                new_path.display(),
+                e
+            );
+            return Some((Content::Lines(vec![Spans::from(line)]), "error".to_string()));
+        }
+    };
+    if mime::is_binary(&old) || mime::is_binary(&new) {
+        return None;
+    }
+    let mut title = format!("Moved: {} -> {}", old_path.display(), new_path.display());
+    if old == new {
+        title += " (same contents)";
+    } else {
+        let similar = compare::similarity(&old, &new);
+        title += &format!(" ({:.0}% similar)", similar * 100.0);
+    }
+    let diff = Diff::new(text::decode_lossy(&old), text::decode_lossy(&new));
+    Some((Content::Diff(diff), title))
+}
+
+/// The entries a folder modified under `--dir-changes` lost and gained.
+fn dir_changes<S: Source>(engine: &Engine<S>, rel: &Path) -> (Content, String) {
+    let title = format!("Folder: {}", text::slash_path(rel));
+    let (old, new) = match engine.dir_entries(rel) {
+        Ok(names) => names,
+        Err(e) => {
+            let line = format!("list {}: {}", text::slash_path(rel), e);
+            return (Content::Lines(vec![Spans::from(line)]), title);
+        }
+    };
+    let summary = match (old.is_empty(), new.is_empty()) {
+        (false, true) => "became empty",
+        (true, false) => "was empty",
+        _ => "entries changed",
+    };
+    let mut lines = vec![Spans::from(summary), Spans::default()];
+    for name in old.difference(&new) {
+        lines.push(Spans::from(Span::styled(
+            format!("- {}", name),
+            Style::default().fg(Color::Red),
+        )));
+    }
+    for name in new.difference(&old) {
+        lines.push(Spans::from(Span::styled(
+            format!("+ {}", name),
+            Style::default().fg(Color::Green),
+        )));
+    }
+    (Content::Lines(lines), title)
+}
+
+/// `file` as a custom renderer shows it, both sides read in full.
+fn rendered<S: Source>(
+    engine: &Engine<S>,
+    file: &FolderStatefulList,
+    rel: &Path,
+    renderer: &dyn DiffRenderer,
+    path: &str,
+) -> (Content, String) {
+    let read = |source: &S, present: bool| {
+        if present {
+            source.read(rel).map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+    let lines = read(
+        &engine.old,
+        file.state != crate::status::StatusItemType::New,
+    )
+    .and_then(|old| {
+        let new = read(
+            &engine.new,
+            file.state != crate::status::StatusItemType::Deleted,
+        )?;
+        renderer.render(rel, old.as_deref(), new.as_deref())
+    });
+    let title = format!("{}: {}", renderer.name(), path);
+    match lines {
+        Ok(lines) => {
+            let lines = lines
+                .into_iter()
+                .map(|line| match line.color {
+                    Some(color) => {
+                        Spans::from(Span::styled(line.text, Style::default().fg(color.into())))
+                    }
+                    None => Spans::from(line.text),
+                })
+                .collect();
+            (Content::Lines(lines), title)
+        }
+        Err(e) => (
+            Content::Lines(vec![Spans::from(format!(
+                "{} failed on {}: {}",
+                renderer.name(),
+                path,
+                e
+            ))]),
+            title,
+        ),
+    }
+}
+
+/// Size, type and the first bytes of a new or deleted binary file, which has no
+/// lines worth listing.
+fn binary_preview(file: &FolderStatefulList, path: &str, bytes: &[u8]) -> (Content, String) {
+    let label = Style::default().add_modifier(Modifier::BOLD);
+    let mut contents = vec![
+        Spans::from(vec![
+            Span::styled("size: ", label),
+            Span::raw(format!(
+                "{} ({} bytes)",
+                format::size(file.entry.size),
+                file.entry.size
+            )),
+        ]),
+        Spans::from(vec![
+            Span::styled("type: ", label),
+            Span::raw(mime::detect(bytes)),
+        ]),
+    ];
+    if let Some(mtime) = file.entry.mtime {
+        contents.push(Spans::from(vec![
+            Span::styled("modified: ", label),
+            Span::raw(format::date(mtime)),
+        ]));
+    }
+    contents.push(Spans::default());
+    contents.extend(
+        text::hexdump(bytes, PREVIEW_BYTES)
+            .into_iter()
+            .map(|line| Spans::from(Span::styled(line, Style::default().fg(Color::DarkGray)))),
+    );
+    if bytes.len() > PREVIEW_BYTES {
+        contents.push(Spans::from("..."));
+    }
+    let title = match file.state {
+        crate::status::StatusItemType::Deleted => format!("Deleted binary: {}", path),
+        _ => format!("New binary: {}", path),
+    };
+    (Content::Lines(contents), title)
+}
+
+const MSG: [u8; 318] = [
+    84, 104, 105, 115, 32, 112, 114, 111, 106, 101, 99, 116, 32, 119, 97, 115, 32, 105, 110, 115,
+    112, 105, 114, 101, 100, 32, 98, 121, 32, 109, 121, 32, 103, 105, 114, 108, 102, 114, 105, 101,
+    110, 100, 44, 32, 119, 104, 111, 32, 114, 101, 113, 117, 101, 115, 116, 101, 100, 32, 97, 32,
+    116, 111, 111, 108, 32, 102, 111, 114, 32, 99, 111, 109, 112, 97, 114, 105, 110, 103, 32, 100,
+    105, 114, 101, 99, 116, 111, 114, 105, 101, 115, 59, 32, 97, 108, 116, 104, 111, 117, 103, 104,
+    32, 116, 104, 111, 117, 103, 104, 32, 86, 83, 32, 67, 111, 100, 101, 32, 97, 108, 114, 101, 97,
+    100, 121, 32, 111, 102, 102, 101, 114, 115, 32, 115, 117, 99, 104, 32, 97, 32, 112, 108, 117,
+    103, 45, 105, 110, 44, 32, 73, 32, 115, 116, 105, 108, 108, 32, 119, 97, 110, 116, 32, 116,
+    111, 32, 99, 114, 101, 97, 116, 101, 32, 111, 110, 101, 32, 102, 111, 114, 32, 104, 101, 114,
+    32, 40, 109, 111, 115, 116, 108, 121, 32, 115, 105, 110, 99, 101, 32, 73, 32, 100, 111, 110,
+    39, 116, 32, 104, 97, 118, 101, 32, 97, 110, 121, 32, 109, 111, 110, 101, 121, 32, 116, 111,
+    32, 112, 117, 114, 99, 104, 97, 115, 101, 32, 111, 116, 104, 101, 114, 32, 116, 104, 105, 110,
+    103, 115, 41, 59, 10, 73, 32, 119, 105, 115, 104, 32, 102, 111, 114, 32, 101, 118, 101, 114,
+    121, 111, 110, 101, 39, 115, 32, 104, 97, 112, 112, 105, 110, 101, 115, 115, 44, 32, 104, 101,
+    97, 108, 116, 104, 44, 32, 97, 110, 100, 32, 105, 110, 99, 114, 101, 97, 115, 105, 110, 103,
+    32, 119, 101, 97, 108, 116, 104, 59, 10, 50, 48, 50, 51, 48, 50, 49, 52,
+];
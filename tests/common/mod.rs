@@ -0,0 +1,69 @@
+//! Fixtures shared by the integration tests.
+
+// each test binary uses its own part of these
+#![allow(dead_code)]
+
+use diff_folders::{
+    cli::Options, engine::Engine, model::Comparison, source::Source, status::StatusItemType,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A pair of fresh trees under the system temp dir, removed on drop.
+pub struct Trees {
+    pub root: PathBuf,
+    pub old: PathBuf,
+    pub new: PathBuf,
+}
+
+impl Trees {
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "diff-folders-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let (old, new) = (root.join("old"), root.join("new"));
+        fs::create_dir_all(&old).unwrap();
+        fs::create_dir_all(&new).unwrap();
+        Trees { root, old, new }
+    }
+
+    pub fn options(&self) -> Options {
+        Options {
+            old_dir: self.old.to_string_lossy().into_owned(),
+            new_dir: self.new.to_string_lossy().into_owned(),
+            no_cache: true,
+            ..Options::default()
+        }
+    }
+}
+
+impl Drop for Trees {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+pub fn write(path: PathBuf, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// Relative path and state of every result, sorted by path.
+pub fn states<S: Source>(
+    engine: &Engine<S>,
+    comparison: &Comparison,
+) -> Vec<(String, StatusItemType)> {
+    let mut states: Vec<_> = comparison
+        .results
+        .iter()
+        .map(|i| (engine.rel_path(i).to_string_lossy().into_owned(), i.state))
+        .collect();
+    states.sort_by(|a, b| a.0.cmp(&b.0));
+    states
+}
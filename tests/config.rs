@@ -0,0 +1,238 @@
+mod common;
+
+use common::{write, Trees};
+use diff_folders::{
+    cli::parse_args,
+    config::{Config, Favorite, Layout},
+    mime::TypeFilter,
+    model::Order,
+    recent,
+    text::PathStyle,
+};
+use std::{fs, path::Path};
+
+const CONFIG: &str = "
+[defaults]
+max-files = 5
+order = bfs
+rename-threshold = 0.6
+
+[profile release-audit]
+type = binary
+hidden = true
+order = alpha
+
+[profile.quick]
+no-cache = true
+max-files = 1000
+
+[favorite staging]
+old = /srv/prod
+new = /srv/staging
+";
+
+#[test]
+fn profiles_keep_their_settings_in_order() {
+    let trees = Trees::new();
+    let file = trees.root.join("config");
+    write(file.clone(), CONFIG);
+    let config = Config::load_from(&file).unwrap();
+    assert_eq!(
+        config.profiles["release-audit"],
+        vec![
+            ("type".to_string(), "binary".to_string()),
+            ("hidden".to_string(), "true".to_string()),
+            ("order".to_string(), "alpha".to_string()),
+        ]
+    );
+    assert_eq!(config.profiles["quick"].len(), 2);
+}
+
+#[test]
+fn favorites_and_recent_pairs_are_kept_for_the_start_screen() {
+    let trees = Trees::new();
+    let file = trees.root.join("config");
+    write(file.clone(), CONFIG);
+    let config = Config::load_from(&file).unwrap();
+    assert_eq!(
+        config.favorites,
+        vec![Favorite {
+            name: "staging".to_string(),
+            old: "/srv/prod".to_string(),
+            new: "/srv/staging".to_string(),
+        }]
+    );
+    write(file.clone(), "[favorite half]\nold = /a\n");
+    let err = format!("{:#}", Config::load_from(&file).err().unwrap());
+    assert!(err.contains("needs both old and new"), "{}", err);
+
+    // the newest on top, a pair compared again moves up
+    let recent_file = trees.root.join("cache/recent");
+    assert!(recent::load_from(&recent_file).unwrap().is_empty());
+    for (old, new) in [("a", "b"), ("c", "d"), ("a", "b")] {
+        recent::record_to(&recent_file, old, new).unwrap();
+    }
+    let pair = |old: &str, new: &str| (old.to_string(), new.to_string());
+    assert_eq!(
+        recent::load_from(&recent_file).unwrap(),
+        vec![pair("a", "b"), pair("c", "d")]
+    );
+    for i in 0..recent::MAX_RECENT {
+        recent::record_to(&recent_file, &i.to_string(), "new").unwrap();
+    }
+    let pairs = recent::load_from(&recent_file).unwrap();
+    assert_eq!(pairs.len(), recent::MAX_RECENT);
+    assert!(!pairs.contains(&pair("a", "b")));
+}
+
+#[test]
+fn layout_is_saved_over_the_last_one_keeping_the_rest() {
+    let trees = Trees::new();
+    let file = trees.root.join("config");
+    write(file.clone(), &format!("[layout]\nsplit = 30\n{}", CONFIG));
+    let layout = Layout {
+        split: Some(55),
+        wrap: Some(false),
+        columns: Some(true),
+        path_style: Some(PathStyle::Name),
+        ..Layout::default()
+    };
+    layout.save_to(&file).unwrap();
+    layout.save_to(&file).unwrap();
+    let text = fs::read_to_string(&file).unwrap();
+    assert_eq!(text.matches("[layout]").count(), 1);
+    assert!(!text.contains("split = 30"));
+    let config = Config::load_from(&file).unwrap();
+    assert_eq!(config.layout, layout);
+    assert_eq!(config.defaults.len(), 3);
+    assert_eq!(config.profiles["quick"].len(), 2);
+}
+
+#[test]
+fn layers_from_defaults_to_the_command_line() {
+    let trees = Trees::new();
+    let file = trees.root.join("diff-folders/config");
+    write(file.clone(), CONFIG);
+    let config = || Config::load_from(&file).unwrap();
+    let env_args = |env: &[(&str, &str)], args: &[&str]| {
+        let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        args.push(trees.old.to_string_lossy().into_owned());
+        args.push(trees.new.to_string_lossy().into_owned());
+        let env = env.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        parse_args(
+            args.into_iter(),
+            config(),
+            env.collect::<Vec<_>>().into_iter(),
+        )
+    };
+    let args = |args: &[&str]| env_args(&[], args);
+
+    let options = args(&[]).unwrap();
+    assert_eq!(options.max_files, Some(5));
+    assert_eq!(options.order, Order::Bfs);
+    assert_eq!(options.rename_threshold, Some(0.6));
+    let options = args(&["--rename-threshold", "0.9"]).unwrap();
+    assert_eq!(options.rename_threshold, Some(0.9));
+    let err = args(&["--modify-threshold", "50"])
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("from 0 to 1"), "{}", err);
+
+    let options = args(&["--preset", "release-audit"]).unwrap();
+    assert_eq!(options.type_filter, Some(TypeFilter::Binary));
+    assert!(options.hidden);
+    assert_eq!(options.order, Order::Alpha);
+    assert_eq!(options.max_files, Some(5));
+
+    let options = args(&["--order", "dfs", "--preset", "release-audit"]).unwrap();
+    assert_eq!(options.order, Order::Dfs);
+
+    let env = [
+        ("DIFF_FOLDERS_ORDER", "bfs"),
+        ("DIFF_FOLDERS_MAX_FILES", "7"),
+        ("DIFF_FOLDERS_PRESET", "quick"),
+        // another program's variable
+        ("DIFF_FOLDERS_FOO", "1"),
+    ];
+    let options = env_args(&env, &["--preset", "release-audit"]).unwrap();
+    assert_eq!(options.order, Order::Bfs);
+    assert_eq!(options.max_files, Some(7));
+    assert!(options.hidden);
+    let options = env_args(&env, &["--max-files", "9"]).unwrap();
+    assert_eq!(options.max_files, Some(9));
+    assert!(options.no_cache);
+    // 1 and 0 are values for options that take one, flags only for flags
+    let options = env_args(&[("DIFF_FOLDERS_MAX_FILES", "1")], &[]).unwrap();
+    assert_eq!(options.max_files, Some(1));
+    let options = env_args(&[("DIFF_FOLDERS_MAX_FILES", "0")], &[]).unwrap();
+    assert_eq!(options.max_files, None);
+    let options = env_args(&[("DIFF_FOLDERS_HIDDEN", "1")], &[]).unwrap();
+    assert!(options.hidden);
+    let err = env_args(&[("DIFF_FOLDERS_HIDDEN", "yes please")], &[])
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("hidden is a flag"), "{}", err);
+
+    let err = args(&["--preset", "nope"]).err().unwrap().to_string();
+    assert!(err.contains("known: quick, release-audit"), "{}", err);
+
+    // pairs from the file are relative to it, the first one given takes the
+    // place of missing positional folders
+    write(trees.root.join("qa/a.txt"), "");
+    write(
+        trees.root.join("pairs.txt"),
+        "# environments\nqa\tnew\n\nold   qa\n",
+    );
+    let pairs_from = trees.root.join("pairs.txt");
+    let pairs_from = pairs_from.to_str().unwrap();
+    let options = args(&["--pairs-from", pairs_from]).unwrap();
+    let qa = trees.root.join("qa").canonicalize().unwrap();
+    let qa = qa.to_string_lossy().into_owned();
+    let (old, new) = (
+        options.old_dir.clone(),
+        trees
+            .new
+            .canonicalize()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned(),
+    );
+    assert_eq!(options.pairs.len(), 2);
+    assert_eq!(options.pairs[0], (qa.clone(), new.clone()));
+    assert_eq!(options.pairs[1].1, qa);
+    let options = parse_args(
+        ["--pair", &qa, &new, "--pairs-from", pairs_from]
+            .iter()
+            .map(|a| a.to_string()),
+        config(),
+        std::iter::empty(),
+    )
+    .unwrap();
+    assert_eq!((options.old_dir, options.new_dir), (qa, new));
+    assert_eq!(options.pairs[1].0, old);
+    let err = args(&["--pairs-from", pairs_from, "--brief"])
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("only open tabs in the TUI"), "{}", err);
+}
+
+#[test]
+fn sync_options_only_work_with_sync() {
+    let trees = Trees::new();
+    let parse = |args: &[&str]| {
+        let args = args.iter().map(|a| a.to_string());
+        parse_args(args, Config::default(), std::iter::empty())
+    };
+    let (old, new) = (trees.old.to_str().unwrap(), trees.new.to_str().unwrap());
+    let err = parse(&["--delete", old, new]).err().unwrap().to_string();
+    assert!(err.contains("only work with sync"), "{}", err);
+
+    // parsing creates nothing, the sync does when it runs
+    let dst = trees.root.join("dst");
+    let options = parse(&["sync", "--delete", new, dst.to_str().unwrap()]).unwrap();
+    assert!(!dst.exists());
+    assert_eq!(Path::new(&options.old_dir), dst);
+}
@@ -0,0 +1,927 @@
+mod common;
+
+use common::{states, write, Trees};
+use diff_folders::{
+    agent::{self, Agent},
+    cache::Cache,
+    checkpoint::Checkpoint,
+    ci,
+    cli::Options,
+    delta::{self, Op, Signature},
+    engine::Engine,
+    events, external,
+    model::{Comparison, Order},
+    ops::ApplyDirection,
+    profile,
+    source::{Memory, Source},
+    status::{Entry, StatusItemType},
+    sync::Action,
+};
+use regex::RegexSet;
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+};
+
+fn compare(options: &Options) -> (Engine, Comparison) {
+    let engine = Engine::new(options).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    (engine, comparison)
+}
+
+#[test]
+fn equal_trees_have_no_differences() {
+    let trees = Trees::new();
+    for root in [&trees.old, &trees.new] {
+        write(root.join("a.txt"), "same\n");
+        write(root.join("dir/b.txt"), "also same\n");
+    }
+    let (_, comparison) = compare(&trees.options());
+    assert!(!comparison.has_differences());
+    assert_eq!(comparison.size_delta, 0);
+    assert!(!comparison.is_partial);
+}
+
+#[test]
+fn finds_new_deleted_and_modified_files() {
+    let trees = Trees::new();
+    write(trees.old.join("same.txt"), "same\n");
+    write(trees.new.join("same.txt"), "same\n");
+    write(trees.old.join("changed.txt"), "one\n");
+    write(trees.new.join("changed.txt"), "two\n");
+    write(trees.old.join("gone.txt"), "bye\n");
+    write(trees.new.join("added.txt"), "hello\n");
+    let (engine, comparison) = compare(&trees.options());
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![
+            ("added.txt".to_string(), StatusItemType::New),
+            ("changed.txt".to_string(), StatusItemType::Modified),
+            ("gone.txt".to_string(), StatusItemType::Deleted),
+        ]
+    );
+    assert_eq!(comparison.size_delta, 6 - 4);
+}
+
+#[test]
+fn new_folder_stands_for_its_contents() {
+    let trees = Trees::new();
+    write(trees.new.join("fresh/a.txt"), "a\n");
+    write(trees.new.join("fresh/deep/b.txt"), "bb\n");
+    let (engine, comparison) = compare(&trees.options());
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![("fresh".to_string(), StatusItemType::New)]
+    );
+    // files inside the folder still count towards the size change
+    assert_eq!(comparison.size_delta, 5);
+
+    // and are listed when the folder is opened, as new as it is
+    let inside = engine.expand(&comparison.results[0]).unwrap();
+    let inside: Vec<_> = inside
+        .iter()
+        .map(|i| (engine.rel_path(i), i.state, i.size_delta))
+        .collect();
+    assert_eq!(
+        inside,
+        vec![
+            (PathBuf::from("fresh/a.txt"), StatusItemType::New, 2),
+            (PathBuf::from("fresh/deep/b.txt"), StatusItemType::New, 3),
+        ]
+    );
+}
+
+#[test]
+fn folders_whose_entries_differ_are_modified_with_dir_changes() {
+    let trees = Trees::new();
+    write(trees.old.join("logs/app.log"), "line\n");
+    fs::create_dir_all(trees.new.join("logs")).unwrap();
+    fs::create_dir_all(trees.old.join("cache")).unwrap();
+    write(trees.new.join("cache/x"), "x\n");
+    fs::create_dir_all(trees.old.join("run")).unwrap();
+    fs::create_dir_all(trees.new.join("run")).unwrap();
+    for root in [&trees.old, &trees.new] {
+        write(root.join("conf/a.ini"), "a\n");
+    }
+    write(trees.new.join("conf/b.ini"), "b\n");
+    let files = vec![
+        ("cache/x".to_string(), StatusItemType::New),
+        ("conf/b.ini".to_string(), StatusItemType::New),
+        ("logs/app.log".to_string(), StatusItemType::Deleted),
+    ];
+    let (engine, comparison) = compare(&trees.options());
+    assert_eq!(states(&engine, &comparison), files);
+
+    let options = Options {
+        dir_changes: true,
+        ..trees.options()
+    };
+    let (engine, comparison) = compare(&options);
+    // the empty `run` is the same on both sides
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![
+            ("cache".to_string(), StatusItemType::Modified),
+            files[0].clone(),
+            ("conf".to_string(), StatusItemType::Modified),
+            files[1].clone(),
+            ("logs".to_string(), StatusItemType::Modified),
+            files[2].clone(),
+        ]
+    );
+    let (old, new) = engine.dir_entries(Path::new("logs")).unwrap();
+    assert_eq!(old.into_iter().collect::<Vec<_>>(), vec!["app.log"]);
+    assert!(new.is_empty());
+}
+
+#[test]
+fn moved_files_are_paired_by_contents() {
+    let trees = Trees::new();
+    write(trees.old.join("src/util.rs"), "fn a() {}\n");
+    write(trees.new.join("lib/util.rs"), "fn a() {}\n");
+    // same size, other bytes
+    write(trees.old.join("notes.txt"), "x\n");
+    write(trees.new.join("other.txt"), "y\n");
+    // of two equal files the one with the same name is taken
+    write(trees.old.join("a/x.txt"), "same\n");
+    write(trees.old.join("b/y.txt"), "same\n");
+    write(trees.new.join("c/y.txt"), "same\n");
+    let options = Options {
+        renames: true,
+        ..trees.options()
+    };
+    let (engine, comparison) = compare(&options);
+    let mut moves: Vec<_> = comparison
+        .results
+        .iter()
+        .filter_map(|i| Some((engine.rel_path(i), i.state, i.moved.clone()?)))
+        .collect();
+    moves.sort_by(|a, b| a.0.cmp(&b.0));
+    let path = PathBuf::from;
+    assert_eq!(
+        moves,
+        vec![
+            (path("b/y.txt"), StatusItemType::Deleted, path("c/y.txt")),
+            (path("c/y.txt"), StatusItemType::New, path("b/y.txt")),
+            (
+                path("lib/util.rs"),
+                StatusItemType::New,
+                path("src/util.rs")
+            ),
+            (
+                path("src/util.rs"),
+                StatusItemType::Deleted,
+                path("lib/util.rs")
+            ),
+        ]
+    );
+    // both ends are read for the diff
+    let item = comparison
+        .results
+        .iter()
+        .find(|i| engine.rel_path(i) == Path::new("lib/util.rs"))
+        .unwrap();
+    assert_eq!(
+        engine.read_sides(item).unwrap(),
+        ("fn a() {}\n".to_string(), "fn a() {}\n".to_string())
+    );
+
+    let (_, comparison) = compare(&trees.options());
+    assert!(comparison.results.iter().all(|i| i.moved.is_none()));
+}
+
+#[test]
+fn thresholds_decide_between_moved_modified_and_replaced() {
+    let trees = Trees::new();
+    let lines =
+        |n: usize, last: &str| (0..n).map(|i| format!("line {}\n", i)).collect::<String>() + last;
+    // nine of ten lines kept while moving
+    write(trees.old.join("old.txt"), &lines(9, "old\n"));
+    write(trees.new.join("renamed.txt"), &lines(9, "new\n"));
+    // a file written anew under the same name
+    write(trees.old.join("notes.txt"), "a\nb\nc\n");
+    write(trees.new.join("notes.txt"), "x\ny\nz\n");
+    let moved = |options: &Options| {
+        let (engine, comparison) = compare(options);
+        let moves: Vec<_> = comparison
+            .results
+            .iter()
+            .filter_map(|i| Some((engine.rel_path(i), i.moved.clone()?)))
+            .collect();
+        (states(&engine, &comparison), moves)
+    };
+
+    let (states, moves) = moved(&Options {
+        rename_threshold: Some(0.8),
+        ..trees.options()
+    });
+    assert_eq!(moves.len(), 2);
+    assert!(states.contains(&("notes.txt".to_string(), StatusItemType::Modified)));
+    let (_, moves) = moved(&Options {
+        rename_threshold: Some(0.95),
+        ..trees.options()
+    });
+    assert!(moves.is_empty());
+
+    let (states, _) = moved(&Options {
+        modify_threshold: Some(0.5),
+        ..trees.options()
+    });
+    assert_eq!(
+        states,
+        vec![
+            ("notes.txt".to_string(), StatusItemType::New),
+            ("notes.txt".to_string(), StatusItemType::Deleted),
+            ("old.txt".to_string(), StatusItemType::Deleted),
+            ("renamed.txt".to_string(), StatusItemType::New),
+        ]
+    );
+}
+
+#[test]
+fn ignored_lines_do_not_count_as_changes() {
+    let trees = Trees::new();
+    write(trees.old.join("a.txt"), "# generated 1\nbody\n");
+    write(trees.new.join("a.txt"), "# generated 2\nbody\n");
+    let mut options = trees.options();
+    let (_, comparison) = compare(&options);
+    assert!(comparison.has_differences());
+    options.ignore_lines = Some(RegexSet::new(["^# generated"]).unwrap());
+    let (_, comparison) = compare(&options);
+    assert!(!comparison.has_differences());
+}
+
+#[test]
+fn hidden_files_are_skipped_unless_asked_for() {
+    let trees = Trees::new();
+    write(trees.new.join(".hidden"), "x\n");
+    let (mut engine, comparison) = compare(&trees.options());
+    assert!(!comparison.has_differences());
+    engine.set_show_hidden(true);
+    assert!(engine.compare(&mut |_| true).unwrap().has_differences());
+}
+
+#[test]
+fn reads_both_sides_and_builds_a_unified_diff() {
+    let trees = Trees::new();
+    write(trees.old.join("a.txt"), "one\ntwo\n");
+    write(trees.new.join("a.txt"), "one\nthree\n");
+    write(trees.new.join("b.txt"), "new\n");
+    let (engine, comparison) = compare(&trees.options());
+    let find = |name: &str| {
+        comparison
+            .results
+            .iter()
+            .find(|i| engine.rel_path(i) == Path::new(name))
+            .unwrap()
+    };
+
+    let modified = find("a.txt");
+    let (old, new) = engine.read_sides(modified).unwrap();
+    assert_eq!(old, "one\ntwo\n");
+    assert_eq!(new, "one\nthree\n");
+    let diff = engine.unified_diff(modified).unwrap();
+    assert!(diff.contains("-two\n"));
+    assert!(diff.contains("+three\n"));
+
+    // the missing side of a new file reads as empty
+    let added = find("b.txt");
+    assert_eq!(engine.side_paths(added).0, None);
+    assert_eq!(engine.read_sides(added).unwrap().0, "");
+    assert!(engine
+        .unified_diff(added)
+        .unwrap()
+        .starts_with("--- /dev/null"));
+
+    // `show` asks by path, without a comparison
+    assert_eq!(
+        engine.unified_diff_at(Path::new("a.txt")).unwrap(),
+        engine.unified_diff(modified).unwrap()
+    );
+    assert!(engine
+        .unified_diff_at(Path::new("b.txt"))
+        .unwrap()
+        .starts_with("--- /dev/null"));
+    assert!(engine.unified_diff_at(Path::new("missing.txt")).is_err());
+}
+
+#[test]
+fn sync_plan_makes_the_target_match() {
+    let trees = Trees::new();
+    write(trees.old.join("changed.txt"), "one\n");
+    write(trees.new.join("changed.txt"), "two\n");
+    write(trees.old.join("gone.txt"), "bye\n");
+    write(trees.new.join("added.txt"), "hello\n");
+    let (engine, comparison) = compare(&trees.options());
+    let plan = engine.sync_plan(&comparison, ApplyDirection::NewToOld);
+    let mut ops: Vec<_> = plan
+        .ops
+        .iter()
+        .map(|op| (op.rel.to_string_lossy().into_owned(), op.action))
+        .collect();
+    ops.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        ops,
+        vec![
+            ("added.txt".to_string(), Action::Create),
+            ("changed.txt".to_string(), Action::Update),
+            ("gone.txt".to_string(), Action::Delete),
+        ]
+    );
+    assert!(!plan.to_text(false).contains('\x1b'));
+    let colored = plan.to_text(true);
+    assert!(colored.contains("\x1b[32mcreate  added.txt"));
+    assert!(colored.contains("\x1b[31mdelete  gone.txt"));
+}
+
+#[test]
+fn sync_with_dir_changes_keeps_what_only_the_target_has() {
+    let trees = Trees::new();
+    write(trees.old.join("conf/a.ini"), "a\n");
+    write(trees.new.join("conf/.env"), "secret\n");
+    let options = Options {
+        dir_changes: true,
+        hidden: true,
+        ..trees.options()
+    };
+    let (engine, comparison) = compare(&options);
+    let mut plan = engine.sync_plan(&comparison, ApplyDirection::OldToNew);
+    // as without --delete
+    plan.ops.retain(|op| op.action != Action::Delete);
+    let ops: Vec<_> = plan
+        .ops
+        .iter()
+        .map(|op| (op.rel.clone(), op.action))
+        .collect();
+    assert_eq!(ops, vec![(PathBuf::from("conf/a.ini"), Action::Create)]);
+    assert_eq!(plan.apply(None, true, &mut |_, _| {}), 0);
+    assert_eq!(
+        fs::read_to_string(trees.new.join("conf/a.ini")).unwrap(),
+        "a\n"
+    );
+    assert_eq!(
+        fs::read_to_string(trees.new.join("conf/.env")).unwrap(),
+        "secret\n"
+    );
+}
+
+#[test]
+fn export_merge_writes_conflict_markers() {
+    let trees = Trees::new();
+    write(trees.old.join("dir/a.txt"), "one\n");
+    write(trees.new.join("dir/a.txt"), "two\n");
+    let (engine, comparison) = compare(&trees.options());
+    let out = trees.root.join("merged");
+    assert_eq!(engine.export_merge(&comparison, &out).unwrap(), 1);
+    let merged = fs::read_to_string(out.join("dir/a.txt")).unwrap();
+    assert!(merged.contains("<<<<<<<"));
+    assert!(merged.contains("one"));
+    assert!(merged.contains("two"));
+}
+
+#[test]
+fn results_follow_the_order() {
+    let old = Memory::new("old").file("a/x.txt", "x\n");
+    let new = Memory::new("new")
+        .file("a/b/c.txt", "c\n")
+        .file("a.txt", "a\n")
+        .file("z.txt", "z\n");
+    let listed = |order: Order| {
+        let options = Options {
+            order,
+            ..Options::default()
+        };
+        let engine = Engine::with_sources(old.clone(), new.clone(), &options);
+        let comparison = engine.compare(&mut |_| true).unwrap();
+        let results: Vec<_> = comparison
+            .results
+            .iter()
+            .map(|i| engine.rel_path(i))
+            .collect();
+        // the sync plan keeps the same order
+        let plan = engine.sync_plan(&comparison, ApplyDirection::NewToOld);
+        let ops: Vec<_> = plan.ops.iter().map(|op| op.rel.clone()).collect();
+        assert_eq!(ops, results);
+        results
+    };
+    let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+    assert_eq!(
+        listed(Order::Dfs),
+        paths(&["a/b", "a/x.txt", "a.txt", "z.txt"])
+    );
+    assert_eq!(
+        listed(Order::Bfs),
+        paths(&["a.txt", "z.txt", "a/b", "a/x.txt"])
+    );
+    assert_eq!(
+        listed(Order::Alpha),
+        paths(&["a.txt", "a/b", "a/x.txt", "z.txt"])
+    );
+}
+
+#[test]
+fn walk_over_the_cap_compares_only_what_both_sides_listed() {
+    let trees = Trees::new();
+    for i in 0..10 {
+        write(trees.old.join(format!("{}.txt", i)), "same\n");
+        write(trees.new.join(format!("{}.txt", i)), "same\n");
+    }
+    write(trees.new.join("0.txt"), "changed\n");
+    write(trees.new.join("added.txt"), "added\n");
+    let options = Options {
+        max_files: Some(3),
+        ..trees.options()
+    };
+    let (engine, comparison) = compare(&options);
+    assert_eq!(comparison.truncated, Some(3));
+    assert!(comparison.is_partial);
+    // both walks were cut short, so nothing can be told to be new or deleted
+    assert!(comparison
+        .results
+        .iter()
+        .all(|i| i.state == StatusItemType::Modified));
+    assert!(engine.duplicates().is_err());
+
+    let (engine, comparison) = compare(&Options {
+        max_files: None,
+        ..options
+    });
+    assert_eq!(comparison.truncated, None);
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![
+            ("0.txt".to_string(), StatusItemType::Modified),
+            ("added.txt".to_string(), StatusItemType::New),
+        ]
+    );
+}
+
+#[test]
+fn every_compared_file_is_timed() {
+    let old = Memory::new("old")
+        .file("a/same.txt", "same\n")
+        .file("a/changed.txt", "one\n")
+        .file("gone.txt", "bye\n");
+    let new = Memory::new("new")
+        .file("a/same.txt", "same\n")
+        .file("a/changed.txt", "two\n")
+        .file("added.txt", "hi\n");
+    let engine = Engine::with_sources(old, new, &Options::default());
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    // only files on both sides are read, new and deleted ones cost nothing
+    let mut timed: Vec<_> = comparison.timings.iter().map(|t| t.rel.clone()).collect();
+    timed.sort();
+    assert_eq!(
+        timed,
+        vec![PathBuf::from("a/changed.txt"), PathBuf::from("a/same.txt")]
+    );
+    let dirs = profile::slowest_dirs(&comparison.timings, 10);
+    assert_eq!(dirs.len(), 1);
+    assert_eq!(dirs[0].rel, Path::new("a"));
+    assert_eq!(dirs[0].files, 2);
+    assert_eq!(dirs[0].bytes, 9);
+    assert!(profile::report(&comparison.timings, 10).contains("compared 2 files"));
+}
+
+#[test]
+fn changes_are_reported_as_they_are_found() {
+    let old = Memory::new("old")
+        .file("gone/a.txt", "a\n")
+        .file("x.txt", "1\n");
+    let new = Memory::new("new").file("x.txt", "2\n").file("y.txt", "y\n");
+    let engine = Engine::with_sources(old, new, &Options::default());
+    let mut found = Vec::new();
+    let comparison = engine
+        .compare_with(&mut |_| true, &mut |item| {
+            found.push(events::change(&engine.rel_path(item), item))
+        })
+        .unwrap();
+    found.sort();
+    // the folder and its file both come before collapsing
+    assert_eq!(
+        found,
+        vec![
+            r#"{"event":"change","state":"deleted","path":"gone","dir":true,"size_delta":0}"#,
+            r#"{"event":"change","state":"deleted","path":"gone/a.txt","dir":false,"size_delta":-2}"#,
+            r#"{"event":"change","state":"modified","path":"x.txt","dir":false,"size_delta":0}"#,
+            r#"{"event":"change","state":"new","path":"y.txt","dir":false,"size_delta":2}"#,
+        ]
+    );
+    assert_eq!(comparison.results.len(), 3);
+    assert_eq!(
+        events::done(&comparison),
+        r#"{"event":"done","new":1,"modified":1,"deleted":1,"size_delta":0,"partial":false,"truncated":null}"#
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn on_change_runs_once_per_difference() {
+    let trees = Trees::new();
+    write(trees.old.join("changed.txt"), "one\n");
+    write(trees.new.join("changed.txt"), "two\n");
+    write(trees.new.join("it's new.txt"), "hi\n");
+    let (engine, comparison) = compare(&trees.options());
+    let log = trees.root.join("runs.txt");
+    let template = format!("echo {{status}} {{path}} >> '{}'", log.display());
+    assert!(external::on_change(&template, &engine, &comparison).is_empty());
+    let mut runs: Vec<_> = fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    runs.sort();
+    assert_eq!(runs, vec!["modified changed.txt", "new it's new.txt"]);
+
+    let failed = external::on_change("test {status} = new", &engine, &comparison);
+    assert_eq!(failed.len(), 1);
+    assert!(failed[0].starts_with("test modified = new"), "{:?}", failed);
+
+    // a placeholder in a file name is part of the name, not of the command
+    let hostile = Trees::new();
+    write(hostile.new.join("{new};echo INJECTED;#"), "x\n");
+    let (engine, comparison) = compare(&hostile.options());
+    let log = hostile.root.join("runs.txt");
+    let template = format!("echo {{path}} >> '{}'", log.display());
+    assert!(external::on_change(&template, &engine, &comparison).is_empty());
+    assert_eq!(fs::read_to_string(&log).unwrap(), "{new};echo INJECTED;#\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn placeholders_in_file_names_are_not_substituted() {
+    let trees = Trees::new();
+    let name = "{new};touch PWNED;#";
+    write(trees.old.join(name), "one\n");
+    write(trees.new.join(name), "two\n");
+    let (old, new) = (trees.old.join(name), trees.new.join(name));
+    let same = external::same_by_command("cmp -s {old} {new}", &old, &new).unwrap();
+    assert!(!same);
+    assert!(external::command("cat {old} {new}", &old, &new)
+        .current_dir(&trees.root)
+        .output()
+        .unwrap()
+        .status
+        .success());
+    assert!(!trees.root.join("PWNED").exists());
+    assert!(!Path::new("PWNED").exists());
+}
+
+#[test]
+fn differences_become_ci_annotations() {
+    let old = Memory::new("old")
+        .file("a,b.txt", "one\n")
+        .file("gone.txt", "bye\n");
+    let new = Memory::new("new").file("a,b.txt", "one\ntwo\n");
+    let engine = Engine::with_sources(old, new, &Options::default());
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    let items: Vec<_> = comparison
+        .results
+        .iter()
+        .map(|item| (engine.rel_path(item), item))
+        .collect();
+    assert_eq!(
+        ci::github(&items),
+        "::warning file=new/a%2Cb.txt,title=diff-folders%3A modified::a,b.txt modified (+4 B)\n\
+         ::warning file=old/gone.txt,title=diff-folders%3A deleted::gone.txt deleted (-4 B)\n"
+    );
+    let gitlab = ci::gitlab(&items);
+    assert!(gitlab.starts_with("[{\"description\":\"a,b.txt modified (+4 B)\",\"check_name\":\"diff-folders-modified\",\"fingerprint\":\""), "{}", gitlab);
+    assert!(gitlab.contains("\"severity\":\"major\",\"location\":{\"path\":\"old/gone.txt\",\"lines\":{\"begin\":1}}}]"), "{}", gitlab);
+}
+
+#[test]
+fn ci_annotations_point_at_files_relative_to_the_working_directory() {
+    // tests run in the package folder, so a tree under target/ is inside it
+    let root = Path::new("target").join(format!("ci-test-{}", std::process::id()));
+    let (old, new) = (root.join("old"), root.join("new"));
+    write(old.join("src/a.txt"), "one\n");
+    write(new.join("src/a.txt"), "two\n");
+    let options = Options {
+        old_dir: fs::canonicalize(&old)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned(),
+        new_dir: fs::canonicalize(&new)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned(),
+        no_cache: true,
+        ..Options::default()
+    };
+    let (engine, comparison) = compare(&options);
+    let items: Vec<_> = comparison
+        .results
+        .iter()
+        .filter(|item| !item.entry.is_dir())
+        .map(|item| (engine.rel_path(item), item))
+        .collect();
+    let github = ci::github(&items);
+    let gitlab = ci::gitlab(&items);
+    let _ = fs::remove_dir_all(&root);
+    let expected = format!("target/ci-test-{}/new/src/a.txt", std::process::id());
+    assert!(
+        github.starts_with(&format!("::warning file={},", expected)),
+        "{}",
+        github
+    );
+    assert!(
+        gitlab.contains(&format!("\"path\":\"{}\"", expected)),
+        "{}",
+        gitlab
+    );
+}
+
+#[test]
+fn files_are_compared_in_parallel_with_the_gauge_following_bytes() {
+    let trees = Trees::new();
+    for i in 0..40 {
+        let name = format!("dir{}/f{}.txt", i % 4, i);
+        write(trees.old.join(&name), &format!("{}\n", i));
+        // same sizes, so every file is read
+        let new = if i % 7 == 0 {
+            format!("{}!", i)
+        } else {
+            format!("{}\n", i)
+        };
+        write(trees.new.join(&name), &new);
+    }
+    let engine = Engine::new(&trees.options()).unwrap();
+    let mut seen = Vec::new();
+    let comparison = engine
+        .compare(&mut |p| {
+            seen.push(p.percent);
+            true
+        })
+        .unwrap();
+    let modified: Vec<_> = states(&engine, &comparison)
+        .into_iter()
+        .filter(|(_, state)| *state == StatusItemType::Modified)
+        .map(|(rel, _)| rel)
+        .collect();
+    assert_eq!(modified.len(), 6);
+    assert!(modified.contains(&"dir3/f7.txt".to_string()));
+    assert_eq!(comparison.timings.len(), 40);
+    // one step per file between 40 and 80, never going back
+    assert!(seen.windows(2).all(|w| w[0] <= w[1]));
+    assert!(seen.iter().filter(|p| (40..=80).contains(*p)).count() >= 40);
+    assert_eq!(seen.last(), Some(&100));
+}
+
+#[test]
+fn files_of_different_sizes_are_settled_without_reading() {
+    let trees = Trees::new();
+    write(trees.old.join("big.bin"), &"a".repeat(1 << 16));
+    write(trees.new.join("big.bin"), &"a".repeat((1 << 16) + 1));
+    write(trees.old.join("same.txt"), "same\n");
+    write(trees.new.join("same.txt"), "same\n");
+    let (engine, comparison) = compare(&trees.options());
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![("big.bin".to_string(), StatusItemType::Modified)]
+    );
+    // both files are still timed, the one told apart by size in no time
+    let took: Vec<_> = comparison
+        .timings
+        .iter()
+        .filter(|t| t.rel == Path::new("big.bin"))
+        .map(|t| t.took)
+        .collect();
+    assert_eq!(took, vec![std::time::Duration::ZERO]);
+    assert_eq!(comparison.timings.len(), 2);
+}
+
+#[test]
+fn served_folders_are_compared_over_the_network() {
+    let trees = Trees::new();
+    write(trees.old.join("same.txt"), "same\n");
+    write(trees.new.join("same.txt"), "same\n");
+    write(trees.old.join("changed.txt"), "one\n");
+    write(trees.new.join("changed.txt"), "two\n");
+    write(trees.new.join("added/file.txt"), "hi\n");
+    let log = |changed: usize| -> String {
+        (0..5000)
+            .map(|i| {
+                if i == changed {
+                    format!("line {} changed\n", i)
+                } else {
+                    format!("line {}\n", i)
+                }
+            })
+            .collect()
+    };
+    write(trees.old.join("big.log"), &log(usize::MAX));
+    write(trees.new.join("big.log"), &log(2500));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let root = trees.new.clone();
+    thread::spawn(move || agent::serve(listener, &root, "secret"));
+
+    agent::set_token("secret");
+    let options = Options {
+        new_dir: format!("127.0.0.1:{}:{}", port, trees.new.display()),
+        ..trees.options()
+    };
+    let (engine, comparison) = compare(&options);
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![
+            ("added".to_string(), StatusItemType::New),
+            ("big.log".to_string(), StatusItemType::Modified),
+            ("changed.txt".to_string(), StatusItemType::Modified),
+        ]
+    );
+    let read_new = |rel: &str| {
+        let item = comparison
+            .results
+            .iter()
+            .find(|i| engine.rel_path(i) == Path::new(rel))
+            .unwrap();
+        engine.read_sides(item).unwrap().1
+    };
+    assert_eq!(read_new("changed.txt"), "two\n");
+    // rebuilt from the old copy and the blocks that differ
+    assert_eq!(read_new("big.log"), log(2500));
+    // only the start of a file crosses the network for its type
+    assert_eq!(
+        engine.new.head(Path::new("big.log"), 7).unwrap(),
+        b"line 0\n"
+    );
+    assert_eq!(
+        engine.new.head(Path::new("changed.txt"), 512).unwrap(),
+        b"two\n"
+    );
+    // opening a new folder walks only that folder on the server
+    let added = comparison
+        .results
+        .iter()
+        .find(|i| engine.rel_path(i) == Path::new("added"))
+        .unwrap();
+    let inside: Vec<_> = engine
+        .expand(added)
+        .unwrap()
+        .iter()
+        .map(|i| engine.rel_path(i))
+        .collect();
+    assert_eq!(inside, [Path::new("added").join("file.txt")]);
+
+    let wrong = Agent::open(&format!("127.0.0.1:{}:.", port), "guess");
+    assert_eq!(
+        wrong.err().map(|e| e.kind()),
+        Some(io::ErrorKind::PermissionDenied)
+    );
+    // the token lets a client into the served folder and nowhere else
+    let outside = Agent::open(
+        &format!("127.0.0.1:{}:{}", port, trees.old.display()),
+        "secret",
+    );
+    assert_eq!(
+        outside.err().map(|e| e.kind()),
+        Some(io::ErrorKind::PermissionDenied)
+    );
+    // a greeting longer than the token is turned away without being read
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.write_all(&(1u64 << 40).to_be_bytes()).unwrap();
+    let mut answer = Vec::new();
+    stream.read_to_end(&mut answer).unwrap();
+    assert_eq!(answer[8..10], [1, 2]);
+}
+
+#[test]
+fn only_blocks_that_differ_are_sent() {
+    let basis: String = (0..5000).map(|i| format!("line {}\n", i)).collect();
+    let mut data = basis.replace("line 1000\n", "line 1000 changed\n");
+    data.insert_str(0, "inserted\n");
+    data.push_str("appended\n");
+    let signature = Signature::from_bytes(&Signature::of(basis.as_bytes()).to_bytes()).unwrap();
+    let ops = delta::decode(&delta::encode(&delta::delta(&signature, data.as_bytes()))).unwrap();
+    let rebuilt = delta::apply(basis.as_bytes(), signature.block_size, &ops).unwrap();
+    assert_eq!(rebuilt, data.as_bytes());
+    let sent: usize = ops
+        .iter()
+        .map(|op| match op {
+            Op::Literal(bytes) => bytes.len(),
+            Op::Copy { .. } => 0,
+        })
+        .sum();
+    // the block with the change, and the tails that fit no whole block
+    assert!(sent < 3 * signature.block_size, "{} bytes sent", sent);
+    assert!(basis.len() > 20 * signature.block_size);
+}
+
+#[test]
+fn interrupted_comparison_resumes_from_the_checkpoint() {
+    let trees = Trees::new();
+    for i in 0..10 {
+        write(
+            trees.old.join(format!("{}.txt", i)),
+            &format!("same {}\n", i),
+        );
+        let new = if i == 7 { "diff 7\n" } else { "same {}\n" };
+        write(
+            trees.new.join(format!("{}.txt", i)),
+            &new.replace("{}", &i.to_string()),
+        );
+    }
+    let file = trees.root.join("checkpoint.tsv");
+    let engine = |resume| {
+        let mut engine = Engine::new(&Options {
+            resume,
+            ..trees.options()
+        })
+        .unwrap();
+        engine.set_checkpoint(Some(file.clone()));
+        engine
+    };
+    // stopped after the listing and four files
+    let mut calls = 0;
+    let partial = engine(false)
+        .compare(&mut |_| {
+            calls += 1;
+            calls < 7
+        })
+        .unwrap();
+    assert!(partial.is_partial);
+    let text = fs::read_to_string(&file).unwrap();
+    let done: Vec<&str> = text.lines().filter(|l| l.contains(".txt")).collect();
+    assert_eq!(done.len(), 4);
+
+    // what the checkpoint says is taken as it is, those files are not read again
+    let flipped: String = text
+        .lines()
+        .map(|l| {
+            if done.contains(&l) {
+                format!("0{}\n", &l[1..])
+            } else {
+                format!("{}\n", l)
+            }
+        })
+        .collect();
+    fs::write(&file, flipped).unwrap();
+    let engine_resumed = engine(true);
+    let comparison = engine_resumed.compare(&mut |_| true).unwrap();
+    let mut expected: Vec<_> = done
+        .iter()
+        .map(|l| l.rsplit('\t').next().unwrap().to_string())
+        .chain(["7.txt".to_string()])
+        .collect();
+    expected.sort();
+    expected.dedup();
+    let changed: Vec<_> = states(&engine_resumed, &comparison)
+        .into_iter()
+        .map(|(rel, _)| rel)
+        .collect();
+    assert_eq!(changed, expected);
+    assert!(!file.exists());
+}
+
+#[test]
+fn cached_hashes_survive_odd_paths_and_forget_stale_files() {
+    let trees = Trees::new();
+    let file = Cache::pair_file(&trees.root, &trees.old, &trees.new);
+    let entry = |name: &str, mtime| {
+        let mut entry = Entry::new(PathBuf::from(name), false, 3);
+        entry.mtime = Some(mtime);
+        entry
+    };
+    let (odd, gone) = (entry("a\tb\\c\nd", 1), entry("gone", 2));
+    let mut cache = Cache::load(file.clone());
+    for (e, hash) in [(&odd, "odd"), (&gone, "gone")] {
+        cache.hash(e, || Ok(hash.to_string())).unwrap();
+    }
+    cache.save().unwrap();
+
+    let mut cache = Cache::load(file.clone());
+    let cached = |cache: &mut Cache, e: &Entry| cache.hash(e, || Ok("new".to_string())).unwrap();
+    assert_eq!(cached(&mut cache, &odd), "odd");
+    cache.keep_only([&odd].into_iter());
+    cache.save().unwrap();
+
+    let mut cache = Cache::load(file.clone());
+    assert_eq!(cached(&mut cache, &odd), "odd");
+    assert_eq!(cached(&mut cache, &gone), "new");
+    assert_eq!(fs::read_dir(file.parent().unwrap()).unwrap().count(), 1);
+}
+
+#[test]
+fn checkpoint_trusts_no_file_on_its_size_alone() {
+    let trees = Trees::new();
+    let file = trees.root.join("checkpoint.tsv");
+    let sized = |name: &str| Entry::new(PathBuf::from(name), false, 3);
+    let stamped = |name: &str| {
+        let mut entry = sized(name);
+        entry.mtime = Some(1);
+        entry
+    };
+    let mut checkpoint = Checkpoint::open(file.clone(), false);
+    checkpoint.record(Path::new("sized"), &sized("sized"), &sized("sized"), true);
+    checkpoint.record(Path::new("a\tb"), &stamped("a\tb"), &stamped("a\tb"), true);
+
+    let resumed = Checkpoint::open(file, true);
+    let outcome = |name: &str, entry: &Entry| resumed.outcome(Path::new(name), entry, entry);
+    assert_eq!(outcome("sized", &sized("sized")), None);
+    assert_eq!(outcome("a\tb", &stamped("a\tb")), Some(true));
+}
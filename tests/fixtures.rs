@@ -0,0 +1,605 @@
+mod common;
+
+use common::{states, write, Trees};
+use diff_folders::{
+    cli::Options,
+    engine::Engine,
+    filter::WalkFilter,
+    manifest,
+    oci::Oci,
+    source::{Archive, Memory, Source},
+    status::StatusItemType,
+};
+use std::fs;
+
+// the preview is drawn by the TUI
+#[cfg(feature = "tui")]
+use diff_folders::{
+    color::Color,
+    renderer::{DiffRenderer, StyledLine},
+    text::RenderOptions,
+    view::file_view,
+};
+
+fn compare_dirs(trees: &Trees) -> Vec<(String, StatusItemType)> {
+    let engine = Engine::new(&trees.options()).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    states(&engine, &comparison)
+}
+
+fn compare_memory(old: Memory, new: Memory) -> Vec<(String, StatusItemType)> {
+    let engine = Engine::with_sources(old, new, &Options::default());
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    states(&engine, &comparison)
+}
+
+fn state(name: &str, state: StatusItemType) -> (String, StatusItemType) {
+    (name.to_string(), state)
+}
+
+#[test]
+fn added_file() {
+    let trees = Trees::new();
+    write(trees.new.join("dir/added.txt"), "hello\n");
+    fs::create_dir_all(trees.old.join("dir")).unwrap();
+    assert_eq!(
+        compare_dirs(&trees),
+        vec![state("dir/added.txt", StatusItemType::New)]
+    );
+}
+
+#[test]
+fn removed_file() {
+    let trees = Trees::new();
+    write(trees.old.join("dir/removed.txt"), "bye\n");
+    fs::create_dir_all(trees.new.join("dir")).unwrap();
+    assert_eq!(
+        compare_dirs(&trees),
+        vec![state("dir/removed.txt", StatusItemType::Deleted)]
+    );
+}
+
+#[test]
+fn removed_folder_stands_for_its_contents() {
+    let trees = Trees::new();
+    write(trees.old.join("gone/a.txt"), "a\n");
+    write(trees.old.join("gone/b/c.txt"), "c\n");
+    assert_eq!(
+        compare_dirs(&trees),
+        vec![state("gone", StatusItemType::Deleted)]
+    );
+}
+
+#[test]
+fn modified_file_of_the_same_size() {
+    let trees = Trees::new();
+    write(trees.old.join("a.txt"), "abc\n");
+    write(trees.new.join("a.txt"), "abd\n");
+    write(trees.old.join("b.txt"), "same\n");
+    write(trees.new.join("b.txt"), "same\n");
+    assert_eq!(
+        compare_dirs(&trees),
+        vec![state("a.txt", StatusItemType::Modified)]
+    );
+}
+
+#[test]
+fn modified_binary_file() {
+    let trees = Trees::new();
+    fs::write(trees.old.join("a.bin"), [0u8, 1, 2, 0xff]).unwrap();
+    fs::write(trees.new.join("a.bin"), [0u8, 1, 3, 0xff]).unwrap();
+    fs::write(trees.old.join("b.bin"), [0u8; 64]).unwrap();
+    fs::write(trees.new.join("b.bin"), [0u8; 64]).unwrap();
+    assert_eq!(
+        compare_dirs(&trees),
+        vec![state("a.bin", StatusItemType::Modified)]
+    );
+}
+
+#[test]
+#[cfg(feature = "tui")]
+fn new_binary_file_shows_a_preview() {
+    let trees = Trees::new();
+    fs::write(trees.new.join("a.bin"), [0u8, 159, 146, 150]).unwrap();
+    let engine = Engine::new(&trees.options()).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    let (content, title) = file_view(&engine, &comparison.results[0], false);
+    assert!(title.starts_with("New binary: "), "{}", title);
+    assert!(!content.is_empty());
+}
+
+/// Shows each side as its byte count, like a decoder would show messages.
+#[cfg(feature = "tui")]
+struct ByteCount;
+
+#[cfg(feature = "tui")]
+impl DiffRenderer for ByteCount {
+    fn name(&self) -> &str {
+        "Byte count"
+    }
+
+    fn render(
+        &self,
+        _rel: &std::path::Path,
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> std::io::Result<Vec<StyledLine>> {
+        let count = |side: Option<&[u8]>| side.map_or("none".to_string(), |b| b.len().to_string());
+        Ok(vec![
+            StyledLine::colored(format!("old: {}", count(old)), Color::Red),
+            StyledLine::plain(format!("new: {}", count(new))),
+        ])
+    }
+}
+
+#[test]
+#[cfg(feature = "tui")]
+fn registered_renderer_replaces_the_diff() {
+    let old = Memory::new("old").file("a.pb", "one").file("b.txt", "x\n");
+    let new = Memory::new("new")
+        .file("a.pb", "three")
+        .file("b.txt", "y\n")
+        .file("c.pb", "new");
+    let mut engine = Engine::with_sources(old, new, &Options::default());
+    engine.renderers_mut().add_glob("*.pb", ByteCount).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    let shown = |name: &str| {
+        let item = comparison
+            .results
+            .iter()
+            .find(|i| engine.rel_path(i) == std::path::Path::new(name))
+            .unwrap();
+        let (content, title) = file_view(&engine, item, false);
+        let text: Vec<String> = content
+            .spans(0..content.len(), RenderOptions::default())
+            .iter()
+            .map(|line| line.0.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+        (title, text)
+    };
+    assert_eq!(
+        shown("a.pb"),
+        (
+            "Byte count: new/a.pb".to_string(),
+            vec!["old: 3".to_string(), "new: 5".to_string()]
+        )
+    );
+    assert_eq!(shown("c.pb").1, vec!["old: none", "new: 3"]);
+    // other files keep the line diff
+    assert!(shown("b.txt").0.starts_with("Diff: "));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_to_a_changed_file_is_modified() {
+    use std::os::unix::fs::symlink;
+    let trees = Trees::new();
+    write(trees.old.join("target.txt"), "one\n");
+    write(trees.new.join("target.txt"), "two\n");
+    symlink("target.txt", trees.old.join("link")).unwrap();
+    symlink("target.txt", trees.new.join("link")).unwrap();
+    assert_eq!(
+        compare_dirs(&trees),
+        vec![
+            state("link", StatusItemType::Modified),
+            state("target.txt", StatusItemType::Modified),
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_folder_is_not_followed() {
+    use std::os::unix::fs::symlink;
+    let trees = Trees::new();
+    write(trees.root.join("elsewhere/a.txt"), "a\n");
+    symlink(trees.root.join("elsewhere"), trees.new.join("link")).unwrap();
+    assert_eq!(
+        compare_dirs(&trees),
+        vec![state("link", StatusItemType::New)]
+    );
+}
+
+#[test]
+fn memory_trees_need_no_disk() {
+    let old = Memory::new("old")
+        .file("same.txt", "same\n")
+        .file("changed.txt", "one\n")
+        .file("gone/a.txt", "a\n")
+        .file("b.bin", [0u8, 1, 2]);
+    let new = Memory::new("new")
+        .file("same.txt", "same\n")
+        .file("changed.txt", "two\n")
+        .file("added/deep/c.txt", "c\n")
+        .file("b.bin", [0u8, 1, 3])
+        .dir("empty");
+    assert_eq!(
+        compare_memory(old, new),
+        vec![
+            state("added", StatusItemType::New),
+            state("b.bin", StatusItemType::Modified),
+            state("changed.txt", StatusItemType::Modified),
+            state("empty", StatusItemType::New),
+            state("gone", StatusItemType::Deleted),
+        ]
+    );
+}
+
+#[test]
+fn memory_trees_skip_hidden_entries() {
+    let old = Memory::new("old");
+    let new = Memory::new("new")
+        .file(".hidden", "x\n")
+        .file(".config/a.txt", "a\n");
+    assert_eq!(compare_memory(old, new), vec![]);
+}
+
+/// A layer tarball of `(path, contents)` members, `None` for folders.
+fn layer(members: &[(&str, Option<&str>)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, contents) in members {
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(match contents {
+            Some(_) => tar::EntryType::Regular,
+            None => tar::EntryType::Directory,
+        });
+        header.set_size(contents.map_or(0, |c| c.len() as u64));
+        builder
+            .append_data(&mut header, path, contents.unwrap_or("").as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+/// Two layers, the upper one replacing a file and hiding a folder and the
+/// contents of another with whiteouts.
+fn layers() -> [Vec<u8>; 2] {
+    [
+        layer(&[
+            ("etc/", None),
+            ("etc/os-release", Some("v1\n")),
+            ("usr/bin/tool", Some("a")),
+            ("tmp/cache/x", Some("x")),
+            ("opt/data/old", Some("o")),
+        ]),
+        layer(&[
+            ("etc/os-release", Some("v2\n")),
+            ("tmp/.wh.cache", Some("")),
+            ("opt/data/.wh..wh..opq", Some("")),
+            ("opt/data/new", Some("n")),
+        ]),
+    ]
+}
+
+#[test]
+fn oci_layout_is_compared_as_its_root_filesystem() {
+    let trees = Trees::new();
+    write(trees.old.join("etc/os-release"), "v2\n");
+    write(trees.old.join("etc/hosts"), "localhost\n");
+    write(trees.old.join("usr/bin/tool"), "b");
+    write(trees.old.join("opt/data/new"), "n");
+    fs::create_dir_all(trees.old.join("tmp")).unwrap();
+
+    let image = trees.root.join("image");
+    let blobs = image.join("blobs/sha256");
+    fs::create_dir_all(&blobs).unwrap();
+    let mut digests = Vec::new();
+    for layer in layers() {
+        let digest = manifest::hash_bytes(&layer);
+        fs::write(blobs.join(&digest), layer).unwrap();
+        digests.push(format!("{{\"digest\":\"sha256:{}\"}}", digest));
+    }
+    let image_manifest = format!("{{\"schemaVersion\":2,\"layers\":[{}]}}", digests.join(","));
+    let digest = manifest::hash_bytes(image_manifest.as_bytes());
+    fs::write(blobs.join(&digest), image_manifest).unwrap();
+    fs::write(
+        image.join("oci-layout"),
+        "{\"imageLayoutVersion\":\"1.0.0\"}",
+    )
+    .unwrap();
+    fs::write(
+        image.join("index.json"),
+        format!(
+            "{{\"schemaVersion\":2,\"manifests\":[{{\"digest\":\"sha256:{}\"}}]}}",
+            digest
+        ),
+    )
+    .unwrap();
+
+    let options = Options {
+        new_dir: format!("oci:{}", image.display()),
+        ..trees.options()
+    };
+    let engine = Engine::new(&options).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![
+            state("etc/hosts", StatusItemType::Deleted),
+            state("usr/bin/tool", StatusItemType::Modified),
+        ]
+    );
+}
+
+#[test]
+fn docker_save_tarball_is_read_without_unpacking() {
+    let trees = Trees::new();
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut add = |path: &str, contents: &[u8]| {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(contents.len() as u64);
+        builder.append_data(&mut header, path, contents).unwrap();
+    };
+    let manifest = "[{\"Config\":\"config.json\",\"RepoTags\":[\"app:latest\"],\"Layers\":[\"one/layer.tar\",\"two/layer.tar\"]}]";
+    add("manifest.json", manifest.as_bytes());
+    let [one, two] = layers();
+    add("one/layer.tar", &one);
+    add("two/layer.tar", &two);
+    let file = trees.root.join("app.tar");
+    fs::write(&file, builder.into_inner().unwrap()).unwrap();
+
+    let image = Oci::open(&file.to_string_lossy()).unwrap();
+    let mut paths: Vec<_> = image
+        .list(&WalkFilter::from_rules(&[]))
+        .unwrap()
+        .into_keys()
+        .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "etc",
+            "etc/os-release",
+            "opt",
+            "opt/data",
+            "opt/data/new",
+            "tmp",
+            "usr",
+            "usr/bin",
+            "usr/bin/tool"
+        ]
+    );
+    let read = |rel: &str| image.read(std::path::Path::new(rel)).unwrap();
+    assert_eq!(read("etc/os-release"), b"v2\n");
+    assert_eq!(read("usr/bin/tool"), b"a");
+    // the upper layer again, then a file before the one read last
+    assert_eq!(read("opt/data/new"), b"n");
+    assert_eq!(read("etc/os-release"), b"v2\n");
+    assert_eq!(
+        image
+            .head(std::path::Path::new("etc/os-release"), 2)
+            .unwrap(),
+        b"v2"
+    );
+}
+
+#[test]
+fn tarballs_are_compared_from_their_index() {
+    let trees = Trees::new();
+    let (old, new) = (trees.root.join("old.tar"), trees.root.join("new.tar"));
+    fs::write(
+        &old,
+        layer(&[
+            ("./app/", None),
+            ("./app/main.js", Some("console.log(1)\n")),
+            ("./app/gone.txt", Some("bye\n")),
+            ("./README", Some("same\n")),
+        ]),
+    )
+    .unwrap();
+    fs::write(
+        &new,
+        layer(&[
+            ("./README", Some("same\n")),
+            ("./app/main.js", Some("console.log(2)\n")),
+            ("./app/lib/util.js", Some("export {}\n")),
+        ]),
+    )
+    .unwrap();
+    let options = Options {
+        old_dir: old.to_string_lossy().into_owned(),
+        new_dir: new.to_string_lossy().into_owned(),
+        ..trees.options()
+    };
+    let engine = Engine::new(&options).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![
+            state("app/gone.txt", StatusItemType::Deleted),
+            state("app/lib", StatusItemType::New),
+            state("app/main.js", StatusItemType::Modified),
+        ]
+    );
+
+    let archive = Archive::open(&new).unwrap();
+    let rel = std::path::Path::new("app/main.js");
+    assert_eq!(archive.read(rel).unwrap(), b"console.log(2)\n");
+    assert_eq!(archive.head(rel, 7).unwrap(), b"console");
+    assert!(archive.read(std::path::Path::new("app")).is_err());
+}
+
+#[test]
+fn snapshot_with_contents_is_compared_and_read_back() {
+    let trees = Trees::new();
+    write(trees.old.join("a.txt"), "one\n");
+    write(trees.old.join("sub/b.txt"), "two\n");
+    write(trees.old.join("sub/c.txt"), "three\n");
+    let out = trees.root.join("old.snapshot");
+    let files = diff_folders::source::list_dir(&trees.old, &WalkFilter::from_rules(&[])).unwrap();
+    assert_eq!(manifest::write(&files, &out, true).unwrap(), 4);
+    // the contents went into the snapshot, nothing is left beside it
+    assert_eq!(fs::read_dir(&trees.root).unwrap().count(), 3);
+
+    write(trees.new.join("a.txt"), "one\n");
+    write(trees.new.join("sub/b.txt"), "TWO\n");
+    write(trees.new.join("sub/c.txt"), "three\n");
+    let options = Options {
+        old_dir: out.to_string_lossy().into_owned(),
+        ..trees.options()
+    };
+    let engine = Engine::new(&options).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![state("sub/b.txt", StatusItemType::Modified)]
+    );
+    assert_eq!(
+        engine.old.read(std::path::Path::new("sub/b.txt")).unwrap(),
+        b"two\n"
+    );
+    assert_eq!(
+        engine.old.read(std::path::Path::new("sub/c.txt")).unwrap(),
+        b"three\n"
+    );
+}
+
+#[test]
+fn compressed_archive_members_are_read_in_any_order() {
+    let trees = Trees::new();
+    let members: Vec<(String, String)> = (0..5)
+        .map(|i| (format!("f{}.txt", i), format!("contents {}\n", i)))
+        .collect();
+    let tar = layer(
+        &members
+            .iter()
+            .map(|(path, contents)| (path.as_str(), Some(contents.as_str())))
+            .collect::<Vec<_>>(),
+    );
+    let file = trees.root.join("old.tar.gz");
+    let mut encoder =
+        flate2::write::GzEncoder::new(fs::File::create(&file).unwrap(), Default::default());
+    std::io::Write::write_all(&mut encoder, &tar).unwrap();
+    encoder.finish().unwrap();
+
+    let archive = Archive::open(&file).unwrap();
+    let read = |path: &str| archive.read(std::path::Path::new(path)).unwrap();
+    // on through the stream, back to the start, and after a partial read
+    assert_eq!(read("f1.txt"), b"contents 1\n");
+    assert_eq!(read("f3.txt"), b"contents 3\n");
+    assert_eq!(read("f0.txt"), b"contents 0\n");
+    assert_eq!(
+        archive.head(std::path::Path::new("f2.txt"), 3).unwrap(),
+        b"con"
+    );
+    assert_eq!(read("f2.txt"), b"contents 2\n");
+    assert_eq!(read("f4.txt"), b"contents 4\n");
+
+    for (path, contents) in &members {
+        write(trees.new.join(path), contents);
+    }
+    write(trees.new.join("f3.txt"), "contents X\n");
+    let options = Options {
+        old_dir: file.to_string_lossy().into_owned(),
+        ..trees.options()
+    };
+    let engine = Engine::new(&options).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![state("f3.txt", StatusItemType::Modified)]
+    );
+}
+
+#[test]
+fn checksum_file_is_verified_against_a_folder() {
+    let trees = Trees::new();
+    write(trees.new.join("bin/app"), "app\n");
+    write(trees.new.join("lib/core.so"), "tampered\n");
+    write(trees.new.join("extra.txt"), "extra\n");
+    let sums = trees.root.join("SHA256SUMS");
+    fs::write(
+        &sums,
+        format!(
+            "{}  ./bin/app\n{} *lib/core.so\nSHA256 (share/doc.txt) = {}\nnot a checksum line\n",
+            manifest::hash_bytes(b"app\n"),
+            manifest::hash_bytes(b"core\n"),
+            manifest::hash_bytes(b"doc\n"),
+        ),
+    )
+    .unwrap();
+    let options = Options {
+        old_dir: sums.to_string_lossy().into_owned(),
+        ..trees.options()
+    };
+    let engine = Engine::new(&options).unwrap();
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    assert_eq!(
+        states(&engine, &comparison),
+        vec![
+            state("extra.txt", StatusItemType::New),
+            state("lib/core.so", StatusItemType::Modified),
+            state("share", StatusItemType::Deleted),
+        ]
+    );
+}
+
+#[cfg(feature = "cloud")]
+#[test]
+fn bucket_prefixes_are_taken_as_folders() {
+    use diff_folders::bucket::Bucket;
+    use std::{collections::BTreeMap, io, path::Path};
+
+    let bucket = Bucket::parse("s3://my-bucket/site/").unwrap();
+    assert_eq!(bucket.root(), Path::new("s3://my-bucket/site"));
+    let objects = vec![
+        ("site/index.html".to_string(), 5),
+        ("site/assets/logo one.png".to_string(), 7),
+        ("site/empty/".to_string(), 0),
+        ("site/".to_string(), 0),
+        ("site-old/index.html".to_string(), 1),
+    ];
+    let entries: BTreeMap<_, _> = bucket
+        .entries(objects)
+        .into_iter()
+        .map(|(rel, e)| (rel.to_string_lossy().replace('\\', "/"), e))
+        .collect();
+    assert_eq!(
+        entries.keys().collect::<Vec<_>>(),
+        ["assets", "assets/logo one.png", "empty", "index.html"]
+    );
+    let logo = &entries["assets/logo one.png"];
+    assert_eq!(logo.size, 7);
+    assert_eq!(logo.path(), bucket.root().join("assets/logo one.png"));
+    assert!(entries["assets"].is_dir());
+    assert!(entries["empty"].is_dir());
+
+    let bucket = Bucket::parse("gs://other").unwrap();
+    assert_eq!(bucket.root(), Path::new("gs://other"));
+    let entries = bucket.entries(vec![("a/b.txt".to_string(), 1)]);
+    assert_eq!(entries.len(), 2);
+
+    for url in ["s3://", "gs:///site", "https://my-bucket/site", "./site"] {
+        assert_eq!(
+            Bucket::parse(url).err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidInput),
+            "{}",
+            url
+        );
+    }
+}
+
+#[cfg(feature = "cloud")]
+#[test]
+fn bucket_listings_are_read_line_by_line() {
+    use diff_folders::bucket;
+
+    // what aws and gcloud print, with the `None` of an empty listing and
+    // lines that are cut short or garbled
+    let out = "site/index.html\t5\n\
+               site/a b/c d.txt\t12\n\
+               None\n\
+               site/no size\t\n\
+               site/bad\tbig\n\
+               \n\
+               site/tab\tin key.txt\t 3 \n";
+    assert_eq!(
+        bucket::parse_listing(out),
+        [
+            ("site/index.html".to_string(), 5),
+            ("site/a b/c d.txt".to_string(), 12),
+            ("site/tab\tin key.txt".to_string(), 3),
+        ]
+    );
+}
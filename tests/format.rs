@@ -0,0 +1,85 @@
+use diff_folders::{
+    format::{date_in, eta, size_in, DateStyle, SizeUnits},
+    text::{slash_path, strip_dot_prefix, strip_verbatim},
+};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const SEC: u128 = 1_000_000_000;
+
+#[test]
+fn sizes_in_binary_and_si_units() {
+    assert_eq!(size_in(340, SizeUnits::Binary), "340 B");
+    assert_eq!(size_in(1536, SizeUnits::Binary), "1.5 KB");
+    assert_eq!(size_in(1536, SizeUnits::Si), "1.5 kB");
+    assert_eq!(size_in(1_000_000, SizeUnits::Binary), "976.6 KB");
+    assert_eq!(size_in(1_000_000, SizeUnits::Si), "1.0 MB");
+}
+
+#[test]
+fn relative_dates_count_the_largest_unit() {
+    let now = 1_700_000_000 * SEC;
+    let ago = |secs: u128| date_in(now - secs * SEC, now, DateStyle::Relative);
+    assert_eq!(ago(0), "just now");
+    assert_eq!(ago(1), "1 second ago");
+    assert_eq!(ago(90), "1 minute ago");
+    assert_eq!(ago(3 * 24 * 3600 + 5), "3 days ago");
+    assert_eq!(ago(2 * 365 * 24 * 3600), "2 years ago");
+    // a file from the future has no sensible age
+    assert!(date_in(now + SEC, now, DateStyle::Relative).starts_with("2023-11-1"));
+}
+
+#[test]
+fn time_left_shows_the_two_largest_units() {
+    let left = |secs| eta(Duration::from_secs(secs));
+    assert_eq!(left(40), "40s");
+    assert_eq!(left(80), "1m 20s");
+    assert_eq!(left(3900), "1h 5m");
+}
+
+#[test]
+fn verbatim_prefixes_are_dropped_where_paths_work_without_them() {
+    let strip = |path: &str| strip_verbatim(PathBuf::from(path));
+    assert_eq!(strip(r"\\?\C:\work\dir"), PathBuf::from(r"C:\work\dir"));
+    assert_eq!(
+        strip(r"\\?\UNC\server\share\dir"),
+        PathBuf::from(r"\\server\share\dir")
+    );
+    // volume GUIDs and devices only work with it
+    let volume = r"\\?\Volume{0b1f7d2c-0000-0000-0000-100000000000}\dir";
+    assert_eq!(strip(volume), PathBuf::from(volume));
+    assert_eq!(strip(r"\\?\pipe\name"), PathBuf::from(r"\\?\pipe\name"));
+    let long = format!(r"\\?\C:\{}", "d".repeat(300));
+    assert_eq!(strip(&long), PathBuf::from(&long));
+    assert_eq!(strip("/home/user/dir"), PathBuf::from("/home/user/dir"));
+}
+
+#[test]
+fn dot_prefixes_name_the_same_relative_path() {
+    assert_eq!(strip_dot_prefix("./src/app.rs"), "src/app.rs");
+    assert_eq!(strip_dot_prefix("././src/app.rs"), "src/app.rs");
+    assert_eq!(strip_dot_prefix("../src/app.rs"), "../src/app.rs");
+    assert_eq!(strip_dot_prefix(".hidden"), ".hidden");
+    let windows = if cfg!(windows) {
+        r"src\app.rs"
+    } else {
+        r".\src\app.rs"
+    };
+    assert_eq!(strip_dot_prefix(r".\src\app.rs"), windows);
+    assert_eq!(strip_dot_prefix(r"./.\src\app.rs"), windows);
+}
+
+#[test]
+fn paths_are_spelled_with_slashes() {
+    assert_eq!(slash_path(Path::new("src/app.rs")), "src/app.rs");
+    assert_eq!(
+        slash_path(&Path::new("src").join("ui").join("app.rs")),
+        "src/ui/app.rs"
+    );
+    assert_eq!(slash_path(Path::new("src//app.rs/")), "src/app.rs");
+    if cfg!(windows) {
+        assert_eq!(slash_path(Path::new(r"src\ui\app.rs")), "src/ui/app.rs");
+    }
+}
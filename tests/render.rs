@@ -0,0 +1,875 @@
+//! Renders the app into a `TestBackend` and compares whole frames with the
+//! files under `tests/snapshots`. Run with `UPDATE_SNAPSHOTS=1` to write them
+//! again after an intended change, then review the diff.
+
+mod common;
+
+use crossterm::event::KeyCode;
+use diff_folders::{
+    app::App,
+    cli::{parse_args, Options},
+    config::{Config, Favorite},
+    engine::Engine,
+    external,
+    launch::{self, Screen},
+    launcher::Launcher,
+    session::Session,
+    source::{self, Memory, Source},
+    tabs::Tabs,
+    text::PathStyle,
+    view,
+};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tui::{
+    backend::TestBackend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    Terminal,
+};
+
+/// An app over two trees, in memory unless told otherwise, drawn after every
+/// key like the real loop.
+struct Harness<S: Source = Memory> {
+    app: App<S>,
+    terminal: Terminal<TestBackend>,
+}
+
+impl<S: Source> Harness<S> {
+    fn new(old: S, new: S) -> Self {
+        Self::with_options(old, new, Options::default())
+    }
+
+    fn with_options(old: S, new: S, options: Options) -> Self {
+        let mut app = App::with_sources(old, new, options);
+        app.load().unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        app.draw_terminal(&mut terminal).unwrap();
+        Harness { app, terminal }
+    }
+
+    fn keys(&mut self, keys: &[KeyCode]) -> &mut Self {
+        for key in keys {
+            self.app.event(*key);
+            self.app.draw_terminal(&mut self.terminal).unwrap();
+        }
+        self
+    }
+
+    /// Draws again without a key, like the event loop after a program ran.
+    fn draw(&mut self) -> &mut Self {
+        self.app.draw_terminal(&mut self.terminal).unwrap();
+        self
+    }
+
+    /// Resizes the terminal and draws again, like the event loop on a resize.
+    fn resize(&mut self, width: u16, height: u16) -> &mut Self {
+        self.terminal.backend_mut().resize(width, height);
+        self.terminal
+            .resize(Rect::new(0, 0, width, height))
+            .unwrap();
+        self.app.on_resize(height);
+        self.app.draw_terminal(&mut self.terminal).unwrap();
+        self
+    }
+
+    /// The last frame as text, one line per row with trailing blanks cut.
+    fn frame(&self) -> String {
+        let buffer = self.terminal.backend().buffer();
+        let mut text = String::new();
+        for y in 0..buffer.area.height {
+            let row: String = (0..buffer.area.width)
+                .map(|x| buffer.get(x, y).symbol.as_str())
+                .collect();
+            text += row.trim_end();
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Style of the first cell of `needle` in the last frame.
+    fn style_of(&self, needle: &str) -> Style {
+        let buffer = self.terminal.backend().buffer();
+        for (y, row) in self.frame().lines().enumerate() {
+            if let Some(byte) = row.find(needle) {
+                let x = row[..byte].chars().count() as u16;
+                return buffer.get(x, y as u16).style();
+            }
+        }
+        panic!("{:?} is not on screen:\n{}", needle, self.frame());
+    }
+
+    fn assert_snapshot(&self, name: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/snapshots")
+            .join(format!("{}.txt", name));
+        let frame = self.frame();
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() || !path.exists() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &frame).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap();
+        assert!(
+            frame == expected,
+            "frame differs from {}\n--- expected\n{}--- actual\n{}",
+            path.display(),
+            expected,
+            frame
+        );
+    }
+}
+
+fn trees() -> (Memory, Memory) {
+    let old = Memory::new("old")
+        .file("a.txt", "one\ntwo\n")
+        .file("gone.txt", "bye\n");
+    let new = Memory::new("new")
+        .file("a.txt", "one\nthree\n")
+        .file("b.rs", "fn main() {}\n");
+    (old, new)
+}
+
+#[test]
+fn dashboard() {
+    let (old, new) = trees();
+    Harness::new(old, new).assert_snapshot("dashboard");
+}
+
+#[test]
+fn file_list() {
+    let (old, new) = trees();
+    Harness::new(old, new)
+        .keys(&[KeyCode::Tab])
+        .assert_snapshot("file_list");
+}
+
+#[test]
+fn first_file_is_shown_and_quick_start_otherwise() {
+    // folders come first in the list but a file is opened
+    let (old, new) = trees();
+    let mut harness = Harness::new(old.dir("a"), new);
+    harness.keys(&[KeyCode::Tab]);
+    let frame = harness.frame();
+    assert!(
+        frame.ends_with(
+            "a.txt                                                                   item 2/4\n"
+        ),
+        "{}",
+        frame
+    );
+
+    let old = Memory::new("old").dir("empty");
+    let new = Memory::new("new").dir("fresh");
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab]);
+    harness.assert_snapshot("quick_start");
+}
+
+#[test]
+fn diff_of_a_modified_file() {
+    let (old, new) = trees();
+    Harness::new(old, new)
+        .keys(&[KeyCode::Tab])
+        .assert_snapshot("diff_modified");
+}
+
+#[test]
+fn new_file_is_shown_whole() {
+    let (old, new) = trees();
+    Harness::new(old, new)
+        .keys(&[KeyCode::Tab, KeyCode::Down])
+        .assert_snapshot("new_file");
+}
+
+#[test]
+fn list_colors_follow_the_state() {
+    let (old, new) = trees();
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab]);
+    assert_eq!(harness.style_of("b.rs").fg, Some(Color::Green));
+    assert_eq!(harness.style_of("gone.txt").fg, Some(Color::Red));
+    // the selected line has colors of its own
+    harness.keys(&[KeyCode::Down]);
+    assert_eq!(harness.style_of("a.txt").fg, Some(Color::LightYellow));
+}
+
+#[test]
+fn no_color_marks_states_with_symbols_and_attributes() {
+    let (old, new) = trees();
+    let options = Options {
+        no_color: true,
+        ..Options::default()
+    };
+    let mut harness = Harness::with_options(old, new, options);
+    harness.keys(&[KeyCode::Tab]);
+    let frame = harness.frame();
+    assert!(frame.contains("~ f ./a.txt"), "{}", frame);
+    assert!(frame.contains("+ f ./b.rs"), "{}", frame);
+    assert!(frame.contains("- f ./gone.txt"), "{}", frame);
+    let buffer = harness.terminal.backend().buffer();
+    assert!(buffer
+        .content
+        .iter()
+        .all(|c| c.fg == Color::Reset && c.bg == Color::Reset));
+    let style = harness.style_of("b.rs");
+    assert!(style.add_modifier.contains(Modifier::BOLD));
+    let style = harness.style_of("gone.txt");
+    assert!(style.add_modifier.contains(Modifier::UNDERLINED));
+}
+
+#[test]
+fn path_style_flag_is_not_saved_but_toggling_it_is() {
+    let (old, new) = trees();
+    let mut options = Options {
+        path_style: PathStyle::Full,
+        ..Options::default()
+    };
+    options.layout.path_style = Some(PathStyle::Name);
+    let mut harness = Harness::with_options(old, new, options);
+    assert_eq!(harness.app.layout().path_style, Some(PathStyle::Name));
+    harness.keys(&[KeyCode::Char('P')]);
+    assert_eq!(harness.app.layout().path_style, Some(PathStyle::Name));
+    harness.keys(&[KeyCode::Char('P')]);
+    assert_eq!(harness.app.layout().path_style, Some(PathStyle::Relative));
+}
+
+#[test]
+fn diff_lines_are_colored() {
+    let (old, new) = trees();
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab]);
+    assert_eq!(harness.style_of("two").fg, Some(Color::Red));
+    assert_eq!(harness.style_of("three").fg, Some(Color::Green));
+}
+
+#[test]
+fn scrolling_moves_the_diff() {
+    let lines = |changed: usize| {
+        (0..100)
+            .map(|i| {
+                if i == changed {
+                    "changed\n".to_string()
+                } else {
+                    format!("line {}\n", i)
+                }
+            })
+            .collect::<String>()
+    };
+    let old = Memory::new("old").file("long.txt", lines(0));
+    let new = Memory::new("new").file("long.txt", lines(99));
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab, KeyCode::Right]);
+    harness.assert_snapshot("scroll_top");
+    harness.keys(&[KeyCode::PageDown]);
+    harness.assert_snapshot("scroll_page_down");
+    harness.keys(&[KeyCode::End]);
+    harness.assert_snapshot("scroll_end");
+    harness.keys(&[KeyCode::Home]);
+    harness.assert_snapshot("scroll_top");
+}
+
+#[test]
+fn prompt_is_drawn_over_the_panes() {
+    let (old, new) = trees();
+    Harness::new(old, new)
+        .keys(&[
+            KeyCode::Tab,
+            KeyCode::Char('/'),
+            KeyCode::Char('b'),
+            KeyCode::Char('.'),
+        ])
+        .assert_snapshot("goto_prompt");
+}
+
+#[test]
+fn too_many_files_asks_how_to_go_on() {
+    let old = Memory::new("old").file("a.txt", "a\n").file("b.txt", "b\n");
+    let new = Memory::new("new").file("a.txt", "x\n").file("c.txt", "c\n");
+    let options = || Options {
+        max_files: Some(1),
+        ..Options::default()
+    };
+    let mut harness = Harness::with_options(old.clone(), new.clone(), options());
+    harness.assert_snapshot("too_many_files");
+    harness.keys(&[KeyCode::Char('f'), KeyCode::Char('b')]);
+    harness.assert_snapshot("too_many_files_refine");
+
+    let mut harness = Harness::with_options(old, new, options());
+    harness.keys(&[KeyCode::Char('c'), KeyCode::Tab]);
+    harness.assert_snapshot("too_many_files_continued");
+}
+
+#[test]
+fn type_command_lists_only_that_kind() {
+    let (old, new) = trees();
+    let new = new.file("logo.png", b"\x89PNG\r\n\x1a\n....".to_vec());
+    let mut harness = Harness::new(old, new);
+    let mut keys = vec![KeyCode::Tab, KeyCode::Char(':')];
+    keys.extend("type image".chars().map(KeyCode::Char));
+    keys.push(KeyCode::Enter);
+    harness.keys(&keys);
+    let frame = harness.frame();
+    assert!(frame.contains("logo.png (+12 B)  image/png"), "{}", frame);
+    assert!(frame.contains("[image]"), "{}", frame);
+    assert!(!frame.contains("a.txt"), "{}", frame);
+
+    let mut keys = vec![KeyCode::Char(':')];
+    keys.extend("type text".chars().map(KeyCode::Char));
+    keys.push(KeyCode::Enter);
+    harness.keys(&keys);
+    let frame = harness.frame();
+    assert!(frame.contains("a.txt"), "{}", frame);
+    assert!(!frame.contains("logo.png"), "{}", frame);
+}
+
+#[test]
+fn resizing_redraws_and_keeps_the_scroll_in_range() {
+    let lines = |last: &str| {
+        (0..100)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>()
+            + last
+    };
+    let old = Memory::new("old").file("long.txt", lines("old\n"));
+    let new = Memory::new("new").file("long.txt", lines("new\n"));
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab, KeyCode::Right, KeyCode::End]);
+    assert!(!harness.frame().contains("line 60\n"));
+
+    // taller: the end of the diff stays at the bottom, more lines show above it
+    harness.resize(80, 60);
+    let frame = harness.frame();
+    assert_eq!(frame.lines().count(), 60);
+    assert!(frame.contains("+ new"), "{}", frame);
+    assert!(frame.contains("line 60"), "{}", frame);
+
+    // and back: the top line stays put and no rows of the big frame are left
+    harness.resize(80, 20);
+    let frame = harness.frame();
+    assert_eq!(frame.lines().count(), 20);
+    assert!(
+        frame.lines().nth(2).unwrap().contains("line 47"),
+        "{}",
+        frame
+    );
+}
+
+#[test]
+fn focused_diff_shows_both_files_in_the_status_bar() {
+    let (old, new) = trees();
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab, KeyCode::Right]);
+    let bar = |harness: &Harness| harness.frame().lines().last().unwrap().to_string();
+    assert!(
+        bar(&harness).starts_with("old: old/a.txt (8 B)  new: new/a.txt (10 B) "),
+        "{}",
+        bar(&harness)
+    );
+
+    harness.keys(&[KeyCode::Left, KeyCode::Down, KeyCode::Right]);
+    assert!(
+        bar(&harness).starts_with("old: none  new: new/b.rs (13 B) "),
+        "{}",
+        bar(&harness)
+    );
+
+    // the list has the focus again
+    harness.keys(&[KeyCode::Left]);
+    assert!(bar(&harness).starts_with("b.rs "), "{}", bar(&harness));
+}
+
+#[test]
+fn wrapped_lines_are_scrolled_by_the_rows_they_take() {
+    // every line wraps to three rows of the diff window
+    let text: String = (0..40)
+        .map(|i| format!("line {:02} {}\n", i, "x".repeat(120)))
+        .collect::<String>()
+        + "the end\n";
+    let old = Memory::new("old");
+    let new = Memory::new("new").file("long.txt", text);
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab, KeyCode::Right]);
+    // a page of rows moves past the lines it showed, not half a screen of lines
+    harness.keys(&[KeyCode::PageDown]);
+    let frame = harness.frame();
+    assert!(frame.contains("line 02"), "{}", frame);
+    assert!(!frame.contains("line 01"), "{}", frame);
+    // the end is reachable even though the last lines take more rows than
+    // the window has for them counted as one each
+    harness.keys(&[KeyCode::End]);
+    let frame = harness.frame();
+    assert!(frame.contains("the end"), "{}", frame);
+}
+
+#[test]
+fn wide_characters_keep_the_columns_aligned() {
+    let old = Memory::new("old");
+    let new = Memory::new("new").file("cjk.txt", "漢\tend\nab\tend\nabcdefg漢end\nabcdefghiend\n");
+    let mut harness = Harness::new(old, new);
+    // the cell `end` starts in on the row holding `line`; the frame has a
+    // blank cell after each wide character
+    let column = |harness: &Harness, line: &str| {
+        let frame = harness.frame();
+        let row = frame.lines().find(|row| row.contains(line));
+        let row = row.unwrap_or_else(|| panic!("{:?} is not on screen:\n{}", line, frame));
+        row[..row.find("end").unwrap()].chars().count()
+    };
+    harness.keys(&[KeyCode::Tab]);
+    // a wide character takes two columns, so both tabs stop at the same one
+    assert_eq!(column(&harness, "漢 "), column(&harness, "ab "));
+
+    // scrolling sideways past half of a wide character leaves a space in its
+    // place rather than shifting the rest of the line
+    harness.keys(&[KeyCode::Char('w'), KeyCode::Right, KeyCode::Right]);
+    let frame = harness.frame();
+    assert!(!frame.contains("abcdefg"), "{}", frame);
+    assert_eq!(
+        column(&harness, " end"),
+        column(&harness, "iend"),
+        "{}",
+        frame
+    );
+}
+
+#[test]
+fn status_bar_shows_the_selection_and_modes() {
+    let (old, new) = trees();
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab, KeyCode::Down]);
+    let bar = |harness: &Harness| harness.frame().lines().last().unwrap().to_string();
+    assert!(bar(&harness).starts_with("b.rs "), "{}", bar(&harness));
+    assert!(bar(&harness).ends_with(" item 2/3"), "{}", bar(&harness));
+
+    harness.keys(&[KeyCode::Down, KeyCode::Down]);
+    assert!(
+        bar(&harness).starts_with("wrapped to the top"),
+        "{}",
+        bar(&harness)
+    );
+    assert!(bar(&harness).ends_with(" item 1/3"), "{}", bar(&harness));
+
+    let mut keys = vec![KeyCode::Char(':')];
+    keys.extend("filter new".chars().map(KeyCode::Char));
+    keys.push(KeyCode::Enter);
+    harness.keys(&keys);
+    assert!(
+        bar(&harness).ends_with("[new] item 1/1"),
+        "{}",
+        bar(&harness)
+    );
+    assert!(!harness.frame().contains("folder new [new]"));
+}
+
+#[test]
+fn selection_stays_on_its_entry_or_the_one_in_its_place() {
+    let (old, new) = trees();
+    let mut harness = Harness::new(old, new.file("c.rs", "// c\n"));
+    let command = |line: &str| {
+        let mut keys = vec![KeyCode::Char(':')];
+        keys.extend(line.chars().map(KeyCode::Char));
+        keys.push(KeyCode::Enter);
+        keys
+    };
+    let bar = |harness: &Harness| harness.frame().lines().last().unwrap().to_string();
+    // gone.txt is filtered out, c.rs takes the last place the list has
+    harness.keys(&[KeyCode::Tab, KeyCode::End]);
+    harness.keys(&command("filter new"));
+    assert!(bar(&harness).starts_with("c.rs "), "{}", bar(&harness));
+    assert!(bar(&harness).ends_with(" item 2/2"), "{}", bar(&harness));
+    assert!(
+        harness.frame().contains("New File: new/c.rs"),
+        "{}",
+        harness.frame()
+    );
+    // and keeps it when the list grows again
+    harness.keys(&command("filter all"));
+    assert!(bar(&harness).ends_with(" item 3/4"), "{}", bar(&harness));
+}
+
+#[test]
+fn enter_lists_the_files_of_a_deleted_folder() {
+    let (old, new) = trees();
+    let old = old
+        .file("docs/guide.md", "# guide\n")
+        .file("docs/img/logo.svg", "<svg/>\n");
+    let mut harness = Harness::new(old, new);
+    harness.keys(&[KeyCode::Tab, KeyCode::Down, KeyCode::Down]);
+    assert!(!harness.frame().contains("guide.md"));
+    harness.keys(&[KeyCode::Enter]);
+    let frame = harness.frame();
+    assert!(frame.contains("f ./docs/guide.md (-8 B)"), "{}", frame);
+    assert!(frame.contains("f ./docs/img/logo.svg (-7 B)"), "{}", frame);
+    assert_eq!(harness.style_of("guide.md").fg, Some(Color::Red));
+
+    // the files are opened like any other, Enter on the folder folds them in
+    harness.keys(&[KeyCode::Down]);
+    assert!(harness.frame().contains("# guide"), "{}", harness.frame());
+    harness.keys(&[KeyCode::Up, KeyCode::Enter]);
+    assert!(!harness.frame().contains("guide.md"), "{}", harness.frame());
+}
+
+#[test]
+fn moved_file_shows_the_diff_to_where_it_was() {
+    let old = Memory::new("old").file("src/main.rs", "fn main() {}\n");
+    let new = Memory::new("new").file("app/main.rs", "fn main() {}\n");
+    let options = Options {
+        renames: true,
+        ..Options::default()
+    };
+    let engine = Engine::with_sources(old.clone(), new.clone(), &options);
+    let mut harness = Harness::with_options(old, new, options);
+    harness.keys(&[KeyCode::Tab]);
+    let frame = harness.frame();
+    assert!(
+        frame.contains("./app/main.rs (+13 B) moved from src/main.rs"),
+        "{}",
+        frame
+    );
+    assert!(
+        frame.contains("./src/main.rs (-13 B) moved to app/main.rs"),
+        "{}",
+        frame
+    );
+    harness.keys(&[KeyCode::Char('z')]);
+    harness.assert_snapshot("moved_file");
+
+    // every view of the move names both of its ends
+    harness.keys(&[KeyCode::Char('c')]);
+    let frame = harness.frame();
+    assert!(frame.contains("old/src/main.rs"), "{}", frame);
+    assert!(frame.contains("new/app/main.rs"), "{}", frame);
+    let comparison = engine.compare(&mut |_| true).unwrap();
+    let moved = comparison
+        .results
+        .iter()
+        .find(|i| i.moved.is_some() && engine.rel_path(i) == Path::new("app/main.rs"))
+        .unwrap();
+    let diff = engine.unified_diff(moved).unwrap();
+    assert!(!diff.contains("/dev/null"), "{}", diff);
+    let sides = view::sides(&engine, moved);
+    assert!(
+        sides.starts_with("old: old/src/main.rs (13 B)"),
+        "{}",
+        sides
+    );
+}
+
+#[test]
+fn bookmarks_are_jumped_to_and_exported() {
+    let (old, new) = trees();
+    let mut harness = Harness::new(old, new);
+    // mark a.txt and gone.txt, then go round them from b.rs
+    harness.keys(&[
+        KeyCode::Tab,
+        KeyCode::Char('m'),
+        KeyCode::Down,
+        KeyCode::Down,
+        KeyCode::Char('m'),
+        KeyCode::Up,
+    ]);
+    let frame = harness.frame();
+    assert!(frame.contains("a.txt (+2 B) *"), "{}", frame);
+    assert!(frame.contains("[2 bookmarked] item 2/3"), "{}", frame);
+    harness.keys(&[KeyCode::Char('\'')]);
+    assert!(
+        harness.frame().ends_with("item 3/3\n"),
+        "{}",
+        harness.frame()
+    );
+    harness.keys(&[KeyCode::Char('\'')]);
+    assert!(
+        harness.frame().ends_with("item 1/3\n"),
+        "{}",
+        harness.frame()
+    );
+
+    let trees = common::Trees::new();
+    let file = trees.root.join("marks.txt");
+    let mut keys = vec![KeyCode::Char(':')];
+    keys.extend(
+        format!("bookmarks {}", file.display())
+            .chars()
+            .map(KeyCode::Char),
+    );
+    keys.push(KeyCode::Enter);
+    harness.keys(&keys);
+    assert_eq!(fs::read_to_string(&file).unwrap(), "a.txt\ngone.txt\n");
+    // and read back as they were written
+    let args = [&file, &trees.old, &trees.new].map(|p| p.to_string_lossy().into_owned());
+    let args = std::iter::once("--bookmarks".to_string()).chain(args);
+    let options = parse_args(args, Config::default(), std::iter::empty()).unwrap();
+    let marked: Vec<_> = options.session.bookmarks.iter().collect();
+    assert_eq!(marked, [Path::new("a.txt"), Path::new("gone.txt")]);
+}
+
+#[test]
+fn notes_are_saved_to_the_session_and_exported() {
+    let dir = common::Trees::new();
+    let session_file = dir.root.join("review.session");
+    let (old, new) = trees();
+    let options = Options {
+        session: Session::open(&session_file).unwrap(),
+        ..Options::default()
+    };
+    let mut harness = Harness::with_options(old.clone(), new.clone(), options);
+    let mut keys = vec![KeyCode::Tab, KeyCode::Char('N')];
+    keys.extend("check | this".chars().map(KeyCode::Char));
+    keys.push(KeyCode::Enter);
+    harness.keys(&keys);
+    let frame = harness.frame();
+    assert!(frame.contains("a.txt  note: check | this"), "{}", frame);
+    harness.keys(&[KeyCode::Char('m')]);
+    let frame = harness.frame();
+    assert!(frame.contains("a.txt (+2 B) * [note]"), "{}", frame);
+
+    // a later run picks the review up again
+    let options = Options {
+        session: Session::open(&session_file).unwrap(),
+        ..Options::default()
+    };
+    let mut harness = Harness::with_options(old, new, options);
+    let markdown = dir.root.join("review.md");
+    let json = dir.root.join("review.json");
+    for file in [&markdown, &json] {
+        let mut keys = vec![KeyCode::Char(':')];
+        keys.extend(
+            format!("export {}", file.display())
+                .chars()
+                .map(KeyCode::Char),
+        );
+        keys.push(KeyCode::Enter);
+        harness.keys(&keys);
+    }
+    let markdown = fs::read_to_string(markdown).unwrap();
+    assert!(
+        markdown.contains("| modified | a.txt | +2 B |  | yes | check \\| this |\n"),
+        "{}",
+        markdown
+    );
+    assert!(
+        markdown.contains("| new | b.rs | +13 B |  |  |  |\n"),
+        "{}",
+        markdown
+    );
+    let json = fs::read_to_string(json).unwrap();
+    assert!(
+        json.contains(r#"{"state":"modified","path":"a.txt","dir":false,"size_delta":2,"review":null,"bookmarked":true,"note":"check | this"}"#),
+        "{}",
+        json
+    );
+}
+
+#[test]
+fn reviewed_entries_count_towards_progress_and_can_be_hidden() {
+    let dir = common::Trees::new();
+    let (old, new) = trees();
+    let options = Options {
+        session: Session::open(&dir.root.join("review.tsv")).unwrap(),
+        ..Options::default()
+    };
+    let mut harness = Harness::with_options(old, new, options);
+    // a.txt ends up accepted, b.rs reviewed
+    harness.keys(&[
+        KeyCode::Tab,
+        KeyCode::Char('r'),
+        KeyCode::Char('r'),
+        KeyCode::Down,
+        KeyCode::Char('r'),
+    ]);
+    let frame = harness.frame();
+    assert!(frame.contains("a.txt (+2 B) [accepted]"), "{}", frame);
+    assert!(frame.contains("b.rs (+13 B) [reviewed]"), "{}", frame);
+    assert!(frame.contains("reviewed 2/3 (66%)"), "{}", frame);
+
+    harness.keys(&[KeyCode::Char('R')]);
+    let frame = harness.frame();
+    assert!(!frame.contains("a.txt"), "{}", frame);
+    assert!(frame.contains("gone.txt"), "{}", frame);
+    assert!(frame.contains("[unreviewed] item 1/1"), "{}", frame);
+
+    // reviewing the last one empties the list
+    harness.keys(&[KeyCode::Char('r')]);
+    assert!(
+        harness.frame().contains("reviewed 3/3 (100%)"),
+        "{}",
+        harness.frame()
+    );
+    let saved = fs::read_to_string(dir.root.join("review.tsv")).unwrap();
+    assert_eq!(
+        saved,
+        "review\ta.txt\taccepted\nreview\tb.rs\treviewed\nreview\tgone.txt\treviewed\n"
+    );
+}
+
+#[test]
+fn pairs_open_in_tabs_and_are_compared_when_shown() {
+    let app = |old: &str, new: &str, file: &str| {
+        let old = Memory::new(old).file(file, "one\n");
+        let new = Memory::new(new)
+            .file(file, "two\n")
+            .file("extra.txt", "x\n");
+        App::with_sources(old, new, Options::default())
+    };
+    let mut tabs = Tabs::new(vec![
+        app("staging", "prod", "a.txt"),
+        app("qa", "prod", "b.txt"),
+    ]);
+    let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+    let mut keys = |tabs: &mut Tabs<Memory>, keys: &[KeyCode]| {
+        for key in keys {
+            tabs.event(*key);
+        }
+        tabs.draw_terminal(&mut terminal).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.width)
+            .map(|x| buffer.get(x, 0).symbol.as_str())
+            .collect::<String>()
+    };
+    // the second pair is not compared before its tab is shown
+    let bar = keys(&mut tabs, &[]);
+    assert_eq!(bar.trim_end(), " 1 staging → prod (2)  2 qa → prod");
+    let bar = keys(&mut tabs, &[KeyCode::Tab]);
+    assert_eq!(bar.trim_end(), " 1 staging → prod (2)  2 qa → prod (2)");
+    keys(&mut tabs, &[KeyCode::Char(':')]);
+    assert!(tabs.is_typing());
+    // Tab is the command line's while it is typed
+    keys(&mut tabs, &[KeyCode::Tab, KeyCode::Esc, KeyCode::BackTab]);
+    let (old, _) = tabs.active().roots();
+    assert_eq!(old, "staging");
+}
+
+#[test]
+fn start_screen_offers_favorites_then_recent_pairs() {
+    let favorites = [Favorite {
+        name: "staging".to_string(),
+        old: "/srv/prod".to_string(),
+        new: "/srv/staging".to_string(),
+    }];
+    let pair = |old: &str, new: &str| (old.to_string(), new.to_string());
+    let recent = [pair("/srv/prod", "/srv/staging"), pair("/tmp/a", "/tmp/b")];
+    let mut launcher = Launcher::new(&favorites, &recent);
+    let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+    terminal.draw(|f| launcher.draw(f)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let row = |y| {
+        (0..buffer.area.width)
+            .map(|x| buffer.get(x, y).symbol.as_str())
+            .collect::<String>()
+    };
+    // a recent pair that is also a favorite is listed once
+    assert!(row(1).starts_with("│staging /srv/prod → /srv/staging "));
+    assert!(row(2).starts_with("│/tmp/a → /tmp/b "));
+    assert!(row(3).starts_with("│ "));
+    assert!(row(19).starts_with("Enter compares the pair"));
+
+    assert_eq!(launcher.event(KeyCode::Down), None);
+    assert_eq!(launcher.event(KeyCode::Down), None);
+    assert_eq!(
+        launcher.event(KeyCode::Enter),
+        Some(pair("/tmp/a", "/tmp/b"))
+    );
+}
+
+/// Notes what the app's terminal went through, and fails to give it up
+/// when told to.
+#[derive(Default)]
+struct FakeScreen {
+    calls: Vec<&'static str>,
+    fail_suspend: bool,
+}
+
+impl Screen for FakeScreen {
+    fn suspend(&mut self) -> io::Result<()> {
+        self.calls.push("suspend");
+        if self.fail_suspend {
+            return Err(io::Error::other("not a terminal"));
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        self.calls.push("resume");
+        Ok(())
+    }
+}
+
+#[test]
+fn external_tools_get_the_terminal_back_however_they_end() {
+    let mut screen = FakeScreen::default();
+    let status = launch::run(&mut screen, &mut external::shell("exit 3")).unwrap();
+    assert_eq!(status.code(), Some(3));
+    let missing = launch::run(&mut screen, &mut Command::new("/nonexistent/difftool"));
+    assert!(missing.is_err());
+    assert_eq!(screen.calls, ["suspend", "resume", "suspend", "resume"]);
+
+    // a terminal that cannot be given up is taken back without running anything
+    let dir = common::Trees::new();
+    let ran = dir.root.join("ran");
+    let mut screen = FakeScreen {
+        fail_suspend: true,
+        ..FakeScreen::default()
+    };
+    let touch = format!("touch '{}'", ran.display());
+    assert!(launch::run(&mut screen, &mut external::shell(&touch)).is_err());
+    assert!(!ran.exists());
+    assert_eq!(screen.calls, ["suspend", "resume"]);
+}
+
+#[test]
+fn difftool_opens_the_selected_pair_and_its_failure_is_shown() {
+    let trees = common::Trees::new();
+    common::write(trees.old.join("a.txt"), "one\n");
+    common::write(trees.new.join("a.txt"), "two\n");
+    let options = Options {
+        difftool: Some("cmp -s {old} {new}".to_string()),
+        ..trees.options()
+    };
+    let old = source::open(&options.old_dir).unwrap();
+    let new = source::open(&options.new_dir).unwrap();
+    let mut h = Harness::with_options(old, new, options);
+    assert!(h.app.take_launch().is_none());
+
+    h.keys(&[KeyCode::Tab, KeyCode::Char('D')]);
+    let mut difftool = h.app.take_launch().unwrap();
+    assert_eq!(difftool.name, "cmp");
+    let command = format!("{:?}", difftool.command);
+    assert!(command.contains(&trees.old.join("a.txt").display().to_string()));
+    assert!(command.contains(&trees.new.join("a.txt").display().to_string()));
+    let status = launch::run(&mut FakeScreen::default(), &mut difftool.command);
+    h.app.launched(&difftool.name, status);
+    let frame = h.draw().frame();
+    assert!(frame.contains("cmp failed (exit status: 1)"), "{}", frame);
+
+    // the editor gets the new copy
+    h.keys(&[KeyCode::Char('e')]);
+    let editor = h.app.take_launch().unwrap();
+    assert!(
+        format!("{:?}", editor.command).contains(&trees.new.join("a.txt").display().to_string())
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn difftool_runs_nothing_spelled_in_a_file_name() {
+    let trees = common::Trees::new();
+    let name = "{new};touch PWNED;#";
+    common::write(trees.old.join(name), "one\n");
+    common::write(trees.new.join(name), "two\n");
+    let options = Options {
+        difftool: Some("cmp -s {old} {new}".to_string()),
+        ..trees.options()
+    };
+    let old = source::open(&options.old_dir).unwrap();
+    let new = source::open(&options.new_dir).unwrap();
+    let mut h = Harness::with_options(old, new, options);
+    h.keys(&[KeyCode::Tab, KeyCode::Char('D')]);
+    let mut difftool = h.app.take_launch().unwrap();
+    difftool.command.current_dir(&trees.root);
+    let status = launch::run(&mut FakeScreen::default(), &mut difftool.command).unwrap();
+    assert_eq!(status.code(), Some(1));
+    assert!(!trees.root.join("PWNED").exists());
+}